@@ -0,0 +1,152 @@
+//! Compile programs with the native backends, build them with the system C compiler and check
+//! that they give the same results as the stack VM. The x86-64 backend needs an x86-64 Linux.
+//!
+//! usage: cargo run -p whiskc --example backend_check [file.wsk]...
+//!
+//! Without arguments the programs of `test/` are checked. The compiler is taken from `$CC`,
+//! `cc` by default.
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::{self, Command},
+};
+
+use whiskc::{
+    codegen::{Target, C_RUNTIME_HEADER},
+    compile::{compile_source, CompileOutput, CompileSwitch},
+    ir::opt::OptLevel,
+};
+use wsk_vm::{Value, VM};
+
+fn main() {
+    let mut paths: Vec<PathBuf> = env::args().skip(1).map(PathBuf::from).collect();
+    if paths.is_empty() {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/../test");
+        paths = fs::read_dir(dir)
+            .expect("cannot read the test directory")
+            .map(|v| v.unwrap().path())
+            .filter(|v| v.extension().is_some_and(|v| v == "wsk"))
+            .collect();
+        paths.sort();
+    }
+    let cc = env::var("CC").unwrap_or_else(|_| "cc".to_owned());
+    let out_dir = env::temp_dir().join(format!("whiskc-backend-check-{}", process::id()));
+    fs::create_dir_all(&out_dir).expect("cannot create the output directory");
+
+    let mut ok = true;
+    println!(
+        "{:<16} {:<6} {:>20} {:>20} {:>20}",
+        "program", "opt", "vm", "c", "x86-64"
+    );
+    for path in paths {
+        let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+        let source = fs::read_to_string(&path).expect("cannot read the program");
+        for opt_level in [OptLevel::O0, OptLevel::O2] {
+            let Some(vm_output) = compile(&name, &source, opt_level, Target::WskVm) else {
+                ok = false;
+                continue;
+            };
+            let mut vm = VM::default();
+            let expected = match vm.execute(vm_output.program.unwrap()) {
+                Ok(()) => match vm.stack() {
+                    [Value::Int(v)] => v.to_string(),
+                    stack => format!("{:?}", stack),
+                },
+                Err(e) => format!("{:?}", e),
+            };
+
+            let mut results = vec![];
+            for target in [Target::C, Target::X86_64] {
+                let Some(output) = compile(&name, &source, opt_level, target) else {
+                    results.push("failed to compile".to_owned());
+                    continue;
+                };
+                let built = match target {
+                    Target::C => {
+                        fs::write(out_dir.join("wsk_rt.h"), C_RUNTIME_HEADER)
+                            .expect("cannot write the runtime header");
+                        let src = out_dir.join(format!("{}.c", name));
+                        fs::write(&src, output.c_source.unwrap()).expect("cannot write the source");
+                        build(
+                            &cc,
+                            &src,
+                            &["-std=c99", "-O2", "-Wall", "-Werror", "-DWSK_PRINT_RESULT"],
+                        )
+                    }
+                    _ => {
+                        let src = out_dir.join(format!("{}.s", name));
+                        fs::write(&src, output.asm_source.unwrap())
+                            .expect("cannot write the source");
+                        build(&cc, &src, &["-Wa,--defsym,WSK_PRINT_RESULT=1"])
+                    }
+                };
+                results.push(
+                    built
+                        .and_then(|exe| run(&exe))
+                        .unwrap_or_else(|e| format!("failed: {}", e)),
+                );
+            }
+
+            println!(
+                "{:<16} {:<6} {:>20} {:>20} {:>20}",
+                name,
+                format!("{:?}", opt_level),
+                expected,
+                results[0],
+                results[1]
+            );
+            ok &= results.iter().all(|v| *v == expected);
+        }
+    }
+    let _ = fs::remove_dir_all(&out_dir);
+    if !ok {
+        process::exit(1);
+    }
+}
+
+fn compile(name: &str, source: &str, opt_level: OptLevel, target: Target) -> Option<CompileOutput> {
+    let switches = CompileSwitch {
+        do_parse_ast: true,
+        do_resolve_module: true,
+        opt_level,
+        do_codegen: true,
+        target,
+        ..Default::default()
+    };
+    let (sources, output) = compile_source(name, source, &switches);
+    if output.has_errors() {
+        for diag in &output.diagnostics {
+            eprintln!("{}", diag.display(&sources));
+        }
+        return None;
+    }
+    Some(output)
+}
+
+/// Build the source next to it into an executable, returning its path.
+fn build(cc: &str, src: &Path, flags: &[&str]) -> Result<PathBuf, String> {
+    let exe = src.with_extension("");
+    let build = Command::new(cc)
+        .args(flags)
+        .arg("-o")
+        .arg(&exe)
+        .arg(src)
+        .output()
+        .map_err(|e| format!("cannot run {}: {}", cc, e))?;
+    if !build.status.success() {
+        eprint!("{}", String::from_utf8_lossy(&build.stderr));
+        return Err(format!("{} exited with {}", cc, build.status));
+    }
+    Ok(exe)
+}
+
+/// Run the executable, returning what it printed. The exit code is the low byte of the result.
+fn run(exe: &Path) -> Result<String, String> {
+    let run = Command::new(exe).output().map_err(|e| e.to_string())?;
+    if run.status.code().is_none() {
+        eprint!("{}", String::from_utf8_lossy(&run.stderr));
+        return Err(run.status.to_string());
+    }
+    Ok(String::from_utf8_lossy(&run.stdout).trim().to_owned())
+}
@@ -0,0 +1,98 @@
+//! Compare the size of the code of programs in the fixed and compact encodings of the VM.
+//!
+//! usage: cargo run -p whiskc --example bytecode_size [file.wsk]...
+//!
+//! Without arguments the programs of `test/` are measured.
+
+use std::{env, fs, path::PathBuf};
+
+use whiskc::{
+    codegen::Target,
+    compile::{compile_source, CompileSwitch},
+    ir::opt::OptLevel,
+};
+use wsk_vm::{
+    inst_code::Encoding,
+    program::{Function, Program},
+    Inst,
+};
+
+fn main() {
+    let mut paths: Vec<PathBuf> = env::args().skip(1).map(PathBuf::from).collect();
+    if paths.is_empty() {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/../test");
+        paths = fs::read_dir(dir)
+            .expect("cannot read the test directory")
+            .map(|v| v.unwrap().path())
+            .filter(|v| v.extension().is_some_and(|v| v == "wsk"))
+            .collect();
+        paths.sort();
+    }
+
+    println!(
+        "{:<16} {:>8} {:>8} {:>8} {:>7}",
+        "program", "fixed", "compact", "pooled", "ratio"
+    );
+    for path in paths {
+        let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+        let source = fs::read_to_string(&path).expect("cannot read the program");
+        let switches = CompileSwitch {
+            do_parse_ast: true,
+            debug_ast: false,
+            do_resolve_module: true,
+            print_module: false,
+            emit_ir: false,
+            opt_level: OptLevel::O2,
+            do_codegen: true,
+            target: Target::WskVm,
+        };
+        let (sources, output) = compile_source(&name, &source, &switches);
+        let Some(pooled) = output.program else {
+            for diag in &output.diagnostics {
+                eprintln!("{}", diag.display(&sources));
+            }
+            continue;
+        };
+
+        let unpooled = unpool(&pooled);
+        let fixed = code_size(&unpooled, Encoding::Fixed);
+        let compact = code_size(&unpooled, Encoding::Compact);
+        // an entry of the pool is a tag byte and the integer as in a push.
+        let pool: usize = pooled
+            .constants()
+            .iter()
+            .map(|v| Inst::Push(*v).encoded_len(Encoding::Compact).unwrap())
+            .sum();
+        let pooled = code_size(&pooled, Encoding::Compact) + pool;
+        println!(
+            "{:<16} {:>8} {:>8} {:>8} {:>6.1}%",
+            name,
+            fixed,
+            compact,
+            pooled,
+            pooled as f64 * 100.0 / fixed as f64
+        );
+    }
+}
+
+fn code_size(prog: &Program, encoding: Encoding) -> usize {
+    let mut out = vec![];
+    for func in prog.funcs() {
+        func.to_bin(&mut out, encoding).unwrap();
+    }
+    out.len()
+}
+
+/// The program with the constants of the pool pushed in place.
+fn unpool(prog: &Program) -> Program {
+    let mut out = Program::new(prog.get_entry_point());
+    for func in prog.funcs() {
+        let mut new = Function::new(func.param_cnt(), func.ret_cnt());
+        new.push_insts(func.get_insts().iter().map(|inst| match *inst {
+            Inst::PushConst(i) => Inst::Push(prog.constants()[i]),
+            inst => inst,
+        }));
+        out.add_func(new);
+    }
+    out
+}
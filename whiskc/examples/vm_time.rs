@@ -0,0 +1,128 @@
+//! Time running programs on the stack VM, without and with superinstructions, and on the register
+//! VM, checking that all of them give the same results.
+//!
+//! usage: cargo run --release -p whiskc --example vm_time [file.wsk]...
+//!
+//! Without arguments the programs of `test/` are timed.
+//!
+//! The stack VM kept the locals of a frame in a `HashMap` before slots on its value stack. To
+//! compare with it, time the programs with the first version of this example in a worktree of the
+//! commit before the slots, in a target directory of its own:
+//!
+//! ```text
+//! git worktree add ../hashmap-frames 5898e4b^
+//! git show 5898e4b:whiskc/examples/vm_time.rs > ../hashmap-frames/whiskc/examples/vm_time.rs
+//! git show 5898e4b:test/sum.wsk > ../hashmap-frames/test/sum.wsk
+//! cd ../hashmap-frames && cargo run --release -p whiskc --example vm_time
+//! ```
+
+use std::{
+    env, fs,
+    path::PathBuf,
+    process,
+    time::{Duration, Instant},
+};
+
+use whiskc::{
+    codegen::{codegen_reg_vm, Target},
+    compile::{compile_source, CompileSwitch},
+    ir::opt::OptLevel,
+};
+use wsk_vm::{reg, VM};
+
+/// Every program runs at least this long in total, to smooth out the timing of short programs.
+const MIN_TIME: Duration = Duration::from_millis(500);
+
+fn main() {
+    let mut paths: Vec<PathBuf> = env::args().skip(1).map(PathBuf::from).collect();
+    if paths.is_empty() {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/../test");
+        paths = fs::read_dir(dir)
+            .expect("cannot read the test directory")
+            .map(|v| v.unwrap().path())
+            .filter(|v| v.extension().is_some_and(|v| v == "wsk"))
+            .collect();
+        paths.sort();
+    }
+
+    let mut ok = true;
+    println!(
+        "{:<16} {:>12} {:>12} {:>8} {:>12} {:>8}",
+        "program", "plain/run", "fused/run", "speedup", "reg/run", "speedup"
+    );
+    for path in paths {
+        let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+        let source = fs::read_to_string(&path).expect("cannot read the program");
+        let switches = CompileSwitch {
+            do_parse_ast: true,
+            debug_ast: false,
+            do_resolve_module: true,
+            print_module: false,
+            emit_ir: false,
+            opt_level: OptLevel::O2,
+            do_codegen: true,
+            target: Target::WskVm,
+        };
+        let (sources, output) = compile_source(&name, &source, &switches);
+        let (Some(program), Some(ir)) = (output.program, output.ir) else {
+            for diag in &output.diagnostics {
+                eprintln!("{}", diag.display(&sources));
+            }
+            ok = false;
+            continue;
+        };
+        let reg_program = codegen_reg_vm(&ir).expect("the stack VM code generated");
+
+        let mut plain_vm = VM::default();
+        plain_vm.set_superinstructions(false);
+        plain_vm.load(program.clone()).expect("the program failed");
+        let plain_time = time(|| plain_vm.rerun().expect("the program failed"));
+        let mut vm = VM::default();
+        vm.load(program).expect("the program failed");
+        let stack_time = time(|| vm.rerun().expect("the program failed"));
+        let mut reg_vm = reg::VM::default();
+        let reg_time = time(|| reg_vm.execute(&reg_program).expect("the program failed"));
+
+        if plain_vm.stack() != vm.stack() {
+            eprintln!(
+                "{}: the stack VM gives {:?} without superinstructions but {:?} with them",
+                name,
+                plain_vm.stack(),
+                vm.stack()
+            );
+            ok = false;
+        }
+        if vm.stack() != reg_vm.results() {
+            eprintln!(
+                "{}: the stack VM gives {:?} but the register VM {:?}",
+                name,
+                vm.stack(),
+                reg_vm.results()
+            );
+            ok = false;
+        }
+        println!(
+            "{:<16} {:>12.2?} {:>12.2?} {:>7.2}x {:>12.2?} {:>7.2}x",
+            name,
+            plain_time,
+            stack_time,
+            plain_time.as_secs_f64() / stack_time.as_secs_f64(),
+            reg_time,
+            stack_time.as_secs_f64() / reg_time.as_secs_f64()
+        );
+    }
+    if !ok {
+        process::exit(1);
+    }
+}
+
+/// Average time of running `f` repeatedly for at least [`MIN_TIME`].
+fn time(mut f: impl FnMut()) -> Duration {
+    let mut runs = 0;
+    let start = Instant::now();
+    while start.elapsed() < MIN_TIME {
+        f();
+        runs += 1;
+    }
+    start.elapsed() / runs
+}
@@ -32,41 +32,63 @@ impl<T: Locatable> From<T> for Located<T> {
     }
 }
 
-#[derive(Default, Clone, Copy, PartialEq, Eq)]
+/// Identifies a file registered in a [`SourceMap`](crate::source_map::SourceMap).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FileId(pub u32);
+
+/// A byte range `start..end` in the source of `file`.
+///
+/// Lines and columns are not stored, they are computed on demand by the
+/// [`SourceMap`](crate::source_map::SourceMap).
+#[derive(Default, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Span {
-    pub start: Location,
-    pub end: Location,
+    pub file: FileId,
+    pub start: u32,
+    pub end: u32,
 }
 impl Span {
-    pub fn new(start: Location, end: Location) -> Self {
-        Self { start, end }
+    pub fn new(file: FileId, start: u32, end: u32) -> Self {
+        Self { file, start, end }
     }
 
-    pub fn next(&self) -> Location {
-        self.end.next()
+    /// The empty span right after this one.
+    pub fn next(&self) -> Self {
+        Self {
+            file: self.file,
+            start: self.end,
+            end: self.end,
+        }
     }
 
     pub fn combine(left: Self, right: Self) -> Self {
         Self {
-            start: min::<Location>(left.start, right.start),
-            end: max::<Location>(left.end, right.end),
+            file: left.file,
+            start: min(left.start, right.start),
+            end: max(left.end, right.end),
         }
     }
+
+    pub fn len(&self) -> usize {
+        (self.end - self.start) as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
 }
 impl fmt::Debug for Span {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        if self.start == self.end {
-            write!(f, "[{:#?}]", self.start)
-        } else {
-            write!(f, "[{:#?}-{:#?}]", self.start, self.end)
-        }
+        write!(f, "[{}:{}..{}]", self.file.0, self.start, self.end)
     }
 }
 impl PartialOrd for Span {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        Some(if self.end < other.start {
+        if self.file != other.file {
+            return None;
+        }
+        Some(if self.end <= other.start && self != other {
             std::cmp::Ordering::Less
-        } else if self.start > other.end {
+        } else if self.start >= other.end && self != other {
             std::cmp::Ordering::Greater
         } else if self.start == other.start && self.end == other.end {
             std::cmp::Ordering::Equal
@@ -75,15 +97,8 @@ impl PartialOrd for Span {
         })
     }
 }
-impl From<Location> for Span {
-    fn from(value: Location) -> Self {
-        Self {
-            start: value,
-            end: value,
-        }
-    }
-}
 
+/// A 1-based line and column, columns count characters rather than bytes.
 #[derive(Default, Clone, Copy, PartialEq, Eq)]
 pub struct Location {
     pub line: u32,
@@ -93,20 +108,6 @@ impl Location {
     pub fn new(line: u32, col: u32) -> Self {
         Self { line, col }
     }
-
-    pub fn front(&self) -> Self {
-        Self {
-            line: self.line,
-            col: if self.col >= 1 { self.col - 1 } else { 0 },
-        }
-    }
-
-    pub fn next(&self) -> Self {
-        Self {
-            line: self.line,
-            col: self.col + 1,
-        }
-    }
 }
 impl fmt::Debug for Location {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
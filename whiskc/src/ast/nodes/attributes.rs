@@ -1,11 +1,22 @@
 use core::fmt;
 
-use crate::ast::{location::Located, parsing::token::Keyword};
+use crate::ast::{
+    location::{Located, Span},
+    parsing::token::Keyword,
+};
 
 #[derive(Clone, Default)]
 pub struct Attributes {
     pub attribs: Vec<Located<Keyword>>,
 }
+impl Attributes {
+    /// Covers all attributes, `None` when there are none.
+    pub fn span(&self) -> Option<Span> {
+        let first = self.attribs.first()?;
+        let last = self.attribs.last()?;
+        Some(Span::combine(first.1, last.1))
+    }
+}
 impl fmt::Debug for Attributes {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{:?}", self.attribs)
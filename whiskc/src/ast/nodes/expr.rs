@@ -19,6 +19,11 @@ pub enum Expr {
     Return(ReturnExpr),
     If(IfExpr),
     Loop(LoopExpr),
+    Try(TryExpr),
+    Tuple(TupleExpr),
+    TupleIndex(TupleIndexExpr),
+    /// Expression that failed to parse, its error is already reported.
+    Error(Span),
 }
 impl Expr {
     pub fn is_block(&self) -> bool {
@@ -28,13 +33,7 @@ impl Expr {
     pub fn has_eval_expr(&self) -> bool {
         match self {
             Self::Block(v) => v.eval_expr.is_some(),
-            Self::If(v) => {
-                v.then.eval_expr.is_some()
-                    && v.else_expr
-                        .as_ref()
-                        .map(|v| v.body.eval_expr.is_some())
-                        .unwrap_or(false)
-            }
+            Self::If(v) => v.has_eval_expr(),
             Self::Loop(v) => v.body.eval_expr.is_some(),
             _ => false,
         }
@@ -55,6 +54,10 @@ impl Locatable for Expr {
             Expr::Return(expr) => expr.get_location(),
             Expr::If(expr) => expr.get_location(),
             Expr::Loop(expr) => expr.get_location(),
+            Expr::Try(expr) => expr.get_location(),
+            Expr::Tuple(expr) => expr.get_location(),
+            Expr::TupleIndex(expr) => expr.get_location(),
+            Expr::Error(loc) => *loc,
         }
     }
 }
@@ -153,10 +156,25 @@ impl Locatable for IfExpr {
     }
 }
 
+impl IfExpr {
+    /// Whether every branch of the chain ends with an eval expression.
+    pub fn has_eval_expr(&self) -> bool {
+        self.then.eval_expr.is_some()
+            && self
+                .else_expr
+                .as_ref()
+                .map(|v| match &v.body {
+                    ElseBody::Block(body) => body.eval_expr.is_some(),
+                    ElseBody::If(if_expr) => if_expr.has_eval_expr(),
+                })
+                .unwrap_or(false)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct ElseExpr {
     pub else_tok: Located<Keyword>,
-    pub body: BlockExpr,
+    pub body: ElseBody,
 }
 impl Locatable for ElseExpr {
     fn get_location(&self) -> Span {
@@ -164,6 +182,21 @@ impl Locatable for ElseExpr {
     }
 }
 
+#[derive(Debug, Clone)]
+pub enum ElseBody {
+    Block(BlockExpr),
+    /// `else if`, continuing the chain.
+    If(Box<IfExpr>),
+}
+impl Locatable for ElseBody {
+    fn get_location(&self) -> Span {
+        match self {
+            ElseBody::Block(v) => v.get_location(),
+            ElseBody::If(v) => v.get_location(),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct LoopExpr {
     pub loop_tok: Located<Keyword>,
@@ -174,3 +207,38 @@ impl Locatable for LoopExpr {
         Span::combine(self.loop_tok.1, self.body.get_location())
     }
 }
+
+#[derive(Debug, Clone)]
+pub struct TryExpr {
+    pub expr: Box<Expr>,
+    pub try_tok: Located<Operator>,
+}
+impl Locatable for TryExpr {
+    fn get_location(&self) -> Span {
+        Span::combine(self.expr.get_location(), self.try_tok.1)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TupleExpr {
+    pub paren_open_tok: Located<Delimiter>,
+    pub elems: Punctuated<Expr>,
+    pub paren_close_tok: Located<Delimiter>,
+}
+impl Locatable for TupleExpr {
+    fn get_location(&self) -> Span {
+        Span::combine(self.paren_open_tok.1, self.paren_close_tok.1)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TupleIndexExpr {
+    pub expr: Box<Expr>,
+    pub dot_tok: Located<Delimiter>,
+    pub index: Located<i64>,
+}
+impl Locatable for TupleIndexExpr {
+    fn get_location(&self) -> Span {
+        Span::combine(self.expr.get_location(), self.index.1)
+    }
+}
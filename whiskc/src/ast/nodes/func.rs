@@ -1,5 +1,5 @@
 use crate::ast::{
-    location::Located,
+    location::{Locatable, Located, Span},
     parsing::token::{Delimiter, Keyword},
 };
 
@@ -10,6 +10,11 @@ pub struct Function {
     pub sig: FunctionSig,
     pub body: BlockExpr,
 }
+impl Locatable for Function {
+    fn get_location(&self) -> Span {
+        Span::combine(self.sig.get_location(), self.body.get_location())
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct ExternFunction {
@@ -17,6 +22,12 @@ pub struct ExternFunction {
     pub sig: FunctionSig,
     pub semi_tok: Located<Delimiter>,
 }
+impl Locatable for ExternFunction {
+    fn get_location(&self) -> Span {
+        let start = self.sig.attributes.span().unwrap_or(self.extern_tok.1);
+        Span::combine(start, self.semi_tok.1)
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct Param(pub Located<String>, pub Type);
@@ -31,3 +42,16 @@ pub struct FunctionSig {
     pub paren_close_tok: Located<Delimiter>,
     pub ret_ty: Type,
 }
+impl Locatable for FunctionSig {
+    fn get_location(&self) -> Span {
+        let start = self.attributes.span().unwrap_or(self.func_tok.1);
+        // an omitted return type is a unit type without a location.
+        let ret_ty = self.ret_ty.get_location();
+        let end = if ret_ty.start >= self.paren_close_tok.1.end {
+            ret_ty
+        } else {
+            self.paren_close_tok.1
+        };
+        Span::combine(start, end)
+    }
+}
@@ -1,3 +1,5 @@
+use crate::ast::location::{Locatable, Span};
+
 use super::{
     func::{ExternFunction, Function},
     ty::TypeDecl,
@@ -9,4 +11,16 @@ pub enum Item {
     Function(Function),
     ExternFunction(ExternFunction),
     TypeDecl(TypeDecl),
+    /// Tokens skipped while recovering from an item that failed to parse.
+    Error(Span),
+}
+impl Locatable for Item {
+    fn get_location(&self) -> Span {
+        match self {
+            Item::Function(v) => v.get_location(),
+            Item::ExternFunction(v) => v.get_location(),
+            Item::TypeDecl(v) => v.get_location(),
+            Item::Error(span) => *span,
+        }
+    }
 }
@@ -0,0 +1,32 @@
+use crate::ast::{
+    location::{Locatable, Located, Span},
+    parsing::token::Delimiter,
+};
+
+use super::punctuate::Punctuated;
+
+#[derive(Debug, Clone)]
+pub enum Pattern {
+    Ident(Located<String>),
+    Tuple(Box<TuplePattern>),
+}
+impl Locatable for Pattern {
+    fn get_location(&self) -> Span {
+        match self {
+            Pattern::Ident(v) => v.1,
+            Pattern::Tuple(v) => v.get_location(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TuplePattern {
+    pub paren_open_tok: Located<Delimiter>,
+    pub elems: Punctuated<Pattern>,
+    pub paren_close_tok: Located<Delimiter>,
+}
+impl Locatable for TuplePattern {
+    fn get_location(&self) -> Span {
+        Span::combine(self.paren_open_tok.1, self.paren_close_tok.1)
+    }
+}
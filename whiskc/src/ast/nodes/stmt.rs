@@ -3,7 +3,7 @@ use crate::ast::{
     parsing::token::{Delimiter, Keyword, Operator},
 };
 
-use super::{expr::Expr, ty::Type};
+use super::{expr::Expr, pattern::Pattern, ty::Type};
 
 #[derive(Debug, Clone)]
 pub enum Stmt {
@@ -20,7 +20,7 @@ pub struct ExprStmt {
 #[derive(Debug, Clone)]
 pub struct LetStmt {
     pub let_tok: Located<Keyword>,
-    pub name: Located<String>,
+    pub pat: Pattern,
     pub ty: Option<Type>,
     pub assign_tok: Located<Operator>,
     pub value: Expr,
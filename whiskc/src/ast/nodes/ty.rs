@@ -10,10 +10,22 @@ pub struct TypeDecl {
     pub attributes: Attributes,
     pub ty_tok: Located<Keyword>,
     pub name: Located<String>,
-    pub assign_tok: Located<Operator>,
+    /// `type A = B;` declares a transparent alias, `type A B;` declares a distinct newtype.
+    pub assign_tok: Option<Located<Operator>>,
     pub kind: TypeDeclKind,
     pub semi_tok: Located<Delimiter>,
 }
+impl Locatable for TypeDecl {
+    fn get_location(&self) -> Span {
+        let start = self.attributes.span().unwrap_or(self.ty_tok.1);
+        Span::combine(start, self.semi_tok.1)
+    }
+}
+impl TypeDecl {
+    pub fn is_alias(&self) -> bool {
+        self.assign_tok.is_some() && matches!(self.kind, TypeDeclKind::Type(_))
+    }
+}
 
 #[derive(Debug, Clone)]
 pub enum TypeDeclKind {
@@ -33,15 +45,44 @@ impl Locatable for TypeDeclKind {
 pub enum Type {
     Primitive(Located<PrimType>),
     Ident(Located<String>),
+    Generic(GenericType),
+    Tuple(TupleType),
 }
 impl Locatable for Type {
     fn get_location(&self) -> Span {
         match self {
             Type::Primitive(ty) => ty.1,
             Type::Ident(ty) => ty.1,
+            Type::Generic(ty) => ty.get_location(),
+            Type::Tuple(ty) => ty.get_location(),
         }
     }
 }
+
+#[derive(Debug, Clone)]
+pub struct GenericType {
+    pub name: Located<String>,
+    pub bracket_open_tok: Located<Delimiter>,
+    pub args: Punctuated<Type>,
+    pub bracket_close_tok: Located<Delimiter>,
+}
+impl Locatable for GenericType {
+    fn get_location(&self) -> Span {
+        Span::combine(self.name.1, self.bracket_close_tok.1)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TupleType {
+    pub paren_open_tok: Located<Delimiter>,
+    pub elems: Punctuated<Type>,
+    pub paren_close_tok: Located<Delimiter>,
+}
+impl Locatable for TupleType {
+    fn get_location(&self) -> Span {
+        Span::combine(self.paren_open_tok.1, self.paren_close_tok.1)
+    }
+}
 impl From<Type> for TypeDeclKind {
     fn from(value: Type) -> Self {
         Self::Type(value)
@@ -1,26 +1,28 @@
-use std::{fs, path::Path, str::FromStr};
+use std::str::FromStr;
 
-use crate::ast::location::{Location, Span};
+use crate::ast::location::{FileId, Span};
 
 use super::token::{
-    Delimiter, Identifier, Keyword, Literal, LiteralKeyword, Operator, OperatorChar, Token,
-    TokenKind, TypeKeyword,
+    Comment, CommentKind, Delimiter, Identifier, Keyword, Literal, LiteralKeyword, Operator,
+    OperatorChar, Token, TokenKind, TypeKeyword,
 };
 
 #[derive(Debug, Default)]
 pub struct Lexer {
+    file: FileId,
     source: String,
+    /// Byte offset of the next character.
     index: usize,
     tokens: Vec<Token>,
-    current_loc: Location,
     prev_loc: Span,
+    comments: Vec<Comment>,
 }
 impl Lexer {
-    pub fn new(source_path: &Path) -> Self {
-        let source = fs::read_to_string(source_path).expect("valid path to source file");
+    pub fn new(file: FileId, source: impl Into<String>) -> Self {
         Lexer {
-            source,
-            current_loc: Location { line: 1, col: 1 },
+            file,
+            source: source.into(),
+            prev_loc: Span::new(file, 0, 0),
             ..Default::default()
         }
     }
@@ -30,7 +32,25 @@ impl Lexer {
     }
 
     pub fn is_at_buffer_end(&self, ahead: usize) -> bool {
-        self.index + ahead >= self.source.len()
+        self.peek_char(ahead).is_none()
+    }
+
+    /// Lexes the whole source, the last token is always `EndOfFile`.
+    pub fn tokenize(self) -> Vec<Token> {
+        self.tokenize_with_comments().0
+    }
+
+    /// Same as [`Lexer::tokenize`], but also returns the skipped comments in source order.
+    pub fn tokenize_with_comments(mut self) -> (Vec<Token>, Vec<Comment>) {
+        let mut tokens = Vec::new();
+        loop {
+            let token = self.next_token();
+            let is_eof = token.kind == TokenKind::EndOfFile;
+            tokens.push(token);
+            if is_eof {
+                return (tokens, self.comments);
+            }
+        }
     }
 
     pub fn peek_token_kind(&mut self, ahead: usize) -> &TokenKind {
@@ -68,22 +88,23 @@ impl Lexer {
 
     fn make_token(&mut self) -> Token {
         self.skip_comment_and_whitespace();
-        let start: Location = self.current_loc;
+        let start = self.index;
 
         if self.is_eof() {
             Token {
                 kind: TokenKind::EndOfFile,
-                loc: start.into(),
+                loc: self.span_from(start),
             }
         } else if self.is_peek_char_f(0, char::is_numeric) {
             let value = self.get_str_while(char::is_numeric).unwrap();
-            let value = value.parse::<i64>().unwrap();
+            // literals that overflow i64 are left for the parser to report.
+            let kind = match value.parse::<i64>() {
+                Ok(value) => TokenKind::Literal(Literal::Int(value)),
+                Err(_) => TokenKind::Unknown,
+            };
             Token {
-                kind: TokenKind::Literal(Literal::Int(value)),
-                loc: Span {
-                    start,
-                    end: self.current_loc.front(),
-                },
+                kind,
+                loc: self.span_from(start),
             }
         } else if self.is_peek_char_f(0, |c| {
             OperatorChar::from_str(c.to_string().as_str()).is_ok()
@@ -99,18 +120,16 @@ impl Lexer {
                     }
                     return Token {
                         kind: TokenKind::Operator(op),
-                        loc: Span {
-                            start,
-                            end: self.current_loc.front(),
-                        },
+                        loc: self.span_from(start),
                     };
                 }
                 peek_op_chars.pop();
             }
 
+            self.next_char();
             Token {
                 kind: TokenKind::Unknown,
-                loc: start.into(),
+                loc: self.span_from(start),
             }
         } else if let Some(Ok(delim)) = {
             self.peek_char(0)
@@ -119,7 +138,7 @@ impl Lexer {
             self.next_char();
             Token {
                 kind: TokenKind::Delimiter(delim),
-                loc: start.into(),
+                loc: self.span_from(start),
             }
         } else if self.is_peek_char_f(0, |c| char::is_alphabetic(c) || c == '_') {
             let ident = self
@@ -129,65 +148,64 @@ impl Lexer {
             if let Ok(kw) = Keyword::from_str(&ident) {
                 Token {
                     kind: TokenKind::Keyword(kw),
-                    loc: Span {
-                        start,
-                        end: self.current_loc.front(),
-                    },
+                    loc: self.span_from(start),
                 }
             } else if let Ok(kw) = TypeKeyword::from_str(&ident) {
                 Token {
                     kind: TokenKind::TypeKeyword(kw),
-                    loc: Span {
-                        start,
-                        end: self.current_loc.front(),
-                    },
+                    loc: self.span_from(start),
                 }
             } else if let Ok(kw) = LiteralKeyword::from_str(&ident) {
                 Token {
                     kind: TokenKind::LiteralKeyword(kw),
-                    loc: Span {
-                        start,
-                        end: self.current_loc.front(),
-                    },
+                    loc: self.span_from(start),
                 }
             } else {
                 Token {
                     kind: TokenKind::Identifier(Identifier(ident)),
-                    loc: Span {
-                        start,
-                        end: self.current_loc.front(),
-                    },
+                    loc: self.span_from(start),
                 }
             }
         } else {
+            self.next_char();
             Token {
                 kind: TokenKind::Unknown,
-                loc: start.into(),
+                loc: self.span_from(start),
             }
         }
     }
 
     fn skip_comment_and_whitespace(&mut self) {
         loop {
-            let loc: Location = self.current_loc;
+            let index = self.index;
             self.skip_while(char::is_whitespace);
 
+            let start = self.index;
             if self.match_str("//") {
                 self.skip_while(|c| c != '\n');
+                self.push_comment(start, CommentKind::Line);
             }
 
+            let start = self.index;
             if self.match_str("/*") {
-                while !self.match_str("*/") {
-                    self.next_char();
-                }
+                while !self.match_str("*/") && self.next_char().is_some() {}
+                self.push_comment(start, CommentKind::Block);
             }
 
-            if self.current_loc == loc {
+            if self.index == index {
                 break;
             }
         }
     }
 
+    fn push_comment(&mut self, start: usize, kind: CommentKind) {
+        self.comments.push(Comment {
+            text: self.source[start..self.index].trim_end().to_owned(),
+            loc: self.span_from(start),
+            kind,
+        });
+    }
+
     fn skip_while<F>(&mut self, cond: F)
     where
         F: Fn(char) -> bool,
@@ -206,7 +224,7 @@ impl Lexer {
         }
 
         let mut value = String::new();
-        while let Some(c) = self.peek_char(value.len()) {
+        while let Some(c) = self.peek_char(value.chars().count()) {
             if cond(c) {
                 value.push(c);
             } else {
@@ -239,14 +257,14 @@ impl Lexer {
                 return false;
             }
         }
-        for _ in 0..s.len() {
+        for _ in s.chars() {
             self.next_char();
         }
         true
     }
 
-    fn peek_char(&mut self, ahead: usize) -> Option<char> {
-        self.source.chars().nth(self.index + ahead)
+    fn peek_char(&self, ahead: usize) -> Option<char> {
+        self.source[self.index..].chars().nth(ahead)
     }
 
     fn is_peek_char(&mut self, ahead: usize, c: char) -> bool {
@@ -262,14 +280,13 @@ impl Lexer {
 
     fn next_char(&mut self) -> Option<char> {
         let c = self.peek_char(0)?;
-        if c == '\n' {
-            self.current_loc.line += 1;
-            self.current_loc.col = 0;
-        }
-        self.current_loc.col += 1;
-        self.index += 1;
+        self.index += c.len_utf8();
         Some(c)
     }
+
+    fn span_from(&self, start: usize) -> Span {
+        Span::new(self.file, start as u32, self.index as u32)
+    }
 }
 
 macro_rules! match_token_kind {
@@ -300,7 +317,7 @@ macro_rules! match_identifier {
         match_identifier!($parser).ok_or_else(|| {
             Located(
                 $crate::ast::parsing::ParseError::MissingIdentifier($err_str),
-                $parser.lexer.get_prev_loc().next().into(),
+                $parser.lexer.get_prev_loc().next(),
             )
         })
     }};
@@ -337,7 +354,7 @@ macro_rules! match_delimiter {
         match_unit_token_kind!($parser, Delimiter, $delim.clone()).ok_or_else(|| {
             Located(
                 $crate::ast::parsing::ParseError::MissingDelimiter($delim),
-                $parser.lexer.get_prev_loc().next().into(),
+                $parser.lexer.get_prev_loc().next(),
             )
         })
     }};
@@ -362,7 +379,7 @@ macro_rules! match_keyword {
         match_unit_token_kind!($parser, Keyword, $kw.clone()).ok_or_else(|| {
             Located(
                 $crate::ast::parsing::ParseError::MissingKeyword($kw),
-                $parser.lexer.get_prev_loc().next().into(),
+                $parser.lexer.get_prev_loc().next(),
             )
         })
     }};
@@ -387,7 +404,7 @@ macro_rules! match_operator {
         match_unit_token_kind!($parser, Operator, $op.clone()).ok_or_else(|| {
             Located(
                 $crate::ast::parsing::ParseError::MissingOperator($op),
-                $parser.lexer.get_prev_loc().next().into(),
+                $parser.lexer.get_prev_loc().next(),
             )
         })
     }};
@@ -1,13 +1,15 @@
+use core::fmt;
+
 use nodes::func::FunctionParseError;
 
 use crate::ast::parsing::nodes::ty::TypeParseError;
 
 use super::{
-    location::Located,
+    location::{Located, Span},
     parsing::{
         lexer::Lexer,
         nodes::{expr::ExprParseError, item::ItemParseError, stmt::StmtParseError},
-        token::{Delimiter, Keyword, Operator},
+        token::{Delimiter, Keyword, Operator, TokenKind},
     },
 };
 
@@ -54,13 +56,37 @@ impl ParseContext {
         self.errors.push(e);
     }
 
-    pub fn finalize(self) -> Result<(), Vec<Located<ParseError>>> {
-        if self.errors.is_empty() {
-            Ok(())
-        } else {
-            Err(self.errors)
+    pub fn error_count(&self) -> usize {
+        self.errors.len()
+    }
+
+    /// Panic mode: skip tokens until one matching `stop` is found outside of the braces opened
+    /// while skipping, or until EOF. The matching token is not consumed.
+    ///
+    /// Returns the span of the skipped tokens, or None if nothing was skipped.
+    pub fn skip_until(&mut self, stop: impl Fn(&TokenKind) -> bool) -> Option<Span> {
+        let mut skipped: Option<Span> = None;
+        let mut depth = 0usize;
+        loop {
+            let kind = self.lexer.peek_token_kind(0);
+            if *kind == TokenKind::EndOfFile || (depth == 0 && stop(kind)) {
+                return skipped;
+            }
+            match kind {
+                TokenKind::Delimiter(Delimiter::BraceOpen) => depth += 1,
+                TokenKind::Delimiter(Delimiter::BraceClose) => depth = depth.saturating_sub(1),
+                _ => (),
+            }
+            let tok = self.lexer.next_token();
+            skipped = Some(skipped.map_or(tok.loc, |v| Span::combine(v, tok.loc)));
         }
     }
+
+    /// Returns the errors ordered by their location in the source.
+    pub fn finalize(mut self) -> Vec<Located<ParseError>> {
+        self.errors.sort_by_key(|e| e.1.start);
+        self.errors
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -76,6 +102,57 @@ pub enum ParseError {
     ExprParseError(ExprParseError),
 }
 
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::MissingKeyword(kw) => write!(f, "expected '{}' keyword", kw),
+            ParseError::MissingDelimiter(delim) => write!(f, "expected '{}'", delim),
+            ParseError::MissingIdentifier(name) => write!(f, "expected {}", name),
+            ParseError::MissingOperator(op) => write!(f, "expected '{}' operator", op),
+            ParseError::ItemParseError(e) => match e {
+                ItemParseError::UnexpectedToken(t) => write!(f, "expected an item, found {}", t),
+            },
+            ParseError::FuncParseError(e) => match e {
+                FunctionParseError::MissingFunctionBody { func_name } => {
+                    write!(f, "function '{}' is missing its body", func_name)
+                }
+            },
+            ParseError::TypeParseError(e) => match e {
+                TypeParseError::UnexpectedToken(t) => write!(f, "expected a type, found {}", t),
+                TypeParseError::UnexpectedInfixOperator(t) => {
+                    write!(f, "unexpected {} after type", t)
+                }
+                TypeParseError::IntegerSizeOutOfRange(size) => {
+                    write!(f, "integer size {} is out of range", size)
+                }
+                TypeParseError::ExpectedArrayLength => write!(f, "expected array length"),
+                TypeParseError::InvalidArrayLength(len) => {
+                    write!(f, "invalid array length {}", len)
+                }
+                TypeParseError::NonGenericTypeWithArguments => {
+                    write!(f, "type arguments given to a non-generic type")
+                }
+            },
+            ParseError::StmtParseError(e) => match e {
+                StmtParseError::UnexpectedToken(t) => {
+                    write!(f, "expected a statement, found {}", t)
+                }
+            },
+            ParseError::ExprParseError(e) => match e {
+                ExprParseError::UnexpectedToken(t) => {
+                    write!(f, "expected an expression, found {}", t)
+                }
+                ExprParseError::UnexpectedInfixOperator(t) => {
+                    write!(f, "unexpected {} after expression", t)
+                }
+                ExprParseError::ExpectedTupleIndex(t) => {
+                    write!(f, "expected tuple index, found {}", t)
+                }
+            },
+        }
+    }
+}
+
 pub type ParseResult<T> = Option<T>;
 
 pub type TryParseResult<T> = Option<T>;
@@ -1,25 +1,42 @@
-use std::path::Path;
-
 use crate::ast::{
-    location::Located,
+    location::{FileId, Located, Span},
     nodes::item::Item,
-    parsing::{lexer::Lexer, Parse, ParseContext, ParseError},
+    parsing::{
+        lexer::Lexer,
+        token::{Keyword, TokenKind},
+        Parse, ParseContext, ParseError,
+    },
     AST,
 };
 
-pub fn parse(source: &Path) -> Result<AST, Vec<Located<ParseError>>> {
-    let mut ctx = ParseContext::new(Lexer::new(source));
+/// Parse the whole source, recovering from syntax errors so that all of them are reported.
+///
+/// Items that failed to parse are kept as `Item::Error`.
+pub fn parse(file: FileId, source: &str) -> (AST, Vec<Located<ParseError>>) {
+    let mut ctx = ParseContext::new(Lexer::new(file, source));
 
     let mut items = Vec::new();
-    while !ctx.lexer.is_eof() {
-        let item = Item::parse(&mut ctx);
-        if let Some(item) = item {
+    while *ctx.lexer.peek_token_kind(0) != TokenKind::EndOfFile {
+        let start = *ctx.lexer.peek_loc(0);
+        if let Some(item) = Item::parse(&mut ctx) {
             items.push(item);
-        } else {
+            continue;
+        }
+
+        // skip at least one token, a failed item may not have consumed any.
+        if start == *ctx.lexer.peek_loc(0) {
             ctx.lexer.next_token();
         }
+        let skipped = ctx.skip_until(|kind| {
+            matches!(
+                kind,
+                TokenKind::Keyword(Keyword::Pub | Keyword::Extern | Keyword::Func | Keyword::Type)
+            )
+        });
+        let end = skipped.unwrap_or(*ctx.lexer.get_prev_loc());
+        items.push(Item::Error(Span::combine(start, end)));
     }
 
-    ctx.finalize()?;
-    Ok(AST { items })
+    let errors = ctx.finalize();
+    (AST { items }, errors)
 }
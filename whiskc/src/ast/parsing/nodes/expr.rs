@@ -20,6 +20,7 @@ use crate::ast::{
 pub enum ExprParseError {
     UnexpectedToken(TokenKind),
     UnexpectedInfixOperator(TokenKind),
+    ExpectedTupleIndex(TokenKind),
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
@@ -107,6 +108,18 @@ impl pratt_parser::Handlers<Expr, BindingPower> for ExprHandlers {
             BindingPower::Call,
             parse_call_expr,
         );
+
+        led(
+            TokenKind::Operator(Operator::Try),
+            BindingPower::Call,
+            parse_try_expr,
+        );
+
+        led(
+            TokenKind::Delimiter(Delimiter::Dot),
+            BindingPower::Call,
+            parse_tuple_index_expr,
+        );
     }
 }
 
@@ -115,19 +128,33 @@ fn parse_unit_or_group_expr(
     parser: &mut ParseContext,
 ) -> ParseResult<Expr> {
     let paren_open_tok = match_delimiter!(parser, Delimiter::ParenOpen =>);
-    Some(
-        if let Ok(paren_close_tok) = match_delimiter!(parser, Delimiter::ParenClose) {
-            Expr::Unit(Span::combine(paren_open_tok.1, paren_close_tok.1))
-        } else {
-            let expr = Expr::parse(parser)?;
-            let paren_close_tok = match_delimiter!(parser, Delimiter::ParenClose =>);
-            Expr::Grouped(GroupedExpr {
-                paren_open_tok,
-                expr: Box::new(expr),
-                paren_close_tok,
-            })
-        },
-    )
+    if let Ok(paren_close_tok) = match_delimiter!(parser, Delimiter::ParenClose) {
+        return Some(Expr::Unit(Span::combine(
+            paren_open_tok.1,
+            paren_close_tok.1,
+        )));
+    }
+
+    let expr = Expr::parse(parser)?;
+    if match_delimiter!(parser, Delimiter::Comma).is_err() {
+        let paren_close_tok = match_delimiter!(parser, Delimiter::ParenClose =>);
+        return Some(Expr::Grouped(GroupedExpr {
+            paren_open_tok,
+            expr: Box::new(expr),
+            paren_close_tok,
+        }));
+    }
+
+    // a comma after the first expression makes it a tuple, e.g. `(1,)` or `(1, true)`.
+    let mut elems =
+        Punctuated::parse(parser, Delimiter::Comma, Delimiter::ParenClose, Expr::parse)?;
+    elems.items.insert(0, expr);
+    let paren_close_tok = match_delimiter!(parser, Delimiter::ParenClose =>);
+    Some(Expr::Tuple(TupleExpr {
+        paren_open_tok,
+        elems,
+        paren_close_tok,
+    }))
 }
 
 fn parse_primary_expr(
@@ -161,7 +188,12 @@ fn parse_prefix_unary_expr(
         panic!("non operator token is not supported");
     };
 
-    let expr = Expr::handle_err(pratt_parser.parse(parser, BindingPower::Unary), parser)?;
+    let start = *parser.lexer.peek_loc(0);
+    let expr = Expr::handle_err(
+        pratt_parser.parse(parser, BindingPower::Unary),
+        start,
+        parser,
+    )?;
     Some(Expr::Unary(UnaryExpr {
         op: Located(op, loc),
         expr: Box::new(expr),
@@ -182,7 +214,8 @@ fn parse_binary_expr(
         panic!("non operator token is not supported");
     };
 
-    let right = Expr::handle_err(pratt_parser.parse(parser, bp), parser)?;
+    let start = *parser.lexer.peek_loc(0);
+    let right = Expr::handle_err(pratt_parser.parse(parser, bp), start, parser)?;
     Some(Expr::Binary(BinaryExpr {
         op: Located(op, loc),
         left: Box::new(left),
@@ -207,11 +240,64 @@ fn parse_call_expr(
     }))
 }
 
+fn parse_try_expr(
+    _pratt_parser: &PrattParser<Expr, BindingPower>,
+    parser: &mut ParseContext,
+    left: Expr,
+    _bp: BindingPower,
+) -> ParseResult<Expr> {
+    let try_tok = match_operator!(parser, Operator::Try =>);
+    Some(Expr::Try(TryExpr {
+        expr: Box::new(left),
+        try_tok,
+    }))
+}
+
+fn parse_tuple_index_expr(
+    _pratt_parser: &PrattParser<Expr, BindingPower>,
+    parser: &mut ParseContext,
+    left: Expr,
+    _bp: BindingPower,
+) -> ParseResult<Expr> {
+    let dot_tok = match_delimiter!(parser, Delimiter::Dot =>);
+    let Some(Token {
+        kind: TokenKind::Literal(Literal::Int(index)),
+        loc,
+    }) = match_token_kind!(parser, TokenKind::Literal(Literal::Int(_)))
+    else {
+        let tok = parser.lexer.next_token();
+        parser.push_error(Located(
+            ParseError::ExprParseError(ExprParseError::ExpectedTupleIndex(tok.kind)),
+            tok.loc,
+        ));
+        return None;
+    };
+    Some(Expr::TupleIndex(TupleIndexExpr {
+        expr: Box::new(left),
+        dot_tok,
+        index: Located(index, loc),
+    }))
+}
+
 fn parse_block_expr(
     _pratt_parser: &PrattParser<Expr, BindingPower>,
     parser: &mut ParseContext,
 ) -> ParseResult<Expr> {
-    let brace_open_tok = match_delimiter!(parser, Delimiter::BraceOpen =>);
+    let brace_open_tok = match match_delimiter!(parser, Delimiter::BraceOpen) {
+        Ok(v) => v,
+        Err(e) => {
+            // without the opening brace the statements would eat the enclosing block, so the block
+            // only evaluates to an error.
+            let loc = e.1;
+            parser.push_error(e);
+            return Some(Expr::Block(BlockExpr {
+                brace_open_tok: Located(Delimiter::BraceOpen, loc),
+                stmts: Vec::new(),
+                eval_expr: Some(Box::new(Expr::Error(loc))),
+                brace_close_tok: Located(Delimiter::BraceClose, loc),
+            }));
+        }
+    };
 
     let mut stmts = Vec::new();
     let brace_close_tok = loop {
@@ -222,33 +308,63 @@ fn parse_block_expr(
             break match_delimiter!(parser, Delimiter::BraceClose =>);
         }
 
+        let start = *parser.lexer.peek_loc(0);
+        let error_count = parser.error_count();
         let stmt = Stmt::parse(parser);
-        if let Some(stmt) = stmt {
-            stmts.push(stmt);
-            continue;
-        }
-
-        // panic mode: skip until next semicolon or brace_close or EOF
-        while !matches!(
-            parser.lexer.peek_token_kind(0),
-            TokenKind::EndOfFile
-                | TokenKind::Delimiter(Delimiter::Semicolon | Delimiter::BraceClose)
-        ) {
-            parser.lexer.next_token();
+        let has_progressed = start != *parser.lexer.peek_loc(0);
+        match stmt {
+            // a statement with errors that did not end where expected is resynchronised on the
+            // next statement boundary instead of reporting the leftovers as more errors.
+            Some(Stmt::Expr(mut stmt))
+                if has_progressed
+                    && parser.error_count() > error_count
+                    && stmt.semi_tok.is_none()
+                    && !matches!(
+                        parser.lexer.peek_token_kind(0),
+                        TokenKind::Delimiter(Delimiter::BraceClose)
+                    ) =>
+            {
+                parser.skip_until(|kind| {
+                    matches!(
+                        kind,
+                        TokenKind::Delimiter(Delimiter::Semicolon | Delimiter::BraceClose)
+                    )
+                });
+                stmt.semi_tok = match_delimiter!(parser, Delimiter::Semicolon).ok();
+                stmts.push(Stmt::Expr(stmt));
+                continue;
+            }
+            Some(stmt) if has_progressed => {
+                stmts.push(stmt);
+                continue;
+            }
+            _ => (),
         }
 
-        // panic mode: found semicolon, proceed to next stmt
-        if matches!(
-            parser.lexer.peek_token_kind(0),
-            TokenKind::Delimiter(Delimiter::Semicolon)
-        ) {
-            parser.lexer.next_token();
-        }
+        // panic mode: skip until next semicolon or brace_close of this block, or EOF.
+        let skipped = parser.skip_until(|kind| {
+            matches!(
+                kind,
+                TokenKind::Delimiter(Delimiter::Semicolon | Delimiter::BraceClose)
+            )
+        });
+        let semi_tok = match_delimiter!(parser, Delimiter::Semicolon).ok();
+        let end = semi_tok
+            .as_ref()
+            .map(|v| v.1)
+            .or(skipped)
+            .unwrap_or(*parser.lexer.get_prev_loc());
+        stmts.push(Stmt::Expr(ExprStmt {
+            expr: Expr::Error(Span::combine(start, end)),
+            semi_tok,
+        }));
     };
 
+    // a trailing if, loop or block is taken as the value too, the resolver leaves it as a statement
+    // when it has none.
     let eval_expr = if matches!(
         stmts.last(),
-        Some(Stmt::Expr(ExprStmt { semi_tok: None, expr })) if expr.has_eval_expr() || (!expr.has_eval_expr() && matches!(expr, Expr::Loop(_))) || !expr.is_block()
+        Some(Stmt::Expr(ExprStmt { semi_tok: None, .. }))
     ) {
         let Some(Stmt::Expr(ExprStmt {
             expr,
@@ -266,10 +382,10 @@ fn parse_block_expr(
         let Stmt::Expr(ExprStmt { expr, semi_tok }) = stmt else {
             continue;
         };
-        if !expr.is_block() && semi_tok.is_none() {
+        if !expr.is_block() && !matches!(expr, Expr::Error(_)) && semi_tok.is_none() {
             parser.push_error(Located(
                 ParseError::MissingDelimiter(Delimiter::Semicolon),
-                expr.get_location().next().into(),
+                expr.get_location().next(),
             ));
         }
     }
@@ -310,7 +426,15 @@ fn parse_if_expr(
     parser: &mut ParseContext,
 ) -> ParseResult<Expr> {
     let if_tok = match_keyword!(parser, Keyword::If =>);
-    let cond = Expr::parse(parser)?;
+    let mut cond = Expr::parse(parser)?;
+    if !matches!(
+        parser.lexer.peek_token_kind(0),
+        TokenKind::Delimiter(Delimiter::BraceOpen)
+    ) {
+        // the condition most likely took the body, e.g. `if { 1 } else { 2 }`, so its type says
+        // nothing about what was meant.
+        cond = Expr::Error(cond.get_location());
+    }
     let Expr::Block(block) = parse_block_expr(pratt_parser, parser)? else {
         unreachable!();
     };
@@ -333,13 +457,21 @@ fn parse_else_expr(
     parser: &mut ParseContext,
 ) -> ParseResult<ElseExpr> {
     let else_tok = match_keyword!(parser, Keyword::Else =>);
-    let Expr::Block(block) = parse_block_expr(pratt_parser, parser)? else {
-        unreachable!()
+    let body = if matches!(
+        parser.lexer.peek_token_kind(0),
+        TokenKind::Keyword(Keyword::If)
+    ) {
+        let Expr::If(if_expr) = parse_if_expr(pratt_parser, parser)? else {
+            unreachable!()
+        };
+        ElseBody::If(Box::new(if_expr))
+    } else {
+        let Expr::Block(block) = parse_block_expr(pratt_parser, parser)? else {
+            unreachable!()
+        };
+        ElseBody::Block(block)
     };
-    Some(ElseExpr {
-        else_tok,
-        body: block,
-    })
+    Some(ElseExpr { else_tok, body })
 }
 
 fn parse_loop_expr(
@@ -360,25 +492,43 @@ impl Expr {
     pub fn parse(parser: &mut ParseContext) -> ParseResult<Expr> {
         static EXPR_PARSER: Lazy<PrattParser<Expr, BindingPower>> =
             Lazy::new(|| PrattParser::new(&ExprHandlers));
-        Self::handle_err(EXPR_PARSER.parse(parser, BindingPower::Zero), parser)
+        let start = *parser.lexer.peek_loc(0);
+        Self::handle_err(EXPR_PARSER.parse(parser, BindingPower::Zero), start, parser)
     }
 
-    fn handle_err(e: PrattParseResult<Expr>, parser: &mut ParseContext) -> ParseResult<Expr> {
+    /// Failed expressions become `Expr::Error` so that the enclosing node is kept, the offending
+    /// token is left for the caller to recover from, unless it is unknown to the lexer.
+    fn handle_err(
+        e: PrattParseResult<Expr>,
+        start: Span,
+        parser: &mut ParseContext,
+    ) -> ParseResult<Expr> {
         let Err(e) = e else {
             return e.ok();
         };
         match e {
-            PrattParseError::NoNudHandlerFound(e) => parser.push_error(Located(
-                ParseError::ExprParseError(ExprParseError::UnexpectedToken(e.kind)),
-                e.loc,
-            )),
-            PrattParseError::NoLedHandlerFound(e) => parser.push_error(Located(
-                ParseError::ExprParseError(ExprParseError::UnexpectedInfixOperator(e.kind)),
-                e.loc,
-            )),
-            PrattParseError::ParseError => (),
+            PrattParseError::NoNudHandlerFound(e) => {
+                if e.kind == TokenKind::Unknown && *parser.lexer.peek_loc(0) == e.loc {
+                    parser.lexer.next_token();
+                }
+                parser.push_error(Located(
+                    ParseError::ExprParseError(ExprParseError::UnexpectedToken(e.kind)),
+                    e.loc,
+                ));
+                Some(Expr::Error(e.loc))
+            }
+            PrattParseError::NoLedHandlerFound(e) => {
+                parser.push_error(Located(
+                    ParseError::ExprParseError(ExprParseError::UnexpectedInfixOperator(e.kind)),
+                    e.loc,
+                ));
+                Some(Expr::Error(Span::combine(start, e.loc)))
+            }
+            PrattParseError::ParseError => Some(Expr::Error(Span::combine(
+                start,
+                *parser.lexer.get_prev_loc(),
+            ))),
         }
-        None
     }
 }
 
@@ -3,8 +3,8 @@ pub(super) mod attributes;
 pub(super) mod expr;
 pub(super) mod func;
 pub(super) mod item;
+pub(super) mod pattern;
 pub(super) mod punctuate;
 pub(super) mod stmt;
 pub(super) mod ty;
 pub(super) mod ty_decl;
-
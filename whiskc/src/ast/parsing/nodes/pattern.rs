@@ -0,0 +1,36 @@
+use crate::ast::{
+    nodes::{
+        pattern::{Pattern, TuplePattern},
+        punctuate::Punctuated,
+    },
+    parsing::{
+        token::{Delimiter, TokenKind},
+        Parse, ParseContext, ParseResult,
+    },
+};
+
+impl Parse for Pattern {
+    fn parse(parser: &mut ParseContext) -> ParseResult<Self> {
+        if matches!(
+            parser.lexer.peek_token_kind(0),
+            TokenKind::Delimiter(Delimiter::ParenOpen)
+        ) {
+            let paren_open_tok = match_delimiter!(parser, Delimiter::ParenOpen =>);
+            let elems = Punctuated::parse(
+                parser,
+                Delimiter::Comma,
+                Delimiter::ParenClose,
+                Pattern::parse,
+            )?;
+            let paren_close_tok = match_delimiter!(parser, Delimiter::ParenClose =>);
+            Some(Pattern::Tuple(Box::new(TuplePattern {
+                paren_open_tok,
+                elems,
+                paren_close_tok,
+            })))
+        } else {
+            let name = match_identifier!(parser, "pattern's binding name".to_owned() =>)?;
+            Some(Pattern::Ident(name))
+        }
+    }
+}
@@ -16,10 +16,19 @@ impl<T> Punctuated<T> {
     {
         let mut items = Vec::new();
         while !matches!(parser.lexer.peek_token_kind(0), TokenKind::Delimiter(d) if *d == delim) {
+            let start = *parser.lexer.peek_loc(0);
             items.push(parse_fn(parser)?);
+            // an item that failed without consuming anything would be parsed again forever, leave
+            // the token for the caller's closing delimiter to report.
+            if start == *parser.lexer.peek_loc(0) {
+                break;
+            }
 
-            if !matches!(parser.lexer.peek_token_kind(0), TokenKind::Delimiter(d) if *d == delim) {
-                match_delimiter!(parser, sep =>);
+            if !matches!(parser.lexer.peek_token_kind(0), TokenKind::Delimiter(d) if *d == delim)
+                && match_delimiter!(parser, sep).is_err()
+            {
+                // missing separator, the caller reports the missing closing delimiter.
+                break;
             }
         }
 
@@ -2,7 +2,7 @@ use once_cell::sync::Lazy;
 
 use crate::ast::{
     location::{Located, Span},
-    nodes::{expr::Expr, stmt::*, ty::Type},
+    nodes::{expr::Expr, pattern::Pattern, stmt::*, ty::Type},
     parsing::{
         parsers::lookup_parser::{self, LookUpParseError, LookUpParser},
         token::{Delimiter, Keyword, Operator, TokenKind},
@@ -38,15 +38,29 @@ fn parse_expr_stmt(parser: &mut ParseContext) -> ParseResult<Stmt> {
 impl Parse for LetStmt {
     fn parse(parser: &mut ParseContext) -> ParseResult<Self> {
         let let_tok = match_keyword!(parser, Keyword::Let =>);
-        let name = match_identifier!(parser, "let declaration's name".to_owned() =>)?;
+        let pat = Pattern::parse(parser)?;
         let ty = Type::try_parse(parser);
         let assign_tok = match_operator!(parser, Operator::Assign =>);
+        let error_count = parser.error_count();
         let value = Expr::parse(parser)?;
-        let semi_tok = match_delimiter!(parser, Delimiter::Semicolon =>);
+        let semi_tok = if parser.error_count() > error_count {
+            // the value may have stopped before the tokens it reported, they are skipped up to the
+            // end of the statement instead of being parsed and reported again.
+            parser.skip_until(|kind| {
+                matches!(
+                    kind,
+                    TokenKind::Delimiter(Delimiter::Semicolon | Delimiter::BraceClose)
+                )
+            });
+            match_delimiter!(parser, Delimiter::Semicolon)
+                .unwrap_or(Located::new_temp(Delimiter::Semicolon))
+        } else {
+            match_delimiter!(parser, Delimiter::Semicolon =>)
+        };
 
         Some(Self {
             let_tok,
-            name,
+            pat,
             ty,
             assign_tok,
             value,
@@ -2,10 +2,10 @@ use once_cell::sync::Lazy;
 use strum::IntoEnumIterator;
 
 use crate::ast::{
-    location::{Located, Span},
+    location::{Locatable, Located, Span},
     nodes::{
         punctuate::Punctuated,
-        ty::{Field, PrimType, Struct, Type},
+        ty::{Field, GenericType, PrimType, Struct, TupleType, Type},
     },
     parsing::{
         parsers::pratt_parser::{self, PrattParseError, PrattParser},
@@ -21,11 +21,13 @@ pub enum TypeParseError {
     IntegerSizeOutOfRange(u16),
     ExpectedArrayLength,
     InvalidArrayLength(i64),
+    NonGenericTypeWithArguments,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 enum BindingPower {
     Zero,
+    Generic,
     Primary,
 }
 
@@ -47,24 +49,72 @@ impl pratt_parser::Handlers<Type, BindingPower> for TypeHandlers {
         }
 
         nud(TokenKind::Delimiter(Delimiter::ParenOpen), |_, parser| {
-            parse_unit_type(parser)
+            parse_unit_or_tuple_type(parser)
         });
     }
 
-    fn leds<F>(&self, _led: F)
+    fn leds<F>(&self, mut led: F)
     where
         F: FnMut(TokenKind, BindingPower, pratt_parser::LedHandler<Type, BindingPower>),
     {
+        led(
+            TokenKind::Delimiter(Delimiter::BracketOpen),
+            BindingPower::Generic,
+            |_, parser, left, _| parse_generic_type(parser, left),
+        );
     }
 }
 
-fn parse_unit_type(parser: &mut ParseContext) -> ParseResult<Type> {
+fn parse_generic_type(parser: &mut ParseContext, left: Type) -> ParseResult<Type> {
+    let Type::Ident(name) = left else {
+        parser.push_error(Located(
+            ParseError::TypeParseError(TypeParseError::NonGenericTypeWithArguments),
+            left.get_location(),
+        ));
+        return None;
+    };
+    let bracket_open_tok = match_delimiter!(parser, Delimiter::BracketOpen =>);
+    let args = Punctuated::parse(
+        parser,
+        Delimiter::Comma,
+        Delimiter::BracketClose,
+        Type::parse,
+    )?;
+    let bracket_close_tok = match_delimiter!(parser, Delimiter::BracketClose =>);
+    Some(Type::Generic(GenericType {
+        name,
+        bracket_open_tok,
+        args,
+        bracket_close_tok,
+    }))
+}
+
+/// `()` is the unit type, a single parenthesized type is the type itself and anything with a comma
+/// is a tuple, e.g. `(int,)` or `(int, bool)`.
+fn parse_unit_or_tuple_type(parser: &mut ParseContext) -> ParseResult<Type> {
     let paren_open_tok = match_delimiter!(parser, Delimiter::ParenOpen =>);
+    if let Ok(paren_close_tok) = match_delimiter!(parser, Delimiter::ParenClose) {
+        return Some(Type::Primitive(Located(
+            PrimType::Unit,
+            Span::combine(paren_open_tok.1, paren_close_tok.1),
+        )));
+    }
+
+    let first = Type::parse(parser)?;
+    if match_delimiter!(parser, Delimiter::Comma).is_err() {
+        match_delimiter!(parser, Delimiter::ParenClose =>);
+        return Some(first);
+    }
+
+    let mut elems =
+        Punctuated::parse(parser, Delimiter::Comma, Delimiter::ParenClose, Type::parse)?;
+    elems.items.insert(0, first);
     let paren_close_tok = match_delimiter!(parser, Delimiter::ParenClose =>);
-    Some(Type::Primitive(Located(
-        PrimType::Unit,
-        Span::combine(paren_open_tok.1, paren_close_tok.1),
-    )))
+    Some(Type::Tuple(TupleType {
+        paren_open_tok,
+        elems,
+        paren_close_tok,
+    }))
 }
 
 fn parse_keyword_type(parser: &mut ParseContext) -> ParseResult<Type> {
@@ -15,7 +15,7 @@ impl Parse for TypeDecl {
     fn parse(ctx: &mut ParseContext) -> ParseResult<Self> {
         let ty_tok = match_keyword!(ctx, Keyword::Type =>);
         let name = match_identifier!(ctx, "type's name".to_owned() =>)?;
-        let assign_tok = match_operator!(ctx, Operator::Assign =>);
+        let assign_tok = match_operator!(ctx, Operator::Assign).ok();
 
         let kind: TypeDeclKind = if matches!(
             ctx.lexer.peek_token_kind(0),
@@ -22,6 +22,22 @@ impl Token {
     }
 }
 
+/// Comments are not tokens, the lexer keeps them aside so that tools like the formatter can put
+/// them back.
+#[derive(Debug, Clone)]
+pub struct Comment {
+    /// The full comment text including the `//` or `/* */` markers.
+    pub text: String,
+    pub loc: Span,
+    pub kind: CommentKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommentKind {
+    Line,
+    Block,
+}
+
 #[derive(Clone, PartialEq, Eq, Hash)]
 pub enum TokenKind {
     Unknown,
@@ -225,6 +241,7 @@ pub enum Delimiter {
     Colon,
     Semicolon,
     Comma,
+    Dot,
 }
 impl fmt::Display for Delimiter {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -241,6 +258,7 @@ impl fmt::Display for Delimiter {
                 Self::Colon => ':',
                 Self::Semicolon => ';',
                 Self::Comma => ',',
+                Self::Dot => '.',
             }
         )
     }
@@ -271,6 +289,7 @@ pub enum OperatorChar {
     Exclaimation,
     Ampersand,
     Pipe,
+    Question,
 }
 impl fmt::Display for OperatorChar {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -289,6 +308,7 @@ impl fmt::Display for OperatorChar {
                 Self::Exclaimation => '!',
                 Self::Ampersand => '&',
                 Self::Pipe => '|',
+                Self::Question => '?',
             }
         )
     }
@@ -323,6 +343,7 @@ pub enum Operator {
     LessEqual,
     Greater,
     GreaterEqual,
+    Try,
 }
 impl fmt::Display for Operator {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -345,6 +366,7 @@ impl fmt::Display for Operator {
                 Self::LessEqual => "<=",
                 Self::Greater => ">",
                 Self::GreaterEqual => ">=",
+                Self::Try => "?",
             }
         )
     }
@@ -0,0 +1,409 @@
+use std::fmt::Write;
+
+use super::{
+    parallel_move::{sequentialize, MoveSrc, Step},
+    PRINT_RESULT,
+};
+use crate::ir::{
+    self, BinaryOp, Block, BlockCall, CmpOp, Const, FuncRef, InstKind, Operand, Terminator, Ty,
+    UnaryOp,
+};
+
+/// The runtime the generated code includes as `wsk_rt.h`.
+pub const RUNTIME_HEADER: &str = include_str!("wsk_rt.h");
+
+/// Every IR value is a C local named after it and every block a label. Functions of the module
+/// are prefixed with `wsk_fn_`, apart from the names of the runtime, while extern functions keep
+/// their names so they link against plain C.
+pub(super) fn codegen_module(module: &ir::Module, main: FuncRef) -> String {
+    let mut out = String::new();
+    writeln!(
+        out,
+        "/* generated by whiskc {} from {} */",
+        env!("CARGO_PKG_VERSION"),
+        module.name
+    )
+    .unwrap();
+    out.push_str("#include \"wsk_rt.h\"\n\n");
+
+    for func in module.funcs.iter().filter(|v| v.sig.rets.len() > 1) {
+        write!(out, "{} {{", ret_ty(func)).unwrap();
+        for (i, ty) in func.sig.rets.iter().enumerate() {
+            write!(out, " {} r{};", c_ty(*ty), i).unwrap();
+        }
+        out.push_str(" };\n");
+    }
+    for func in &module.funcs {
+        let params: Vec<_> = func.sig.params.iter().map(|v| c_ty(*v)).collect();
+        writeln!(
+            out,
+            "{}{} {}({});",
+            if func.is_extern { "extern " } else { "" },
+            ret_ty(func),
+            func_name(func),
+            if params.is_empty() {
+                "void".to_owned()
+            } else {
+                params.join(", ")
+            }
+        )
+        .unwrap();
+    }
+
+    for func in module.funcs.iter().filter(|v| !v.is_extern) {
+        out.push('\n');
+        FuncCodegen::new(module, func, &mut out).codegen();
+    }
+
+    writeln!(
+        out,
+        "\nint main(void) {{\n\
+         \twsk_int result = {name}();\n\
+         #ifdef {print}\n\
+         \tprintf(\"%\" PRId64 \"\\n\", result);\n\
+         #endif\n\
+         \treturn (int)result;\n\
+         }}",
+        name = func_name(module.get(main)),
+        print = PRINT_RESULT,
+    )
+    .unwrap();
+    out
+}
+
+fn c_ty(ty: Ty) -> &'static str {
+    match ty {
+        Ty::Int => "wsk_int",
+        Ty::Bool => "wsk_bool",
+        Ty::Ref => "wsk_ref",
+    }
+}
+
+/// The field of `union wsk_field` holding a value of the type.
+fn field(ty: Ty) -> &'static str {
+    match ty {
+        Ty::Int => "i",
+        Ty::Bool => "b",
+        Ty::Ref => "r",
+    }
+}
+
+fn func_name(func: &ir::Function) -> String {
+    if func.is_extern {
+        func.name.clone()
+    } else {
+        format!("wsk_fn_{}", func.name)
+    }
+}
+
+/// Functions with several results return them in a struct of their own.
+fn ret_ty(func: &ir::Function) -> String {
+    match func.sig.rets[..] {
+        [] => "void".to_owned(),
+        [ty] => c_ty(ty).to_owned(),
+        _ => format!("struct wsk_rets_{}", func.name),
+    }
+}
+
+fn const_value(c: Const) -> String {
+    match c {
+        // the literal of the minimum would be the negation of an out of range integer.
+        Const::Int(i64::MIN) => "INT64_MIN".to_owned(),
+        Const::Int(v) => format!("INT64_C({})", v),
+        Const::Bool(v) => v.to_string(),
+    }
+}
+
+struct FuncCodegen<'a> {
+    module: &'a ir::Module,
+    func: &'a ir::Function,
+    out: &'a mut String,
+    /// Whether a value is read anywhere, the others are neither declared nor assigned.
+    used: Vec<bool>,
+    /// Whether a block is jumped to and needs a label.
+    jumped_to: Vec<bool>,
+}
+impl<'a> FuncCodegen<'a> {
+    fn new(module: &'a ir::Module, func: &'a ir::Function, out: &'a mut String) -> Self {
+        let mut used = vec![false; func.values.len()];
+        let mut jumped_to = vec![false; func.blocks.len()];
+        for block in func.block_ids() {
+            let data = func.block(block);
+            let ops = data.insts.iter().flat_map(|v| v.operands());
+            for v in ops
+                .chain(data.term.operands())
+                .filter_map(Operand::as_value)
+            {
+                used[v.index()] = true;
+            }
+            match &data.term {
+                Terminator::Jump(call) => {
+                    jumped_to[call.block.index()] |= !falls_through(block, call)
+                }
+                Terminator::Branch { then, else_, .. } => {
+                    jumped_to[then.block.index()] = true;
+                    jumped_to[else_.block.index()] |= !falls_through(block, else_);
+                }
+                Terminator::Return(_) | Terminator::Unreachable | Terminator::Missing => {}
+            }
+        }
+        Self {
+            module,
+            func,
+            out,
+            used,
+            jumped_to,
+        }
+    }
+
+    fn codegen(&mut self) {
+        let func = self.func;
+        let params = &func.block(func.entry()).params;
+        let param_decls: Vec<_> = params
+            .iter()
+            .map(|v| format!("{} v{}", c_ty(func.value_ty(*v)), v.0))
+            .collect();
+        writeln!(
+            self.out,
+            "{} {}({}) {{",
+            ret_ty(func),
+            func_name(func),
+            if param_decls.is_empty() {
+                "void".to_owned()
+            } else {
+                param_decls.join(", ")
+            }
+        )
+        .unwrap();
+        for v in params.iter().filter(|v| !self.used[v.index()]) {
+            writeln!(self.out, "\t(void)v{};", v.0).unwrap();
+        }
+        for (i, ty) in func.values.iter().enumerate() {
+            if self.used[i] && !params.iter().any(|v| v.index() == i) {
+                writeln!(self.out, "\t{} v{};", c_ty(*ty), i).unwrap();
+            }
+        }
+
+        for block in func.block_ids() {
+            if self.jumped_to[block.index()] {
+                // a label needs a statement after it, even before the closing brace.
+                writeln!(self.out, "b{}:;", block.0).unwrap();
+            }
+            let data = func.block(block);
+            for inst in &data.insts {
+                self.codegen_inst(&inst.kind, &inst.results);
+            }
+            self.codegen_terminator(block, &data.term);
+        }
+        self.out.push_str("}\n");
+    }
+
+    fn operand(&self, op: Operand) -> String {
+        match op {
+            Operand::Value(v) => format!("v{}", v.0),
+            Operand::Const(c) => const_value(c),
+        }
+    }
+
+    fn operands(&self, ops: &[Operand]) -> String {
+        let ops: Vec<_> = ops.iter().map(|v| self.operand(*v)).collect();
+        ops.join(", ")
+    }
+
+    fn codegen_inst(&mut self, kind: &InstKind, results: &[ir::Value]) {
+        let dst = results.first().map(|v| format!("v{}", v.0));
+        // pure instructions with an unused result are left out.
+        if !matches!(kind, InstKind::Call(..)) && !self.used[results[0].index()] {
+            return;
+        }
+        let rhs = match kind {
+            InstKind::Unary(op, src) => {
+                let src = self.operand(*src);
+                match op {
+                    UnaryOp::Neg => format!("wsk_neg({})", src),
+                    UnaryOp::Not => format!("!{}", src),
+                }
+            }
+            InstKind::Binary(op, lhs, rhs) => {
+                let (lhs, rhs) = (self.operand(*lhs), self.operand(*rhs));
+                match op {
+                    BinaryOp::Add => format!("wsk_add({}, {})", lhs, rhs),
+                    BinaryOp::Sub => format!("wsk_sub({}, {})", lhs, rhs),
+                    BinaryOp::Mul => format!("wsk_mul({}, {})", lhs, rhs),
+                    BinaryOp::Div => format!("wsk_div({}, {})", lhs, rhs),
+                    BinaryOp::Mod => format!("wsk_mod({}, {})", lhs, rhs),
+                    BinaryOp::And => format!("{} && {}", lhs, rhs),
+                    BinaryOp::Or => format!("{} || {}", lhs, rhs),
+                }
+            }
+            InstKind::Cmp(op, lhs, rhs) => {
+                let op = match op {
+                    CmpOp::Equal => "==",
+                    CmpOp::NotEqual => "!=",
+                    CmpOp::Less => "<",
+                    CmpOp::LessEqual => "<=",
+                    CmpOp::Greater => ">",
+                    CmpOp::GreaterEqual => ">=",
+                };
+                format!("{} {} {}", self.operand(*lhs), op, self.operand(*rhs))
+            }
+            InstKind::Call(callee, args) => {
+                let callee = self.module.get(*callee);
+                let call = format!("{}({})", func_name(callee), self.operands(args));
+                let used: Vec<_> = results
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, v)| self.used[v.index()])
+                    .collect();
+                match (results.len(), &used[..]) {
+                    (_, []) => writeln!(self.out, "\t{};", call).unwrap(),
+                    (1, _) => writeln!(self.out, "\t{} = {};", dst.unwrap(), call).unwrap(),
+                    _ => {
+                        writeln!(self.out, "\t{{\n\t\t{} rets = {};", ret_ty(callee), call)
+                            .unwrap();
+                        for (i, v) in used {
+                            writeln!(self.out, "\t\tv{} = rets.r{};", v.0, i).unwrap();
+                        }
+                        self.out.push_str("\t}\n");
+                    }
+                }
+                return;
+            }
+            InstKind::MakeStruct(fields) => {
+                let dst = dst.unwrap();
+                writeln!(self.out, "\t{} = wsk_alloc({});", dst, fields.len()).unwrap();
+                for (i, v) in fields.iter().enumerate() {
+                    let ty = self.func.operand_ty(*v);
+                    writeln!(
+                        self.out,
+                        "\t{}[{}].{} = {};",
+                        dst,
+                        i,
+                        field(ty),
+                        self.operand(*v)
+                    )
+                    .unwrap();
+                }
+                return;
+            }
+            InstKind::GetField(src, index) => {
+                let ty = self.func.value_ty(results[0]);
+                format!("{}[{}].{}", self.operand(*src), index, field(ty))
+            }
+        };
+        writeln!(self.out, "\t{} = {};", dst.unwrap(), rhs).unwrap();
+    }
+
+    fn codegen_terminator(&mut self, block: Block, term: &Terminator) {
+        match term {
+            Terminator::Jump(call) => self.codegen_edge(block, call, true, "\t"),
+            Terminator::Branch { cond, then, else_ } => {
+                writeln!(self.out, "\tif ({}) {{", self.operand(*cond)).unwrap();
+                self.codegen_edge(block, then, false, "\t\t");
+                self.out.push_str("\t}\n");
+                self.codegen_edge(block, else_, true, "\t");
+            }
+            Terminator::Return(values) => match values[..] {
+                [] => self.out.push_str("\treturn;\n"),
+                [v] => writeln!(self.out, "\treturn {};", self.operand(v)).unwrap(),
+                _ => writeln!(
+                    self.out,
+                    "\treturn ({}){{{}}};",
+                    ret_ty(self.func),
+                    self.operands(values)
+                )
+                .unwrap(),
+            },
+            Terminator::Unreachable | Terminator::Missing => {
+                self.out.push_str("\twsk_unreachable();\n")
+            }
+        }
+    }
+
+    /// Go to the target of the edge with its arguments, unless it falls through to the next block.
+    fn codegen_edge(&mut self, block: Block, call: &BlockCall, fall_through: bool, indent: &str) {
+        let moves = self
+            .func
+            .block(call.block)
+            .params
+            .iter()
+            .zip(&call.args)
+            .filter(|(dst, _)| self.used[dst.index()])
+            .map(|(dst, arg)| (*dst, *arg));
+        let steps = sequentialize(moves, |arg| arg.as_value());
+        // a saved parameter is declared in a block of its own, with the type of the parameter.
+        let saves = steps.iter().any(|v| matches!(v, Step::Save(_)));
+        let inner = if saves {
+            writeln!(self.out, "{}{{", indent).unwrap();
+            format!("{}\t", indent)
+        } else {
+            indent.to_owned()
+        };
+        let mut scratch = 0;
+        for step in steps {
+            match step {
+                Step::Save(v) => {
+                    scratch += 1;
+                    let ty = c_ty(self.func.value_ty(v));
+                    writeln!(self.out, "{}{} t{} = v{};", inner, ty, scratch, v.0).unwrap();
+                }
+                Step::Move(dst, src) => {
+                    let src = match src {
+                        MoveSrc::Src(arg) => self.operand(arg),
+                        MoveSrc::Scratch => format!("t{}", scratch),
+                    };
+                    writeln!(self.out, "{}v{} = {};", inner, dst.0, src).unwrap();
+                }
+            }
+        }
+        if saves {
+            writeln!(self.out, "{}}}", indent).unwrap();
+        }
+        if !fall_through || !falls_through(block, call) {
+            writeln!(self.out, "{}goto b{};", indent, call.block.0).unwrap();
+        }
+    }
+}
+
+fn falls_through(block: Block, call: &BlockCall) -> bool {
+    call.block.0 == block.0.wrapping_add(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::RUNTIME_HEADER;
+    use crate::{
+        codegen::{
+            testing::{build_and_run, c_compiler, compile, run_on_vm, test_programs, OutDir},
+            Target, PRINT_RESULT,
+        },
+        ir::opt::OptLevel,
+    };
+
+    #[test]
+    fn c_agrees_with_stack_vm() {
+        let Some(cc) = c_compiler() else {
+            return;
+        };
+        let out_dir = OutDir::new("c");
+        fs::write(out_dir.0.join("wsk_rt.h"), RUNTIME_HEADER).unwrap();
+        for (name, source) in test_programs() {
+            for opt_level in [OptLevel::O0, OptLevel::O2] {
+                let output = compile(&name, &source, opt_level, Target::C);
+                let src = out_dir.0.join(format!("{}.c", name));
+                fs::write(&src, output.c_source.unwrap()).unwrap();
+                let print = format!("-D{}", PRINT_RESULT);
+                let flags = ["-std=c99", "-O2", "-Wall", "-Werror", &print];
+                assert_eq!(
+                    build_and_run(&cc, &src, &flags),
+                    run_on_vm(&name, &source, opt_level),
+                    "{} at {:?}",
+                    name,
+                    opt_level
+                );
+            }
+        }
+    }
+}
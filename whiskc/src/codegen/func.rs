@@ -1,36 +1,223 @@
-use wsk_vm::Inst;
+use wsk_vm::{
+    program::{Function, FunctionBuilder},
+    Cmp, Inst,
+};
 
-use super::{expr::ExprCodegen, Codegen};
+use super::BlockLabels;
+use crate::ir::{
+    self, BinaryOp, Block, BlockCall, CmpOp, Const, InstKind, Operand, Terminator, UnaryOp, Value,
+};
 
-use crate::lowering::nodes::{self as ast};
+/// Every IR value has its own local, indexed by the value. A value used once by the instructions
+/// that follow it in its block stays on the operand stack instead.
+pub(super) fn codegen_function(func: &ir::Function) -> Function {
+    let mut uses = vec![0; func.values.len()];
+    let mut use_block = vec![None; func.values.len()];
+    let mut count_use = |op: Operand, block: Block| {
+        if let Operand::Value(v) = op {
+            uses[v.index()] += 1;
+            use_block[v.index()] = Some(block);
+        }
+    };
+    for block in func.block_ids() {
+        let data = func.block(block);
+        for inst in &data.insts {
+            for op in inst.operands() {
+                count_use(op, block);
+            }
+        }
+        match &data.term {
+            // the arguments of a branch are passed on one of its edges only, they are always
+            // loaded from their locals.
+            Terminator::Branch { cond, then, else_ } => {
+                count_use(*cond, block);
+                for op in then.args.iter().chain(&else_.args) {
+                    count_use(*op, Block(u32::MAX));
+                }
+            }
+            term => {
+                for op in term.operands() {
+                    count_use(op, block);
+                }
+            }
+        }
+    }
+
+    let mut stackable = vec![false; func.values.len()];
+    for block in func.block_ids() {
+        for inst in &func.block(block).insts {
+            if let [v] = inst.results[..] {
+                stackable[v.index()] = uses[v.index()] == 1 && use_block[v.index()] == Some(block);
+            }
+        }
+    }
 
-impl Codegen for ast::func::Function {
-    fn codegen(&self, ctx: &mut super::Context) -> Result<(), super::CodegenError> {
-        ctx.set_current_fi(self.func_id);
-        let func_sym = self.func_id.sym(ctx.sym_table);
+    let mut out = FunctionBuilder::new(func.sig.params.len(), func.sig.rets.len());
+    let labels = func.blocks.iter().map(|_| out.new_label()).collect();
+    let mut cg = FuncCodegen {
+        func,
+        out,
+        uses,
+        stackable,
+        stack: vec![],
+        labels,
+    };
+    cg.codegen();
+    cg.out
+        .build()
+        .expect("every block is placed and jumps only to blocks")
+}
 
-        for param_id in &func_sym.params {
-            let id = ctx.get_local(*param_id);
-            ctx.get_current_fi_mut().push_inst(Inst::Store(id));
+struct FuncCodegen<'a> {
+    func: &'a ir::Function,
+    out: FunctionBuilder,
+    uses: Vec<usize>,
+    stackable: Vec<bool>,
+    /// Values on the operand stack that are not stored in their locals, from the bottom up.
+    stack: Vec<Value>,
+    labels: BlockLabels,
+}
+impl FuncCodegen<'_> {
+    fn codegen(&mut self) {
+        let func = self.func;
+        // the last argument is on the top of the stack.
+        for param in func.block(func.entry()).params.iter().rev() {
+            self.out.push(Inst::Store(param.index()));
         }
 
-        self.body.codegen(ctx)?;
+        for block in func.block_ids() {
+            self.out.bind_label(self.labels[block.index()]);
+            let data = func.block(block);
+            for inst in &data.insts {
+                self.load_operands(&inst.operands());
+                self.codegen_inst(&inst.kind);
+                match inst.results[..] {
+                    [v] if self.stackable[v.index()] => self.stack.push(v),
+                    _ => {
+                        for v in inst.results.iter().rev() {
+                            self.out.push(if self.uses[v.index()] == 0 {
+                                Inst::Pop
+                            } else {
+                                Inst::Store(v.index())
+                            });
+                        }
+                    }
+                }
+            }
+            self.codegen_terminator(block, &data.term);
+            debug_assert!(self.stack.is_empty(), "values left on the stack");
+        }
+    }
 
-        if func_sym.ret_ty == ctx.sym_table.common_type().unit
-            || self
-                .body
-                .eval_expr
-                .as_ref()
-                .map(|v| v.ty == ctx.sym_table.common_type().never)
-                .unwrap_or(false)
-        {
-            let func = ctx.get_current_fi_mut();
-            if !matches!(func.get_insts()[..], [.., Inst::Ret]) {
-                func.push_inst(Inst::Ret);
+    /// Push the operands in order, reusing the ones already on the top of the stack.
+    fn load_operands(&mut self, ops: &[Operand]) {
+        let on_stack = |stack: &[Value], k: usize| {
+            stack[stack.len() - k..]
+                .iter()
+                .zip(&ops[..k])
+                .all(|(v, op)| Operand::Value(*v) == *op)
+        };
+        let mut k = (0..=ops.len().min(self.stack.len()))
+            .rev()
+            .find(|k| on_stack(&self.stack, *k))
+            .unwrap_or(0);
+        let buried = ops[k..]
+            .iter()
+            .any(|op| op.as_value().is_some_and(|v| self.stack.contains(&v)));
+        if buried {
+            self.spill();
+            k = 0;
+        }
+
+        self.stack.truncate(self.stack.len() - k);
+        for op in &ops[k..] {
+            self.out.push(match op {
+                Operand::Value(v) => Inst::Load(v.index()),
+                Operand::Const(Const::Int(v)) => Inst::Push((*v).into()),
+                Operand::Const(Const::Bool(v)) => Inst::Push((*v).into()),
+            });
+        }
+    }
+
+    /// Store every value on the stack into its local.
+    fn spill(&mut self) {
+        while let Some(v) = self.stack.pop() {
+            self.out.push(Inst::Store(v.index()));
+        }
+    }
+
+    fn codegen_inst(&mut self, kind: &InstKind) {
+        let insts: &[Inst] = match kind {
+            InstKind::Unary(UnaryOp::Neg, _) => &[Inst::Neg],
+            InstKind::Unary(UnaryOp::Not, _) => &[Inst::Not],
+            InstKind::Binary(op, _, _) => &[match op {
+                BinaryOp::Add => Inst::Add,
+                BinaryOp::Sub => Inst::Sub,
+                BinaryOp::Mul => Inst::Mul,
+                BinaryOp::Div => Inst::Div,
+                BinaryOp::Mod => Inst::Mod,
+                BinaryOp::And => Inst::And,
+                BinaryOp::Or => Inst::Or,
+            }],
+            InstKind::Cmp(op, _, _) => match op {
+                CmpOp::Equal => &[Inst::Cmp(Cmp::Equal)],
+                CmpOp::NotEqual => &[Inst::Cmp(Cmp::Equal), Inst::Not],
+                CmpOp::Less => &[Inst::Cmp(Cmp::Less)],
+                CmpOp::LessEqual => &[Inst::Cmp(Cmp::Greater), Inst::Not],
+                CmpOp::Greater => &[Inst::Cmp(Cmp::Greater)],
+                CmpOp::GreaterEqual => &[Inst::Cmp(Cmp::Less), Inst::Not],
+            },
+            InstKind::Call(callee, _) => &[Inst::Call(callee.index())],
+            InstKind::MakeStruct(fields) => &[Inst::MakeStruct(fields.len())],
+            InstKind::GetField(_, index) => &[Inst::GetField(*index)],
+        };
+        self.out.push_insts(insts.iter().copied());
+    }
+
+    fn codegen_terminator(&mut self, block: Block, term: &Terminator) {
+        match term {
+            Terminator::Jump(call) => {
+                self.load_operands(&call.args);
+                self.codegen_edge(block, call, true);
+            }
+            Terminator::Branch { cond, then, else_ } => {
+                self.load_operands(&[*cond]);
+                if else_.args.is_empty() {
+                    self.out.jmp_false(self.labels[else_.block.index()]);
+                    self.load_operands(&then.args);
+                    self.codegen_edge(block, then, true);
+                } else if then.args.is_empty() {
+                    self.out.jmp_true(self.labels[then.block.index()]);
+                    self.load_operands(&else_.args);
+                    self.codegen_edge(block, else_, true);
+                } else {
+                    let to_else = self.out.new_label();
+                    self.out.jmp_false(to_else);
+                    self.load_operands(&then.args);
+                    // the else edge follows, so the then edge cannot fall through.
+                    self.codegen_edge(block, then, false);
+                    self.out.bind_label(to_else);
+                    self.load_operands(&else_.args);
+                    self.codegen_edge(block, else_, true);
+                }
+            }
+            Terminator::Return(values) => {
+                self.load_operands(values);
+                self.out.push(Inst::Ret);
             }
+            // the vm has no trap, stop the whole program if control ever gets here.
+            Terminator::Unreachable | Terminator::Missing => self.out.push(Inst::Halt),
         }
+    }
 
-        ctx.unset_current_fi();
-        Ok(())
+    /// Pop the arguments already on the stack into the target's parameters and go to the target,
+    /// falling through when allowed and the target is placed right after the block.
+    fn codegen_edge(&mut self, block: Block, call: &BlockCall, fall_through: bool) {
+        for param in self.func.block(call.block).params.iter().rev() {
+            self.out.push(Inst::Store(param.index()));
+        }
+        if !fall_through || call.block.0 != block.0 + 1 {
+            self.out.jmp(self.labels[call.block.index()]);
+        }
     }
 }
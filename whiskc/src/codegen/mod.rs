@@ -1,139 +1,276 @@
-use std::collections::HashMap;
-
 use wsk_vm::{
-    program::{Function, Program},
+    program::{DebugInfo, Export, Function, Program},
     Inst,
 };
 
-use crate::{
-    lowering::{nodes::item::Item, Module},
-    symbol::{FuncId, SymbolTable, VarId},
-};
+use crate::ir::{self, FuncRef, Ty};
 
-mod expr;
+mod c;
 mod func;
-mod stmt;
+mod parallel_move;
+mod reg;
+mod wasm;
+mod x86_64;
 
-pub fn codegen_wsk_vm(module: &Module) -> Result<Program, CodegenError> {
-    let mut ctx = Context::new(&module.sym_table);
-    let mut has_entry = false;
+pub use c::RUNTIME_HEADER as C_RUNTIME_HEADER;
+pub use wasm::{validate as validate_wasm, ValidateError as WasmValidateError};
 
-    for item in &module.items {
-        let Item::Function(func) = item else {
-            return Err(CodegenError::UnsupportedItem);
-        };
-        let fi = ctx.prog.add_func(Function::default());
-        ctx.add_fi(func.func_id, fi);
+/// The symbol that makes the `main` of [`Target::C`] and [`Target::X86_64`] print the result of
+/// the main function, for the tests comparing it with the VM as exit codes are too small. Define
+/// it as a macro of the C compiler, or a symbol of the assembler.
+pub const PRINT_RESULT: &str = "WSK_PRINT_RESULT";
 
-        let func_sym = func.func_id.sym(ctx.sym_table);
-        if func_sym.name == "main" {
-            ctx.prog.set_entry_point(fi);
-            has_entry = true;
+/// The label of the start of every block of a function, by block index.
+type BlockLabels = Vec<wsk_vm::label::Label>;
 
-            if !func_sym.params.is_empty() || func_sym.ret_ty != ctx.sym_table.common_type().int {
-                return Err(CodegenError::UnsupportedMainFunctionSig);
-            }
-        }
-    }
+/// What [`crate::compile`] generates from the IR.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    /// A `.wc` program of the stack VM.
+    #[default]
+    WskVm,
+    /// A `.c` source file, to be compiled along [`C_RUNTIME_HEADER`] saved as `wsk_rt.h`.
+    C,
+    /// A `.s` file of GNU assembler for x86-64 Linux, to be linked with the C library.
+    X86_64,
+    /// A `.wasm` module, importing the extern functions from `env`.
+    Wasm,
+    /// A `.wcr` program of the register VM of [`wsk_vm::reg`].
+    Reg,
+}
 
-    for item in &module.items {
-        let Item::Function(func) = item else {
-            continue;
-        };
-        ctx.clear_locals();
-        func.codegen(&mut ctx)?;
+/// Generate the VM program of the module, the function indices of the program are the indices of
+/// the IR functions.
+pub fn codegen_wsk_vm(module: &ir::Module) -> Result<Program, CodegenError> {
+    let main = check_module(module)?;
+
+    let mut prog = Program::default();
+    for func in &module.funcs {
+        prog.add_func(func::codegen_function(func));
     }
 
-    if has_entry {
-        // attach runtime entry
-        let rtfunc = Function::from_insts([Inst::Call(ctx.prog.get_entry_point()), Inst::Halt]);
-        let rtid = ctx.prog.add_func(rtfunc);
-        ctx.prog.set_entry_point(rtid);
+    // attach runtime entry
+    let rtfunc = Function::from_insts([Inst::Call(main.index()), Inst::Halt]);
+    let rtid = prog.add_func(rtfunc);
+    prog.set_entry_point(rtid);
+    prog.pool_constants();
+
+    prog.add_export(Export {
+        name: "main".to_owned(),
+        func: main.index(),
+    });
+    let mut func_names: Vec<_> = module.funcs.iter().map(|v| v.name.clone()).collect();
+    func_names.push("_start".to_owned());
+    prog.set_debug_info(DebugInfo {
+        source: module.name.clone(),
+        func_names,
+    });
+    prog.set_metadata("producer", concat!("whiskc ", env!("CARGO_PKG_VERSION")));
+
+    Ok(prog)
+}
+
+/// Generate the program of the register VM of [`wsk_vm::reg`], its entry point is the main
+/// function.
+pub fn codegen_reg_vm(module: &ir::Module) -> Result<wsk_vm::reg::Program, CodegenError> {
+    let main = check_module(module)?;
 
-        Ok(ctx.prog)
-    } else {
-        Err(CodegenError::NoMainFunction)
+    let mut prog = wsk_vm::reg::Program::default();
+    for func in &module.funcs {
+        prog.add_func(reg::codegen_function(func));
     }
+    prog.set_entry_point(main.index());
+    Ok(prog)
 }
 
-struct Context<'a> {
-    pub sym_table: &'a SymbolTable,
-    pub prog: Program,
-    fis: HashMap<FuncId, usize>,
-    cur_fi: Option<usize>,
-    locals: HashMap<VarId, usize>,
-    local_cnts: Vec<usize>,
-    active_local_cnt: usize,
+/// Generate portable C for the module. Extern functions are declared for the C functions of the
+/// same name and the C `main` returns the result of the main function.
+pub fn codegen_c(module: &ir::Module) -> Result<String, CodegenError> {
+    let main = find_main(module)?;
+    Ok(c::codegen_module(module, main))
 }
-impl<'a> Context<'a> {
-    pub fn new(sym_table: &'a SymbolTable) -> Self {
-        Self {
-            sym_table,
-            prog: Program::default(),
-            fis: HashMap::new(),
-            cur_fi: None,
-            locals: HashMap::new(),
-            local_cnts: vec![],
-            active_local_cnt: 0,
-        }
-    }
 
-    pub fn set_current_fi(&mut self, fid: FuncId) {
-        let fi = self.get_fi(fid).expect("set fi");
-        self.cur_fi = Some(fi);
+/// Generate x86-64 assembly of the module, for the GNU assembler. The `main` symbol calls the
+/// main function and returns its result.
+pub fn codegen_x86_64(module: &ir::Module) -> Result<String, CodegenError> {
+    let main = find_main(module)?;
+    // a C function returns several values in a struct, the layout of which depends on its fields.
+    if module
+        .funcs
+        .iter()
+        .any(|v| v.is_extern && v.sig.rets.len() > 1)
+    {
+        return Err(CodegenError::UnsupportedExternFunctionSig);
     }
+    Ok(x86_64::codegen_module(module, main))
+}
+
+/// Generate a WebAssembly module, exporting the `pub` functions and the main function.
+pub fn codegen_wasm(module: &ir::Module) -> Result<Vec<u8>, CodegenError> {
+    let main = find_main(module)?;
+    wasm::codegen_module(module, main)
+}
 
-    pub fn unset_current_fi(&mut self) {
-        self.cur_fi = None;
+/// Check that the VMs can run the module, returning its main function.
+fn check_module(module: &ir::Module) -> Result<FuncRef, CodegenError> {
+    if module.funcs.iter().any(|v| v.is_extern) {
+        return Err(CodegenError::UnsupportedItem);
     }
+    find_main(module)
+}
 
-    pub fn get_current_fi_mut(&mut self) -> &mut Function {
-        let fi = self.cur_fi.expect("within function context");
-        self.prog.get_mut(fi).unwrap()
+/// The main function of the module, checking its signature.
+fn find_main(module: &ir::Module) -> Result<FuncRef, CodegenError> {
+    let main = module.find("main").ok_or(CodegenError::NoMainFunction)?;
+    let sig = &module.get(main).sig;
+    if !sig.params.is_empty() || sig.rets != [Ty::Int] {
+        return Err(CodegenError::UnsupportedMainFunctionSig);
     }
+    Ok(main)
+}
 
-    pub fn add_fi(&mut self, fid: FuncId, fi: usize) {
-        assert!(!self.fis.contains_key(&fid), "duplicate symbols to fi");
-        self.fis.insert(fid, fi);
+#[derive(Debug)]
+pub enum CodegenError {
+    UnsupportedItem,
+    NoMainFunction,
+    UnsupportedMainFunctionSig,
+    UnsupportedExternFunctionSig,
+    IrreducibleControlFlow,
+}
+impl std::fmt::Display for CodegenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CodegenError::UnsupportedItem => {
+                write!(f, "extern functions are not supported by the vm")
+            }
+            CodegenError::NoMainFunction => write!(f, "no main function found"),
+            CodegenError::UnsupportedMainFunctionSig => {
+                write!(f, "main function must take no parameters and return int")
+            }
+            CodegenError::UnsupportedExternFunctionSig => write!(
+                f,
+                "extern functions returning more than one value are not supported on x86-64"
+            ),
+            CodegenError::IrreducibleControlFlow => write!(
+                f,
+                "control flow cannot be expressed with the blocks and loops of WebAssembly"
+            ),
+        }
     }
+}
 
-    pub fn get_fi(&self, fid: FuncId) -> Option<usize> {
-        self.fis.get(&fid).copied()
+/// Compiling and running the programs of `test/` the backends are checked on.
+#[cfg(test)]
+mod testing {
+    use std::{
+        env, fs,
+        path::{Path, PathBuf},
+        process::{self, Command},
+    };
+
+    use wsk_vm::{Value, VM};
+
+    use super::Target;
+    use crate::{
+        compile::{compile_source, CompileOutput, CompileSwitch},
+        ir::opt::OptLevel,
+    };
+
+    /// The names and sources of the programs of `test/`.
+    pub fn test_programs() -> Vec<(String, String)> {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/../test");
+        let mut programs: Vec<_> = fs::read_dir(dir)
+            .unwrap()
+            .map(|v| v.unwrap().path())
+            .filter(|v| v.extension().is_some_and(|v| v == "wsk"))
+            .map(|v| {
+                let name = v.file_stem().unwrap().to_string_lossy().into_owned();
+                (name, fs::read_to_string(&v).unwrap())
+            })
+            .collect();
+        programs.sort();
+        programs
     }
 
-    pub fn clear_locals(&mut self) {
-        self.locals.clear();
-        self.local_cnts.clear();
-        self.active_local_cnt = 0;
+    pub fn compile(name: &str, source: &str, opt_level: OptLevel, target: Target) -> CompileOutput {
+        let switches = CompileSwitch {
+            do_parse_ast: true,
+            do_resolve_module: true,
+            opt_level,
+            do_codegen: true,
+            target,
+            ..Default::default()
+        };
+        let (sources, output) = compile_source(name, source, &switches);
+        let messages: Vec<_> = output
+            .diagnostics
+            .iter()
+            .map(|v| v.display(&sources).to_string())
+            .collect();
+        assert!(messages.is_empty(), "{}: {:?}", name, messages);
+        output
     }
 
-    pub fn get_local(&mut self, vid: VarId) -> usize {
-        if let Some(id) = self.locals.get(&vid) {
-            return *id;
+    /// The result of the program on the stack VM, as the native backends print it.
+    pub fn run_on_vm(name: &str, source: &str, opt_level: OptLevel) -> String {
+        let output = compile(name, source, opt_level, Target::WskVm);
+        let mut vm = VM::default();
+        vm.execute(output.program.unwrap()).unwrap();
+        match vm.stack() {
+            [Value::Int(v)] => v.to_string(),
+            stack => panic!("{}: the program left {:?}", name, stack),
         }
-        let id = self.active_local_cnt;
-        self.active_local_cnt += 1;
-        self.locals.insert(vid, id);
-        id
     }
 
-    pub fn push_bound(&mut self) {
-        self.local_cnts.push(self.active_local_cnt);
+    /// A directory of its own for the files of a test, removed when dropped.
+    pub struct OutDir(pub PathBuf);
+    impl OutDir {
+        pub fn new(test: &str) -> Self {
+            let dir = env::temp_dir().join(format!("whiskc-{}-{}", test, process::id()));
+            fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
     }
-
-    pub fn pop_bound(&mut self) {
-        let new_active = self.local_cnts.pop().expect("no bound to pop");
-        self.active_local_cnt = new_active;
+    impl Drop for OutDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
     }
-}
 
-#[derive(Debug)]
-pub enum CodegenError {
-    UnsupportedItem,
-    NoMainFunction,
-    UnsupportedMainFunctionSig,
-}
+    /// The C compiler from `$CC`, `cc` by default, `None` when it cannot be run.
+    pub fn c_compiler() -> Option<String> {
+        let cc = env::var("CC").unwrap_or_else(|_| "cc".to_owned());
+        let found = Command::new(&cc).arg("--version").output().is_ok();
+        if !found {
+            eprintln!("skipped: cannot run the C compiler {}", cc);
+        }
+        found.then_some(cc)
+    }
 
-trait Codegen {
-    fn codegen(&self, ctx: &mut Context) -> Result<(), CodegenError>;
+    /// Build the source next to it into an executable and run it, returning what it printed.
+    pub fn build_and_run(cc: &str, src: &Path, flags: &[&str]) -> String {
+        let exe = src.with_extension("");
+        let build = Command::new(cc)
+            .args(flags)
+            .arg("-o")
+            .arg(&exe)
+            .arg(src)
+            .output()
+            .unwrap();
+        assert!(
+            build.status.success(),
+            "{}: {}",
+            src.display(),
+            String::from_utf8_lossy(&build.stderr)
+        );
+        let run = Command::new(&exe).output().unwrap();
+        // the exit code is the low byte of the result, only a signal is a failure.
+        assert!(
+            run.status.code().is_some(),
+            "{}: {}",
+            exe.display(),
+            run.status
+        );
+        String::from_utf8_lossy(&run.stdout).trim().to_owned()
+    }
 }
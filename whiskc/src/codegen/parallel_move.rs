@@ -0,0 +1,103 @@
+//! Parallel assignments, as the arguments of a jump to the parameters of its target block, done
+//! one move at a time by the backends.
+
+/// Where an ordered move reads its value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum MoveSrc<S> {
+    Src(S),
+    /// The scratch location of the last [`Step::Save`].
+    Scratch,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Step<L, S> {
+    /// Copy the location to the scratch location, the moves still reading it read the copy.
+    Save(L),
+    Move(L, MoveSrc<S>),
+}
+
+/// Order the moves of a parallel assignment, which reads every source before writing any
+/// destination, so that doing them in sequence has the same effect. `reads` is the location a
+/// source reads, `None` for constants. A move is done once no other move still reads its
+/// destination, and a cycle of moves is broken by saving one destination to a scratch location.
+/// Moves to the location they read are dropped.
+pub(super) fn sequentialize<L: Copy + Eq, S: Copy>(
+    moves: impl IntoIterator<Item = (L, S)>,
+    reads: impl Fn(&S) -> Option<L>,
+) -> Vec<Step<L, S>> {
+    let mut pending: Vec<_> = moves
+        .into_iter()
+        .filter(|(dst, src)| reads(src) != Some(*dst))
+        .map(|(dst, src)| (dst, MoveSrc::Src(src)))
+        .collect();
+    let read = |src: &MoveSrc<S>| match src {
+        MoveSrc::Src(src) => reads(src),
+        MoveSrc::Scratch => None,
+    };
+    let mut steps = Vec::with_capacity(pending.len());
+    while !pending.is_empty() {
+        let free = pending
+            .iter()
+            .position(|(dst, _)| !pending.iter().any(|(_, src)| read(src) == Some(*dst)));
+        match free {
+            Some(i) => {
+                let (dst, src) = pending.remove(i);
+                steps.push(Step::Move(dst, src));
+            }
+            None => {
+                // every destination is read by another move, only one cycle is broken at a time
+                // so the scratch location is free again.
+                let dst = pending[0].0;
+                steps.push(Step::Save(dst));
+                for (_, src) in &mut pending {
+                    if read(src) == Some(dst) {
+                        *src = MoveSrc::Scratch;
+                    }
+                }
+            }
+        }
+    }
+    steps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Do the steps on `locs`, sources are locations or constants.
+    fn run(locs: &mut [i64], moves: &[(usize, Result<usize, i64>)]) {
+        let mut scratch = None;
+        for step in sequentialize(moves.iter().copied(), |src| src.ok()) {
+            match step {
+                Step::Save(loc) => scratch = Some(locs[loc]),
+                Step::Move(dst, MoveSrc::Src(Ok(src))) => locs[dst] = locs[src],
+                Step::Move(dst, MoveSrc::Src(Err(c))) => locs[dst] = c,
+                Step::Move(dst, MoveSrc::Scratch) => locs[dst] = scratch.unwrap(),
+            }
+        }
+    }
+
+    #[test]
+    fn moves_read_the_values_before_the_assignment() {
+        // a swap, a cycle of three and a constant written to a location read by another move.
+        let moves = [
+            (0, Ok(1)),
+            (1, Ok(0)),
+            (2, Ok(3)),
+            (3, Ok(4)),
+            (4, Ok(2)),
+            (5, Err(7)),
+            (6, Ok(5)),
+            (7, Ok(7)),
+        ];
+        let mut locs = [10, 11, 12, 13, 14, 15, 16, 17];
+        run(&mut locs, &moves);
+        assert_eq!(locs, [11, 10, 13, 14, 12, 7, 15, 17]);
+    }
+
+    #[test]
+    fn moves_to_their_own_source_are_dropped() {
+        let steps = sequentialize([(0, 0), (1, 0)], |src| Some(*src));
+        assert_eq!(steps, [Step::Move(1, MoveSrc::Src(0))]);
+    }
+}
@@ -0,0 +1,286 @@
+use wsk_vm::{
+    reg::{Function, FunctionBuilder, Inst, Reg},
+    Cmp,
+};
+
+use super::{
+    parallel_move::{sequentialize, MoveSrc, Step},
+    BlockLabels,
+};
+use crate::ir::{
+    self, BinaryOp, Block, BlockCall, CmpOp, Const, InstKind, Operand, Terminator, UnaryOp,
+};
+
+/// Every IR value has its own register, the parameters of the entry block being the argument
+/// registers. The registers past the values are scratch space for constants, the arguments of
+/// calls and the other operands that must be consecutive.
+pub(super) fn codegen_function(func: &ir::Function) -> Function {
+    let entry_params = &func.block(func.entry()).params;
+    let mut regs = vec![Reg(u32::MAX); func.values.len()];
+    for (i, param) in entry_params.iter().enumerate() {
+        regs[param.index()] = Reg(i as u32);
+    }
+    let mut next = entry_params.len() as u32;
+    for reg in regs.iter_mut().filter(|v| v.0 == u32::MAX) {
+        *reg = Reg(next);
+        next += 1;
+    }
+
+    let mut out = FunctionBuilder::new(func.sig.params.len(), func.sig.rets.len());
+    let labels = func.blocks.iter().map(|_| out.new_label()).collect();
+    let mut cg = FuncCodegen {
+        func,
+        out,
+        regs,
+        scratch: next,
+        labels,
+    };
+    cg.codegen();
+    cg.out
+        .build()
+        .expect("every block is placed and jumps only to blocks")
+}
+
+struct FuncCodegen<'a> {
+    func: &'a ir::Function,
+    out: FunctionBuilder,
+    /// The register of every value.
+    regs: Vec<Reg>,
+    /// The first scratch register.
+    scratch: u32,
+    labels: BlockLabels,
+}
+impl FuncCodegen<'_> {
+    fn codegen(&mut self) {
+        let func = self.func;
+        for block in func.block_ids() {
+            self.out.bind_label(self.labels[block.index()]);
+            let data = func.block(block);
+            for inst in &data.insts {
+                self.codegen_inst(&inst.kind, &inst.results);
+            }
+            self.codegen_terminator(block, &data.term);
+        }
+    }
+
+    fn scratch(&self, i: usize) -> Reg {
+        Reg(self.scratch + i as u32)
+    }
+
+    /// The register of the operand, constants are put into the `i`th scratch register.
+    fn operand(&mut self, op: Operand, i: usize) -> Reg {
+        match op {
+            Operand::Value(v) => self.regs[v.index()],
+            Operand::Const(c) => {
+                let reg = self.scratch(i);
+                self.out.push(Inst::Const(reg, const_value(c)));
+                reg
+            }
+        }
+    }
+
+    /// Copy the operands into consecutive scratch registers, returning the first.
+    fn scratch_operands(&mut self, ops: &[Operand]) -> Reg {
+        for (i, op) in ops.iter().enumerate() {
+            let dst = self.scratch(i);
+            self.out.push(match *op {
+                Operand::Value(v) => Inst::Move(dst, self.regs[v.index()]),
+                Operand::Const(c) => Inst::Const(dst, const_value(c)),
+            });
+        }
+        self.scratch(0)
+    }
+
+    fn codegen_inst(&mut self, kind: &InstKind, results: &[ir::Value]) {
+        let dst = |i: usize| self.regs[results[i].index()];
+        match kind {
+            InstKind::Unary(op, src) => {
+                let (dst, src) = (dst(0), self.operand(*src, 0));
+                self.out.push(match op {
+                    UnaryOp::Neg => Inst::Neg(dst, src),
+                    UnaryOp::Not => Inst::Not(dst, src),
+                });
+            }
+            InstKind::Binary(op, lhs, rhs) => {
+                let dst = dst(0);
+                let (lhs, rhs) = (self.operand(*lhs, 0), self.operand(*rhs, 1));
+                self.out.push(match op {
+                    BinaryOp::Add => Inst::Add(dst, lhs, rhs),
+                    BinaryOp::Sub => Inst::Sub(dst, lhs, rhs),
+                    BinaryOp::Mul => Inst::Mul(dst, lhs, rhs),
+                    BinaryOp::Div => Inst::Div(dst, lhs, rhs),
+                    BinaryOp::Mod => Inst::Mod(dst, lhs, rhs),
+                    BinaryOp::And => Inst::And(dst, lhs, rhs),
+                    BinaryOp::Or => Inst::Or(dst, lhs, rhs),
+                });
+            }
+            InstKind::Cmp(op, lhs, rhs) => {
+                let dst = dst(0);
+                let (lhs, rhs) = (self.operand(*lhs, 0), self.operand(*rhs, 1));
+                let (cmp, negate) = match op {
+                    CmpOp::Equal => (Cmp::Equal, false),
+                    CmpOp::NotEqual => (Cmp::Equal, true),
+                    CmpOp::Less => (Cmp::Less, false),
+                    CmpOp::LessEqual => (Cmp::Greater, true),
+                    CmpOp::Greater => (Cmp::Greater, false),
+                    CmpOp::GreaterEqual => (Cmp::Less, true),
+                };
+                self.out.push(Inst::Cmp(cmp, dst, lhs, rhs));
+                if negate {
+                    self.out.push(Inst::Not(dst, dst));
+                }
+            }
+            InstKind::Call(callee, args) => {
+                let first = self.scratch_operands(args);
+                self.out
+                    .reserve_regs(first.index() + args.len().max(results.len()));
+                self.out.push(Inst::Call(callee.index(), first));
+                for (i, v) in results.iter().enumerate() {
+                    self.out
+                        .push(Inst::Move(self.regs[v.index()], self.scratch(i)));
+                }
+            }
+            InstKind::MakeStruct(fields) => {
+                let dst = dst(0);
+                let first = self.scratch_operands(fields);
+                self.out.push(Inst::MakeStruct(dst, first, fields.len()));
+            }
+            InstKind::GetField(src, index) => {
+                let (dst, src) = (dst(0), self.operand(*src, 0));
+                self.out.push(Inst::GetField(dst, src, *index));
+            }
+        }
+    }
+
+    fn codegen_terminator(&mut self, block: Block, term: &Terminator) {
+        match term {
+            Terminator::Jump(call) => self.codegen_edge(block, call, true),
+            Terminator::Branch { cond, then, else_ } => {
+                let cond = self.operand(*cond, 0);
+                if else_.args.is_empty() {
+                    self.out.jmp_false(cond, self.labels[else_.block.index()]);
+                    self.codegen_edge(block, then, true);
+                } else if then.args.is_empty() {
+                    self.out.jmp_true(cond, self.labels[then.block.index()]);
+                    self.codegen_edge(block, else_, true);
+                } else {
+                    let to_else = self.out.new_label();
+                    self.out.jmp_false(cond, to_else);
+                    // the else edge follows, so the then edge cannot fall through.
+                    self.codegen_edge(block, then, false);
+                    self.out.bind_label(to_else);
+                    self.codegen_edge(block, else_, true);
+                }
+            }
+            Terminator::Return(values) => {
+                let first = match values[..] {
+                    [Operand::Value(v)] => self.regs[v.index()],
+                    _ => self.scratch_operands(values),
+                };
+                self.out.reserve_regs(first.index() + values.len());
+                self.out.push(Inst::Ret(first));
+            }
+            // the vm has no trap, stop the whole program if control ever gets here.
+            Terminator::Unreachable | Terminator::Missing => self.out.push(Inst::Halt),
+        }
+    }
+
+    /// Copy the arguments into the registers of the target's parameters, the first scratch
+    /// register holding a saved one, and jump unless falling through to the next block.
+    fn codegen_edge(&mut self, block: Block, call: &BlockCall, fall_through: bool) {
+        let params = &self.func.block(call.block).params;
+        let moves = params
+            .iter()
+            .zip(&call.args)
+            .map(|(dst, arg)| (self.regs[dst.index()], *arg));
+        let steps = sequentialize(moves, |arg| arg.as_value().map(|v| self.regs[v.index()]));
+        for step in steps {
+            self.out.push(match step {
+                Step::Save(reg) => Inst::Move(self.scratch(0), reg),
+                Step::Move(dst, MoveSrc::Src(Operand::Const(c))) => {
+                    Inst::Const(dst, const_value(c))
+                }
+                Step::Move(dst, MoveSrc::Src(Operand::Value(v))) => {
+                    Inst::Move(dst, self.regs[v.index()])
+                }
+                Step::Move(dst, MoveSrc::Scratch) => Inst::Move(dst, self.scratch(0)),
+            });
+        }
+        if !fall_through || call.block.0 != block.0 + 1 {
+            self.out.jmp(self.labels[call.block.index()]);
+        }
+    }
+}
+
+fn const_value(c: Const) -> wsk_vm::Value {
+    match c {
+        Const::Int(v) => v.into(),
+        Const::Bool(v) => v.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use wsk_vm::{reg, VM};
+
+    use crate::{
+        codegen::{
+            testing::{compile, test_programs, OutDir},
+            Target,
+        },
+        compile::{self, CompileSwitch},
+        ir::opt::OptLevel,
+    };
+
+    #[test]
+    fn register_vm_agrees_with_stack_vm() {
+        for (name, source) in test_programs() {
+            for opt_level in [OptLevel::O0, OptLevel::O2] {
+                let output = compile(&name, &source, opt_level, Target::WskVm);
+                let reg_output = compile(&name, &source, opt_level, Target::Reg);
+                let reg_program = reg_output.reg_program.unwrap();
+
+                let mut plain_vm = VM::default();
+                plain_vm.set_superinstructions(false);
+                plain_vm.execute(output.program.clone().unwrap()).unwrap();
+                let mut vm = VM::default();
+                vm.execute(output.program.unwrap()).unwrap();
+                let mut reg_vm = reg::VM::default();
+                reg_vm.execute(&reg_program).unwrap();
+
+                let context = format!("{} at {:?}", name, opt_level);
+                assert_eq!(plain_vm.stack(), vm.stack(), "{}", context);
+                assert_eq!(vm.stack(), reg_vm.results(), "{}", context);
+            }
+        }
+    }
+
+    #[test]
+    fn written_programs_run_on_the_register_vm() {
+        let dir = OutDir::new("reg");
+        for (name, source) in test_programs() {
+            let src = dir.0.join(format!("{}.wsk", name));
+            fs::write(&src, &source).unwrap();
+            let switches = CompileSwitch {
+                do_parse_ast: true,
+                do_resolve_module: true,
+                do_codegen: true,
+                target: Target::Reg,
+                ..Default::default()
+            };
+            assert!(compile::compile(src.clone(), switches), "{}", name);
+
+            let bin = fs::read(src.with_extension("wcr")).unwrap();
+            let reg_program = reg::Program::from_bytes(&bin).unwrap();
+            let mut reg_vm = reg::VM::default();
+            reg_vm.execute(&reg_program).unwrap();
+
+            let output = compile(&name, &source, OptLevel::O0, Target::WskVm);
+            let mut vm = VM::default();
+            vm.execute(output.program.unwrap()).unwrap();
+            assert_eq!(vm.stack(), reg_vm.results(), "{}", name);
+        }
+    }
+}
@@ -0,0 +1,628 @@
+//! WebAssembly modules in the binary format.
+//!
+//! `int` is `i64`, `bool` is `i32` and references are `i32` addresses in the memory of the
+//! module, where objects get 8 bytes for every field from a bump allocator and are never freed.
+//! Extern functions are imported from the `env` module, `pub` functions and `main` are exported.
+//! Every value lives in a local, the stack of the machine only holds the operands of the
+//! instruction being run.
+
+use crate::ir::{self, BinaryOp, CmpOp, Const, FuncRef, InstKind, Operand, Ty, UnaryOp, Value};
+
+use super::CodegenError;
+
+use structure::Stmt;
+
+mod structure;
+mod validate;
+
+pub use validate::{validate, ValidateError};
+
+/// The bytes of the binary format, shared by the encoder and the validator.
+mod op {
+    pub const MAGIC: &[u8] = b"\0asm";
+    pub const VERSION: &[u8] = &[1, 0, 0, 0];
+
+    pub const SECTION_CUSTOM: u8 = 0;
+    pub const SECTION_TYPE: u8 = 1;
+    pub const SECTION_IMPORT: u8 = 2;
+    pub const SECTION_FUNCTION: u8 = 3;
+    pub const SECTION_MEMORY: u8 = 5;
+    pub const SECTION_GLOBAL: u8 = 6;
+    pub const SECTION_EXPORT: u8 = 7;
+    pub const SECTION_CODE: u8 = 10;
+
+    pub const FUNC_TYPE: u8 = 0x60;
+    pub const I32: u8 = 0x7f;
+    pub const I64: u8 = 0x7e;
+    pub const BLOCK_TYPE_EMPTY: u8 = 0x40;
+    pub const EXTERN_FUNC: u8 = 0x00;
+    pub const EXTERN_MEMORY: u8 = 0x02;
+    pub const EXTERN_GLOBAL: u8 = 0x03;
+    pub const LIMITS_MIN: u8 = 0x00;
+    pub const LIMITS_MIN_MAX: u8 = 0x01;
+    pub const MUT_VAR: u8 = 0x01;
+
+    pub const UNREACHABLE: u8 = 0x00;
+    pub const NOP: u8 = 0x01;
+    pub const BLOCK: u8 = 0x02;
+    pub const LOOP: u8 = 0x03;
+    pub const IF: u8 = 0x04;
+    pub const ELSE: u8 = 0x05;
+    pub const END: u8 = 0x0b;
+    pub const BR: u8 = 0x0c;
+    pub const BR_IF: u8 = 0x0d;
+    pub const RETURN: u8 = 0x0f;
+    pub const CALL: u8 = 0x10;
+    pub const DROP: u8 = 0x1a;
+    pub const SELECT: u8 = 0x1b;
+    pub const LOCAL_GET: u8 = 0x20;
+    pub const LOCAL_SET: u8 = 0x21;
+    pub const LOCAL_TEE: u8 = 0x22;
+    pub const GLOBAL_GET: u8 = 0x23;
+    pub const GLOBAL_SET: u8 = 0x24;
+    pub const I32_LOAD: u8 = 0x28;
+    pub const I64_LOAD: u8 = 0x29;
+    pub const I32_STORE: u8 = 0x36;
+    pub const I64_STORE: u8 = 0x37;
+    pub const MEMORY_SIZE: u8 = 0x3f;
+    pub const MEMORY_GROW: u8 = 0x40;
+    pub const I32_CONST: u8 = 0x41;
+    pub const I64_CONST: u8 = 0x42;
+
+    pub const I32_EQZ: u8 = 0x45;
+    pub const I32_EQ: u8 = 0x46;
+    pub const I32_NE: u8 = 0x47;
+    pub const I32_LE_U: u8 = 0x4d;
+    pub const I32_GE_U: u8 = 0x4f;
+    pub const I64_EQZ: u8 = 0x50;
+    pub const I64_EQ: u8 = 0x51;
+    pub const I64_NE: u8 = 0x52;
+    pub const I64_LT_S: u8 = 0x53;
+    pub const I64_GT_S: u8 = 0x55;
+    pub const I64_LE_S: u8 = 0x57;
+    pub const I64_GE_S: u8 = 0x59;
+    pub const I64_GE_U: u8 = 0x5a;
+    pub const I32_CLZ: u8 = 0x67;
+    pub const I32_POPCNT: u8 = 0x69;
+    pub const I32_ADD: u8 = 0x6a;
+    pub const I32_SUB: u8 = 0x6b;
+    pub const I32_AND: u8 = 0x71;
+    pub const I32_OR: u8 = 0x72;
+    pub const I32_SHL: u8 = 0x74;
+    pub const I32_SHR_U: u8 = 0x76;
+    pub const I32_ROTR: u8 = 0x78;
+    pub const I64_CLZ: u8 = 0x79;
+    pub const I64_POPCNT: u8 = 0x7b;
+    pub const I64_ADD: u8 = 0x7c;
+    pub const I64_SUB: u8 = 0x7d;
+    pub const I64_MUL: u8 = 0x7e;
+    pub const I64_DIV_S: u8 = 0x7f;
+    pub const I64_REM_S: u8 = 0x81;
+    pub const I64_AND: u8 = 0x83;
+    pub const I64_OR: u8 = 0x84;
+    pub const I64_ROTR: u8 = 0x8a;
+    pub const I32_WRAP_I64: u8 = 0xa7;
+    pub const I64_EXTEND_I32_S: u8 = 0xac;
+    pub const I64_EXTEND_I32_U: u8 = 0xad;
+}
+
+/// Memory grows by pages of `1 << PAGE_BITS` bytes.
+const PAGE_BITS: u8 = 16;
+/// The global holding the address the next object is allocated at.
+const HEAP_GLOBAL: u8 = 0;
+
+pub(super) fn codegen_module(module: &ir::Module, main: FuncRef) -> Result<Vec<u8>, CodegenError> {
+    let mut types = TypeSection::default();
+    // imported functions come first in the index space.
+    let mut indices = vec![0; module.funcs.len()];
+    let mut imports = vec![];
+    let mut funcs = vec![];
+    for (i, func) in module.funcs.iter().enumerate() {
+        if func.is_extern {
+            indices[i] = imports.len() as u32;
+            imports.push(func);
+        }
+    }
+    for (i, func) in module.funcs.iter().enumerate() {
+        if !func.is_extern {
+            indices[i] = (imports.len() + funcs.len()) as u32;
+            funcs.push(func);
+        }
+    }
+    let alloc_index = (imports.len() + funcs.len()) as u32;
+
+    let mut import_section = vec![];
+    write_u32(&mut import_section, imports.len() as u32);
+    for func in &imports {
+        write_name(&mut import_section, "env");
+        write_name(&mut import_section, &func.name);
+        import_section.push(op::EXTERN_FUNC);
+        write_u32(&mut import_section, types.index_of(&func.sig));
+    }
+
+    let mut function_section = vec![];
+    write_u32(&mut function_section, funcs.len() as u32 + 1);
+    let mut code_section = vec![];
+    write_u32(&mut code_section, funcs.len() as u32 + 1);
+    for func in &funcs {
+        write_u32(&mut function_section, types.index_of(&func.sig));
+        let body = FuncCodegen::new(module, func, &indices, alloc_index).codegen()?;
+        write_u32(&mut code_section, body.len() as u32);
+        code_section.extend(body);
+    }
+    let alloc_sig = ir::Signature {
+        params: vec![Ty::Ref],
+        rets: vec![Ty::Ref],
+    };
+    write_u32(&mut function_section, types.index_of(&alloc_sig));
+    let body = alloc_body();
+    write_u32(&mut code_section, body.len() as u32);
+    code_section.extend(body);
+
+    // one page to start with, the allocator grows the memory when it is full.
+    let memory_section = vec![1, op::LIMITS_MIN, 1];
+
+    let mut global_section = vec![1, op::I32, op::MUT_VAR, op::I32_CONST];
+    write_i64(&mut global_section, 0);
+    global_section.push(op::END);
+
+    let exports: Vec<_> = module
+        .funcs
+        .iter()
+        .enumerate()
+        .filter(|(i, v)| !v.is_extern && (v.is_pub || *i == main.index()))
+        .collect();
+    let mut export_section = vec![];
+    write_u32(&mut export_section, exports.len() as u32);
+    for (i, func) in exports {
+        write_name(&mut export_section, &func.name);
+        export_section.push(op::EXTERN_FUNC);
+        write_u32(&mut export_section, indices[i]);
+    }
+
+    let mut out = vec![];
+    out.extend(op::MAGIC);
+    out.extend(op::VERSION);
+    write_section(&mut out, op::SECTION_TYPE, &types.bytes());
+    write_section(&mut out, op::SECTION_IMPORT, &import_section);
+    write_section(&mut out, op::SECTION_FUNCTION, &function_section);
+    write_section(&mut out, op::SECTION_MEMORY, &memory_section);
+    write_section(&mut out, op::SECTION_GLOBAL, &global_section);
+    write_section(&mut out, op::SECTION_EXPORT, &export_section);
+    write_section(&mut out, op::SECTION_CODE, &code_section);
+    Ok(out)
+}
+
+fn val_type(ty: Ty) -> u8 {
+    match ty {
+        Ty::Int => op::I64,
+        Ty::Bool | Ty::Ref => op::I32,
+    }
+}
+
+/// The function types of the module, each written once.
+#[derive(Default)]
+struct TypeSection {
+    types: Vec<ir::Signature>,
+}
+impl TypeSection {
+    fn index_of(&mut self, sig: &ir::Signature) -> u32 {
+        let key = |sig: &ir::Signature| {
+            let params: Vec<_> = sig.params.iter().map(|v| val_type(*v)).collect();
+            let rets: Vec<_> = sig.rets.iter().map(|v| val_type(*v)).collect();
+            (params, rets)
+        };
+        let index = match self.types.iter().position(|v| key(v) == key(sig)) {
+            Some(index) => index,
+            None => {
+                self.types.push(sig.clone());
+                self.types.len() - 1
+            }
+        };
+        index as u32
+    }
+
+    fn bytes(&self) -> Vec<u8> {
+        let mut out = vec![];
+        write_u32(&mut out, self.types.len() as u32);
+        for sig in &self.types {
+            out.push(op::FUNC_TYPE);
+            for tys in [&sig.params, &sig.rets] {
+                write_u32(&mut out, tys.len() as u32);
+                out.extend(tys.iter().map(|v| val_type(*v)));
+            }
+        }
+        out
+    }
+}
+
+fn write_u32(out: &mut Vec<u8>, mut v: u32) {
+    loop {
+        let byte = (v & 0x7f) as u8;
+        v >>= 7;
+        if v == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn write_i64(out: &mut Vec<u8>, mut v: i64) {
+    loop {
+        let byte = (v & 0x7f) as u8;
+        v >>= 7;
+        // the sign bit of the last byte extends to the rest of the value.
+        if (v == 0 && byte & 0x40 == 0) || (v == -1 && byte & 0x40 != 0) {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn write_name(out: &mut Vec<u8>, name: &str) {
+    write_u32(out, name.len() as u32);
+    out.extend(name.as_bytes());
+}
+
+fn write_section(out: &mut Vec<u8>, id: u8, contents: &[u8]) {
+    out.push(id);
+    write_u32(out, contents.len() as u32);
+    out.extend(contents);
+}
+
+/// The body of the function allocating the number of bytes of its parameter, growing the memory
+/// when the object does not fit in it. Running out of memory traps.
+fn alloc_body() -> Vec<u8> {
+    const SIZE: u8 = 0;
+    const ADDR: u8 = 1;
+    // one local, the address of the object.
+    let mut code = vec![1, 1, op::I32];
+    // addr = heap; heap = addr + size
+    code.extend([op::GLOBAL_GET, HEAP_GLOBAL, op::LOCAL_TEE, ADDR]);
+    code.extend([op::LOCAL_GET, SIZE, op::I32_ADD]);
+    code.extend([op::GLOBAL_SET, HEAP_GLOBAL]);
+    // if heap < addr { unreachable }, the object goes past the end of the address space.
+    code.extend([op::GLOBAL_GET, HEAP_GLOBAL, op::LOCAL_GET, ADDR]);
+    code.extend([op::I32_GE_U, op::I32_EQZ]);
+    code.extend([op::IF, op::BLOCK_TYPE_EMPTY, op::UNREACHABLE, op::END]);
+    code.extend([op::BLOCK, op::BLOCK_TYPE_EMPTY]);
+    // br_if heap <= memory.size << PAGE_BITS
+    code.extend([op::GLOBAL_GET, HEAP_GLOBAL, op::MEMORY_SIZE, 0]);
+    code.extend([op::I32_CONST, PAGE_BITS, op::I32_SHL]);
+    code.extend([op::I32_LE_U, op::BR_IF, 0]);
+    // br_if memory.grow(((heap + page size - 1) >> PAGE_BITS) - memory.size) != -1
+    code.extend([op::GLOBAL_GET, HEAP_GLOBAL, op::I32_CONST]);
+    write_i64(&mut code, (1 << PAGE_BITS) - 1);
+    code.extend([op::I32_ADD, op::I32_CONST, PAGE_BITS, op::I32_SHR_U]);
+    code.extend([op::MEMORY_SIZE, 0, op::I32_SUB, op::MEMORY_GROW, 0]);
+    code.extend([op::I32_CONST, 0x7f, op::I32_NE, op::BR_IF, 0]);
+    code.extend([op::UNREACHABLE, op::END]);
+    code.extend([op::LOCAL_GET, ADDR, op::END]);
+    code
+}
+
+struct FuncCodegen<'a> {
+    module: &'a ir::Module,
+    func: &'a ir::Function,
+    /// The index of every function of the module.
+    indices: &'a [u32],
+    alloc_index: u32,
+    /// The local of every value, `None` for unused values.
+    locals: Vec<Option<u32>>,
+    /// The types of the locals after the parameters.
+    local_tys: Vec<u8>,
+    /// An `i32` local holding the object being made.
+    scratch: u32,
+    code: Vec<u8>,
+}
+impl<'a> FuncCodegen<'a> {
+    fn new(
+        module: &'a ir::Module,
+        func: &'a ir::Function,
+        indices: &'a [u32],
+        alloc_index: u32,
+    ) -> Self {
+        let mut used = vec![false; func.values.len()];
+        for block in &func.blocks {
+            let insts = block.insts.iter().flat_map(|v| v.operands());
+            for op in insts.chain(block.term.operands()) {
+                if let Operand::Value(v) = op {
+                    used[v.index()] = true;
+                }
+            }
+        }
+
+        // the parameters are the first locals, the others are grouped by type.
+        let params = func.block(func.entry()).params.as_slice();
+        let mut locals = vec![None; func.values.len()];
+        for (i, v) in params.iter().enumerate() {
+            locals[v.index()] = Some(i as u32);
+        }
+        let mut local_tys = vec![];
+        for ty in [op::I64, op::I32] {
+            for (i, v) in func.values.iter().enumerate() {
+                if used[i] && locals[i].is_none() && val_type(*v) == ty {
+                    locals[i] = Some((params.len() + local_tys.len()) as u32);
+                    local_tys.push(ty);
+                }
+            }
+        }
+        let scratch = (params.len() + local_tys.len()) as u32;
+        local_tys.push(op::I32);
+
+        Self {
+            module,
+            func,
+            indices,
+            alloc_index,
+            locals,
+            local_tys,
+            scratch,
+            code: vec![],
+        }
+    }
+
+    fn emit(&mut self, bytes: &[u8]) {
+        self.code.extend(bytes);
+    }
+
+    fn emit_u32(&mut self, v: u32) {
+        write_u32(&mut self.code, v);
+    }
+
+    fn codegen(mut self) -> Result<Vec<u8>, CodegenError> {
+        let stmts = structure::structure(self.func)?;
+        self.codegen_stmts(&stmts);
+        // control never leaves the last construct, but its end leaves nothing on the stack.
+        if !matches!(
+            stmts.last(),
+            Some(Stmt::Br(_) | Stmt::Return(_) | Stmt::Unreachable)
+        ) {
+            self.emit(&[op::UNREACHABLE]);
+        }
+        self.emit(&[op::END]);
+
+        let mut body = vec![];
+        // runs of locals of the same type.
+        let mut runs: Vec<(u32, u8)> = vec![];
+        for ty in &self.local_tys {
+            match runs.last_mut() {
+                Some((cnt, last)) if last == ty => *cnt += 1,
+                _ => runs.push((1, *ty)),
+            }
+        }
+        write_u32(&mut body, runs.len() as u32);
+        for (cnt, ty) in runs {
+            write_u32(&mut body, cnt);
+            body.push(ty);
+        }
+        body.extend(self.code);
+        Ok(body)
+    }
+
+    fn codegen_stmts(&mut self, stmts: &[Stmt]) {
+        for stmt in stmts {
+            match stmt {
+                Stmt::Insts(block) => {
+                    for inst in &self.func.block(*block).insts {
+                        self.codegen_inst(&inst.kind, &inst.results);
+                    }
+                }
+                Stmt::Block(body) | Stmt::Loop(body) => {
+                    let kind = if matches!(stmt, Stmt::Block(_)) {
+                        op::BLOCK
+                    } else {
+                        op::LOOP
+                    };
+                    self.emit(&[kind, op::BLOCK_TYPE_EMPTY]);
+                    self.codegen_stmts(body);
+                    self.emit(&[op::END]);
+                }
+                Stmt::If(cond, then, else_) => {
+                    self.operand(*cond);
+                    self.emit(&[op::IF, op::BLOCK_TYPE_EMPTY]);
+                    self.codegen_stmts(then);
+                    if !else_.is_empty() {
+                        self.emit(&[op::ELSE]);
+                        self.codegen_stmts(else_);
+                    }
+                    self.emit(&[op::END]);
+                }
+                Stmt::Args(call) => {
+                    // the arguments are all read before the parameters are set.
+                    let params = &self.func.block(call.block).params;
+                    let used: Vec<_> = params
+                        .iter()
+                        .zip(&call.args)
+                        .filter(|(param, arg)| {
+                            self.locals[param.index()].is_some() && arg.as_value() != Some(**param)
+                        })
+                        .collect();
+                    for (_, arg) in &used {
+                        self.operand(**arg);
+                    }
+                    for (param, _) in used.iter().rev() {
+                        self.set(**param);
+                    }
+                }
+                Stmt::Br(depth) => {
+                    self.emit(&[op::BR]);
+                    self.emit_u32(*depth);
+                }
+                Stmt::Return(values) => {
+                    for v in values.iter() {
+                        self.operand(*v);
+                    }
+                    self.emit(&[op::RETURN]);
+                }
+                Stmt::Unreachable => self.emit(&[op::UNREACHABLE]),
+            }
+        }
+    }
+
+    fn operand(&mut self, src: Operand) {
+        match src {
+            Operand::Value(v) => {
+                self.emit(&[op::LOCAL_GET]);
+                self.emit_u32(self.locals[v.index()].unwrap());
+            }
+            Operand::Const(Const::Int(v)) => {
+                self.emit(&[op::I64_CONST]);
+                write_i64(&mut self.code, v);
+            }
+            Operand::Const(Const::Bool(v)) => self.emit(&[op::I32_CONST, v as u8]),
+        }
+    }
+
+    /// Pop the value on top of the stack into the local of `v`, dropping it when unused.
+    fn set(&mut self, v: Value) {
+        match self.locals[v.index()] {
+            Some(local) => {
+                self.emit(&[op::LOCAL_SET]);
+                self.emit_u32(local);
+            }
+            None => self.emit(&[op::DROP]),
+        }
+    }
+
+    fn codegen_inst(&mut self, kind: &InstKind, results: &[Value]) {
+        let is_call = matches!(kind, InstKind::Call(..));
+        if !is_call && results.iter().all(|v| self.locals[v.index()].is_none()) {
+            return;
+        }
+        match kind {
+            InstKind::Unary(UnaryOp::Neg, src) => {
+                self.emit(&[op::I64_CONST, 0]);
+                self.operand(*src);
+                self.emit(&[op::I64_SUB]);
+            }
+            InstKind::Unary(UnaryOp::Not, src) => {
+                self.operand(*src);
+                self.emit(&[op::I32_EQZ]);
+            }
+            InstKind::Binary(bin_op, lhs, rhs) => {
+                let int = self.func.operand_ty(*lhs) == Ty::Int;
+                if *bin_op == BinaryOp::Mod
+                    && !matches!(rhs, Operand::Const(Const::Int(v)) if *v != 0 && *v != -1)
+                {
+                    // the remainder of the overflowing division is 0 rather than a trap, the
+                    // division traps for both.
+                    self.operand(*lhs);
+                    self.operand(*rhs);
+                    self.emit(&[op::I64_DIV_S, op::DROP]);
+                }
+                self.operand(*lhs);
+                self.operand(*rhs);
+                self.emit(&[match (bin_op, int) {
+                    (BinaryOp::Add, _) => op::I64_ADD,
+                    (BinaryOp::Sub, _) => op::I64_SUB,
+                    (BinaryOp::Mul, _) => op::I64_MUL,
+                    (BinaryOp::Div, _) => op::I64_DIV_S,
+                    (BinaryOp::Mod, _) => op::I64_REM_S,
+                    (BinaryOp::And, true) => op::I64_AND,
+                    (BinaryOp::Or, true) => op::I64_OR,
+                    (BinaryOp::And, false) => op::I32_AND,
+                    (BinaryOp::Or, false) => op::I32_OR,
+                }]);
+            }
+            InstKind::Cmp(cmp_op, lhs, rhs) => {
+                let int = self.func.operand_ty(*lhs) == Ty::Int;
+                self.operand(*lhs);
+                self.operand(*rhs);
+                self.emit(&[match (cmp_op, int) {
+                    (CmpOp::Equal, true) => op::I64_EQ,
+                    (CmpOp::NotEqual, true) => op::I64_NE,
+                    (CmpOp::Equal, false) => op::I32_EQ,
+                    (CmpOp::NotEqual, false) => op::I32_NE,
+                    (CmpOp::Less, _) => op::I64_LT_S,
+                    (CmpOp::LessEqual, _) => op::I64_LE_S,
+                    (CmpOp::Greater, _) => op::I64_GT_S,
+                    (CmpOp::GreaterEqual, _) => op::I64_GE_S,
+                }]);
+            }
+            InstKind::Call(callee, args) => {
+                for arg in args {
+                    self.operand(*arg);
+                }
+                self.emit(&[op::CALL]);
+                self.emit_u32(self.indices[callee.index()]);
+                debug_assert_eq!(self.module.get(*callee).sig.rets.len(), results.len());
+                for v in results.iter().rev() {
+                    self.set(*v);
+                }
+                return;
+            }
+            InstKind::MakeStruct(fields) => {
+                self.emit(&[op::I32_CONST]);
+                write_i64(&mut self.code, 8 * fields.len() as i64);
+                self.emit(&[op::CALL]);
+                self.emit_u32(self.alloc_index);
+                self.emit(&[op::LOCAL_SET]);
+                self.emit_u32(self.scratch);
+                for (i, field) in fields.iter().enumerate() {
+                    self.emit(&[op::LOCAL_GET]);
+                    self.emit_u32(self.scratch);
+                    self.operand(*field);
+                    self.mem_op(self.func.operand_ty(*field), true, i);
+                }
+                self.emit(&[op::LOCAL_GET]);
+                self.emit_u32(self.scratch);
+            }
+            InstKind::GetField(src, index) => {
+                self.operand(*src);
+                self.mem_op(self.func.value_ty(results[0]), false, *index);
+            }
+        }
+        self.set(results[0]);
+    }
+
+    /// Load or store the field of the type at the index of the object.
+    fn mem_op(&mut self, ty: Ty, store: bool, index: usize) {
+        // the alignment is the log2 of the size, every field is aligned to 8 bytes.
+        let (opcode, align) = match (ty, store) {
+            (Ty::Int, false) => (op::I64_LOAD, 3),
+            (Ty::Int, true) => (op::I64_STORE, 3),
+            (_, false) => (op::I32_LOAD, 2),
+            (_, true) => (op::I32_STORE, 2),
+        };
+        self.emit(&[opcode, align]);
+        self.emit_u32(8 * index as u32);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::validate;
+    use crate::{
+        codegen::{
+            testing::{compile, test_programs},
+            Target,
+        },
+        ir::opt::OptLevel,
+    };
+
+    #[test]
+    fn test_programs_are_valid() {
+        for (name, source) in test_programs() {
+            for opt_level in [OptLevel::O0, OptLevel::O2] {
+                let wasm = compile(&name, &source, opt_level, Target::Wasm)
+                    .wasm
+                    .unwrap();
+                if let Err(e) = validate(&wasm) {
+                    panic!("{} at {:?}: {}", name, opt_level, e);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn truncated_module_is_invalid() {
+        let (name, source) = &test_programs()[0];
+        let wasm = compile(name, source, OptLevel::O0, Target::Wasm)
+            .wasm
+            .unwrap();
+        assert!(validate(&wasm[..wasm.len() - 1]).is_err());
+    }
+}
@@ -0,0 +1,156 @@
+//! Structured control flow from the blocks of a function, after Ramsey's "Beyond Relooper".
+//!
+//! The blocks of the IR come from `if` and `loop` expressions, so the control flow graph is
+//! reducible and the dominator tree gives the nesting back. A block dominating the targets of
+//! edges going back to it is wrapped in a `loop`. A block with several forward edges into it
+//! follows a `block` holding the code of its immediate dominator, whose end the edges branch to.
+//! The other blocks have a single predecessor and are placed where it branches to them.
+
+use crate::{
+    codegen::CodegenError,
+    ir::{self, dominates, Block, BlockCall, Operand, Terminator},
+};
+
+#[derive(Debug)]
+pub(super) enum Stmt<'a> {
+    /// The instructions of the block, its terminator excepted.
+    Insts(Block),
+    Block(Vec<Stmt<'a>>),
+    Loop(Vec<Stmt<'a>>),
+    If(Operand, Vec<Stmt<'a>>, Vec<Stmt<'a>>),
+    /// Assign the arguments to the parameters of the target.
+    Args(&'a BlockCall),
+    /// Branch to the label of the enclosing construct at the depth, 0 being the innermost.
+    Br(u32),
+    Return(&'a [Operand]),
+    Unreachable,
+}
+
+/// The constructs around the code being generated, each of them is a label for `br`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Frame {
+    IfThenElse,
+    /// Branching to the label continues the loop of the block.
+    LoopHeadedBy(Block),
+    /// Branching to the label goes to the block placed right after the construct.
+    BlockFollowedBy(Block),
+}
+
+pub(super) fn structure(func: &ir::Function) -> Result<Vec<Stmt<'_>>, CodegenError> {
+    let rpo = func.reverse_postorder();
+    let mut order = vec![usize::MAX; func.blocks.len()];
+    for (i, block) in rpo.iter().enumerate() {
+        order[block.index()] = i;
+    }
+    let idoms = func.dominators();
+
+    let mut children = vec![vec![]; func.blocks.len()];
+    for block in rpo.iter().skip(1) {
+        children[idoms[block.index()].unwrap().index()].push(*block);
+    }
+    let mut is_loop_header = vec![false; func.blocks.len()];
+    let mut forward_preds = vec![0; func.blocks.len()];
+    for pred in &rpo {
+        for succ in func.block(*pred).term.successors() {
+            if order[succ.index()] > order[pred.index()] {
+                forward_preds[succ.index()] += 1;
+            } else if dominates(&idoms, succ, *pred) {
+                is_loop_header[succ.index()] = true;
+            } else {
+                // a loop entered from the side, no `if` or `loop` makes one.
+                return Err(CodegenError::IrreducibleControlFlow);
+            }
+        }
+    }
+
+    let mut structurer = Structurer {
+        func,
+        order,
+        children,
+        is_loop_header,
+        is_merge: forward_preds.into_iter().map(|v| v > 1).collect(),
+        context: vec![],
+    };
+    Ok(match rpo.first() {
+        Some(entry) => structurer.do_tree(*entry),
+        None => vec![],
+    })
+}
+
+struct Structurer<'a> {
+    func: &'a ir::Function,
+    /// Position of every block in the reverse postorder, `usize::MAX` for unreachable blocks.
+    order: Vec<usize>,
+    /// Children of every block in the dominator tree, in reverse postorder.
+    children: Vec<Vec<Block>>,
+    is_loop_header: Vec<bool>,
+    /// Whether several forward edges go into the block.
+    is_merge: Vec<bool>,
+    context: Vec<Frame>,
+}
+impl<'a> Structurer<'a> {
+    /// The code of the block and of the blocks it dominates.
+    fn do_tree(&mut self, block: Block) -> Vec<Stmt<'a>> {
+        let merges: Vec<_> = self.children[block.index()]
+            .iter()
+            .copied()
+            .filter(|v| self.is_merge[v.index()])
+            .collect();
+        if self.is_loop_header[block.index()] {
+            self.context.push(Frame::LoopHeadedBy(block));
+            let body = self.node_within(block, &merges);
+            self.context.pop();
+            vec![Stmt::Loop(body)]
+        } else {
+            self.node_within(block, &merges)
+        }
+    }
+
+    /// The code of the block followed by the merge blocks it dominates, the last one being the
+    /// outermost as the earlier ones may branch to it.
+    fn node_within(&mut self, block: Block, merges: &[Block]) -> Vec<Stmt<'a>> {
+        if let Some((last, merges)) = merges.split_last() {
+            self.context.push(Frame::BlockFollowedBy(*last));
+            let inner = self.node_within(block, merges);
+            self.context.pop();
+            let mut stmts = vec![Stmt::Block(inner)];
+            stmts.extend(self.do_tree(*last));
+            return stmts;
+        }
+
+        let mut stmts = vec![Stmt::Insts(block)];
+        match &self.func.block(block).term {
+            Terminator::Jump(call) => stmts.extend(self.do_branch(block, call)),
+            Terminator::Branch { cond, then, else_ } => {
+                self.context.push(Frame::IfThenElse);
+                let then = self.do_branch(block, then);
+                let else_ = self.do_branch(block, else_);
+                self.context.pop();
+                stmts.push(Stmt::If(*cond, then, else_));
+            }
+            Terminator::Return(values) => stmts.push(Stmt::Return(values)),
+            Terminator::Unreachable | Terminator::Missing => stmts.push(Stmt::Unreachable),
+        }
+        stmts
+    }
+
+    fn do_branch(&mut self, from: Block, call: &'a BlockCall) -> Vec<Stmt<'a>> {
+        let mut stmts = vec![];
+        if !call.args.is_empty() {
+            stmts.push(Stmt::Args(call));
+        }
+        let to = call.block;
+        let frame = if self.order[to.index()] <= self.order[from.index()] {
+            Frame::LoopHeadedBy(to)
+        } else if self.is_merge[to.index()] {
+            Frame::BlockFollowedBy(to)
+        } else {
+            // the only edge into the block, it goes right here.
+            stmts.extend(self.do_tree(to));
+            return stmts;
+        };
+        let depth = self.context.iter().rev().position(|v| *v == frame).unwrap();
+        stmts.push(Stmt::Br(depth as u32));
+        stmts
+    }
+}
@@ -0,0 +1,692 @@
+//! A decoder checking modules against the validation rules of the specification, without running
+//! them.
+//!
+//! It knows the integer subset of WebAssembly 1.0, with functions returning several values:
+//! the sections the backend writes and the instructions on `i32` and `i64`. Tables, floats and
+//! the rest are reported as unsupported. The function bodies are type checked with the algorithm
+//! of the appendix of the specification.
+
+use core::fmt;
+use std::collections::HashSet;
+
+use super::op;
+
+/// Why a module is invalid, at the offset of the byte where it was found.
+#[derive(Debug, Clone)]
+pub struct ValidateError {
+    pub offset: usize,
+    pub message: String,
+}
+impl fmt::Display for ValidateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "offset {:#x}: {}", self.offset, self.message)
+    }
+}
+
+type Result<T> = std::result::Result<T, ValidateError>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ValType {
+    I32,
+    I64,
+}
+impl fmt::Display for ValType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValType::I32 => write!(f, "i32"),
+            ValType::I64 => write!(f, "i64"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct FuncType {
+    params: Vec<ValType>,
+    results: Vec<ValType>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Global {
+    ty: ValType,
+    mutable: bool,
+}
+
+/// What the sections declared so far, the function bodies are checked against it.
+#[derive(Debug, Default)]
+struct Module {
+    types: Vec<FuncType>,
+    /// The type index of every function, the imported ones first.
+    funcs: Vec<u32>,
+    imported_funcs: usize,
+    memories: u32,
+    globals: Vec<Global>,
+}
+
+/// Check that the bytes are a valid module.
+pub fn validate(bytes: &[u8]) -> Result<()> {
+    let mut r = Reader { bytes, pos: 0 };
+    if r.take(4)? != op::MAGIC {
+        return Err(r.error_at(0, "not a WebAssembly module"));
+    }
+    if r.take(4)? != op::VERSION {
+        return Err(r.error_at(4, "unsupported version"));
+    }
+
+    let mut module = Module::default();
+    let mut last_id = 0;
+    let mut bodies = None;
+    while !r.is_empty() {
+        let id = r.u8()?;
+        let len = r.u32()? as usize;
+        let start = r.pos;
+        r.take(len)?;
+        let mut s = Reader {
+            bytes: &bytes[..r.pos],
+            pos: start,
+        };
+        if id != op::SECTION_CUSTOM {
+            if id <= last_id {
+                return Err(s.error_at(start - 1, format!("section {} out of order", id)));
+            }
+            last_id = id;
+        }
+        match id {
+            op::SECTION_CUSTOM => {
+                s.name()?;
+                s.pos = s.bytes.len();
+            }
+            op::SECTION_TYPE => type_section(&mut s, &mut module)?,
+            op::SECTION_IMPORT => import_section(&mut s, &mut module)?,
+            op::SECTION_FUNCTION => function_section(&mut s, &mut module)?,
+            op::SECTION_MEMORY => {
+                for _ in 0..s.u32()? {
+                    s.limits()?;
+                    module.memories += 1;
+                }
+            }
+            op::SECTION_GLOBAL => global_section(&mut s, &mut module)?,
+            op::SECTION_EXPORT => export_section(&mut s, &module)?,
+            op::SECTION_CODE => {
+                let cnt = s.u32()?;
+                bodies = Some(cnt);
+                for i in 0..cnt {
+                    let func = module.imported_funcs + i as usize;
+                    let Some(&ty) = module.funcs.get(func) else {
+                        return Err(s.error("more bodies than functions"));
+                    };
+                    let len = s.u32()? as usize;
+                    let start = s.pos;
+                    s.take(len)?;
+                    let body = Reader {
+                        bytes: &s.bytes[..s.pos],
+                        pos: start,
+                    };
+                    FuncValidator::new(&module, body, ty)?.validate()?;
+                }
+            }
+            _ => return Err(s.error_at(start - 1, format!("unsupported section {}", id))),
+        }
+        if module.memories > 1 {
+            return Err(s.error("more than one memory"));
+        }
+        if !s.is_empty() {
+            return Err(s.error("unexpected bytes at the end of the section"));
+        }
+    }
+    let defined = module.funcs.len() - module.imported_funcs;
+    if bodies.unwrap_or(0) as usize != defined {
+        return Err(r.error("functions without bodies"));
+    }
+    Ok(())
+}
+
+fn type_section(r: &mut Reader, module: &mut Module) -> Result<()> {
+    for _ in 0..r.u32()? {
+        if r.u8()? != op::FUNC_TYPE {
+            return Err(r.error("expected a function type"));
+        }
+        let mut ty = FuncType::default();
+        for tys in [&mut ty.params, &mut ty.results] {
+            for _ in 0..r.u32()? {
+                tys.push(r.val_type()?);
+            }
+        }
+        module.types.push(ty);
+    }
+    Ok(())
+}
+
+fn import_section(r: &mut Reader, module: &mut Module) -> Result<()> {
+    for _ in 0..r.u32()? {
+        r.name()?;
+        r.name()?;
+        match r.u8()? {
+            op::EXTERN_FUNC => {
+                let ty = r.type_index(module)?;
+                module.funcs.push(ty);
+                module.imported_funcs += 1;
+            }
+            op::EXTERN_MEMORY => {
+                r.limits()?;
+                module.memories += 1;
+            }
+            op::EXTERN_GLOBAL => {
+                let global = r.global_type()?;
+                module.globals.push(global);
+            }
+            kind => return Err(r.error(format!("unsupported import kind {}", kind))),
+        }
+    }
+    Ok(())
+}
+
+fn function_section(r: &mut Reader, module: &mut Module) -> Result<()> {
+    for _ in 0..r.u32()? {
+        let ty = r.type_index(module)?;
+        module.funcs.push(ty);
+    }
+    Ok(())
+}
+
+fn global_section(r: &mut Reader, module: &mut Module) -> Result<()> {
+    for _ in 0..r.u32()? {
+        let global = r.global_type()?;
+        // the initial value is a constant, or an imported global that cannot change.
+        let ty = match r.u8()? {
+            op::I32_CONST => {
+                r.s32()?;
+                ValType::I32
+            }
+            op::I64_CONST => {
+                r.s64()?;
+                ValType::I64
+            }
+            op::GLOBAL_GET => {
+                let index = r.u32()? as usize;
+                match module.globals.get(index) {
+                    Some(v) if !v.mutable => v.ty,
+                    _ => return Err(r.error("invalid global in a constant expression")),
+                }
+            }
+            _ => return Err(r.error("unsupported constant expression")),
+        };
+        if ty != global.ty {
+            return Err(r.error("initial value of the wrong type"));
+        }
+        if r.u8()? != op::END {
+            return Err(r.error("expected the end of the constant expression"));
+        }
+        module.globals.push(global);
+    }
+    Ok(())
+}
+
+fn export_section(r: &mut Reader, module: &Module) -> Result<()> {
+    let mut names = HashSet::new();
+    for _ in 0..r.u32()? {
+        let name = r.name()?;
+        if !names.insert(name) {
+            return Err(r.error("duplicate export name"));
+        }
+        let kind = r.u8()?;
+        let index = r.u32()? as usize;
+        let in_range = match kind {
+            op::EXTERN_FUNC => index < module.funcs.len(),
+            op::EXTERN_MEMORY => index < module.memories as usize,
+            op::EXTERN_GLOBAL => index < module.globals.len(),
+            _ => return Err(r.error(format!("unsupported export kind {}", kind))),
+        };
+        if !in_range {
+            return Err(r.error("exported index out of range"));
+        }
+    }
+    Ok(())
+}
+
+struct Reader<'a> {
+    /// The bytes up to the end of what is being read, offsets count from the module start.
+    bytes: &'a [u8],
+    pos: usize,
+}
+impl<'a> Reader<'a> {
+    fn error(&self, message: impl Into<String>) -> ValidateError {
+        self.error_at(self.pos, message)
+    }
+
+    fn error_at(&self, offset: usize, message: impl Into<String>) -> ValidateError {
+        ValidateError {
+            offset,
+            message: message.into(),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pos == self.bytes.len()
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.bytes.len() - self.pos < len {
+            return Err(self.error("unexpected end"));
+        }
+        self.pos += len;
+        Ok(&self.bytes[self.pos - len..self.pos])
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        let mut result = 0u64;
+        for i in 0..5 {
+            let byte = self.u8()?;
+            result |= ((byte & 0x7f) as u64) << (7 * i);
+            if byte & 0x80 == 0 {
+                return u32::try_from(result).map_err(|_| self.error("integer too large"));
+            }
+        }
+        Err(self.error("integer representation too long"))
+    }
+
+    /// A signed integer of `bits` bits.
+    fn signed(&mut self, bits: u32) -> Result<i64> {
+        let mut result = 0i128;
+        let mut shift = 0;
+        loop {
+            let byte = self.u8()?;
+            result |= ((byte & 0x7f) as i128) << shift;
+            shift += 7;
+            if byte & 0x80 == 0 {
+                if byte & 0x40 != 0 {
+                    result -= 1 << shift;
+                }
+                break;
+            }
+            if shift >= bits {
+                return Err(self.error("integer representation too long"));
+            }
+        }
+        let min = -(1i128 << (bits - 1));
+        if result < min || result > -min - 1 {
+            return Err(self.error("integer too large"));
+        }
+        Ok(result as i64)
+    }
+
+    fn s32(&mut self) -> Result<i32> {
+        Ok(self.signed(32)? as i32)
+    }
+
+    fn s64(&mut self) -> Result<i64> {
+        self.signed(64)
+    }
+
+    fn name(&mut self) -> Result<String> {
+        let len = self.u32()? as usize;
+        let bytes = self.take(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| self.error("name is not UTF-8"))
+    }
+
+    fn val_type(&mut self) -> Result<ValType> {
+        match self.u8()? {
+            op::I32 => Ok(ValType::I32),
+            op::I64 => Ok(ValType::I64),
+            ty => Err(self.error(format!("unsupported value type {:#04x}", ty))),
+        }
+    }
+
+    fn type_index(&mut self, module: &Module) -> Result<u32> {
+        let index = self.u32()?;
+        if index as usize >= module.types.len() {
+            return Err(self.error("type index out of range"));
+        }
+        Ok(index)
+    }
+
+    fn limits(&mut self) -> Result<()> {
+        const MAX_PAGES: u32 = 1 << 16;
+        let (min, max) = match self.u8()? {
+            op::LIMITS_MIN => (self.u32()?, None),
+            op::LIMITS_MIN_MAX => (self.u32()?, Some(self.u32()?)),
+            _ => return Err(self.error("invalid limits")),
+        };
+        if min > MAX_PAGES || max.is_some_and(|max| max > MAX_PAGES || max < min) {
+            return Err(self.error("invalid memory size"));
+        }
+        Ok(())
+    }
+
+    fn global_type(&mut self) -> Result<Global> {
+        let ty = self.val_type()?;
+        let mutable = match self.u8()? {
+            0 => false,
+            op::MUT_VAR => true,
+            _ => return Err(self.error("invalid mutability")),
+        };
+        Ok(Global { ty, mutable })
+    }
+}
+
+/// A construct of the body being checked.
+#[derive(Debug)]
+struct Ctrl {
+    opcode: u8,
+    start: Vec<ValType>,
+    end: Vec<ValType>,
+    /// Height of the operand stack when the construct was entered.
+    height: usize,
+    /// Whether the rest of the construct cannot be reached, anything can be popped then.
+    unreachable: bool,
+}
+impl Ctrl {
+    /// The values a branch to the construct takes.
+    fn label_types(&self) -> &[ValType] {
+        if self.opcode == op::LOOP {
+            &self.start
+        } else {
+            &self.end
+        }
+    }
+}
+
+struct FuncValidator<'a> {
+    module: &'a Module,
+    r: Reader<'a>,
+    locals: Vec<ValType>,
+    /// The operand stack, `None` for the values of unknown type of unreachable code.
+    vals: Vec<Option<ValType>>,
+    ctrls: Vec<Ctrl>,
+}
+impl<'a> FuncValidator<'a> {
+    fn new(module: &'a Module, mut r: Reader<'a>, ty: u32) -> Result<Self> {
+        let ty = &module.types[ty as usize];
+        let mut locals = ty.params.clone();
+        for _ in 0..r.u32()? {
+            let cnt = r.u32()?;
+            let local_ty = r.val_type()?;
+            if locals.len() as u64 + cnt as u64 > u32::MAX as u64 {
+                return Err(r.error("too many locals"));
+            }
+            locals.extend((0..cnt).map(|_| local_ty));
+        }
+        let mut validator = Self {
+            module,
+            r,
+            locals,
+            vals: vec![],
+            ctrls: vec![],
+        };
+        validator.push_ctrl(op::BLOCK, vec![], ty.results.clone());
+        Ok(validator)
+    }
+
+    fn error(&self, message: impl Into<String>) -> ValidateError {
+        self.r.error(message)
+    }
+
+    fn push(&mut self, ty: impl Into<Option<ValType>>) {
+        self.vals.push(ty.into());
+    }
+
+    fn pop(&mut self) -> Result<Option<ValType>> {
+        let ctrl = self.ctrls.last().unwrap();
+        if self.vals.len() == ctrl.height {
+            if ctrl.unreachable {
+                return Ok(None);
+            }
+            return Err(self.error("operand stack underflow"));
+        }
+        Ok(self.vals.pop().unwrap())
+    }
+
+    fn pop_expect(&mut self, expected: ValType) -> Result<()> {
+        match self.pop()? {
+            Some(ty) if ty != expected => {
+                Err(self.error(format!("expected {}, found {}", expected, ty)))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn pop_all(&mut self, tys: &[ValType]) -> Result<()> {
+        for ty in tys.iter().rev() {
+            self.pop_expect(*ty)?;
+        }
+        Ok(())
+    }
+
+    fn push_ctrl(&mut self, opcode: u8, start: Vec<ValType>, end: Vec<ValType>) {
+        self.vals.extend(start.iter().map(|v| Some(*v)));
+        self.ctrls.push(Ctrl {
+            opcode,
+            start,
+            end,
+            height: self.vals.len(),
+            unreachable: false,
+        });
+    }
+
+    fn pop_ctrl(&mut self) -> Result<Ctrl> {
+        let end = self.ctrls.last().unwrap().end.clone();
+        self.pop_all(&end)?;
+        if self.vals.len() != self.ctrls.last().unwrap().height {
+            return Err(self.error("values left on the operand stack"));
+        }
+        Ok(self.ctrls.pop().unwrap())
+    }
+
+    fn set_unreachable(&mut self) {
+        let ctrl = self.ctrls.last_mut().unwrap();
+        self.vals.truncate(ctrl.height);
+        ctrl.unreachable = true;
+    }
+
+    /// The construct the label of a branch refers to.
+    fn label(&mut self) -> Result<Vec<ValType>> {
+        let depth = self.r.u32()? as usize;
+        match self.ctrls.len().checked_sub(depth + 1) {
+            Some(i) => Ok(self.ctrls[i].label_types().to_vec()),
+            None => Err(self.error("branch depth out of range")),
+        }
+    }
+
+    fn block_type(&mut self) -> Result<FuncType> {
+        match self.r.bytes.get(self.r.pos) {
+            Some(&op::BLOCK_TYPE_EMPTY) => {
+                self.r.pos += 1;
+                Ok(FuncType::default())
+            }
+            Some(&(op::I32 | op::I64)) => Ok(FuncType {
+                params: vec![],
+                results: vec![self.r.val_type()?],
+            }),
+            _ => {
+                let index = self.r.signed(33)?;
+                match usize::try_from(index)
+                    .ok()
+                    .and_then(|v| self.module.types.get(v))
+                {
+                    Some(ty) => Ok(ty.clone()),
+                    None => Err(self.error("invalid block type")),
+                }
+            }
+        }
+    }
+
+    fn local(&mut self) -> Result<ValType> {
+        let index = self.r.u32()? as usize;
+        match self.locals.get(index) {
+            Some(ty) => Ok(*ty),
+            None => Err(self.error("local index out of range")),
+        }
+    }
+
+    fn global(&mut self) -> Result<Global> {
+        let index = self.r.u32()? as usize;
+        match self.module.globals.get(index) {
+            Some(global) => Ok(*global),
+            None => Err(self.error("global index out of range")),
+        }
+    }
+
+    /// Check the memory argument of an access of `size` bytes.
+    fn mem_arg(&mut self, size: u32) -> Result<()> {
+        if self.module.memories == 0 {
+            return Err(self.error("memory access without a memory"));
+        }
+        let align = self.r.u32()?;
+        self.r.u32()?;
+        if align >= 32 || 1 << align > size {
+            return Err(self.error("alignment larger than the access"));
+        }
+        Ok(())
+    }
+
+    fn validate(mut self) -> Result<()> {
+        use ValType::{I32, I64};
+
+        while !self.ctrls.is_empty() {
+            let opcode = self.r.u8()?;
+            match opcode {
+                op::UNREACHABLE => self.set_unreachable(),
+                op::NOP => {}
+                op::BLOCK | op::LOOP | op::IF => {
+                    if opcode == op::IF {
+                        self.pop_expect(I32)?;
+                    }
+                    let ty = self.block_type()?;
+                    self.pop_all(&ty.params)?;
+                    self.push_ctrl(opcode, ty.params, ty.results);
+                }
+                op::ELSE => {
+                    let ctrl = self.pop_ctrl()?;
+                    if ctrl.opcode != op::IF {
+                        return Err(self.error("else outside of an if"));
+                    }
+                    self.push_ctrl(op::ELSE, ctrl.start, ctrl.end);
+                }
+                op::END => {
+                    let ctrl = self.pop_ctrl()?;
+                    // without an else, the values given to the if are its results.
+                    if ctrl.opcode == op::IF && ctrl.start != ctrl.end {
+                        return Err(self.error("if without else changes the operand types"));
+                    }
+                    self.vals.extend(ctrl.end.iter().map(|v| Some(*v)));
+                }
+                op::BR => {
+                    let tys = self.label()?;
+                    self.pop_all(&tys)?;
+                    self.set_unreachable();
+                }
+                op::BR_IF => {
+                    let tys = self.label()?;
+                    self.pop_expect(I32)?;
+                    self.pop_all(&tys)?;
+                    self.vals.extend(tys.iter().map(|v| Some(*v)));
+                }
+                op::RETURN => {
+                    let tys = self.ctrls[0].end.clone();
+                    self.pop_all(&tys)?;
+                    self.set_unreachable();
+                }
+                op::CALL => {
+                    let index = self.r.u32()? as usize;
+                    let Some(&ty) = self.module.funcs.get(index) else {
+                        return Err(self.error("function index out of range"));
+                    };
+                    let ty = &self.module.types[ty as usize];
+                    self.pop_all(&ty.params)?;
+                    self.vals.extend(ty.results.iter().map(|v| Some(*v)));
+                }
+                op::DROP => {
+                    self.pop()?;
+                }
+                op::SELECT => {
+                    self.pop_expect(I32)?;
+                    let (a, b) = (self.pop()?, self.pop()?);
+                    if a.is_some() && b.is_some() && a != b {
+                        return Err(self.error("select operands of different types"));
+                    }
+                    self.push(a.or(b));
+                }
+                op::LOCAL_GET => {
+                    let ty = self.local()?;
+                    self.push(ty);
+                }
+                op::LOCAL_SET | op::LOCAL_TEE => {
+                    let ty = self.local()?;
+                    self.pop_expect(ty)?;
+                    if opcode == op::LOCAL_TEE {
+                        self.push(ty);
+                    }
+                }
+                op::GLOBAL_GET => {
+                    let global = self.global()?;
+                    self.push(global.ty);
+                }
+                op::GLOBAL_SET => {
+                    let global = self.global()?;
+                    if !global.mutable {
+                        return Err(self.error("global is immutable"));
+                    }
+                    self.pop_expect(global.ty)?;
+                }
+                op::I32_LOAD | op::I64_LOAD => {
+                    let ty = if opcode == op::I32_LOAD { I32 } else { I64 };
+                    self.mem_arg(if ty == I32 { 4 } else { 8 })?;
+                    self.pop_expect(I32)?;
+                    self.push(ty);
+                }
+                op::I32_STORE | op::I64_STORE => {
+                    let ty = if opcode == op::I32_STORE { I32 } else { I64 };
+                    self.mem_arg(if ty == I32 { 4 } else { 8 })?;
+                    self.pop_expect(ty)?;
+                    self.pop_expect(I32)?;
+                }
+                op::MEMORY_SIZE | op::MEMORY_GROW => {
+                    if self.r.u8()? != 0 || self.module.memories == 0 {
+                        return Err(self.error("invalid memory index"));
+                    }
+                    if opcode == op::MEMORY_GROW {
+                        self.pop_expect(I32)?;
+                    }
+                    self.push(I32);
+                }
+                op::I32_CONST => {
+                    self.r.s32()?;
+                    self.push(I32);
+                }
+                op::I64_CONST => {
+                    self.r.s64()?;
+                    self.push(I64);
+                }
+                _ => {
+                    let (params, result): (&[ValType], ValType) = match opcode {
+                        op::I32_EQZ => (&[I32], I32),
+                        op::I32_EQ..=op::I32_GE_U => (&[I32, I32], I32),
+                        op::I64_EQZ => (&[I64], I32),
+                        op::I64_EQ..=op::I64_GE_U => (&[I64, I64], I32),
+                        op::I32_CLZ..=op::I32_POPCNT => (&[I32], I32),
+                        op::I32_ADD..=op::I32_ROTR => (&[I32, I32], I32),
+                        op::I64_CLZ..=op::I64_POPCNT => (&[I64], I64),
+                        op::I64_ADD..=op::I64_ROTR => (&[I64, I64], I64),
+                        op::I32_WRAP_I64 => (&[I64], I32),
+                        op::I64_EXTEND_I32_S | op::I64_EXTEND_I32_U => (&[I32], I64),
+                        _ => {
+                            return Err(self.r.error_at(
+                                self.r.pos - 1,
+                                format!("unsupported opcode {:#04x}", opcode),
+                            ))
+                        }
+                    };
+                    self.pop_all(params)?;
+                    self.push(result);
+                }
+            }
+        }
+        if !self.r.is_empty() {
+            return Err(self.error("code after the end of the function"));
+        }
+        Ok(())
+    }
+}
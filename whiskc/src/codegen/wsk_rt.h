@@ -0,0 +1,76 @@
+/* Runtime of the C code generated by whiskc. */
+#ifndef WSK_RT_H
+#define WSK_RT_H
+
+#include <inttypes.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+
+typedef int64_t wsk_int;
+typedef bool wsk_bool;
+typedef union wsk_field *wsk_ref;
+
+/* A field of a heap object, the code knows the type of every field it reads. */
+union wsk_field {
+    wsk_int i;
+    wsk_bool b;
+    wsk_ref r;
+};
+
+static inline void wsk_trap(const char *msg) {
+    fprintf(stderr, "whisk: %s\n", msg);
+    abort();
+}
+
+/* Integers wrap around, going through unsigned integers keeps the overflow defined. */
+static inline wsk_int wsk_add(wsk_int lhs, wsk_int rhs) {
+    return (wsk_int)((uint64_t)lhs + (uint64_t)rhs);
+}
+
+static inline wsk_int wsk_sub(wsk_int lhs, wsk_int rhs) {
+    return (wsk_int)((uint64_t)lhs - (uint64_t)rhs);
+}
+
+static inline wsk_int wsk_mul(wsk_int lhs, wsk_int rhs) {
+    return (wsk_int)((uint64_t)lhs * (uint64_t)rhs);
+}
+
+static inline wsk_int wsk_neg(wsk_int v) {
+    return (wsk_int)(0 - (uint64_t)v);
+}
+
+static inline void wsk_check_div(wsk_int lhs, wsk_int rhs) {
+    if (rhs == 0) {
+        wsk_trap("division by zero");
+    }
+    if (lhs == INT64_MIN && rhs == -1) {
+        wsk_trap("division overflow");
+    }
+}
+
+static inline wsk_int wsk_div(wsk_int lhs, wsk_int rhs) {
+    wsk_check_div(lhs, rhs);
+    return lhs / rhs;
+}
+
+static inline wsk_int wsk_mod(wsk_int lhs, wsk_int rhs) {
+    wsk_check_div(lhs, rhs);
+    return lhs % rhs;
+}
+
+/* Objects are never freed, they live until the program exits. */
+static inline wsk_ref wsk_alloc(size_t field_cnt) {
+    wsk_ref r = calloc(field_cnt ? field_cnt : 1, sizeof(union wsk_field));
+    if (r == NULL) {
+        wsk_trap("out of memory");
+    }
+    return r;
+}
+
+static inline void wsk_unreachable(void) {
+    wsk_trap("reached unreachable code");
+}
+
+#endif
@@ -0,0 +1,580 @@
+//! GNU assembler for x86-64 Linux, in AT&T syntax.
+//!
+//! Functions follow the System V calling convention: every value is passed in 64 bits, up to six
+//! arguments in registers and the others on the stack. One or two results come back in `rax` and
+//! `rdx`, more are written to memory the caller passes as a hidden first argument. Heap objects
+//! are allocated with `calloc` and never freed, `main` is the C entry point of the process.
+
+use std::fmt::{self, Write};
+
+use regalloc::{Allocation, Loc};
+
+use super::{
+    parallel_move::{sequentialize, MoveSrc, Step},
+    PRINT_RESULT,
+};
+use crate::ir::{
+    self, BinaryOp, Block, BlockCall, CmpOp, Const, FuncRef, InstKind, Operand, Terminator, Ty,
+    UnaryOp,
+};
+
+mod regalloc;
+
+const ARG_REGS: [Reg; 6] = [Reg::Rdi, Reg::Rsi, Reg::Rdx, Reg::Rcx, Reg::R8, Reg::R9];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(super) enum Reg {
+    Rax,
+    Rbx,
+    Rcx,
+    Rdx,
+    Rsi,
+    Rdi,
+    R8,
+    R9,
+    R10,
+    R11,
+    R12,
+    R13,
+    R14,
+    R15,
+}
+impl fmt::Display for Reg {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Reg::Rax => "rax",
+            Reg::Rbx => "rbx",
+            Reg::Rcx => "rcx",
+            Reg::Rdx => "rdx",
+            Reg::Rsi => "rsi",
+            Reg::Rdi => "rdi",
+            Reg::R8 => "r8",
+            Reg::R9 => "r9",
+            Reg::R10 => "r10",
+            Reg::R11 => "r11",
+            Reg::R12 => "r12",
+            Reg::R13 => "r13",
+            Reg::R14 => "r14",
+            Reg::R15 => "r15",
+        };
+        write!(f, "%{}", name)
+    }
+}
+
+pub(super) fn codegen_module(module: &ir::Module, main: FuncRef) -> String {
+    let mut out = String::new();
+    writeln!(
+        out,
+        "# generated by whiskc {} from {}",
+        env!("CARGO_PKG_VERSION"),
+        module.name
+    )
+    .unwrap();
+    out.push_str("\t.text\n");
+    for (i, func) in module.funcs.iter().enumerate() {
+        if !func.is_extern {
+            FuncCodegen::new(module, i, &mut out).codegen();
+        }
+    }
+
+    writeln!(
+        out,
+        "\n\t.globl main\n\
+         \t.type main, @function\n\
+         main:\n\
+         \tpushq %rbp\n\
+         \tmovq %rsp, %rbp\n\
+         \tcall {name}\n\
+         .ifdef {print}\n\
+         \tpushq %rax\n\
+         \tpushq %rax\n\
+         \tmovq %rax, %rsi\n\
+         \tleaq .Lresult_fmt(%rip), %rdi\n\
+         \txorl %eax, %eax\n\
+         \tcall printf@PLT\n\
+         \tpopq %rax\n\
+         \tpopq %rax\n\
+         .endif\n\
+         \tpopq %rbp\n\
+         \tret\n\
+         .ifdef {print}\n\
+         \t.section .rodata\n\
+         .Lresult_fmt:\n\
+         \t.string \"%ld\\n\"\n\
+         .endif\n\
+         \t.section .note.GNU-stack,\"\",@progbits",
+        name = func_name(module.get(main)),
+        print = PRINT_RESULT,
+    )
+    .unwrap();
+    out
+}
+
+fn func_name(func: &ir::Function) -> String {
+    if func.is_extern {
+        format!("{}@PLT", func.name)
+    } else {
+        format!("wsk_fn_{}", func.name)
+    }
+}
+
+/// A source of a move.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Src {
+    Loc(Loc),
+    Imm(i64),
+}
+impl From<Loc> for Src {
+    fn from(value: Loc) -> Self {
+        Self::Loc(value)
+    }
+}
+impl From<Reg> for Src {
+    fn from(value: Reg) -> Self {
+        Self::Loc(Loc::Reg(value))
+    }
+}
+
+struct FuncCodegen<'a> {
+    module: &'a ir::Module,
+    fi: usize,
+    func: &'a ir::Function,
+    out: &'a mut String,
+    alloc: Allocation,
+    /// The first slot of the memory receiving the results of callees returning more than two.
+    ret_area: u32,
+    ret_area_len: u32,
+    /// The slot keeping the address results are written to, when the function returns more than
+    /// two.
+    ret_ptr: Option<u32>,
+    /// Number of labels made inside blocks.
+    label_cnt: usize,
+}
+impl<'a> FuncCodegen<'a> {
+    fn new(module: &'a ir::Module, fi: usize, out: &'a mut String) -> Self {
+        let func = &module.funcs[fi];
+        let alloc = regalloc::allocate(func);
+        let ret_area = alloc.slots;
+        let ret_area_len = func
+            .blocks
+            .iter()
+            .flat_map(|v| &v.insts)
+            .filter_map(|v| match &v.kind {
+                InstKind::Call(callee, _) => Some(module.get(*callee).sig.rets.len()),
+                _ => None,
+            })
+            .filter(|v| *v > 2)
+            .max()
+            .unwrap_or(0) as u32;
+        let ret_ptr = (func.sig.rets.len() > 2).then_some(ret_area + ret_area_len);
+        Self {
+            module,
+            fi,
+            func,
+            out,
+            alloc,
+            ret_area,
+            ret_area_len,
+            ret_ptr,
+            label_cnt: 0,
+        }
+    }
+
+    fn emit(&mut self, inst: impl fmt::Display) {
+        writeln!(self.out, "\t{}", inst).unwrap();
+    }
+
+    fn block_label(&self, block: Block) -> String {
+        format!(".Lf{}_b{}", self.fi, block.0)
+    }
+
+    fn new_label(&mut self) -> String {
+        self.label_cnt += 1;
+        format!(".Lf{}_l{}", self.fi, self.label_cnt)
+    }
+
+    fn loc(&self, loc: Loc) -> String {
+        match loc {
+            Loc::Reg(reg) => reg.to_string(),
+            Loc::Stack(slot) => {
+                let offset = 8 * (self.alloc.saved.len() as u32 + slot + 1);
+                format!("-{}(%rbp)", offset)
+            }
+            Loc::Arg(i) => format!("{}(%rbp)", 16 + 8 * i),
+        }
+    }
+
+    fn src(&self, op: Operand) -> Src {
+        match op {
+            Operand::Value(v) => Src::Loc(self.alloc.locs[v.index()]),
+            Operand::Const(Const::Int(v)) => Src::Imm(v),
+            Operand::Const(Const::Bool(v)) => Src::Imm(v as i64),
+        }
+    }
+
+    fn dst(&self, v: ir::Value) -> Loc {
+        self.alloc.locs[v.index()]
+    }
+
+    /// The source as an operand of an instruction also taking a register or memory, immediates
+    /// not fitting in 32 bits are loaded into `r11`.
+    fn operand(&mut self, src: Src) -> String {
+        match src {
+            Src::Loc(loc) => self.loc(loc),
+            Src::Imm(v) if i32::try_from(v).is_ok() => format!("${}", v),
+            Src::Imm(v) => {
+                self.emit(format_args!("movabsq ${}, %r11", v));
+                "%r11".to_owned()
+            }
+        }
+    }
+
+    fn mov(&mut self, dst: Loc, src: Src) {
+        match (dst, src) {
+            (Loc::Reg(_), Src::Imm(v)) if i32::try_from(v).is_err() => {
+                self.emit(format_args!("movabsq ${}, {}", v, self.loc(dst)))
+            }
+            (Loc::Reg(_), _) | (_, Src::Loc(Loc::Reg(_))) => {
+                let src = self.operand(src);
+                self.emit(format_args!("movq {}, {}", src, self.loc(dst)))
+            }
+            // there is no move from memory or a large immediate to memory, go through rax.
+            _ => {
+                self.mov(Loc::Reg(Reg::Rax), src);
+                self.emit(format_args!("movq %rax, {}", self.loc(dst)))
+            }
+        }
+    }
+
+    /// Do the moves as if all at once, a location may be the source of a move and the
+    /// destination of another. Memory to memory moves go through `rax`, which cannot be a
+    /// source, and cycles are broken through `r11`.
+    /// `%r11` is the scratch location of the moves.
+    fn parallel_move(&mut self, moves: impl IntoIterator<Item = (Loc, Src)>) {
+        let reads = |src: &Src| match src {
+            Src::Loc(loc) => Some(*loc),
+            Src::Imm(_) => None,
+        };
+        for step in sequentialize(moves, reads) {
+            match step {
+                Step::Save(loc) => self.mov(Loc::Reg(Reg::R11), loc.into()),
+                Step::Move(dst, MoveSrc::Src(src)) => self.mov(dst, src),
+                Step::Move(dst, MoveSrc::Scratch) => self.mov(dst, Reg::R11.into()),
+            }
+        }
+    }
+
+    fn codegen(&mut self) {
+        let func = self.func;
+        let name = func_name(func);
+        writeln!(self.out, "\n\t.type {}, @function\n{}:", name, name).unwrap();
+        self.emit("pushq %rbp");
+        self.emit("movq %rsp, %rbp");
+        for reg in self.alloc.saved.clone() {
+            self.emit(format_args!("pushq {}", reg));
+        }
+        let slots = self.ret_area + self.ret_area_len + self.ret_ptr.is_some() as u32;
+        // keep the stack aligned to 16 bytes for calls.
+        let frame = 8 * (slots + (self.alloc.saved.len() as u32 + slots) % 2);
+        if frame > 0 {
+            self.emit(format_args!("subq ${}, %rsp", frame));
+        }
+
+        let mut arg_regs = &ARG_REGS[..];
+        let mut moves = vec![];
+        if let Some(slot) = self.ret_ptr {
+            moves.push((Loc::Stack(slot), Reg::Rdi.into()));
+            arg_regs = &ARG_REGS[1..];
+        }
+        for (i, param) in func.block(func.entry()).params.iter().enumerate() {
+            let src = match arg_regs.get(i) {
+                Some(reg) => Loc::Reg(*reg),
+                None => Loc::Arg((i - arg_regs.len()) as u32),
+            };
+            moves.push((self.dst(*param), src.into()));
+        }
+        self.parallel_move(moves);
+
+        for block in func.block_ids() {
+            writeln!(self.out, "{}:", self.block_label(block)).unwrap();
+            let data = func.block(block);
+            for inst in &data.insts {
+                self.codegen_inst(&inst.kind, &inst.results);
+            }
+            self.codegen_terminator(block, &data.term);
+        }
+        writeln!(self.out, "\t.size {}, .-{}", name, name).unwrap();
+    }
+
+    fn codegen_inst(&mut self, kind: &InstKind, results: &[ir::Value]) {
+        match kind {
+            InstKind::Unary(op, src) => {
+                self.mov(Loc::Reg(Reg::Rax), self.src(*src));
+                self.emit(match op {
+                    UnaryOp::Neg => "negq %rax",
+                    UnaryOp::Not => "xorq $1, %rax",
+                });
+                self.mov(self.dst(results[0]), Reg::Rax.into());
+            }
+            InstKind::Binary(op, lhs, rhs) => {
+                let (lhs, rhs) = (self.src(*lhs), self.src(*rhs));
+                self.mov(Loc::Reg(Reg::Rax), lhs);
+                let result = match op {
+                    BinaryOp::Div | BinaryOp::Mod => {
+                        // idiv takes no immediate and traps on a zero divisor.
+                        let rhs = match rhs {
+                            Src::Imm(_) => {
+                                self.mov(Loc::Reg(Reg::R11), rhs);
+                                "%r11".to_owned()
+                            }
+                            Src::Loc(loc) => self.loc(loc),
+                        };
+                        self.emit("cqto");
+                        self.emit(format_args!("idivq {}", rhs));
+                        if *op == BinaryOp::Div {
+                            Reg::Rax
+                        } else {
+                            Reg::Rdx
+                        }
+                    }
+                    _ => {
+                        let rhs = self.operand(rhs);
+                        let inst = match op {
+                            BinaryOp::Add => "addq",
+                            BinaryOp::Sub => "subq",
+                            BinaryOp::Mul => "imulq",
+                            BinaryOp::And => "andq",
+                            BinaryOp::Or => "orq",
+                            BinaryOp::Div | BinaryOp::Mod => unreachable!(),
+                        };
+                        self.emit(format_args!("{} {}, %rax", inst, rhs));
+                        Reg::Rax
+                    }
+                };
+                self.mov(self.dst(results[0]), result.into());
+            }
+            InstKind::Cmp(op, lhs, rhs) => {
+                let (lhs, rhs) = (self.src(*lhs), self.src(*rhs));
+                self.mov(Loc::Reg(Reg::Rax), lhs);
+                let rhs = self.operand(rhs);
+                self.emit(format_args!("cmpq {}, %rax", rhs));
+                let cc = match op {
+                    CmpOp::Equal => "e",
+                    CmpOp::NotEqual => "ne",
+                    CmpOp::Less => "l",
+                    CmpOp::LessEqual => "le",
+                    CmpOp::Greater => "g",
+                    CmpOp::GreaterEqual => "ge",
+                };
+                self.emit(format_args!("set{} %al", cc));
+                self.emit("movzbq %al, %rax");
+                self.mov(self.dst(results[0]), Reg::Rax.into());
+            }
+            InstKind::Call(callee, args) => self.codegen_call(*callee, args, results),
+            InstKind::MakeStruct(fields) => {
+                // the fields wait on the stack while calloc may clobber their registers.
+                let pad = fields.len() % 2 == 1;
+                if pad {
+                    self.emit("subq $8, %rsp");
+                }
+                for field in fields {
+                    self.push(self.src(*field));
+                }
+                self.emit(format_args!("movq ${}, %rdi", fields.len().max(1)));
+                self.emit("movq $8, %rsi");
+                self.emit("call calloc@PLT");
+                let ok = self.new_label();
+                self.emit("testq %rax, %rax");
+                self.emit(format_args!("jnz {}", ok));
+                self.emit("ud2");
+                writeln!(self.out, "{}:", ok).unwrap();
+                for i in (0..fields.len()).rev() {
+                    self.emit("popq %rcx");
+                    self.emit(format_args!("movq %rcx, {}(%rax)", 8 * i));
+                }
+                if pad {
+                    self.emit("addq $8, %rsp");
+                }
+                self.mov(self.dst(results[0]), Reg::Rax.into());
+            }
+            InstKind::GetField(src, index) => {
+                self.mov(Loc::Reg(Reg::Rax), self.src(*src));
+                self.emit(format_args!("movq {}(%rax), %rax", 8 * index));
+                self.mov(self.dst(results[0]), Reg::Rax.into());
+            }
+        }
+    }
+
+    fn push(&mut self, src: Src) {
+        let src = match src {
+            Src::Imm(v) if i32::try_from(v).is_err() => {
+                self.mov(Loc::Reg(Reg::Rax), src);
+                "%rax".to_owned()
+            }
+            src => self.operand(src),
+        };
+        self.emit(format_args!("pushq {}", src));
+    }
+
+    fn codegen_call(&mut self, callee: FuncRef, args: &[Operand], results: &[ir::Value]) {
+        let callee = self.module.get(callee);
+        let hidden = callee.sig.rets.len() > 2;
+        let arg_regs = if hidden { &ARG_REGS[1..] } else { &ARG_REGS };
+        let stack_args = args.len().saturating_sub(arg_regs.len());
+        let pad = stack_args % 2 == 1;
+        if pad {
+            self.emit("subq $8, %rsp");
+        }
+        for arg in args[args.len() - stack_args..].iter().rev() {
+            self.push(self.src(*arg));
+        }
+        let moves: Vec<_> = arg_regs
+            .iter()
+            .zip(args)
+            .map(|(reg, arg)| (Loc::Reg(*reg), self.src(*arg)))
+            .collect();
+        self.parallel_move(moves);
+        if hidden {
+            let ret_area = self.loc(Loc::Stack(self.ret_area + callee.sig.rets.len() as u32 - 1));
+            self.emit(format_args!("leaq {}, %rdi", ret_area));
+        }
+        self.emit(format_args!("call {}", func_name(callee)));
+        if callee.is_extern && callee.sig.rets == [Ty::Bool] {
+            // C only sets the low byte of a bool.
+            self.emit("movzbq %al, %rax");
+        }
+        if stack_args > 0 {
+            self.emit(format_args!(
+                "addq ${}, %rsp",
+                8 * (stack_args + pad as usize)
+            ));
+        }
+
+        let moves: Vec<_> = results
+            .iter()
+            .enumerate()
+            .map(|(i, v)| {
+                let src = match (hidden, i) {
+                    (false, 0) => Loc::Reg(Reg::Rax),
+                    (false, _) => Loc::Reg(Reg::Rdx),
+                    (true, _) => self.ret_slot(callee.sig.rets.len(), i),
+                };
+                (self.dst(*v), src.into())
+            })
+            .collect();
+        self.parallel_move(moves);
+    }
+
+    /// The slot of the ret area holding result `i` of `cnt`, the memory of the results goes up
+    /// from the first one.
+    fn ret_slot(&self, cnt: usize, i: usize) -> Loc {
+        Loc::Stack(self.ret_area + (cnt - 1 - i) as u32)
+    }
+
+    fn codegen_terminator(&mut self, block: Block, term: &Terminator) {
+        match term {
+            Terminator::Jump(call) => self.codegen_edge(block, call, true),
+            Terminator::Branch { cond, then, else_ } => {
+                self.mov(Loc::Reg(Reg::Rax), self.src(*cond));
+                self.emit("testq %rax, %rax");
+                if self.edge_moves(else_).is_empty() {
+                    self.emit(format_args!("jz {}", self.block_label(else_.block)));
+                    self.codegen_edge(block, then, true);
+                } else if self.edge_moves(then).is_empty() {
+                    self.emit(format_args!("jnz {}", self.block_label(then.block)));
+                    self.codegen_edge(block, else_, true);
+                } else {
+                    let to_else = self.new_label();
+                    self.emit(format_args!("jz {}", to_else));
+                    self.codegen_edge(block, then, false);
+                    writeln!(self.out, "{}:", to_else).unwrap();
+                    self.codegen_edge(block, else_, true);
+                }
+            }
+            Terminator::Return(values) => {
+                if let Some(slot) = self.ret_ptr {
+                    self.mov(Loc::Reg(Reg::R11), Loc::Stack(slot).into());
+                    for (i, v) in values.iter().enumerate() {
+                        let src = self.src(*v);
+                        self.mov(Loc::Reg(Reg::Rax), src);
+                        self.emit(format_args!("movq %rax, {}(%r11)", 8 * i));
+                    }
+                    self.emit("movq %r11, %rax");
+                } else {
+                    let moves: Vec<_> = [Reg::Rax, Reg::Rdx]
+                        .into_iter()
+                        .zip(values)
+                        .map(|(reg, v)| (Loc::Reg(reg), self.src(*v)))
+                        .collect();
+                    self.parallel_move(moves);
+                }
+                let saved = self.alloc.saved.len();
+                if saved > 0 {
+                    self.emit(format_args!("leaq -{}(%rbp), %rsp", 8 * saved));
+                }
+                for reg in self.alloc.saved.clone().into_iter().rev() {
+                    self.emit(format_args!("popq {}", reg));
+                }
+                self.emit("leave");
+                self.emit("ret");
+            }
+            // there is nothing to return, stop the process if control ever gets here.
+            Terminator::Unreachable | Terminator::Missing => self.emit("ud2"),
+        }
+    }
+
+    fn edge_moves(&self, call: &BlockCall) -> Vec<(Loc, Src)> {
+        self.func
+            .block(call.block)
+            .params
+            .iter()
+            .zip(&call.args)
+            .map(|(param, arg)| (self.dst(*param), self.src(*arg)))
+            .collect()
+    }
+
+    /// Move the arguments to where the target keeps its parameters and jump there, unless the
+    /// target is the next block and `fall_through` allows it.
+    fn codegen_edge(&mut self, block: Block, call: &BlockCall, fall_through: bool) {
+        let moves = self.edge_moves(call);
+        self.parallel_move(moves);
+        if !fall_through || call.block.0 != block.0 + 1 {
+            self.emit(format_args!("jmp {}", self.block_label(call.block)));
+        }
+    }
+}
+
+#[cfg(all(test, target_arch = "x86_64", target_os = "linux"))]
+mod tests {
+    use std::fs;
+
+    use crate::{
+        codegen::{
+            testing::{build_and_run, c_compiler, compile, run_on_vm, test_programs, OutDir},
+            Target, PRINT_RESULT,
+        },
+        ir::opt::OptLevel,
+    };
+
+    #[test]
+    fn x86_64_agrees_with_stack_vm() {
+        let Some(cc) = c_compiler() else {
+            return;
+        };
+        let out_dir = OutDir::new("x86-64");
+        for (name, source) in test_programs() {
+            for opt_level in [OptLevel::O0, OptLevel::O2] {
+                let output = compile(&name, &source, opt_level, Target::X86_64);
+                let src = out_dir.0.join(format!("{}.s", name));
+                fs::write(&src, output.asm_source.unwrap()).unwrap();
+                assert_eq!(
+                    build_and_run(&cc, &src, &[&format!("-Wa,--defsym,{}=1", PRINT_RESULT)]),
+                    run_on_vm(&name, &source, opt_level),
+                    "{} at {:?}",
+                    name,
+                    opt_level
+                );
+            }
+        }
+    }
+}
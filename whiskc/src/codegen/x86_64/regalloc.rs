@@ -0,0 +1,227 @@
+//! Linear scan register allocation, after Poletto and Sarkar.
+//!
+//! The instructions of the blocks, in their order, get consecutive positions. Every value lives
+//! in one interval from the first to the last position where it is live, holes included, so one
+//! location holds it for its whole life.
+
+use crate::ir::{self, InstKind, Operand, Value};
+
+use super::Reg;
+
+/// Registers a call leaves alone, for the values living across calls.
+const CALLEE_SAVED: [Reg; 5] = [Reg::Rbx, Reg::R12, Reg::R13, Reg::R14, Reg::R15];
+/// Registers for the values not living across calls, the ones calls clobber first. The others
+/// are the scratch registers of the code generator or only hold the arguments during a call.
+const ANY: [Reg; 10] = [
+    Reg::Rsi,
+    Reg::Rdi,
+    Reg::R8,
+    Reg::R9,
+    Reg::R10,
+    Reg::Rbx,
+    Reg::R12,
+    Reg::R13,
+    Reg::R14,
+    Reg::R15,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Loc {
+    Reg(Reg),
+    /// A slot of the frame.
+    Stack(u32),
+    /// An argument passed on the stack by the caller.
+    Arg(u32),
+}
+
+#[derive(Debug)]
+pub(super) struct Allocation {
+    /// The location of every value.
+    pub locs: Vec<Loc>,
+    /// Number of stack slots used by the spilled values.
+    pub slots: u32,
+    /// The callee saved registers used, to be saved by the function.
+    pub saved: Vec<Reg>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Interval {
+    value: Value,
+    start: u32,
+    end: u32,
+    /// Whether a call happens while the value is live.
+    crosses_call: bool,
+}
+
+pub(super) fn allocate(func: &ir::Function) -> Allocation {
+    let mut intervals = build_intervals(func);
+    intervals.sort_by_key(|v| (v.start, v.end));
+
+    let mut locs = vec![Loc::Stack(u32::MAX); func.values.len()];
+    let mut slots = 0;
+    let mut saved = vec![];
+    let mut spill = |v: Value, locs: &mut Vec<Loc>| {
+        locs[v.index()] = Loc::Stack(slots);
+        slots += 1;
+    };
+    // (interval, register) of the values in registers, live at the current position.
+    let mut active: Vec<(Interval, Reg)> = vec![];
+    for interval in intervals {
+        active.retain(|(v, _)| v.end >= interval.start);
+        let allowed: &[Reg] = if interval.crosses_call {
+            &CALLEE_SAVED
+        } else {
+            &ANY
+        };
+        let free = allowed
+            .iter()
+            .find(|reg| !active.iter().any(|(_, v)| v == *reg));
+        let reg = match free {
+            Some(reg) => Some(*reg),
+            None => {
+                // spill the value living longest, the new one if it lives longer.
+                let longest = active
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, (_, reg))| allowed.contains(reg))
+                    .max_by_key(|(_, (v, _))| v.end)
+                    .map(|(i, _)| i);
+                match longest {
+                    Some(i) if active[i].0.end > interval.end => {
+                        let (spilled, reg) = active.swap_remove(i);
+                        spill(spilled.value, &mut locs);
+                        Some(reg)
+                    }
+                    _ => None,
+                }
+            }
+        };
+        match reg {
+            Some(reg) => {
+                locs[interval.value.index()] = Loc::Reg(reg);
+                if CALLEE_SAVED.contains(&reg) && !saved.contains(&reg) {
+                    saved.push(reg);
+                }
+                active.push((interval, reg));
+            }
+            None => spill(interval.value, &mut locs),
+        }
+    }
+    saved.sort();
+    Allocation { locs, slots, saved }
+}
+
+fn build_intervals(func: &ir::Function) -> Vec<Interval> {
+    let live_in = live_in(func);
+
+    let mut ranges = vec![(u32::MAX, 0); func.values.len()];
+    let mut touch = |v: Value, pos: u32| {
+        let range = &mut ranges[v.index()];
+        *range = (range.0.min(pos), range.1.max(pos));
+    };
+    let mut calls = vec![];
+    let mut pos = 0;
+    for block in func.block_ids() {
+        let data = func.block(block);
+        for v in data.params.iter().chain(&live_in[block.index()]) {
+            touch(*v, pos);
+        }
+        for inst in &data.insts {
+            pos += 1;
+            if matches!(inst.kind, InstKind::Call(..) | InstKind::MakeStruct(_)) {
+                calls.push(pos);
+            }
+            for v in inst.operands().into_iter().filter_map(Operand::as_value) {
+                touch(v, pos);
+            }
+            for v in &inst.results {
+                touch(*v, pos);
+            }
+        }
+        pos += 1;
+        for v in data
+            .term
+            .operands()
+            .into_iter()
+            .filter_map(Operand::as_value)
+        {
+            touch(v, pos);
+        }
+        // the parameters of the successors are assigned by the terminator and everything live
+        // into them is live until it.
+        for succ in data.term.successors() {
+            for v in func.block(succ).params.iter().chain(&live_in[succ.index()]) {
+                touch(*v, pos);
+            }
+        }
+        pos += 1;
+    }
+
+    ranges
+        .into_iter()
+        .enumerate()
+        .filter(|(_, (start, end))| start <= end)
+        .map(|(i, (start, end))| Interval {
+            value: Value(i as u32),
+            start,
+            end,
+            crosses_call: calls.iter().any(|v| start < *v && *v < end),
+        })
+        .collect()
+}
+
+/// The values live on entry to every block, its parameters excepted.
+fn live_in(func: &ir::Function) -> Vec<Vec<Value>> {
+    let n = func.values.len();
+    // the values used before being defined in every block, and the values defined in it.
+    let mut uses = vec![vec![false; n]; func.blocks.len()];
+    let mut defs = vec![vec![false; n]; func.blocks.len()];
+    for block in func.block_ids() {
+        let data = func.block(block);
+        let (uses, defs) = (&mut uses[block.index()], &mut defs[block.index()]);
+        for v in &data.params {
+            defs[v.index()] = true;
+        }
+        for inst in &data.insts {
+            for v in inst.operands().into_iter().filter_map(Operand::as_value) {
+                uses[v.index()] |= !defs[v.index()];
+            }
+            for v in &inst.results {
+                defs[v.index()] = true;
+            }
+        }
+        for v in data
+            .term
+            .operands()
+            .into_iter()
+            .filter_map(Operand::as_value)
+        {
+            uses[v.index()] |= !defs[v.index()];
+        }
+    }
+
+    let mut live_in = uses.clone();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for block in (0..func.blocks.len() as u32).rev().map(ir::Block) {
+            for succ in func.block(block).term.successors() {
+                for v in 0..n {
+                    if live_in[succ.index()][v] && !defs[block.index()][v] {
+                        changed |= !live_in[block.index()][v];
+                        live_in[block.index()][v] = true;
+                    }
+                }
+            }
+        }
+    }
+    live_in
+        .into_iter()
+        .map(|live| {
+            (0..n)
+                .filter(|v| live[*v])
+                .map(|v| Value(v as u32))
+                .collect()
+        })
+        .collect()
+}
@@ -1,6 +1,26 @@
-use std::{fs, path::PathBuf};
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+};
 
-use crate::{ast, codegen::codegen_wsk_vm, lowering};
+use wsk_vm::program::Program;
+
+use crate::{
+    ast::{
+        self,
+        location::{FileId, Span},
+        parsing::{lexer::Lexer, token::Token},
+        AST,
+    },
+    codegen::{
+        codegen_c, codegen_reg_vm, codegen_wasm, codegen_wsk_vm, codegen_x86_64, Target,
+        C_RUNTIME_HEADER,
+    },
+    ir::{self, opt::OptLevel},
+    lowering::{self, errors::ResolveErrors, index::SourceIndex, nodes::module::Module},
+    source_map::SourceMap,
+    symbol::SymbolTable,
+};
 
 #[derive(Default)]
 pub struct CompileSwitch {
@@ -8,60 +28,455 @@ pub struct CompileSwitch {
     pub debug_ast: bool,
     pub do_resolve_module: bool,
     pub print_module: bool,
+    /// Write the IR next to the source file as `.wir`, after optimisation, only used by
+    /// [`compile`].
+    pub emit_ir: bool,
+    /// Optimisation of the IR before code generation.
+    pub opt_level: OptLevel,
     pub do_codegen: bool,
+    pub target: Target,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Parse,
+    Resolve,
+    Codegen,
+}
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Stage::Parse => write!(f, "syntax error"),
+            Stage::Resolve => write!(f, "error"),
+            Stage::Codegen => write!(f, "codegen error"),
+        }
+    }
+}
+
+/// A message reported by one of the compile stages.
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub stage: Stage,
+    /// `None` for errors that are not tied to a place in the source, e.g. a missing main function.
+    pub span: Option<Span>,
+    pub message: String,
+}
+impl Diagnostic {
+    /// Formats the diagnostic with the span resolved to lines and columns.
+    pub fn display<'a>(&'a self, sources: &'a SourceMap) -> impl fmt::Display + 'a {
+        DisplayDiagnostic {
+            diag: self,
+            sources,
+        }
+    }
+}
+
+struct DisplayDiagnostic<'a> {
+    diag: &'a Diagnostic,
+    sources: &'a SourceMap,
+}
+impl fmt::Display for DisplayDiagnostic<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.diag.span {
+            Some(span) => write!(
+                f,
+                "{}: {}",
+                self.sources.display_span(span),
+                self.diag.message
+            ),
+            None => write!(f, "{}: {}", self.diag.stage, self.diag.message),
+        }
+    }
 }
 
-pub fn compile(source_path: PathBuf, switches: CompileSwitch) {
-    let source_name = source_path
+/// Everything produced by compiling one file of a [`SourceMap`].
+///
+/// Each stage only runs when the previous one produced its output and the switches ask for it, so
+/// later fields are `None` when an earlier stage failed.
+pub struct CompileOutput {
+    pub file: FileId,
+    pub tokens: Vec<Token>,
+    pub ast: Option<AST>,
+    pub module: Option<Module>,
+    /// Set instead of `module` when resolving failed.
+    pub resolve_errors: Option<Box<ResolveErrors>>,
+    pub ir: Option<ir::Module>,
+    pub program: Option<Program>,
+    /// Set instead of `program` for [`Target::C`].
+    pub c_source: Option<String>,
+    /// Set instead of `program` for [`Target::X86_64`].
+    pub asm_source: Option<String>,
+    /// Set instead of `program` for [`Target::Wasm`].
+    pub wasm: Option<Vec<u8>>,
+    /// Set instead of `program` for [`Target::Reg`].
+    pub reg_program: Option<wsk_vm::reg::Program>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+impl CompileOutput {
+    pub fn has_errors(&self) -> bool {
+        !self.diagnostics.is_empty()
+    }
+
+    /// The symbols and source index of the resolve stage, available even when it failed.
+    pub fn symbols(&self) -> Option<(&SymbolTable, &SourceIndex)> {
+        if let Some(module) = &self.module {
+            Some((&module.sym_table, &module.index))
+        } else {
+            self.resolve_errors
+                .as_ref()
+                .map(|errs| (&errs.sym_table, &errs.index))
+        }
+    }
+}
+
+/// Compiles `file` without touching the filesystem or printing anything.
+pub fn compile_file(sources: &SourceMap, file: FileId, switches: &CompileSwitch) -> CompileOutput {
+    let source_file = sources.get(file);
+    let source = source_file.source.as_str();
+    let module_name = Path::new(&source_file.name)
         .file_stem()
-        .expect("valid file name")
-        .to_str()
-        .expect("valid file name enc")
+        .and_then(|s| s.to_str())
+        .unwrap_or(&source_file.name)
         .to_owned();
 
+    let mut output = CompileOutput {
+        file,
+        tokens: Lexer::new(file, source).tokenize(),
+        ast: None,
+        module: None,
+        resolve_errors: None,
+        ir: None,
+        program: None,
+        c_source: None,
+        asm_source: None,
+        wasm: None,
+        reg_program: None,
+        diagnostics: Vec::new(),
+    };
+
     if !switches.do_parse_ast {
-        return;
+        return output;
     }
-    let ast = match ast::parse(&source_path) {
-        Ok(ast) => ast,
-        Err(errors) => {
-            dbg!(&errors);
-            return;
+    let (ast, parse_errors) = ast::parse(file, source);
+    output
+        .diagnostics
+        .extend(parse_errors.into_iter().map(|e| Diagnostic {
+            stage: Stage::Parse,
+            span: Some(e.1),
+            message: e.0.to_string(),
+        }));
+
+    if switches.do_resolve_module {
+        // resolve even with syntax errors, the recovered items may still have errors worth reporting.
+        match lowering::resolve(&ast, module_name) {
+            Ok(module) => output.module = Some(module),
+            Err(errs) => {
+                output
+                    .diagnostics
+                    .extend(errs.errors.iter().map(|e| Diagnostic {
+                        stage: Stage::Resolve,
+                        span: Some(e.span()),
+                        message: e.message(&errs.sym_table, sources),
+                    }));
+                output.resolve_errors = Some(errs);
+            }
         }
-    };
-    if switches.debug_ast {
-        dbg!(&ast);
     }
+    output.ast = Some(ast);
 
-    if !switches.do_resolve_module {
-        return;
+    if !switches.do_codegen || output.has_errors() {
+        return output;
     }
-    let module = match lowering::resolve(&ast, source_name) {
-        Ok(module) => module,
-        Err(errs) => {
-            dbg!(&errs);
-            return;
+    let Some(module) = &output.module else {
+        return output;
+    };
+    let result = ir::build(module)
+        .map_err(|e| e.to_string())
+        .and_then(|mut ir_module| {
+            // a broken IR is a compiler bug, report it rather than generating broken code.
+            ir::verify(&ir_module).map_err(|e| format!("invalid IR: {}", e))?;
+            if switches.opt_level > OptLevel::O0 {
+                ir::opt::optimize(&mut ir_module, switches.opt_level);
+                ir::verify(&ir_module)
+                    .map_err(|e| format!("invalid IR after optimisation: {}", e))?;
+            }
+            let result = match switches.target {
+                Target::WskVm => codegen_wsk_vm(&ir_module).map(|v| output.program = Some(v)),
+                Target::C => codegen_c(&ir_module).map(|v| output.c_source = Some(v)),
+                Target::X86_64 => codegen_x86_64(&ir_module).map(|v| output.asm_source = Some(v)),
+                Target::Wasm => codegen_wasm(&ir_module).map(|v| output.wasm = Some(v)),
+                Target::Reg => codegen_reg_vm(&ir_module).map(|v| output.reg_program = Some(v)),
+            };
+            output.ir = Some(ir_module);
+            result.map_err(|e| e.to_string())
+        });
+    if let Err(message) = result {
+        output.diagnostics.push(Diagnostic {
+            stage: Stage::Codegen,
+            span: None,
+            message,
+        });
+    }
+
+    output
+}
+
+/// Compiles a single source text, see [`compile_file`].
+pub fn compile_source(
+    name: &str,
+    source: &str,
+    switches: &CompileSwitch,
+) -> (SourceMap, CompileOutput) {
+    let mut sources = SourceMap::new();
+    let file = sources.add_file(name, source);
+    let output = compile_file(&sources, file, switches);
+    (sources, output)
+}
+
+/// Compiles each `(name, source)` pair of a virtual file map on its own.
+pub fn compile_files<'a>(
+    files: impl IntoIterator<Item = (&'a str, &'a str)>,
+    switches: &CompileSwitch,
+) -> (SourceMap, Vec<CompileOutput>) {
+    let mut sources = SourceMap::new();
+    let ids: Vec<_> = files
+        .into_iter()
+        .map(|(name, source)| sources.add_file(name, source))
+        .collect();
+    let outputs = ids
+        .into_iter()
+        .map(|file| compile_file(&sources, file, switches))
+        .collect();
+    (sources, outputs)
+}
+
+/// Compiles the file at `source_path`, printing the diagnostics and writing the binary next to it.
+///
+/// Returns whether the compilation succeeded.
+pub fn compile(source_path: PathBuf, switches: CompileSwitch) -> bool {
+    let source = match fs::read_to_string(&source_path) {
+        Ok(source) => source,
+        Err(e) => {
+            eprintln!("whiskc: cannot read {}: {}", source_path.display(), e);
+            return false;
         }
     };
-    if switches.print_module {
+
+    let (sources, output) = compile_source(&source_path.display().to_string(), &source, &switches);
+    for diag in &output.diagnostics {
+        eprintln!("{}", diag.display(&sources));
+    }
+    if let (true, Some(ast)) = (switches.debug_ast, &output.ast) {
+        eprintln!("{:#?}", ast);
+    }
+    if let (true, Some(module)) = (switches.print_module, &output.module) {
         let mut s = String::new();
         module.pretty_print(&mut s);
         println!("{}", s);
     }
 
-    if !switches.do_codegen {
-        return;
+    if let (true, Some(ir_module)) = (switches.emit_ir, &output.ir) {
+        let mut out_path = source_path.clone();
+        out_path.set_extension("wir");
+        if let Err(e) = fs::write(&out_path, ir_module.to_string()) {
+            eprintln!("whiskc: cannot write {}: {}", out_path.display(), e);
+            return false;
+        }
+        println!("wrote IR to {}", out_path.display());
     }
-    let prog = match codegen_wsk_vm(&module) {
-        Ok(prog) => prog,
-        Err(e) => {
-            eprintln!("{:?}", e);
-            return;
+
+    if let Some(prog) = &output.program {
+        let mut out_path = source_path.clone();
+        out_path.set_extension("wc");
+        let bin = match prog.to_bin() {
+            Ok(bin) => bin,
+            Err(e) => {
+                eprintln!("whiskc: cannot encode {}: {}", out_path.display(), e);
+                return false;
+            }
+        };
+        if let Err(e) = fs::write(&out_path, bin) {
+            eprintln!("whiskc: cannot write {}: {}", out_path.display(), e);
+            return false;
         }
-    };
+        println!("wrote binary to {}", out_path.display());
+    }
+
+    if let Some(c_source) = &output.c_source {
+        let out_path = source_path.with_extension("c");
+        let header_path = source_path.with_file_name("wsk_rt.h");
+        for (path, contents) in [
+            (&out_path, c_source.as_str()),
+            (&header_path, C_RUNTIME_HEADER),
+        ] {
+            if let Err(e) = fs::write(path, contents) {
+                eprintln!("whiskc: cannot write {}: {}", path.display(), e);
+                return false;
+            }
+        }
+        println!("wrote C source to {}", out_path.display());
+    }
+
+    if let Some(asm_source) = &output.asm_source {
+        let out_path = source_path.with_extension("s");
+        if let Err(e) = fs::write(&out_path, asm_source) {
+            eprintln!("whiskc: cannot write {}: {}", out_path.display(), e);
+            return false;
+        }
+        println!("wrote assembly to {}", out_path.display());
+    }
+
+    if let Some(wasm) = &output.wasm {
+        let out_path = source_path.with_extension("wasm");
+        if let Err(e) = fs::write(&out_path, wasm) {
+            eprintln!("whiskc: cannot write {}: {}", out_path.display(), e);
+            return false;
+        }
+        println!("wrote WebAssembly module to {}", out_path.display());
+    }
+
+    if let Some(prog) = &output.reg_program {
+        let out_path = source_path.with_extension("wcr");
+        let bin = match prog.to_bin() {
+            Ok(bin) => bin,
+            Err(e) => {
+                eprintln!("whiskc: cannot encode {}: {}", out_path.display(), e);
+                return false;
+            }
+        };
+        if let Err(e) = fs::write(&out_path, bin) {
+            eprintln!("whiskc: cannot write {}: {}", out_path.display(), e);
+            return false;
+        }
+        println!("wrote register program to {}", out_path.display());
+    }
+
+    !output.has_errors()
+}
 
-    let mut out_path = source_path.clone();
-    out_path.set_extension("wc");
-    println!("wrote binary to {}", out_path.display());
-    fs::write(out_path, prog.to_bin()).unwrap();
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "
+        type Pair = (int, bool);
+        func pair(n int) Pair { (n, n > 0) }
+        func half(n int) Option[int] {
+            if n % 2 == 0 {
+                return Some(n / 2);
+            }
+            None
+        }
+        func main() int {
+            let (n, ok) = pair(4);
+            let h = half(n);
+            if ok { n } else { 0 }
+        }
+    ";
+
+    fn full() -> CompileSwitch {
+        CompileSwitch {
+            do_parse_ast: true,
+            do_resolve_module: true,
+            opt_level: OptLevel::O2,
+            do_codegen: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn every_stage_is_kept_in_the_output() {
+        // the name is never opened, the source is only in memory.
+        let (sources, output) = compile_source("no/such/dir/main.wsk", SOURCE, &full());
+        assert!(output.diagnostics.is_empty());
+        assert_eq!(sources.get(output.file).name, "no/such/dir/main.wsk");
+        assert!(!output.tokens.is_empty());
+        assert!(output.ast.is_some());
+        assert!(output.module.is_some());
+        assert!(output.resolve_errors.is_none());
+        assert!(output.ir.is_some());
+        assert!(output.program.is_some());
+        assert!(output.symbols().is_some());
+        assert!(!Path::new("no/such/dir").exists());
+    }
+
+    #[test]
+    fn switches_stop_after_their_stage() {
+        let (_, output) = compile_source("test", SOURCE, &CompileSwitch::default());
+        assert!(!output.tokens.is_empty());
+        assert!(output.ast.is_none());
+
+        let switches = CompileSwitch {
+            do_parse_ast: true,
+            do_resolve_module: true,
+            ..Default::default()
+        };
+        let (_, output) = compile_source("test", SOURCE, &switches);
+        assert!(output.module.is_some());
+        assert!(output.ir.is_none());
+        assert!(output.program.is_none());
+    }
+
+    #[test]
+    fn bad_input_is_reported_without_panicking() {
+        let mut inputs = vec![
+            "",
+            "}}}",
+            "func",
+            "func main(",
+            "func main() int { \"unterminated",
+            "func main() int { let x: = 1; x }",
+            "func main() int { (1, (2, 3).4 }",
+            "func main() int { ? ? ? }",
+            "type A = A; func main() int { 0 }",
+            "func main() int { élan + 1 }",
+            "\u{0}\u{feff}func",
+            "func main() int { let x foo = 1; x }",
+            "func f() Option[foo] { None } func main() int { let x = f(); 1 }",
+        ];
+        // every cut of a valid program.
+        inputs.extend(SOURCE.char_indices().map(|(i, _)| &SOURCE[..i]));
+        for input in inputs {
+            let (sources, output) = compile_source("test", input, &full());
+            // a program exactly when nothing is reported, the cuts in the trailing whitespace are whole.
+            assert_eq!(output.has_errors(), output.program.is_none(), "{:?}", input);
+            // every span points into the source.
+            for diagnostic in &output.diagnostics {
+                let _ = diagnostic.display(&sources).to_string();
+            }
+        }
+    }
+
+    #[test]
+    fn unknown_type_names_are_reported() {
+        for (input, ty) in [
+            ("func main() int { let x foo = 1; 1 }", "foo"),
+            ("func f(n foo) int { 0 } func main() int { f(1) }", "foo"),
+            (
+                "func f() Option[foo] { None } func main() int { let x = f(); 1 }",
+                "foo",
+            ),
+        ] {
+            let (sources, output) = compile_source("test", input, &full());
+            let spans: Vec<&str> = output
+                .diagnostics
+                .iter()
+                .map(|v| sources.slice(v.span.unwrap()))
+                .collect();
+            assert_eq!(spans, [ty], "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn files_are_compiled_on_their_own() {
+        let (sources, outputs) = compile_files(
+            [("good.wsk", SOURCE), ("bad.wsk", "func main() int { x }")],
+            &full(),
+        );
+        assert!(!outputs[0].has_errors());
+        assert_eq!(outputs[1].diagnostics.len(), 1);
+        let span = outputs[1].diagnostics[0].span.unwrap();
+        assert_eq!(span.file, outputs[1].file);
+        assert_eq!(sources.slice(span), "x");
+    }
 }
@@ -0,0 +1,901 @@
+use std::{fmt::Display, fs, path::Path};
+
+use crate::{
+    ast::{
+        self,
+        location::{FileId, Locatable, Located, Span},
+        nodes::{
+            attributes::Attributes,
+            expr::{BinaryExpr, BlockExpr, ElseBody, Expr, IfExpr},
+            func::FunctionSig,
+            item::Item,
+            pattern::Pattern,
+            punctuate::Punctuated,
+            stmt::Stmt,
+            ty::{PrimType, Struct, Type, TypeDecl, TypeDeclKind},
+        },
+        parsing::{
+            lexer::Lexer,
+            token::{Comment, CommentKind, Delimiter, Operator},
+            ParseError,
+        },
+        AST,
+    },
+    source_map::SourceMap,
+};
+
+const MAX_WIDTH: usize = 100;
+const INDENT: &str = "    ";
+
+/// Formats `source` into the canonical style, comments are kept.
+///
+/// Sources with syntax errors are not formatted, the errors are returned instead.
+pub fn format_source(file: FileId, source: &str) -> Result<String, Vec<Located<ParseError>>> {
+    let (ast, errors) = ast::parse(file, source);
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    let (_, comments) = Lexer::new(file, source).tokenize_with_comments();
+
+    let mut f = Formatter::new(source, &comments, 0);
+    f.at_line_start = true;
+    f.fmt_ast(&ast);
+    Ok(f.out)
+}
+
+/// Formats the files at `paths` in place, or with `check` only reports the files that are not
+/// formatted.
+///
+/// Returns whether every file was already formatted (with `check`) or formatted successfully.
+pub fn format_paths<P: AsRef<Path>>(paths: &[P], check: bool) -> bool {
+    let mut ok = true;
+    for path in paths {
+        let path = path.as_ref();
+        let source = match fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(e) => {
+                eprintln!("whiskc: cannot read {}: {}", path.display(), e);
+                ok = false;
+                continue;
+            }
+        };
+
+        let mut sources = SourceMap::new();
+        let file = sources.add_file(path.display().to_string(), source.as_str());
+        let formatted = match format_source(file, &source) {
+            Ok(formatted) => formatted,
+            Err(errors) => {
+                for e in errors {
+                    eprintln!("{}: {}", sources.display_span(e.1), e.0);
+                }
+                ok = false;
+                continue;
+            }
+        };
+        if formatted == source {
+            continue;
+        }
+
+        if check {
+            println!("would reformat {}", path.display());
+            ok = false;
+        } else if let Err(e) = fs::write(path, formatted) {
+            eprintln!("whiskc: cannot write {}: {}", path.display(), e);
+            ok = false;
+        }
+    }
+    ok
+}
+
+struct Formatter<'a> {
+    source: &'a str,
+    /// Comments that are not emitted yet, in source order.
+    comments: &'a [Comment],
+    out: String,
+    indent: usize,
+    at_line_start: bool,
+    /// Source offset right after the last emitted token or comment, used to keep blank lines.
+    last_end: u32,
+    /// Set at the start of a block, where blank lines are dropped.
+    block_start: bool,
+}
+
+/// An item laid out by [`Formatter::fmt_list`].
+struct ListItem<'a> {
+    text: String,
+    /// A comment ending the source line of the item, after its separator, e.g. `1, // one`.
+    trailing: Option<&'a Comment>,
+}
+
+impl<'a> Formatter<'a> {
+    fn new(source: &'a str, comments: &'a [Comment], indent: usize) -> Self {
+        Self {
+            source,
+            comments,
+            out: String::new(),
+            indent,
+            at_line_start: false,
+            last_end: 0,
+            block_start: true,
+        }
+    }
+
+    /// A formatter for laying out a node on its own. It goes on with the comments of the parent,
+    /// which [`Formatter::adopt`]s them back when it keeps the layout.
+    fn child(&self, indent: usize) -> Formatter<'a> {
+        let mut f = Formatter::new(self.source, self.comments, indent);
+        f.last_end = self.last_end;
+        f
+    }
+
+    fn adopt(&mut self, child: Formatter<'a>) {
+        self.comments = child.comments;
+        self.last_end = child.last_end;
+        self.write(child.out);
+    }
+
+    fn write(&mut self, s: impl Display) {
+        let s = s.to_string();
+        if s.is_empty() {
+            return;
+        }
+        if self.at_line_start {
+            for _ in 0..self.indent {
+                self.out.push_str(INDENT);
+            }
+            self.at_line_start = false;
+        }
+        self.out.push_str(&s);
+    }
+
+    /// Writes the token at `span` with the comments before it, so that comments stay next to the
+    /// tokens they were written next to.
+    fn token(&mut self, span: Span, text: impl Display) {
+        self.inline_comments(span.start);
+        self.write(text);
+        self.last_end = span.end;
+        self.closing_comments(span.end);
+    }
+
+    fn newline(&mut self) {
+        self.out.push('\n');
+        self.at_line_start = true;
+    }
+
+    fn column(&self) -> usize {
+        if self.at_line_start {
+            self.indent * INDENT.len()
+        } else {
+            let line = self.out.rsplit('\n').next().unwrap_or("");
+            line.chars().count()
+        }
+    }
+
+    fn has_blank_line(&self, from: u32, to: u32) -> bool {
+        let Some(between) = self.source.get(from as usize..to as usize) else {
+            return false;
+        };
+        let lines: Vec<_> = between.split('\n').collect();
+        lines.len() > 2
+            && lines[1..lines.len() - 1]
+                .iter()
+                .any(|l| l.trim().is_empty())
+    }
+
+    fn has_comment_before(&self, pos: u32) -> bool {
+        self.comments.first().is_some_and(|c| c.loc.start < pos)
+    }
+
+    /// Whether only whitespace is between the start of the source line and `pos`.
+    fn starts_line(&self, pos: u32) -> bool {
+        let before = &self.source[..pos as usize];
+        before.rsplit('\n').next().unwrap_or("").trim().is_empty()
+    }
+
+    /// Whether only whitespace is between `pos` and the end of the source line.
+    fn ends_line(&self, pos: u32) -> bool {
+        let after = &self.source[pos as usize..];
+        after.split('\n').next().unwrap_or("").trim().is_empty()
+    }
+
+    /// Emits the comments before `pos` on their own lines, then keeps a blank line before `pos`
+    /// when `blank_before` is set and the source had one. A comment followed by code on its line
+    /// is left to [`Formatter::token`].
+    fn leading_comments(&mut self, pos: u32, blank_before: bool) {
+        while let Some((comment, rest)) = self.comments.split_first() {
+            if comment.loc.start >= pos || !self.ends_line(comment.loc.end) {
+                break;
+            }
+            self.comments = rest;
+            if !self.at_line_start {
+                self.newline();
+            }
+            if !self.block_start && self.has_blank_line(self.last_end, comment.loc.start) {
+                self.newline();
+            }
+            self.write(&comment.text);
+            self.newline();
+            self.last_end = comment.loc.end;
+            self.block_start = false;
+        }
+
+        if blank_before && !self.block_start && self.has_blank_line(self.last_end, pos) {
+            self.newline();
+        }
+        self.block_start = false;
+    }
+
+    /// Emits the comments before `pos` among the tokens. A comment alone on its source line stays
+    /// alone on its line, and a line comment always ends the line.
+    fn inline_comments(&mut self, pos: u32) {
+        while let Some((comment, rest)) = self.comments.split_first() {
+            if comment.loc.start >= pos {
+                break;
+            }
+            self.comments = rest;
+            let alone = self.starts_line(comment.loc.start)
+                && (comment.kind == CommentKind::Line || self.ends_line(comment.loc.end));
+            let line_is_empty = self.at_line_start || self.out.is_empty();
+            if alone && !line_is_empty {
+                self.newline();
+            } else if !line_is_empty && !self.out.ends_with([' ', '(', '[']) {
+                self.write(" ");
+            }
+            self.write(&comment.text);
+            if alone || comment.kind == CommentKind::Line {
+                self.newline();
+            } else {
+                self.write(" ");
+            }
+            self.last_end = comment.loc.end;
+        }
+    }
+
+    /// Emits the block comments right after `end` that are followed by a closing token on the same
+    /// line, e.g. `(a /* last */)`, as they belong to the token before them.
+    fn closing_comments(&mut self, end: u32) {
+        while let Some((comment, rest)) = self.comments.split_first() {
+            let Some(between) = self.source.get(end as usize..comment.loc.start as usize) else {
+                return;
+            };
+            let after = self.source[comment.loc.end as usize..].trim_start_matches([' ', '\t']);
+            if comment.kind != CommentKind::Block
+                || !between.trim_matches([' ', '\t']).is_empty()
+                || !after.starts_with([',', ')', ']', ';'])
+            {
+                return;
+            }
+            self.comments = rest;
+            self.write(" ");
+            self.write(&comment.text);
+            self.last_end = comment.loc.end;
+        }
+    }
+
+    /// Emits a comment that follows `end` on the same source line.
+    fn trailing_comment(&mut self, end: u32) {
+        let Some((comment, rest)) = self.comments.split_first() else {
+            return;
+        };
+        let on_same_line = self
+            .source
+            .get(end as usize..comment.loc.start as usize)
+            .is_some_and(|between| between.trim_matches([' ', '\t']).is_empty());
+        if comment.loc.start >= end && on_same_line {
+            self.comments = rest;
+            self.write(" ");
+            self.write(&comment.text);
+            self.last_end = comment.loc.end;
+        }
+    }
+
+    /// Takes the comment ending the source line of a list item ending at `end`, after the
+    /// separator of the item.
+    fn separator_comment(&mut self, end: u32) -> Option<&'a Comment> {
+        let (comment, rest) = self.comments.split_first()?;
+        let between = self
+            .source
+            .get(end as usize..comment.loc.start as usize)?
+            .trim_matches([' ', '\t']);
+        let between = between.strip_prefix(',').unwrap_or(between);
+        if !between.trim_matches([' ', '\t']).is_empty() || !self.ends_line(comment.loc.end) {
+            return None;
+        }
+        self.comments = rest;
+        self.last_end = comment.loc.end;
+        Some(comment)
+    }
+
+    fn fmt_ast(&mut self, ast: &AST) {
+        for (i, item) in ast.items.iter().enumerate() {
+            let loc = item.get_location();
+            if i > 0 {
+                self.newline();
+                // items are always separated by one blank line.
+                self.block_start = true;
+            }
+            self.leading_comments(loc.start, true);
+            self.fmt_item(item);
+            self.last_end = loc.end;
+            self.trailing_comment(loc.end);
+            self.newline();
+        }
+
+        self.leading_comments(u32::MAX, false);
+    }
+
+    fn fmt_item(&mut self, item: &Item) {
+        match item {
+            Item::Function(func) => {
+                self.fmt_attributes(&func.sig.attributes);
+                self.fmt_sig(&func.sig, " {".len());
+                self.write(" ");
+                self.fmt_block(&func.body);
+            }
+            Item::ExternFunction(func) => {
+                self.fmt_attributes(&func.sig.attributes);
+                self.token(func.extern_tok.1, "extern");
+                self.write(" ");
+                self.fmt_sig(&func.sig, ";".len());
+                self.token(func.semi_tok.1, ";");
+            }
+            Item::TypeDecl(decl) => self.fmt_type_decl(decl),
+            Item::Error(span) => self.write_source(*span),
+        }
+    }
+
+    fn fmt_attributes(&mut self, attributes: &Attributes) {
+        for attrib in &attributes.attribs {
+            self.token(attrib.1, attrib.0);
+            self.write(" ");
+        }
+    }
+
+    /// Writes the signature from the `func` keyword on, attributes are left to the caller since
+    /// extern functions put them before `extern`. `after` is the width of what the caller writes
+    /// after the signature on its line.
+    fn fmt_sig(&mut self, sig: &FunctionSig, after: usize) {
+        self.token(sig.func_tok.1, "func");
+        self.write(" ");
+        self.token(sig.name.1, &sig.name.0);
+
+        let params = self.fmt_items(&sig.params, |f, param| {
+            f.token(param.0 .1, &param.0 .0);
+            f.write(" ");
+            f.fmt_type(&param.1);
+        });
+        let has_ret = !matches!(sig.ret_ty, Type::Primitive(Located(PrimType::Unit, _)));
+        // the return type follows the parameters on the line of their closing parenthesis.
+        let ret_width = match has_ret {
+            true => {
+                let mut f = self.child(self.indent);
+                f.fmt_type(&sig.ret_ty);
+                " ".len() + f.out.lines().next().unwrap_or("").len()
+            }
+            false => 0,
+        };
+        self.fmt_list(
+            &sig.paren_open_tok,
+            params,
+            &sig.paren_close_tok,
+            false,
+            ret_width + after,
+        );
+
+        if has_ret {
+            self.write(" ");
+            self.fmt_type(&sig.ret_ty);
+        }
+    }
+
+    fn fmt_type_decl(&mut self, decl: &TypeDecl) {
+        self.fmt_attributes(&decl.attributes);
+        self.token(decl.ty_tok.1, "type");
+        self.write(" ");
+        self.token(decl.name.1, &decl.name.0);
+        self.write(" ");
+        if let Some(assign_tok) = &decl.assign_tok {
+            self.token(assign_tok.1, "=");
+            self.write(" ");
+        }
+        match &decl.kind {
+            TypeDeclKind::Type(ty) => self.fmt_type(ty),
+            TypeDeclKind::Struct(s) => self.fmt_struct(s),
+        }
+        self.token(decl.semi_tok.1, ";");
+    }
+
+    fn fmt_struct(&mut self, s: &Struct) {
+        self.token(s.struct_tok.1, "struct");
+        self.write(" ");
+        self.token(s.brace_open_tok.1, "{");
+        let close = s.brace_close_tok.1.start;
+        if s.fields.items.is_empty() && !self.has_comment_before(close) {
+            self.token(s.brace_close_tok.1, "}");
+            return;
+        }
+
+        self.newline();
+        self.indent += 1;
+        self.block_start = true;
+        for field in &s.fields.items {
+            let loc = field.get_location();
+            self.leading_comments(loc.start, true);
+            self.token(field.name.1, &field.name.0);
+            self.write(" ");
+            self.fmt_type(&field.ty);
+            self.write(",");
+            self.last_end = loc.end;
+            // the comma may sit between the field and its trailing comment.
+            if self.source.as_bytes().get(loc.end as usize) == Some(&b',') {
+                self.trailing_comment(loc.end + 1);
+            } else {
+                self.trailing_comment(loc.end);
+            }
+            self.newline();
+        }
+        self.leading_comments(close, false);
+        self.indent -= 1;
+        self.token(s.brace_close_tok.1, "}");
+    }
+
+    fn fmt_type(&mut self, ty: &Type) {
+        match ty {
+            Type::Primitive(ty) => self.token(
+                ty.1,
+                match ty.0 {
+                    PrimType::Unit => "()",
+                    PrimType::Int => "int",
+                    PrimType::Bool => "bool",
+                },
+            ),
+            Type::Ident(name) => self.token(name.1, &name.0),
+            Type::Generic(ty) => {
+                self.token(ty.name.1, &ty.name.0);
+                let args = self.fmt_items(&ty.args, Self::fmt_type);
+                self.fmt_list(&ty.bracket_open_tok, args, &ty.bracket_close_tok, false, 0);
+            }
+            Type::Tuple(ty) => {
+                let elems = self.fmt_items(&ty.elems, Self::fmt_type);
+                self.fmt_list(&ty.paren_open_tok, elems, &ty.paren_close_tok, true, 0);
+            }
+        }
+    }
+
+    fn fmt_pattern(&mut self, pat: &Pattern) {
+        match pat {
+            Pattern::Ident(name) => self.token(name.1, &name.0),
+            Pattern::Tuple(pat) => {
+                let elems = self.fmt_items(&pat.elems, Self::fmt_pattern);
+                self.fmt_list(&pat.paren_open_tok, elems, &pat.paren_close_tok, true, 0);
+            }
+        }
+    }
+
+    fn fmt_block(&mut self, block: &BlockExpr) {
+        self.token(block.brace_open_tok.1, "{");
+        let close = block.brace_close_tok.1.start;
+        if block.stmts.is_empty() && block.eval_expr.is_none() && !self.has_comment_before(close) {
+            self.token(block.brace_close_tok.1, "}");
+            return;
+        }
+
+        self.indent += 1;
+        self.trailing_comment(self.last_end);
+        self.newline();
+        self.block_start = true;
+        for stmt in &block.stmts {
+            let loc = stmt_location(stmt);
+            self.leading_comments(loc.start, true);
+            self.fmt_stmt(stmt);
+            self.last_end = loc.end;
+            self.trailing_comment(loc.end);
+            self.newline();
+        }
+        if let Some(expr) = &block.eval_expr {
+            let loc = expr.get_location();
+            self.leading_comments(loc.start, true);
+            self.fmt_expr(expr);
+            self.last_end = loc.end;
+            self.trailing_comment(loc.end);
+            self.newline();
+        }
+        self.leading_comments(close, false);
+        self.indent -= 1;
+        self.token(block.brace_close_tok.1, "}");
+    }
+
+    fn fmt_stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Expr(stmt) => {
+                self.fmt_expr(&stmt.expr);
+                if let Some(semi_tok) = &stmt.semi_tok {
+                    self.token(semi_tok.1, ";");
+                }
+            }
+            Stmt::Let(stmt) => {
+                self.token(stmt.let_tok.1, "let");
+                self.write(" ");
+                self.fmt_pattern(&stmt.pat);
+                if let Some(ty) = &stmt.ty {
+                    self.write(" ");
+                    self.fmt_type(ty);
+                }
+                self.write(" ");
+                self.token(stmt.assign_tok.1, "=");
+                self.write(" ");
+                self.fmt_expr(&stmt.value);
+                self.token(stmt.semi_tok.1, ";");
+            }
+        }
+    }
+
+    fn fmt_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Unit(span) => self.token(*span, "()"),
+            Expr::Integer(v) => self.token(v.1, v.0),
+            Expr::Bool(v) => self.token(v.1, v.0),
+            Expr::Identifier(v) => self.token(v.1, &v.0),
+            Expr::Unary(expr) => {
+                self.token(expr.op.1, expr.op.0);
+                self.fmt_expr(&expr.expr);
+            }
+            Expr::Binary(expr) => self.fmt_binary(expr),
+            Expr::Grouped(expr) => {
+                self.token(expr.paren_open_tok.1, "(");
+                self.fmt_expr(&expr.expr);
+                self.token(expr.paren_close_tok.1, ")");
+            }
+            Expr::Call(expr) => {
+                self.fmt_expr(&expr.caller);
+                let args = self.fmt_items(&expr.args, Self::fmt_expr);
+                self.fmt_list(&expr.paren_open_tok, args, &expr.paren_close_tok, false, 0);
+            }
+            Expr::Block(expr) => self.fmt_block(expr),
+            Expr::Return(expr) => {
+                self.token(expr.return_tok.1, "return");
+                if let Some(expr) = &expr.expr {
+                    self.write(" ");
+                    self.fmt_expr(expr);
+                }
+            }
+            Expr::If(expr) => self.fmt_if(expr, true),
+            Expr::Loop(expr) => {
+                self.token(expr.loop_tok.1, "loop");
+                self.write(" ");
+                self.fmt_block(&expr.body);
+            }
+            Expr::Try(expr) => {
+                self.fmt_expr(&expr.expr);
+                self.token(expr.try_tok.1, "?");
+            }
+            Expr::Tuple(expr) => {
+                let elems = self.fmt_items(&expr.elems, Self::fmt_expr);
+                self.fmt_list(&expr.paren_open_tok, elems, &expr.paren_close_tok, true, 0);
+            }
+            Expr::TupleIndex(expr) => {
+                self.fmt_expr(&expr.expr);
+                self.token(expr.dot_tok.1, ".");
+                self.token(expr.index.1, expr.index.0);
+            }
+            Expr::Error(span) => self.write_source(*span),
+        }
+    }
+
+    /// Long chains of the same operator group are broken before each operator.
+    fn fmt_binary(&mut self, expr: &BinaryExpr) {
+        let mut f = self.child(self.indent);
+        f.fmt_expr(&expr.left);
+        f.write(" ");
+        f.token(expr.op.1, expr.op.0);
+        f.write(" ");
+        f.fmt_expr(&expr.right);
+        let group = op_group(expr.op.0);
+        if group.is_none() || (!f.out.contains('\n') && self.column() + f.out.len() <= MAX_WIDTH) {
+            self.adopt(f);
+            return;
+        }
+
+        let mut operands = vec![(expr.op, &expr.right)];
+        let mut first = &expr.left;
+        while let Expr::Binary(left) = first.as_ref() {
+            if op_group(left.op.0) != group {
+                break;
+            }
+            operands.push((left.op, &left.right));
+            first = &left.left;
+        }
+
+        self.fmt_expr(first);
+        self.indent += 1;
+        for (op, right) in operands.into_iter().rev() {
+            self.newline();
+            self.token(op.1, op.0);
+            self.write(" ");
+            self.fmt_expr(right);
+        }
+        self.indent -= 1;
+    }
+
+    /// Formats each item on its own for [`Formatter::fmt_list`], taking the comments among the
+    /// items along.
+    fn fmt_items<T>(
+        &mut self,
+        items: &Punctuated<T>,
+        mut fmt: impl FnMut(&mut Self, &T),
+    ) -> Vec<ListItem<'a>> {
+        let mut out = vec![];
+        for item in &items.items {
+            let mut f = self.child(self.indent + 1);
+            fmt(&mut f, item);
+            self.comments = f.comments;
+            self.last_end = f.last_end;
+            let trailing = self.separator_comment(f.last_end);
+            out.push(ListItem {
+                text: f.out,
+                trailing,
+            });
+        }
+        out
+    }
+
+    /// `allow_flat` is unset for the `else if` parts of a chain, which follow the layout of the
+    /// chain's head.
+    fn fmt_if(&mut self, expr: &IfExpr, allow_flat: bool) {
+        // a short if-else without statements or comments fits on one line.
+        let loc = expr.get_location();
+        if allow_flat && is_simple_if(expr) && !self.has_comment_before(loc.end) {
+            let mut f = self.child(self.indent);
+            f.fmt_if_flat(expr);
+            if !f.out.contains('\n') && self.column() + f.out.len() <= MAX_WIDTH {
+                self.adopt(f);
+                return;
+            }
+        }
+
+        self.token(expr.if_tok.1, "if");
+        self.write(" ");
+        self.fmt_expr(&expr.cond);
+        self.write(" ");
+        self.fmt_block(&expr.then);
+        if let Some(else_expr) = &expr.else_expr {
+            self.write(" ");
+            self.token(else_expr.else_tok.1, "else");
+            self.write(" ");
+            match &else_expr.body {
+                ElseBody::Block(block) => self.fmt_block(block),
+                ElseBody::If(if_expr) => self.fmt_if(if_expr, false),
+            }
+        }
+    }
+
+    /// Writes `if c { a } else { b }`, only valid for [`is_simple_if`] expressions.
+    fn fmt_if_flat(&mut self, expr: &IfExpr) {
+        let flat_block = |f: &mut Self, block: &BlockExpr| {
+            if let Some(eval_expr) = &block.eval_expr {
+                f.token(block.brace_open_tok.1, "{");
+                f.write(" ");
+                f.fmt_expr(eval_expr);
+                f.write(" ");
+                f.token(block.brace_close_tok.1, "}");
+            }
+        };
+        self.token(expr.if_tok.1, "if");
+        self.write(" ");
+        self.fmt_expr(&expr.cond);
+        self.write(" ");
+        flat_block(self, &expr.then);
+        if let Some(else_expr) = &expr.else_expr {
+            if let ElseBody::Block(block) = &else_expr.body {
+                self.write(" ");
+                self.token(else_expr.else_tok.1, "else");
+                self.write(" ");
+                flat_block(self, block);
+            }
+        }
+    }
+
+    /// Writes the items on one line when they fit, otherwise one item per line with a trailing
+    /// separator. `one_tuple` keeps the comma of a single element tuple and `after` is the width
+    /// of the text that has to fit after the closing delimiter.
+    fn fmt_list(
+        &mut self,
+        open: &Located<Delimiter>,
+        items: Vec<ListItem<'a>>,
+        close: &Located<Delimiter>,
+        one_tuple: bool,
+        after: usize,
+    ) {
+        self.token(open.1, open.0);
+        let texts: Vec<_> = items.iter().map(|v| v.text.as_str()).collect();
+        let mut flat = texts.join(", ");
+        if one_tuple && items.len() == 1 {
+            flat.push(',');
+        }
+        flat.push_str(&close.0.to_string());
+
+        let multiline = items
+            .iter()
+            .any(|item| item.text.contains('\n') || item.trailing.is_some())
+            || self.has_comment_before(close.1.start);
+        if !multiline && self.column() + flat.len() + after <= MAX_WIDTH {
+            self.write(flat);
+            self.last_end = close.1.end;
+            self.closing_comments(close.1.end);
+            return;
+        }
+
+        self.newline();
+        self.indent += 1;
+        for item in items {
+            self.write(item.text);
+            self.write(",");
+            if let Some(comment) = item.trailing {
+                self.write(" ");
+                self.write(&comment.text);
+            }
+            self.newline();
+        }
+        self.block_start = true;
+        self.leading_comments(close.1.start, false);
+        self.indent -= 1;
+        self.token(close.1, close.0);
+    }
+
+    fn write_source(&mut self, span: Span) {
+        let text = self
+            .source
+            .get(span.start as usize..span.end as usize)
+            .unwrap_or("");
+        self.token(span, text);
+    }
+}
+
+/// Operators of the same group are chained on one level when broken, assignments are never
+/// broken.
+fn op_group(op: Operator) -> Option<u8> {
+    Some(match op {
+        Operator::Add | Operator::Sub => 0,
+        Operator::Mul | Operator::Div => 1,
+        Operator::Mod => 2,
+        Operator::And => 3,
+        Operator::Or => 4,
+        Operator::Equal
+        | Operator::NotEqual
+        | Operator::Less
+        | Operator::LessEqual
+        | Operator::Greater
+        | Operator::GreaterEqual => 5,
+        _ => return None,
+    })
+}
+
+/// Whether the expression is an `if` with a single `else`, each branch only holding an eval
+/// expression.
+fn is_simple_if(expr: &IfExpr) -> bool {
+    let is_simple_block = |block: &BlockExpr| {
+        block.stmts.is_empty()
+            && block
+                .eval_expr
+                .as_ref()
+                .is_some_and(|expr| !expr.is_block())
+    };
+    is_simple_block(&expr.then)
+        && expr
+            .else_expr
+            .as_ref()
+            .is_some_and(|else_expr| match &else_expr.body {
+                ElseBody::Block(block) => is_simple_block(block),
+                ElseBody::If(_) => false,
+            })
+}
+
+fn stmt_location(stmt: &Stmt) -> Span {
+    match stmt {
+        Stmt::Expr(stmt) => match &stmt.semi_tok {
+            Some(semi_tok) => Span::combine(stmt.expr.get_location(), semi_tok.1),
+            None => stmt.expr.get_location(),
+        },
+        Stmt::Let(stmt) => Span::combine(stmt.let_tok.1, stmt.semi_tok.1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    fn format(source: &str) -> String {
+        format_source(FileId(0), source).expect("the source parses")
+    }
+
+    #[track_caller]
+    fn assert_kept(source: &str) {
+        assert_eq!(format(source), source);
+    }
+
+    #[test]
+    fn formatting_is_idempotent_on_the_test_programs() {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/../test");
+        for entry in fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_none_or(|v| v != "wsk") {
+                continue;
+            }
+            let once = format(&fs::read_to_string(&path).unwrap());
+            assert_eq!(format(&once), once, "{}", path.display());
+        }
+    }
+
+    #[test]
+    fn comment_between_arguments() {
+        assert_kept("func main() {\n    add2(a, /* arg */ b);\n}\n");
+    }
+
+    #[test]
+    fn comments_in_signature() {
+        assert_kept("func add2(a int /* first */, b int) int /* ret */ {\n    a + b\n}\n");
+        assert_kept("extern func f(/* none */ a int) /* ret */ int;\n");
+    }
+
+    #[test]
+    fn comment_after_return() {
+        assert_kept("func f(q int) int {\n    return /* ret val */ q;\n}\n");
+    }
+
+    #[test]
+    fn comment_before_else() {
+        assert_kept(
+            "func f(c bool) {\n    if c {\n        g();\n    } /* mid */ else {\n        h();\n    }\n}\n",
+        );
+    }
+
+    #[test]
+    fn comments_after_list_separators() {
+        assert_kept(
+            "func main() {\n    let p = add2(\n        1, // one\n        2, // two\n    );\n}\n",
+        );
+        assert_kept("type U = (\n    int, // first\n    bool,\n);\n");
+    }
+
+    #[test]
+    fn comments_in_patterns_and_types() {
+        assert_kept("func main() {\n    let (x, /* y */ y) (int, /* e */ bool) = (1, true);\n}\n");
+        assert_kept("type T = Option[/* int */ int];\n");
+    }
+
+    #[test]
+    fn comment_moved_onto_its_line_stays_there() {
+        let once = format("func main() {\n    let x = 1 + // plus\n        2;\n}\n");
+        assert!(once.contains("// plus\n"));
+        assert_eq!(format(&once), once);
+    }
+
+    #[test]
+    fn return_type_counts_toward_the_width_of_the_parameters() {
+        let params =
+            "alpha int, beta int, gamma int, delta int, epsilon int, zeta int, eta int, th int";
+        // the parameters fit on the line but not with the return type after them.
+        let out = format(&format!(
+            "func compute({}) (int, bool, int) {{\n    (1, true, 2)\n}}\n",
+            params
+        ));
+        let broken: String = params
+            .split(", ")
+            .map(|v| format!("    {},\n", v))
+            .collect();
+        assert_eq!(
+            out,
+            format!(
+                "func compute(\n{}) (int, bool, int) {{\n    (1, true, 2)\n}}\n",
+                broken
+            )
+        );
+        assert!(out.lines().all(|v| v.len() <= MAX_WIDTH));
+
+        let out = format(&format!("extern func compute({}) (int, int);\n", params));
+        assert!(out.lines().all(|v| v.len() <= MAX_WIDTH), "{}", out);
+        assert_kept("extern func compute(a int) (int, int);\n");
+    }
+}
@@ -1,26 +1,25 @@
-use std::{
-    collections::HashMap,
-    hash::{DefaultHasher, Hash, Hasher},
-};
+use std::collections::HashMap;
+
+/// Handle of an interned string, dense and numbered in the order the strings were first interned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Symbol(u32);
 
 #[derive(Debug, Default, Clone)]
 pub struct StringInterner {
-    map: HashMap<String, u64>,
-    hasher: DefaultHasher,
+    map: HashMap<String, Symbol>,
 }
 impl StringInterner {
-    pub fn intern(&mut self, s: &str) -> u64 {
-        if let Some(h) = self.map.get(s) {
-            *h
+    pub fn intern(&mut self, s: &str) -> Symbol {
+        if let Some(sym) = self.map.get(s) {
+            *sym
         } else {
-            s.hash(&mut self.hasher);
-            let hash_val = self.hasher.finish();
-            self.map.insert(s.to_owned(), hash_val);
-            hash_val
+            let sym = Symbol(self.map.len() as u32);
+            self.map.insert(s.to_owned(), sym);
+            sym
         }
     }
 
-    pub fn get(&self, s: &str) -> Option<u64> {
+    pub fn get(&self, s: &str) -> Option<Symbol> {
         self.map.get(s).copied()
     }
 }
@@ -0,0 +1,730 @@
+use core::fmt;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use crate::{
+    ast::parsing::token::Operator,
+    lowering::{
+        self,
+        nodes::{
+            expr::{
+                BinaryExpr, BlockExpr, CallExpr, Expr, ExprKind, FuncIdentExpr, IfExpr, LoopExpr,
+                TryExpr, TupleIndexExpr, UnaryExpr, VarIdentExpr, VariantExpr,
+            },
+            item::Item,
+            pattern::Pattern,
+            stmt::Stmt,
+        },
+        visit::{self, Visit},
+    },
+    symbol::{ty::TypeKind, FuncId, SymbolTable, TypeId, VarId},
+};
+
+use super::{
+    BinaryOp, Block, BlockCall, BlockData, CmpOp, Const, FuncRef, Function, Inst, InstKind, Module,
+    Operand, Signature, Terminator, Ty, UnaryOp,
+};
+
+#[derive(Debug)]
+pub enum BuildError {
+    FunctionAsValue,
+}
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildError::FunctionAsValue => {
+                write!(f, "functions can only be called, not used as values")
+            }
+        }
+    }
+}
+
+/// Build the IR of the lowered module, functions keep the order of their items.
+pub fn build(module: &lowering::Module) -> Result<Module, BuildError> {
+    let table = &module.sym_table;
+    let mut refs = HashMap::new();
+    let mut funcs = vec![];
+    for item in &module.items {
+        let (fid, is_extern) = match item {
+            Item::Function(func) => (func.func_id, false),
+            Item::ExternFunction(func) => (func.0, true),
+            Item::TypeDecl(_) => continue,
+        };
+        let sym = fid.sym(table);
+        let sig = Signature {
+            params: sym
+                .params
+                .iter()
+                .flat_map(|v| flatten(table, v.sym(table).ty))
+                .collect(),
+            rets: flatten(table, sym.ret_ty),
+        };
+        refs.insert(fid, FuncRef(funcs.len() as u32));
+        let mut func = Function::new(sym.name.clone(), sig);
+        func.is_extern = is_extern;
+        func.is_pub = sym.is_pub;
+        funcs.push(func);
+    }
+
+    for item in &module.items {
+        let Item::Function(func) = item else {
+            continue;
+        };
+        let fref = refs[&func.func_id];
+        let ir_func = std::mem::replace(
+            &mut funcs[fref.index()],
+            Function::new(String::new(), Signature::default()),
+        );
+        let mut builder = Builder {
+            table,
+            refs: &refs,
+            func: ir_func,
+            current: Block(0),
+            vars: BTreeMap::new(),
+        };
+        builder.build_function(func)?;
+        funcs[fref.index()] = builder.func;
+    }
+
+    Ok(Module {
+        name: module.name.clone(),
+        funcs,
+    })
+}
+
+/// The scalar types a value of the type is made of, tuples are flattened into their elements.
+fn flatten(table: &SymbolTable, ty: TypeId) -> Vec<Ty> {
+    let mut tys = vec![];
+    flatten_into(table, ty, &mut tys);
+    tys
+}
+
+fn flatten_into(table: &SymbolTable, ty: TypeId, tys: &mut Vec<Ty>) {
+    let ty = table.resolve_alias(ty);
+    let common = table.common_type();
+    if ty == common.unit || ty == common.never {
+        return;
+    }
+    match &ty.sym(table).kind {
+        Some(TypeKind::Tuple(elems)) => {
+            for elem in elems {
+                flatten_into(table, *elem, tys);
+            }
+        }
+        // newtypes share the representation of their base type.
+        Some(TypeKind::Ident(base)) => flatten_into(table, *base, tys),
+        _ if ty == common.int => tys.push(Ty::Int),
+        _ if ty == common.bool => tys.push(Ty::Bool),
+        _ => tys.push(Ty::Ref),
+    }
+}
+
+/// The flattened value of an expression, `None` when control does not reach its end, e.g. after a
+/// `return`.
+type Lowered = Result<Option<Vec<Operand>>, BuildError>;
+
+/// Unwrap the value of a subexpression, leaving the current expression when it diverges.
+macro_rules! live {
+    ($lowered:expr) => {
+        match $lowered? {
+            Some(v) => v,
+            None => return Ok(None),
+        }
+    };
+}
+
+/// A block that falls through to the end of a branching expression, with its value and the values
+/// of the variables at its end.
+struct Edge {
+    block: Block,
+    value: Vec<Operand>,
+    vars: BTreeMap<VarId, Vec<Operand>>,
+}
+
+struct Builder<'a> {
+    table: &'a SymbolTable,
+    refs: &'a HashMap<FuncId, FuncRef>,
+    func: Function,
+    current: Block,
+    /// The current values of the variables in scope.
+    vars: BTreeMap<VarId, Vec<Operand>>,
+}
+impl Builder<'_> {
+    fn build_function(&mut self, func: &lowering::nodes::func::Function) -> Result<(), BuildError> {
+        let entry = self.new_block("entry");
+        self.current = entry;
+        for param in &func.func_id.sym(self.table).params {
+            let values = self.new_params(entry, flatten(self.table, param.sym(self.table).ty));
+            self.vars.insert(*param, values);
+        }
+
+        if let Some(value) = self.lower_block(&func.body)? {
+            self.terminate(Terminator::Return(value));
+        }
+        Ok(())
+    }
+
+    fn new_block(&mut self, comment: &'static str) -> Block {
+        self.func.blocks.push(BlockData::new(comment));
+        Block(self.func.blocks.len() as u32 - 1)
+    }
+
+    fn new_params(&mut self, block: Block, tys: Vec<Ty>) -> Vec<Operand> {
+        tys.into_iter()
+            .map(|ty| {
+                let value = self.func.new_value(ty);
+                self.func.block_mut(block).params.push(value);
+                value.into()
+            })
+            .collect()
+    }
+
+    fn push(&mut self, kind: InstKind, tys: Vec<Ty>) -> Vec<Operand> {
+        let results: Vec<_> = tys.into_iter().map(|ty| self.func.new_value(ty)).collect();
+        let operands = results.iter().map(|v| (*v).into()).collect();
+        self.func
+            .block_mut(self.current)
+            .insts
+            .push(Inst { results, kind });
+        operands
+    }
+
+    fn push1(&mut self, kind: InstKind, ty: Ty) -> Operand {
+        self.push(kind, vec![ty])[0]
+    }
+
+    fn terminate(&mut self, term: Terminator) {
+        self.func.block_mut(self.current).term = term;
+    }
+
+    fn flatten(&self, ty: TypeId) -> Vec<Ty> {
+        flatten(self.table, ty)
+    }
+
+    fn lower_expr(&mut self, expr: &Expr) -> Lowered {
+        match &expr.kind {
+            ExprKind::Unit => Ok(Some(vec![])),
+            ExprKind::Integer(v) => Ok(Some(vec![(*v).into()])),
+            ExprKind::Bool(v) => Ok(Some(vec![(*v).into()])),
+            ExprKind::VarIdent(VarIdentExpr { id }) => Ok(Some(self.vars[id].clone())),
+            ExprKind::FuncIdent(_) => Err(BuildError::FunctionAsValue),
+            ExprKind::Unary(v) => self.lower_unary(v, expr.ty),
+            ExprKind::Binary(v) => self.lower_binary(v, expr.ty),
+            ExprKind::Call(v) => self.lower_call(v, expr.ty),
+            ExprKind::Block(v) => self.lower_block(v),
+            ExprKind::Return(v) => {
+                let value = match &v.expr {
+                    Some(expr) => live!(self.lower_expr(expr)),
+                    None => vec![],
+                };
+                self.terminate(Terminator::Return(value));
+                Ok(None)
+            }
+            ExprKind::If(v) => self.lower_if(v),
+            ExprKind::Loop(v) => self.lower_loop(v),
+            ExprKind::Variant(v) => self.lower_variant(v),
+            ExprKind::Try(v) => self.lower_try(v),
+            ExprKind::Tuple(v) => {
+                let mut values = vec![];
+                for elem in &v.elems {
+                    values.extend(live!(self.lower_expr(elem)));
+                }
+                Ok(Some(values))
+            }
+            ExprKind::TupleIndex(v) => self.lower_tuple_index(v),
+            // newtypes share the representation of their base type.
+            ExprKind::Cast(v) => self.lower_expr(&v.expr),
+        }
+    }
+
+    fn lower_unary(&mut self, expr: &UnaryExpr, ty: TypeId) -> Lowered {
+        let value = live!(self.lower_expr(&expr.expr))[0];
+        let op = match expr.op {
+            Operator::Sub => UnaryOp::Neg,
+            Operator::Not => UnaryOp::Not,
+            _ => unimplemented!("lower unary op {}", expr.op),
+        };
+        let ty = self.flatten(ty)[0];
+        Ok(Some(vec![self.push1(InstKind::Unary(op, value), ty)]))
+    }
+
+    fn lower_binary(&mut self, expr: &BinaryExpr, ty: TypeId) -> Lowered {
+        if expr.op == Operator::Assign {
+            let value = live!(self.lower_expr(&expr.right));
+            let ExprKind::VarIdent(VarIdentExpr { id }) = &expr.left.kind else {
+                unimplemented!("unsupported assignment type")
+            };
+            self.vars.insert(*id, value);
+            return Ok(Some(vec![]));
+        }
+
+        let left = live!(self.lower_expr(&expr.left))[0];
+        let right = live!(self.lower_expr(&expr.right))[0];
+        let kind = match expr.op {
+            Operator::Add => InstKind::Binary(BinaryOp::Add, left, right),
+            Operator::Sub => InstKind::Binary(BinaryOp::Sub, left, right),
+            Operator::Mul => InstKind::Binary(BinaryOp::Mul, left, right),
+            Operator::Div => InstKind::Binary(BinaryOp::Div, left, right),
+            Operator::Mod => InstKind::Binary(BinaryOp::Mod, left, right),
+            Operator::And => InstKind::Binary(BinaryOp::And, left, right),
+            Operator::Or => InstKind::Binary(BinaryOp::Or, left, right),
+            Operator::Equal => InstKind::Cmp(CmpOp::Equal, left, right),
+            Operator::NotEqual => InstKind::Cmp(CmpOp::NotEqual, left, right),
+            Operator::Less => InstKind::Cmp(CmpOp::Less, left, right),
+            Operator::LessEqual => InstKind::Cmp(CmpOp::LessEqual, left, right),
+            Operator::Greater => InstKind::Cmp(CmpOp::Greater, left, right),
+            Operator::GreaterEqual => InstKind::Cmp(CmpOp::GreaterEqual, left, right),
+            _ => unimplemented!("lower binary op {}", expr.op),
+        };
+        let ty = self.flatten(ty)[0];
+        Ok(Some(vec![self.push1(kind, ty)]))
+    }
+
+    fn lower_call(&mut self, expr: &CallExpr, ty: TypeId) -> Lowered {
+        let ExprKind::FuncIdent(FuncIdentExpr { id }) = expr.caller.kind else {
+            unimplemented!("unsupported function call type")
+        };
+        let mut args = vec![];
+        for arg in &expr.args {
+            args.extend(live!(self.lower_expr(arg)));
+        }
+        let results = self.push(InstKind::Call(self.refs[&id], args), self.flatten(ty));
+
+        if self.table.resolve_alias(ty) == self.table.common_type().never {
+            return Ok(None);
+        }
+        Ok(Some(results))
+    }
+
+    fn lower_block(&mut self, block: &BlockExpr) -> Lowered {
+        for stmt in &block.stmts {
+            match stmt {
+                Stmt::Expr(stmt) => {
+                    live!(self.lower_expr(&stmt.expr));
+                }
+                Stmt::Let(stmt) => {
+                    let value = live!(self.lower_expr(&stmt.value));
+                    self.bind_pattern(&stmt.pat, &value);
+                }
+            }
+        }
+        match &block.eval_expr {
+            Some(expr) => self.lower_expr(expr),
+            None => Ok(Some(vec![])),
+        }
+    }
+
+    /// Bind the flattened value to the variables of the pattern, returning the width it took.
+    fn bind_pattern(&mut self, pat: &Pattern, value: &[Operand]) -> usize {
+        match pat {
+            Pattern::Var(vid) => {
+                let width = self.flatten(vid.sym(self.table).ty).len();
+                self.vars.insert(*vid, value[..width].to_vec());
+                width
+            }
+            Pattern::Tuple(elems) => {
+                let mut offset = 0;
+                for elem in elems {
+                    offset += self.bind_pattern(elem, &value[offset..]);
+                }
+                offset
+            }
+        }
+    }
+
+    fn lower_if(&mut self, expr: &IfExpr) -> Lowered {
+        let mut edges = vec![];
+        for (i, branch) in expr.branches.iter().enumerate() {
+            let cond = live!(self.lower_expr(&branch.cond))[0];
+            let then = self.new_block("then");
+            let is_last = i + 1 == expr.branches.len();
+            let next = self.new_block(if is_last { "else" } else { "elif" });
+            self.terminate(Terminator::Branch {
+                cond,
+                then: then.into(),
+                else_: next.into(),
+            });
+
+            let vars = self.vars.clone();
+            self.current = then;
+            if let Some(value) = self.lower_expr(&branch.body)? {
+                edges.push(self.edge(value));
+            }
+            self.vars = vars;
+            self.current = next;
+        }
+
+        match &expr.else_ {
+            Some(else_) => {
+                if let Some(value) = self.lower_expr(else_)? {
+                    edges.push(self.edge(value));
+                }
+            }
+            // without an else, the last condition's false block falls through with a unit value.
+            None => edges.push(self.edge(vec![])),
+        }
+        Ok(self.merge(edges))
+    }
+
+    fn edge(&self, value: Vec<Operand>) -> Edge {
+        Edge {
+            block: self.current,
+            value,
+            vars: self.vars.clone(),
+        }
+    }
+
+    /// Continue from the end of all the edges, passing the values that differ between them as
+    /// parameters of a merge block.
+    fn merge(&mut self, edges: Vec<Edge>) -> Option<Vec<Operand>> {
+        if edges.len() <= 1 {
+            let edge = edges.into_iter().next()?;
+            self.current = edge.block;
+            self.vars = edge.vars;
+            return Some(edge.value);
+        }
+        let merge = self.new_block("merge");
+
+        let mut args = vec![vec![]; edges.len()];
+        let mut merge_operands = |builder: &mut Self, get: &dyn Fn(&Edge) -> &[Operand]| {
+            let width = get(&edges[0]).len();
+            (0..width)
+                .map(|i| {
+                    let first = get(&edges[0])[i];
+                    if edges.iter().all(|edge| get(edge)[i] == first) {
+                        return first;
+                    }
+                    let ty = builder.func.operand_ty(first);
+                    for (args, edge) in args.iter_mut().zip(&edges) {
+                        args.push(get(edge)[i]);
+                    }
+                    builder.new_params(merge, vec![ty])[0]
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let value = merge_operands(self, &|edge| &edge.value);
+        let mut vars = BTreeMap::new();
+        for vid in edges[0].vars.keys() {
+            if edges.iter().all(|edge| edge.vars.contains_key(vid)) {
+                let value = merge_operands(self, &|edge| &edge.vars[vid]);
+                vars.insert(*vid, value);
+            }
+        }
+
+        for (edge, args) in edges.iter().zip(args) {
+            self.current = edge.block;
+            self.terminate(Terminator::Jump(BlockCall { block: merge, args }));
+        }
+        self.current = merge;
+        self.vars = vars;
+        Some(value)
+    }
+
+    fn lower_loop(&mut self, expr: &LoopExpr) -> Lowered {
+        let mut assigned = AssignedVars::default();
+        assigned.visit_block_expr(&expr.body);
+        let carried: Vec<_> = assigned
+            .0
+            .into_iter()
+            .filter(|v| self.vars.contains_key(v))
+            .collect();
+
+        // the variables assigned in the body are parameters of the loop header.
+        let header = self.new_block("loop");
+        let mut args = vec![];
+        for vid in &carried {
+            let value = self.vars[vid].clone();
+            let tys = value.iter().map(|v| self.func.operand_ty(*v)).collect();
+            args.extend(value);
+            let params = self.new_params(header, tys);
+            self.vars.insert(*vid, params);
+        }
+        self.terminate(Terminator::Jump(BlockCall {
+            block: header,
+            args,
+        }));
+
+        self.current = header;
+        if self.lower_block(&expr.body)?.is_some() {
+            let args = carried
+                .iter()
+                .flat_map(|vid| self.vars[vid].clone())
+                .collect();
+            self.terminate(Terminator::Jump(BlockCall {
+                block: header,
+                args,
+            }));
+        }
+        // a loop is only left by returning.
+        Ok(None)
+    }
+
+    fn lower_variant(&mut self, expr: &VariantExpr) -> Lowered {
+        let mut fields = vec![Const::Int(expr.variant.tag()).into()];
+        if let Some(value) = &expr.value {
+            fields.extend(live!(self.lower_expr(value)));
+        }
+        Ok(Some(
+            vec![self.push1(InstKind::MakeStruct(fields), Ty::Ref)],
+        ))
+    }
+
+    fn lower_try(&mut self, expr: &TryExpr) -> Lowered {
+        let value = live!(self.lower_expr(&expr.expr))[0];
+        let (Some(&TypeKind::Option(ok_ty)) | Some(&TypeKind::Result(ok_ty, _))) =
+            self.table.get_type_kind(expr.expr.ty)
+        else {
+            unreachable!("try operand is resolved to a prelude type")
+        };
+
+        let tag = self.push1(InstKind::GetField(value, 0), Ty::Int);
+        let is_ok = self.push1(InstKind::Cmp(CmpOp::Equal, tag, 1.into()), Ty::Bool);
+        let ok = self.new_block("ok");
+        let fail = self.new_block("fail");
+        self.terminate(Terminator::Branch {
+            cond: is_ok,
+            then: ok.into(),
+            else_: fail.into(),
+        });
+
+        // the failure variants share their layout with the function's return type.
+        self.current = fail;
+        self.terminate(Terminator::Return(vec![value]));
+
+        self.current = ok;
+        if self.table.resolve_alias(ok_ty) == self.table.common_type().never {
+            // e.g. `None?`, the success side is never produced.
+            self.terminate(Terminator::Unreachable);
+            return Ok(None);
+        }
+        let values = self
+            .flatten(ok_ty)
+            .into_iter()
+            .enumerate()
+            .map(|(i, ty)| self.push1(InstKind::GetField(value, 1 + i), ty))
+            .collect();
+        Ok(Some(values))
+    }
+
+    fn lower_tuple_index(&mut self, expr: &TupleIndexExpr) -> Lowered {
+        let value = live!(self.lower_expr(&expr.expr));
+        let Some(TypeKind::Tuple(elems)) = self.table.get_type_kind(expr.expr.ty) else {
+            unreachable!("tuple index operand is resolved to a tuple type")
+        };
+        let offset: usize = elems[..expr.index]
+            .iter()
+            .map(|v| self.flatten(*v).len())
+            .sum();
+        let width = self.flatten(elems[expr.index]).len();
+        Ok(Some(value[offset..offset + width].to_vec()))
+    }
+}
+
+/// The variables assigned anywhere in a loop body.
+#[derive(Default)]
+struct AssignedVars(BTreeSet<VarId>);
+impl Visit for AssignedVars {
+    fn visit_binary_expr(&mut self, node: &BinaryExpr) {
+        if let (Operator::Assign, ExprKind::VarIdent(VarIdentExpr { id })) =
+            (node.op, &node.left.kind)
+        {
+            self.0.insert(*id);
+        }
+        visit::visit_binary_expr(self, node);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wsk_vm::{heap::Object, program::Function, Inst, Value, VM};
+
+    use crate::{
+        compile::{compile_source, CompileSwitch},
+        ir::opt::OptLevel,
+    };
+
+    /// Compile for the stack VM, the IR is verified on the way, and run the function of the name.
+    fn execute(source: &str, func: &str, opt_level: OptLevel) -> VM {
+        let switches = CompileSwitch {
+            do_parse_ast: true,
+            do_resolve_module: true,
+            opt_level,
+            do_codegen: true,
+            ..Default::default()
+        };
+        let (sources, output) = compile_source("test", source, &switches);
+        let messages: Vec<_> = output
+            .diagnostics
+            .iter()
+            .map(|v| v.display(&sources).to_string())
+            .collect();
+        assert!(messages.is_empty(), "{:?}", messages);
+        // the functions of the program are the functions of the IR.
+        let fi = output.ir.unwrap().find(func).unwrap().index();
+        let mut prog = output.program.unwrap();
+        // only `main` can return other than an int, the others get a start function of their own.
+        let start = prog.add_func(Function::from_insts([Inst::Call(fi), Inst::Halt]));
+        prog.set_entry_point(start);
+        let mut vm = VM::default();
+        vm.execute(prog).unwrap();
+        vm
+    }
+
+    fn run(source: &str, opt_level: OptLevel) -> Vec<Value> {
+        execute(source, "main", opt_level).stack().to_vec()
+    }
+
+    /// The tag and the fields of the variant `probe` returns.
+    fn run_variant(source: &str, opt_level: OptLevel) -> Vec<Value> {
+        let vm = execute(source, "probe", opt_level);
+        let [Value::Ref(r)] = vm.stack() else {
+            panic!("probe left {:?}", vm.stack());
+        };
+        match vm.heap().get(*r).unwrap() {
+            Object::Struct(fields) => fields.clone(),
+            object => panic!("probe returned {:?}", object),
+        }
+    }
+
+    #[test]
+    fn try_returns_early_on_none() {
+        let source = |n: i64| {
+            format!(
+                "
+                func half(n int) Option[int] {{
+                    if n % 2 == 0 {{
+                        return Some(n / 2);
+                    }}
+                    None
+                }}
+                func quarter(n int) Option[int] {{
+                    let h = half(n)?;
+                    Some(half(h)? + 100)
+                }}
+                func probe() Option[int] {{ quarter({}) }}
+                func main() int {{ 0 }}
+                ",
+                n
+            )
+        };
+        for opt_level in [OptLevel::O0, OptLevel::O2] {
+            let variant = |n| run_variant(&source(n), opt_level);
+            assert_eq!(variant(12), [Value::Int(1), Value::Int(103)]);
+            // the first and the second `?` return.
+            assert_eq!(variant(5), [Value::Int(0)]);
+            assert_eq!(variant(6), [Value::Int(0)]);
+        }
+    }
+
+    #[test]
+    fn try_returns_the_error_of_a_result() {
+        let source = |n: i64| {
+            format!(
+                "
+                func check(n int) Result[int, bool] {{
+                    if n < 0 {{
+                        return Err(n == 0 - 1);
+                    }}
+                    Ok(n)
+                }}
+                func inc(n int) Result[int, bool] {{
+                    let v = check(n)?;
+                    Ok(v + 1)
+                }}
+                func probe() Result[int, bool] {{ inc({}) }}
+                func main() int {{ 0 }}
+                ",
+                n
+            )
+        };
+        for opt_level in [OptLevel::O0, OptLevel::O2] {
+            let variant = |n| run_variant(&source(n), opt_level);
+            assert_eq!(variant(41), [Value::Int(1), Value::Int(42)]);
+            assert_eq!(variant(-1), [Value::Int(0), Value::Bool(true)]);
+            assert_eq!(variant(-2), [Value::Int(0), Value::Bool(false)]);
+        }
+    }
+
+    #[test]
+    fn tuples_are_returned_indexed_and_destructured() {
+        let source = "
+            func divmod(a int, b int) (int, int) { (a / b, a % b) }
+            func swap(p (int, bool)) (bool, int) { (p.1, p.0) }
+            func main() int {
+                let (q, r) = divmod(17, 5);
+                let (ok, n) = swap((7, true));
+                let nested = ((1, 2), (q, r));
+                let ((a, b), c) = nested;
+                if ok {
+                    return q * 10000 + r * 1000 + n * 100 + nested.1.1 * 10 + a + b + c.0;
+                }
+                0
+            }
+        ";
+        for opt_level in [OptLevel::O0, OptLevel::O2] {
+            assert_eq!(run(source, opt_level), [Value::Int(32726)]);
+        }
+    }
+
+    #[test]
+    fn newtypes_and_aliases_keep_the_value_of_their_base() {
+        let source = "
+            type Meters int;
+            type Len = Meters;
+            func double(m Len) Meters { Meters(m.0 * 2) }
+            func main() int { let m = double(Meters(20)); m.0 + 2 }
+        ";
+        for opt_level in [OptLevel::O0, OptLevel::O2] {
+            assert_eq!(run(source, opt_level), [Value::Int(42)]);
+        }
+    }
+
+    #[test]
+    fn else_if_chain_takes_the_first_true_branch() {
+        let source = "
+            func classify(n int) int {
+                if n < 0 {
+                    1
+                } else if n == 0 {
+                    return 2;
+                } else if n < 10 {
+                    3
+                } else {
+                    4
+                }
+            }
+            func pick(n int) int {
+                let x = 0;
+                if n == 1 {
+                    x = 10;
+                } else if n == 2 {
+                    return 20;
+                }
+                x + 1
+            }
+            func main() int {
+                classify(0 - 5) + classify(0) * 10 + classify(5) * 100 + classify(50) * 1000
+                    + pick(1) * 10000 + pick(2) * 100000 + pick(3) * 10000000
+            }
+        ";
+        for opt_level in [OptLevel::O0, OptLevel::O2] {
+            assert_eq!(run(source, opt_level), [Value::Int(12114321)]);
+        }
+    }
+
+    #[test]
+    fn try_on_value_that_never_succeeds() {
+        let source = "
+            func none() Option[int] { None? }
+            func err() Result[(), bool] { Err(true)? }
+            func main() int {
+                let a = none();
+                let b = err();
+                7
+            }
+        ";
+        for opt_level in [OptLevel::O0, OptLevel::O2] {
+            assert_eq!(run(source, opt_level), [Value::Int(7)]);
+        }
+    }
+}
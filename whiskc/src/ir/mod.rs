@@ -0,0 +1,492 @@
+//! A control flow graph IR between the lowered tree and the backends.
+//!
+//! Functions are made of basic blocks ending in an explicit terminator. Every value is a scalar
+//! defined exactly once, either as a block parameter or as the result of an instruction, and values
+//! flowing into a block from several predecessors are passed as block arguments. Tuples are
+//! flattened into their elements and unit values have no value at all.
+
+use core::fmt;
+use std::collections::HashMap;
+
+mod build;
+pub mod opt;
+mod print;
+mod verify;
+
+pub use build::{build, BuildError};
+pub use verify::{verify, VerifyError};
+
+#[derive(Debug, Clone)]
+pub struct Module {
+    pub name: String,
+    pub funcs: Vec<Function>,
+}
+impl Module {
+    pub fn get(&self, func: FuncRef) -> &Function {
+        &self.funcs[func.index()]
+    }
+
+    pub fn find(&self, name: &str) -> Option<FuncRef> {
+        self.funcs
+            .iter()
+            .position(|v| v.name == name)
+            .map(|v| FuncRef(v as u32))
+    }
+}
+
+/// Index of a function in its module.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct FuncRef(pub u32);
+impl FuncRef {
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Signature {
+    pub params: Vec<Ty>,
+    pub rets: Vec<Ty>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Function {
+    pub name: String,
+    pub sig: Signature,
+    /// Declared by an `extern func`, such a function has no blocks.
+    pub is_extern: bool,
+    /// Declared `pub`, the backends producing modules export it.
+    pub is_pub: bool,
+    /// The first block is the entry, its parameters are the function's parameters.
+    pub blocks: Vec<BlockData>,
+    /// Type of every value, indexed by the value.
+    pub values: Vec<Ty>,
+}
+impl Function {
+    pub fn new(name: String, sig: Signature) -> Self {
+        Self {
+            name,
+            sig,
+            is_extern: false,
+            is_pub: false,
+            blocks: vec![],
+            values: vec![],
+        }
+    }
+
+    pub fn entry(&self) -> Block {
+        Block(0)
+    }
+
+    pub fn block(&self, block: Block) -> &BlockData {
+        &self.blocks[block.index()]
+    }
+
+    pub fn block_mut(&mut self, block: Block) -> &mut BlockData {
+        &mut self.blocks[block.index()]
+    }
+
+    pub fn block_ids(&self) -> impl Iterator<Item = Block> {
+        (0..self.blocks.len() as u32).map(Block)
+    }
+
+    pub fn new_value(&mut self, ty: Ty) -> Value {
+        self.values.push(ty);
+        Value(self.values.len() as u32 - 1)
+    }
+
+    pub fn value_ty(&self, value: Value) -> Ty {
+        self.values[value.index()]
+    }
+
+    pub fn operand_ty(&self, op: Operand) -> Ty {
+        match op {
+            Operand::Value(v) => self.value_ty(v),
+            Operand::Const(c) => c.ty(),
+        }
+    }
+
+    /// Blocks reachable from the entry, in reverse postorder. The first successor of a block is
+    /// visited last, so it comes right after the block when nothing else gets in between.
+    pub fn reverse_postorder(&self) -> Vec<Block> {
+        if self.blocks.is_empty() {
+            return vec![];
+        }
+        let mut visited = vec![false; self.blocks.len()];
+        let mut order = vec![];
+        // (block, whether its successors were pushed)
+        let mut stack = vec![(self.entry(), false)];
+        while let Some((block, done)) = stack.pop() {
+            if done {
+                order.push(block);
+                continue;
+            }
+            if visited[block.index()] {
+                continue;
+            }
+            visited[block.index()] = true;
+            stack.push((block, true));
+            for succ in self.block(block).term.successors() {
+                if !visited[succ.index()] {
+                    stack.push((succ, false));
+                }
+            }
+        }
+        order.reverse();
+        order
+    }
+
+    /// The immediate dominator of every block, `None` for unreachable blocks. The entry is its own
+    /// immediate dominator.
+    pub fn dominators(&self) -> Vec<Option<Block>> {
+        let rpo = self.reverse_postorder();
+        let mut order = vec![usize::MAX; self.blocks.len()];
+        for (i, block) in rpo.iter().enumerate() {
+            order[block.index()] = i;
+        }
+        let preds = self.predecessors();
+
+        let mut idoms = vec![None; self.blocks.len()];
+        let Some(&entry) = rpo.first() else {
+            return idoms;
+        };
+        idoms[entry.index()] = Some(entry);
+
+        let intersect = |idoms: &[Option<Block>], mut a: Block, mut b: Block| {
+            while a != b {
+                while order[a.index()] > order[b.index()] {
+                    a = idoms[a.index()].unwrap();
+                }
+                while order[b.index()] > order[a.index()] {
+                    b = idoms[b.index()].unwrap();
+                }
+            }
+            a
+        };
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for block in rpo.iter().skip(1) {
+                let mut new_idom = None;
+                for pred in &preds[block.index()] {
+                    if idoms[pred.index()].is_none() {
+                        continue;
+                    }
+                    new_idom = Some(match new_idom {
+                        None => *pred,
+                        Some(idom) => intersect(&idoms, *pred, idom),
+                    });
+                }
+                if new_idom.is_some() && idoms[block.index()] != new_idom {
+                    idoms[block.index()] = new_idom;
+                    changed = true;
+                }
+            }
+        }
+        idoms
+    }
+
+    /// Replace the uses of values by other operands, following chains of replacements.
+    pub fn replace_uses(&mut self, map: &HashMap<Value, Operand>) {
+        if map.is_empty() {
+            return;
+        }
+        let resolve = |mut op: Operand| {
+            while let Some(next) = op.as_value().and_then(|v| map.get(&v)) {
+                op = *next;
+            }
+            op
+        };
+        for block in &mut self.blocks {
+            for inst in &mut block.insts {
+                for op in inst.operands_mut() {
+                    *op = resolve(*op);
+                }
+            }
+            for op in block.term.operands_mut() {
+                *op = resolve(*op);
+            }
+        }
+    }
+
+    /// Predecessors of every block, a block appears once for each edge into the target.
+    pub fn predecessors(&self) -> Vec<Vec<Block>> {
+        let mut preds = vec![vec![]; self.blocks.len()];
+        for block in self.block_ids() {
+            for succ in self.block(block).term.successors() {
+                preds[succ.index()].push(block);
+            }
+        }
+        preds
+    }
+}
+
+/// Whether `a` dominates `b`, given the immediate dominators of [`Function::dominators`].
+pub fn dominates(idoms: &[Option<Block>], a: Block, mut b: Block) -> bool {
+    loop {
+        if a == b {
+            return true;
+        }
+        match idoms[b.index()] {
+            Some(idom) if idom != b => b = idom,
+            _ => return false,
+        }
+    }
+}
+
+/// A scalar type, the only kinds of values the backends deal with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Ty {
+    Int,
+    Bool,
+    /// A reference to a heap object, e.g. the values of the prelude types.
+    Ref,
+}
+impl fmt::Display for Ty {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Ty::Int => "int",
+                Ty::Bool => "bool",
+                Ty::Ref => "ref",
+            }
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Value(pub u32);
+impl Value {
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Block(pub u32);
+impl Block {
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Const {
+    Int(i64),
+    Bool(bool),
+}
+impl Const {
+    pub fn ty(self) -> Ty {
+        match self {
+            Const::Int(_) => Ty::Int,
+            Const::Bool(_) => Ty::Bool,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Operand {
+    Value(Value),
+    Const(Const),
+}
+impl Operand {
+    pub fn as_value(self) -> Option<Value> {
+        match self {
+            Operand::Value(v) => Some(v),
+            Operand::Const(_) => None,
+        }
+    }
+}
+impl From<Value> for Operand {
+    fn from(value: Value) -> Self {
+        Self::Value(value)
+    }
+}
+impl From<Const> for Operand {
+    fn from(value: Const) -> Self {
+        Self::Const(value)
+    }
+}
+impl From<i64> for Operand {
+    fn from(value: i64) -> Self {
+        Self::Const(Const::Int(value))
+    }
+}
+impl From<bool> for Operand {
+    fn from(value: bool) -> Self {
+        Self::Const(Const::Bool(value))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BlockData {
+    /// What the block was created for, only used when printing.
+    pub comment: &'static str,
+    pub params: Vec<Value>,
+    pub insts: Vec<Inst>,
+    pub term: Terminator,
+}
+impl BlockData {
+    pub fn new(comment: &'static str) -> Self {
+        Self {
+            comment,
+            params: vec![],
+            insts: vec![],
+            term: Terminator::Missing,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Inst {
+    pub results: Vec<Value>,
+    pub kind: InstKind,
+}
+impl Inst {
+    pub fn operands(&self) -> Vec<Operand> {
+        match &self.kind {
+            InstKind::Unary(_, v) => vec![*v],
+            InstKind::Binary(_, l, r) | InstKind::Cmp(_, l, r) => vec![*l, *r],
+            InstKind::Call(_, args) | InstKind::MakeStruct(args) => args.clone(),
+            InstKind::GetField(v, _) => vec![*v],
+        }
+    }
+
+    pub fn operands_mut(&mut self) -> Vec<&mut Operand> {
+        match &mut self.kind {
+            InstKind::Unary(_, v) => vec![v],
+            InstKind::Binary(_, l, r) | InstKind::Cmp(_, l, r) => vec![l, r],
+            InstKind::Call(_, args) | InstKind::MakeStruct(args) => args.iter_mut().collect(),
+            InstKind::GetField(v, _) => vec![v],
+        }
+    }
+
+    /// Whether removing the instruction when its results are unused keeps the program's behavior.
+    pub fn is_pure(&self) -> bool {
+        !matches!(self.kind, InstKind::Call(..))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum InstKind {
+    Unary(UnaryOp, Operand),
+    Binary(BinaryOp, Operand, Operand),
+    Cmp(CmpOp, Operand, Operand),
+    /// The results are the flattened return values of the callee.
+    Call(FuncRef, Vec<Operand>),
+    /// Pack the operands into a new heap object.
+    MakeStruct(Vec<Operand>),
+    GetField(Operand, usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOp {
+    Neg,
+    Not,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    And,
+    Or,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CmpOp {
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+}
+
+/// A jump to a block, passing values for its parameters.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockCall {
+    pub block: Block,
+    pub args: Vec<Operand>,
+}
+impl From<Block> for BlockCall {
+    fn from(value: Block) -> Self {
+        Self {
+            block: value,
+            args: vec![],
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Terminator {
+    Jump(BlockCall),
+    Branch {
+        cond: Operand,
+        then: BlockCall,
+        else_: BlockCall,
+    },
+    Return(Vec<Operand>),
+    /// Control never reaches the end of the block.
+    Unreachable,
+    /// The terminator of a block that has none yet, never left in a verified function.
+    Missing,
+}
+impl Terminator {
+    pub fn successors(&self) -> impl DoubleEndedIterator<Item = Block> {
+        let succs = match self {
+            Terminator::Jump(call) => vec![call.block],
+            Terminator::Branch { then, else_, .. } => vec![then.block, else_.block],
+            Terminator::Return(_) | Terminator::Unreachable | Terminator::Missing => vec![],
+        };
+        succs.into_iter()
+    }
+
+    pub fn operands(&self) -> Vec<Operand> {
+        match self {
+            Terminator::Jump(call) => call.args.clone(),
+            Terminator::Branch { cond, then, else_ } => [*cond]
+                .into_iter()
+                .chain(then.args.iter().copied())
+                .chain(else_.args.iter().copied())
+                .collect(),
+            Terminator::Return(values) => values.clone(),
+            Terminator::Unreachable | Terminator::Missing => vec![],
+        }
+    }
+
+    pub fn operands_mut(&mut self) -> Vec<&mut Operand> {
+        match self {
+            Terminator::Jump(call) => call.args.iter_mut().collect(),
+            Terminator::Branch { cond, then, else_ } => [cond]
+                .into_iter()
+                .chain(then.args.iter_mut())
+                .chain(else_.args.iter_mut())
+                .collect(),
+            Terminator::Return(values) => values.iter_mut().collect(),
+            Terminator::Unreachable | Terminator::Missing => vec![],
+        }
+    }
+
+    pub fn block_calls(&self) -> Vec<&BlockCall> {
+        match self {
+            Terminator::Jump(call) => vec![call],
+            Terminator::Branch { then, else_, .. } => vec![then, else_],
+            Terminator::Return(_) | Terminator::Unreachable | Terminator::Missing => vec![],
+        }
+    }
+
+    pub fn block_calls_mut(&mut self) -> Vec<&mut BlockCall> {
+        match self {
+            Terminator::Jump(call) => vec![call],
+            Terminator::Branch { then, else_, .. } => vec![then, else_],
+            Terminator::Return(_) | Terminator::Unreachable | Terminator::Missing => vec![],
+        }
+    }
+}
@@ -0,0 +1,258 @@
+use std::collections::HashMap;
+
+use crate::ir::{
+    BinaryOp, Block, CmpOp, Const, Function, InstKind, Operand, Terminator, UnaryOp, Value,
+};
+
+/// Fold instructions whose operands are constants and replace their results by the constants,
+/// operations the vm would fail on (division by zero, overflow) are left for it to fail.
+pub(super) fn run(func: &mut Function) -> bool {
+    let mut changed = false;
+    loop {
+        let mut subst = HashMap::new();
+        fold_insts(func, &mut subst);
+        fold_params(func, &mut subst);
+        func.replace_uses(&subst);
+        let branches = fold_branches(func);
+        if subst.is_empty() && !branches {
+            return changed;
+        }
+        changed = true;
+    }
+}
+
+fn fold_insts(func: &mut Function, subst: &mut HashMap<Value, Operand>) {
+    // fields of the objects made in the function, objects are never written to.
+    let mut structs = HashMap::new();
+    for block in &func.blocks {
+        for inst in &block.insts {
+            if let (InstKind::MakeStruct(fields), [v]) = (&inst.kind, &inst.results[..]) {
+                structs.insert(*v, fields.clone());
+            }
+        }
+    }
+
+    for block in &mut func.blocks {
+        block.insts.retain(|inst| {
+            let folded = match (&inst.kind, &inst.results[..]) {
+                (InstKind::GetField(Operand::Value(obj), index), [_]) => {
+                    structs.get(obj).and_then(|v| v.get(*index)).copied()
+                }
+                (kind, [_]) => fold(kind).map(Operand::Const),
+                _ => None,
+            };
+            match folded {
+                Some(op) => {
+                    subst.insert(inst.results[0], op);
+                    false
+                }
+                None => true,
+            }
+        });
+    }
+}
+
+fn fold(kind: &InstKind) -> Option<Const> {
+    use Const::{Bool, Int};
+    let konst = |op: &Operand| match op {
+        Operand::Const(v) => Some(*v),
+        Operand::Value(_) => None,
+    };
+    Some(match kind {
+        InstKind::Unary(op, v) => match (op, konst(v)?) {
+            (UnaryOp::Neg, Int(v)) => Int(v.checked_neg()?),
+            (UnaryOp::Not, Bool(v)) => Bool(!v),
+            _ => return None,
+        },
+        InstKind::Binary(op, l, r) => match (op, konst(l)?, konst(r)?) {
+            (BinaryOp::Add, Int(l), Int(r)) => Int(l.checked_add(r)?),
+            (BinaryOp::Sub, Int(l), Int(r)) => Int(l.checked_sub(r)?),
+            (BinaryOp::Mul, Int(l), Int(r)) => Int(l.checked_mul(r)?),
+            (BinaryOp::Div, Int(l), Int(r)) => Int(l.checked_div(r)?),
+            (BinaryOp::Mod, Int(l), Int(r)) => Int(l.checked_rem(r)?),
+            (BinaryOp::And, Bool(l), Bool(r)) => Bool(l && r),
+            (BinaryOp::Or, Bool(l), Bool(r)) => Bool(l || r),
+            _ => return None,
+        },
+        InstKind::Cmp(op, l, r) => match (op, konst(l)?, konst(r)?) {
+            (CmpOp::Equal, l, r) => Bool(l == r),
+            (CmpOp::NotEqual, l, r) => Bool(l != r),
+            (CmpOp::Less, Int(l), Int(r)) => Bool(l < r),
+            (CmpOp::LessEqual, Int(l), Int(r)) => Bool(l <= r),
+            (CmpOp::Greater, Int(l), Int(r)) => Bool(l > r),
+            (CmpOp::GreaterEqual, Int(l), Int(r)) => Bool(l >= r),
+            _ => return None,
+        },
+        InstKind::Call(..) | InstKind::MakeStruct(_) | InstKind::GetField(..) => return None,
+    })
+}
+
+/// Remove the parameters that receive the same operand on every edge, other than the parameter
+/// itself passed around a loop. That operand dominates every predecessor, so it can replace the
+/// parameter.
+fn fold_params(func: &mut Function, subst: &mut HashMap<Value, Operand>) {
+    let resolve = |subst: &HashMap<Value, Operand>, mut op: Operand| {
+        while let Some(next) = op.as_value().and_then(|v| subst.get(&v)) {
+            op = *next;
+        }
+        op
+    };
+
+    let preds = func.predecessors();
+    for block in func.block_ids().skip(1) {
+        if preds[block.index()].is_empty() {
+            continue;
+        }
+        let mut i = 0;
+        while i < func.block(block).params.len() {
+            let param = func.block(block).params[i];
+            let mut incoming = None;
+            let mut same = true;
+            for call in func.blocks.iter().flat_map(|v| v.term.block_calls()) {
+                if call.block != block {
+                    continue;
+                }
+                let arg = resolve(subst, call.args[i]);
+                if arg == Operand::Value(param) {
+                    continue;
+                }
+                match incoming {
+                    None => incoming = Some(arg),
+                    Some(v) if v == arg => {}
+                    Some(_) => same = false,
+                }
+            }
+            match incoming {
+                Some(op) if same => {
+                    subst.insert(param, op);
+                    remove_param(func, block, i);
+                }
+                _ => i += 1,
+            }
+        }
+    }
+}
+
+/// Remove a parameter of a block and the arguments passed for it.
+pub(super) fn remove_param(func: &mut Function, block: Block, index: usize) {
+    func.block_mut(block).params.remove(index);
+    for data in &mut func.blocks {
+        for call in data.term.block_calls_mut() {
+            if call.block == block {
+                call.args.remove(index);
+            }
+        }
+    }
+}
+
+/// Turn the branches on a constant into jumps.
+fn fold_branches(func: &mut Function) -> bool {
+    let mut changed = false;
+    for block in &mut func.blocks {
+        if let Terminator::Branch {
+            cond: Operand::Const(Const::Bool(cond)),
+            then,
+            else_,
+        } = &block.term
+        {
+            let target = if *cond { then } else { else_ };
+            block.term = Terminator::Jump(target.clone());
+            changed = true;
+        }
+    }
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::{
+        opt::testing::{function, new_block, param, print, push},
+        BlockCall, Ty,
+    };
+
+    #[test]
+    fn folds_constants_and_branches() {
+        let mut func = function("f", &[], &[Ty::Int]);
+        let entry = func.entry();
+        let sum = push(
+            &mut func,
+            entry,
+            InstKind::Binary(BinaryOp::Add, 2.into(), 3.into()),
+            Ty::Int,
+        );
+        let is_five = push(
+            &mut func,
+            entry,
+            InstKind::Cmp(CmpOp::Equal, sum, 5.into()),
+            Ty::Bool,
+        );
+        let (then, else_) = (new_block(&mut func, &[]), new_block(&mut func, &[]));
+        func.block_mut(entry).term = Terminator::Branch {
+            cond: is_five,
+            then: then.into(),
+            else_: else_.into(),
+        };
+        func.block_mut(then).term = Terminator::Return(vec![sum]);
+        func.block_mut(else_).term = Terminator::Return(vec![0.into()]);
+
+        assert!(run(&mut func));
+        assert_eq!(
+            print(&[func]),
+            "\
+func f() int {
+  bb_0: // test
+    branch bb_1
+
+  bb_1: // test
+    ret int 5
+
+  bb_2: // test
+    ret int 0
+}
+"
+        );
+    }
+
+    #[test]
+    fn propagates_parameter_always_given_the_same_operand() {
+        let mut func = function("f", &[Ty::Bool], &[Ty::Int]);
+        let entry = func.entry();
+        let cond = param(&func, entry, 0);
+        let (then, else_) = (new_block(&mut func, &[]), new_block(&mut func, &[]));
+        let merge = new_block(&mut func, &[Ty::Int]);
+        func.block_mut(entry).term = Terminator::Branch {
+            cond,
+            then: then.into(),
+            else_: else_.into(),
+        };
+        for block in [then, else_] {
+            func.block_mut(block).term = Terminator::Jump(BlockCall {
+                block: merge,
+                args: vec![7.into()],
+            });
+        }
+        let value = param(&func, merge, 0);
+        func.block_mut(merge).term = Terminator::Return(vec![value]);
+
+        assert!(run(&mut func));
+        assert_eq!(
+            print(&[func]),
+            "\
+func f(bool %0) int {
+  bb_0: // test
+    branch bool %0, bb_1, bb_2
+
+  bb_1: // test
+    branch bb_3
+
+  bb_2: // test
+    branch bb_3
+
+  bb_3: // test
+    ret int 7
+}
+"
+        );
+    }
+}
@@ -0,0 +1,159 @@
+use crate::ir::{Function, Operand, Terminator, Ty, Value};
+
+use super::const_fold::remove_param;
+
+/// Remove the pure instructions and the block parameters whose values are never used, then
+/// renumber the values that are left.
+pub(super) fn run(func: &mut Function) -> bool {
+    let mut changed = false;
+    loop {
+        let uses = count_uses(func);
+        let used = |v: &Value| uses[v.index()] > 0;
+
+        let mut removed = false;
+        for block in &mut func.blocks {
+            let before = block.insts.len();
+            block
+                .insts
+                .retain(|inst| !inst.is_pure() || inst.results.iter().any(used));
+            removed |= block.insts.len() != before;
+        }
+        // the entry's parameters are the function's, they stay.
+        for block in func.block_ids().skip(1) {
+            for i in (0..func.block(block).params.len()).rev() {
+                if !used(&func.block(block).params[i]) {
+                    remove_param(func, block, i);
+                    removed = true;
+                }
+            }
+        }
+
+        if !removed {
+            break;
+        }
+        changed = true;
+    }
+    // folding elsewhere leaves holes in the numbering too.
+    renumber(func) || changed
+}
+
+/// Uses of every value, a parameter passed back to itself around a loop is not a use.
+fn count_uses(func: &Function) -> Vec<usize> {
+    let mut uses = vec![0; func.values.len()];
+    for block in &func.blocks {
+        for inst in &block.insts {
+            for v in inst.operands().into_iter().filter_map(Operand::as_value) {
+                uses[v.index()] += 1;
+            }
+        }
+        if let Terminator::Branch {
+            cond: Operand::Value(v),
+            ..
+        } = block.term
+        {
+            uses[v.index()] += 1;
+        }
+        let calls = block.term.block_calls();
+        let args = calls.iter().flat_map(|call| {
+            let params = &func.block(call.block).params;
+            call.args.iter().zip(params)
+        });
+        for (arg, param) in args {
+            match arg {
+                Operand::Value(v) if v != param => uses[v.index()] += 1,
+                _ => {}
+            }
+        }
+        if let Terminator::Return(values) = &block.term {
+            for v in values.iter().filter_map(|v| v.as_value()) {
+                uses[v.index()] += 1;
+            }
+        }
+    }
+    uses
+}
+
+/// Number the values in the order of their definitions, dropping the ones no longer defined.
+/// Returns whether any was dropped.
+fn renumber(func: &mut Function) -> bool {
+    let mut map = vec![None; func.values.len()];
+    let mut values: Vec<Ty> = vec![];
+    let mut define = |v: &mut Value| {
+        values.push(func.values[v.index()]);
+        let new = Value(values.len() as u32 - 1);
+        map[v.index()] = Some(new);
+        *v = new;
+    };
+    for block in &mut func.blocks {
+        block.params.iter_mut().for_each(&mut define);
+        for inst in &mut block.insts {
+            inst.results.iter_mut().for_each(&mut define);
+        }
+    }
+
+    let remap = |op: &mut Operand| {
+        if let Operand::Value(v) = op {
+            *v = map[v.index()].expect("use of a removed value");
+        }
+    };
+    for block in &mut func.blocks {
+        for inst in &mut block.insts {
+            inst.operands_mut().into_iter().for_each(remap);
+        }
+        block.term.operands_mut().into_iter().for_each(remap);
+    }
+    let dropped = values.len() != func.values.len();
+    func.values = values;
+    dropped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::{
+        opt::testing::{function, new_block, param, print, push},
+        BinaryOp, BlockCall, FuncRef, InstKind, Terminator, Ty,
+    };
+
+    #[test]
+    fn removes_unused_pure_instructions_and_parameters() {
+        let mut func = function("f", &[Ty::Int], &[Ty::Int]);
+        let entry = func.entry();
+        let x = param(&func, entry, 0);
+        let unused = push(
+            &mut func,
+            entry,
+            InstKind::Binary(BinaryOp::Add, x, 1.into()),
+            Ty::Int,
+        );
+        // calls may have effects, they stay even when their results are unused.
+        push(
+            &mut func,
+            entry,
+            InstKind::Call(FuncRef(0), vec![x]),
+            Ty::Int,
+        );
+        let next = new_block(&mut func, &[Ty::Int, Ty::Int]);
+        func.block_mut(entry).term = Terminator::Jump(BlockCall {
+            block: next,
+            args: vec![unused, x],
+        });
+        let y = param(&func, next, 1);
+        func.block_mut(next).term = Terminator::Return(vec![y]);
+
+        assert!(run(&mut func));
+        assert_eq!(
+            print(&[func]),
+            "\
+func f(int %0) int {
+  bb_0: // test
+    %1 = int call f(int %0)
+    branch bb_1(int %0)
+
+  bb_1(int %2): // test
+    ret int %2
+}
+"
+        );
+    }
+}
@@ -0,0 +1,184 @@
+use crate::ir::{
+    Block, BlockCall, BlockData, Function, Inst, InstKind, Module, Operand, Terminator, Value,
+};
+
+/// Callees with at most this many instructions are inlined.
+const MAX_INSTS: usize = 16;
+
+/// Inline the calls to small functions that call nothing themselves, so inlining always ends and
+/// never has to deal with recursion. Callers that become such functions are inlined in later
+/// rounds.
+pub(super) fn run(module: &mut Module) -> bool {
+    let inlinable: Vec<_> = module.funcs.iter().map(is_inlinable).collect();
+    let mut changed = false;
+    for i in 0..module.funcs.len() {
+        let mut func = std::mem::replace(
+            &mut module.funcs[i],
+            Function::new(String::new(), Default::default()),
+        );
+        let mut block = 0;
+        while block < func.blocks.len() {
+            let call =
+                func.blocks[block]
+                    .insts
+                    .iter()
+                    .enumerate()
+                    .find_map(|(i, inst)| match &inst.kind {
+                        InstKind::Call(callee, _) if inlinable[callee.index()] => {
+                            Some((i, *callee))
+                        }
+                        _ => None,
+                    });
+            match call {
+                Some((inst, callee)) => {
+                    inline_call(&mut func, Block(block as u32), inst, module.get(callee));
+                    changed = true;
+                }
+                None => block += 1,
+            }
+        }
+        module.funcs[i] = func;
+    }
+    changed
+}
+
+fn is_inlinable(func: &Function) -> bool {
+    let insts = func.blocks.iter().flat_map(|v| &v.insts);
+    !func.is_extern
+        && insts.clone().count() <= MAX_INSTS
+        && !insts.clone().any(|v| matches!(v.kind, InstKind::Call(..)))
+}
+
+/// Replace the call at `inst` of `block` with the body of the callee. The instructions after the
+/// call move to a new block taking the call's results as parameters, which the callee's returns
+/// jump to.
+fn inline_call(func: &mut Function, block: Block, inst: usize, callee: &Function) {
+    let data = func.block_mut(block);
+    let call = data.insts.remove(inst);
+    let InstKind::Call(_, args) = call.kind else {
+        unreachable!("not a call");
+    };
+    let mut cont = BlockData::new("cont");
+    cont.params = call.results;
+    cont.insts = data.insts.split_off(inst);
+    cont.term = std::mem::replace(&mut data.term, Terminator::Missing);
+    let cont_block = Block(func.blocks.len() as u32);
+    func.blocks.push(cont);
+
+    // the callee's entry parameters are its arguments, every other value gets a new one.
+    let mut values: Vec<Operand> = callee
+        .values
+        .iter()
+        .map(|ty| func.new_value(*ty).into())
+        .collect();
+    for (param, arg) in callee.block(callee.entry()).params.iter().zip(args) {
+        values[param.index()] = arg;
+    }
+    let first = func.blocks.len() as u32;
+    let map_block = |v: Block| Block(first + v.0);
+    let map_value = |v: &Value| values[v.index()].as_value().expect("result is an argument");
+    let map_op = |op: &mut Operand| {
+        if let Operand::Value(v) = op {
+            *op = values[v.index()];
+        }
+    };
+
+    for (i, data) in callee.blocks.iter().enumerate() {
+        let mut new;
+        if i == callee.entry().index() {
+            new = BlockData::new("inlined");
+        } else {
+            new = BlockData::new(data.comment);
+            new.params = data.params.iter().map(map_value).collect();
+        }
+        for inst in &data.insts {
+            let mut inst = Inst {
+                results: inst.results.iter().map(map_value).collect(),
+                kind: inst.kind.clone(),
+            };
+            inst.operands_mut().into_iter().for_each(map_op);
+            new.insts.push(inst);
+        }
+        new.term = data.term.clone();
+        for call in new.term.block_calls_mut() {
+            call.block = map_block(call.block);
+        }
+        if let Terminator::Return(values) = new.term {
+            new.term = Terminator::Jump(BlockCall {
+                block: cont_block,
+                args: values,
+            });
+        }
+        new.term.operands_mut().into_iter().for_each(map_op);
+        func.blocks.push(new);
+    }
+    func.block_mut(block).term = Terminator::Jump(Block(first).into());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::{
+        opt::testing::{function, param, push},
+        BinaryOp, FuncRef, Ty,
+    };
+
+    #[test]
+    fn inlines_small_leaf_function() {
+        let mut add1 = function("add1", &[Ty::Int], &[Ty::Int]);
+        let entry = add1.entry();
+        let x = param(&add1, entry, 0);
+        let sum = push(
+            &mut add1,
+            entry,
+            InstKind::Binary(BinaryOp::Add, x, 1.into()),
+            Ty::Int,
+        );
+        add1.block_mut(entry).term = Terminator::Return(vec![sum]);
+
+        let mut main = function("main", &[], &[Ty::Int]);
+        let entry = main.entry();
+        let result = push(
+            &mut main,
+            entry,
+            InstKind::Call(FuncRef(0), vec![41.into()]),
+            Ty::Int,
+        );
+        let doubled = push(
+            &mut main,
+            entry,
+            InstKind::Binary(BinaryOp::Mul, result, 2.into()),
+            Ty::Int,
+        );
+        main.block_mut(entry).term = Terminator::Return(vec![doubled]);
+
+        let mut module = Module {
+            name: "test".to_owned(),
+            funcs: vec![add1, main],
+        };
+        assert!(run(&mut module));
+        assert_eq!(
+            module.to_string(),
+            "\
+func add1(int %0) int {
+  bb_0: // test
+    %1 = int add int %0, int 1
+    ret int %1
+}
+
+func main() int {
+  bb_0: // test
+    branch bb_2
+
+  bb_1(int %0): // cont
+    %1 = int mul int %0, int 2
+    ret int %1
+
+  bb_2: // inlined
+    %3 = int add int 41, int 1
+    branch bb_1(int %3)
+}
+"
+        );
+    }
+}
@@ -0,0 +1,162 @@
+//! Optimisation passes over the IR.
+//!
+//! Every pass rewrites a module in place and reports whether it changed anything, so a single pass
+//! can be run on its own and its effect seen by printing the module before and after it.
+
+use core::fmt;
+use std::str::FromStr;
+
+use super::Module;
+
+mod const_fold;
+mod dce;
+mod inline;
+mod simplify_cfg;
+
+/// How much to optimise, the levels of the `-O` switch.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum OptLevel {
+    /// No optimisation, the IR is given to the backend as built.
+    #[default]
+    O0,
+    /// Folding, dead code elimination and control flow cleanup.
+    O1,
+    /// Everything of `O1` and the inlining of small functions.
+    O2,
+}
+impl OptLevel {
+    pub fn passes(self) -> &'static [Pass] {
+        match self {
+            OptLevel::O0 => &[],
+            OptLevel::O1 => &[Pass::ConstFold, Pass::SimplifyCfg, Pass::Dce],
+            OptLevel::O2 => &[Pass::Inline, Pass::ConstFold, Pass::SimplifyCfg, Pass::Dce],
+        }
+    }
+}
+impl FromStr for OptLevel {
+    type Err = ();
+
+    /// Parse the level without the `-O` prefix, e.g. `2`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "0" => Ok(OptLevel::O0),
+            "1" => Ok(OptLevel::O1),
+            "2" => Ok(OptLevel::O2),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pass {
+    /// Evaluate instructions and branches on constants, propagate block parameters that always
+    /// receive the same operand.
+    ConstFold,
+    /// Remove unused pure instructions and block parameters.
+    Dce,
+    /// Remove unreachable blocks, merge straight line chains and skip empty blocks.
+    SimplifyCfg,
+    /// Inline small functions that call nothing into their callers.
+    Inline,
+}
+impl Pass {
+    /// Run the pass on the module, returns whether anything changed.
+    pub fn run(self, module: &mut Module) -> bool {
+        match self {
+            Pass::Inline => inline::run(module),
+            pass => {
+                let mut changed = false;
+                for func in module.funcs.iter_mut().filter(|v| !v.is_extern) {
+                    changed |= match pass {
+                        Pass::ConstFold => const_fold::run(func),
+                        Pass::Dce => dce::run(func),
+                        Pass::SimplifyCfg => simplify_cfg::run(func),
+                        Pass::Inline => unreachable!(),
+                    };
+                }
+                changed
+            }
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Pass::ConstFold => "const-fold",
+            Pass::Dce => "dce",
+            Pass::SimplifyCfg => "simplify-cfg",
+            Pass::Inline => "inline",
+        }
+    }
+}
+impl fmt::Display for Pass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Upper bound of the rounds of [`optimize`], each pass exposes more work for the others but the
+/// gains after a few rounds are negligible.
+const MAX_ROUNDS: usize = 8;
+
+/// Run the passes of the level until none of them changes the module.
+pub fn optimize(module: &mut Module, level: OptLevel) {
+    let passes = level.passes();
+    for _ in 0..MAX_ROUNDS {
+        let mut changed = false;
+        for pass in passes {
+            changed |= pass.run(module);
+        }
+        if !changed {
+            break;
+        }
+    }
+}
+
+/// Building the small functions the passes, the verifier and the printer are tested on.
+#[cfg(test)]
+pub(super) mod testing {
+    use crate::ir::{Block, BlockData, Function, Inst, InstKind, Module, Operand, Signature, Ty};
+
+    pub fn function(name: &str, params: &[Ty], rets: &[Ty]) -> Function {
+        let sig = Signature {
+            params: params.to_vec(),
+            rets: rets.to_vec(),
+        };
+        let mut func = Function::new(name.to_owned(), sig);
+        new_block(&mut func, params);
+        func
+    }
+
+    /// Append a block with parameters of the types, [`param`] gets them.
+    pub fn new_block(func: &mut Function, params: &[Ty]) -> Block {
+        func.blocks.push(BlockData::new("test"));
+        let block = Block(func.blocks.len() as u32 - 1);
+        for ty in params {
+            let value = func.new_value(*ty);
+            func.block_mut(block).params.push(value);
+        }
+        block
+    }
+
+    pub fn param(func: &Function, block: Block, index: usize) -> Operand {
+        func.block(block).params[index].into()
+    }
+
+    /// Append an instruction with a single result to the block.
+    pub fn push(func: &mut Function, block: Block, kind: InstKind, ty: Ty) -> Operand {
+        let result = func.new_value(ty);
+        func.block_mut(block).insts.push(Inst {
+            results: vec![result],
+            kind,
+        });
+        result.into()
+    }
+
+    pub fn print(funcs: &[Function]) -> String {
+        Module {
+            name: "test".to_owned(),
+            funcs: funcs.to_vec(),
+        }
+        .to_string()
+    }
+}
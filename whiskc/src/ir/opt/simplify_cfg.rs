@@ -0,0 +1,229 @@
+use std::collections::HashMap;
+
+use crate::ir::{Block, BlockCall, BlockData, Function, Terminator};
+
+/// Clean up the control flow graph: branches to the same place become jumps, jumps through empty
+/// blocks go straight to their targets, a block jumping to a block it is the only predecessor of
+/// absorbs it, and blocks that cannot be reached are removed.
+pub(super) fn run(func: &mut Function) -> bool {
+    let mut changed = false;
+    loop {
+        let step = merge_branches(func) | thread_jumps(func) | merge_blocks(func);
+        if !step {
+            break;
+        }
+        changed = true;
+    }
+    reorder_blocks(func) || changed
+}
+
+fn merge_branches(func: &mut Function) -> bool {
+    let mut changed = false;
+    for block in &mut func.blocks {
+        if let Terminator::Branch { then, else_, .. } = &block.term {
+            if then == else_ {
+                block.term = Terminator::Jump(then.clone());
+                changed = true;
+            }
+        }
+    }
+    changed
+}
+
+/// Redirect the edges into empty blocks without parameters that only jump on. The arguments of
+/// such a jump are defined in blocks dominating the empty block, so they dominate its
+/// predecessors as well.
+fn thread_jumps(func: &mut Function) -> bool {
+    let forward = |func: &Function, block: Block| -> Option<BlockCall> {
+        let data = func.block(block);
+        match &data.term {
+            Terminator::Jump(call)
+                if block != func.entry()
+                    && data.params.is_empty()
+                    && data.insts.is_empty()
+                    && call.block != block =>
+            {
+                Some(call.clone())
+            }
+            _ => None,
+        }
+    };
+
+    let mut changed = false;
+    for block in func.block_ids() {
+        for i in 0..func.block(block).term.block_calls().len() {
+            let mut seen = vec![func.block(block).term.block_calls()[i].block];
+            let mut last = None;
+            while let Some(call) = forward(func, *seen.last().unwrap()) {
+                // an empty infinite loop, leave it as it is.
+                if seen.contains(&call.block) {
+                    last = None;
+                    break;
+                }
+                seen.push(call.block);
+                last = Some(call);
+            }
+            if let Some(call) = last {
+                *func.block_mut(block).term.block_calls_mut()[i] = call;
+                changed = true;
+            }
+        }
+    }
+    changed
+}
+
+/// Append the blocks that are only jumped to by a single jump to the block making it.
+fn merge_blocks(func: &mut Function) -> bool {
+    let mut changed = false;
+    let mut preds = func.predecessors();
+    for block in func.block_ids() {
+        let Terminator::Jump(call) = &func.block(block).term else {
+            continue;
+        };
+        let target = call.block;
+        if target == block || target == func.entry() || preds[target.index()] != [block] {
+            continue;
+        }
+
+        let args = call.args.clone();
+        // the emptied block is left without predecessors, nothing reaches its end.
+        let merged = BlockData {
+            term: Terminator::Unreachable,
+            ..BlockData::new("merged")
+        };
+        let data = std::mem::replace(func.block_mut(target), merged);
+        let subst: HashMap<_, _> = data.params.iter().copied().zip(args).collect();
+        for succ in data.term.successors() {
+            for pred in &mut preds[succ.index()] {
+                if *pred == target {
+                    *pred = block;
+                }
+            }
+        }
+        preds[target.index()].clear();
+
+        let into = func.block_mut(block);
+        into.insts.extend(data.insts);
+        into.term = data.term;
+        func.replace_uses(&subst);
+        changed = true;
+    }
+    changed
+}
+
+/// Remove the blocks not reachable from the entry and lay the others out in reverse postorder,
+/// so the backends can fall through into most successors.
+fn reorder_blocks(func: &mut Function) -> bool {
+    let order = func.reverse_postorder();
+    if order.iter().copied().eq(func.block_ids()) {
+        return false;
+    }
+
+    let mut map = vec![None; func.blocks.len()];
+    for (i, block) in order.iter().enumerate() {
+        map[block.index()] = Some(Block(i as u32));
+    }
+    let mut old: Vec<_> = std::mem::take(&mut func.blocks)
+        .into_iter()
+        .map(Some)
+        .collect();
+    for block in order {
+        let mut data = old[block.index()].take().unwrap();
+        for call in data.term.block_calls_mut() {
+            call.block = map[call.block.index()].expect("edge into an unreachable block");
+        }
+        func.blocks.push(data);
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::{
+        opt::testing::{function, new_block, param, print, push},
+        BinaryOp, InstKind, Ty,
+    };
+
+    #[test]
+    fn threads_jumps_through_empty_blocks() {
+        let mut func = function("f", &[Ty::Bool], &[Ty::Int]);
+        let entry = func.entry();
+        let cond = param(&func, entry, 0);
+        let (empty, other, target) = (
+            new_block(&mut func, &[]),
+            new_block(&mut func, &[]),
+            new_block(&mut func, &[]),
+        );
+        func.block_mut(entry).term = Terminator::Branch {
+            cond,
+            then: empty.into(),
+            else_: other.into(),
+        };
+        func.block_mut(empty).term = Terminator::Jump(target.into());
+        func.block_mut(other).term = Terminator::Return(vec![1.into()]);
+        func.block_mut(target).term = Terminator::Return(vec![0.into()]);
+
+        assert!(thread_jumps(&mut func));
+        assert_eq!(
+            print(&[func]),
+            "\
+func f(bool %0) int {
+  bb_0: // test
+    branch bool %0, bb_3, bb_2
+
+  bb_1: // test
+    branch bb_3
+
+  bb_2: // test
+    ret int 1
+
+  bb_3: // test
+    ret int 0
+}
+"
+        );
+    }
+
+    #[test]
+    fn merges_block_into_its_only_predecessor() {
+        let mut func = function("f", &[Ty::Int], &[Ty::Int]);
+        let entry = func.entry();
+        let x = param(&func, entry, 0);
+        let sum = push(
+            &mut func,
+            entry,
+            InstKind::Binary(BinaryOp::Add, x, 1.into()),
+            Ty::Int,
+        );
+        let next = new_block(&mut func, &[Ty::Int]);
+        func.block_mut(entry).term = Terminator::Jump(BlockCall {
+            block: next,
+            args: vec![sum],
+        });
+        let y = param(&func, next, 0);
+        let product = push(
+            &mut func,
+            next,
+            InstKind::Binary(BinaryOp::Mul, y, 2.into()),
+            Ty::Int,
+        );
+        func.block_mut(next).term = Terminator::Return(vec![product]);
+
+        assert!(merge_blocks(&mut func));
+        assert_eq!(
+            print(&[func]),
+            "\
+func f(int %0) int {
+  bb_0: // test
+    %1 = int add int %0, int 1
+    %3 = int mul int %1, int 2
+    ret int %3
+
+  bb_1: // merged
+    unreachable
+}
+"
+        );
+    }
+}
@@ -0,0 +1,305 @@
+//! The textual form of the IR, e.g.
+//!
+//! ```text
+//! func fib(int %0) int {
+//!   bb_0: // entry
+//!     %1 = bool cmp int %0 less int 2
+//!     branch bool %1, bb_1, bb_2
+//! ...
+//! ```
+
+use core::fmt;
+
+use super::{
+    BinaryOp, BlockCall, CmpOp, Const, Function, InstKind, Module, Operand, Terminator, Ty,
+    UnaryOp, Value,
+};
+
+impl fmt::Display for Module {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, func) in self.funcs.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            FuncPrinter { module: self, func }.fmt(f)?;
+        }
+        Ok(())
+    }
+}
+
+struct FuncPrinter<'a> {
+    module: &'a Module,
+    func: &'a Function,
+}
+impl fmt::Display for FuncPrinter<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let func = self.func;
+        let rets = TyList(&func.sig.rets);
+        if func.is_extern {
+            let params: Vec<_> = func.sig.params.iter().map(|v| v.to_string()).collect();
+            return writeln!(
+                f,
+                "extern func {}({}) {}",
+                func.name,
+                params.join(", "),
+                rets
+            );
+        }
+
+        let params = func.blocks.first().map_or(&[][..], |v| &v.params);
+        let vis = if func.is_pub { "pub " } else { "" };
+        writeln!(
+            f,
+            "{}func {}({}) {} {{",
+            vis,
+            func.name,
+            self.values(params),
+            rets
+        )?;
+        for (i, block) in func.blocks.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            // the entry's parameters are printed as the function's.
+            if i == 0 || block.params.is_empty() {
+                writeln!(f, "  bb_{}: // {}", i, block.comment)?;
+            } else {
+                writeln!(
+                    f,
+                    "  bb_{}({}): // {}",
+                    i,
+                    self.values(&block.params),
+                    block.comment
+                )?;
+            }
+
+            for inst in &block.insts {
+                write!(f, "    ")?;
+                if !inst.results.is_empty() {
+                    let names: Vec<_> = inst.results.iter().map(|v| format!("%{}", v.0)).collect();
+                    let tys: Vec<_> = inst
+                        .results
+                        .iter()
+                        .map(|v| func.value_ty(*v).to_string())
+                        .collect();
+                    write!(f, "{} = {} ", names.join(", "), tys.join(", "))?;
+                }
+                match &inst.kind {
+                    InstKind::Unary(op, v) => write!(
+                        f,
+                        "{} {}",
+                        match op {
+                            UnaryOp::Neg => "neg",
+                            UnaryOp::Not => "not",
+                        },
+                        self.operand(*v)
+                    )?,
+                    InstKind::Binary(op, l, r) => write!(
+                        f,
+                        "{} {}, {}",
+                        match op {
+                            BinaryOp::Add => "add",
+                            BinaryOp::Sub => "sub",
+                            BinaryOp::Mul => "mul",
+                            BinaryOp::Div => "div",
+                            BinaryOp::Mod => "mod",
+                            BinaryOp::And => "and",
+                            BinaryOp::Or => "or",
+                        },
+                        self.operand(*l),
+                        self.operand(*r)
+                    )?,
+                    InstKind::Cmp(op, l, r) => write!(
+                        f,
+                        "cmp {} {} {}",
+                        self.operand(*l),
+                        match op {
+                            CmpOp::Equal => "equal",
+                            CmpOp::NotEqual => "not_equal",
+                            CmpOp::Less => "less",
+                            CmpOp::LessEqual => "less_equal",
+                            CmpOp::Greater => "greater",
+                            CmpOp::GreaterEqual => "greater_equal",
+                        },
+                        self.operand(*r)
+                    )?,
+                    InstKind::Call(callee, args) => write!(
+                        f,
+                        "call {}({})",
+                        self.module.get(*callee).name,
+                        self.operands(args)
+                    )?,
+                    InstKind::MakeStruct(fields) => {
+                        write!(f, "make_struct({})", self.operands(fields))?
+                    }
+                    InstKind::GetField(v, index) => {
+                        write!(f, "get_field {}, {}", self.operand(*v), index)?
+                    }
+                }
+                writeln!(f)?;
+            }
+
+            match &block.term {
+                Terminator::Jump(call) => writeln!(f, "    branch {}", self.block_call(call))?,
+                Terminator::Branch { cond, then, else_ } => writeln!(
+                    f,
+                    "    branch {}, {}, {}",
+                    self.operand(*cond),
+                    self.block_call(then),
+                    self.block_call(else_)
+                )?,
+                Terminator::Return(values) if values.is_empty() => writeln!(f, "    ret")?,
+                Terminator::Return(values) => writeln!(f, "    ret {}", self.operands(values))?,
+                Terminator::Unreachable => writeln!(f, "    unreachable")?,
+                Terminator::Missing => writeln!(f, "    <missing>")?,
+            }
+        }
+        writeln!(f, "}}")
+    }
+}
+impl FuncPrinter<'_> {
+    fn operand(&self, op: Operand) -> String {
+        match op {
+            Operand::Value(v) => format!("{} %{}", self.func.value_ty(v), v.0),
+            Operand::Const(Const::Int(v)) => format!("int {}", v),
+            Operand::Const(Const::Bool(v)) => format!("bool {}", v),
+        }
+    }
+
+    fn operands(&self, ops: &[Operand]) -> String {
+        let ops: Vec<_> = ops.iter().map(|v| self.operand(*v)).collect();
+        ops.join(", ")
+    }
+
+    fn values(&self, values: &[Value]) -> String {
+        let ops: Vec<_> = values.iter().map(|v| self.operand((*v).into())).collect();
+        ops.join(", ")
+    }
+
+    fn block_call(&self, call: &BlockCall) -> String {
+        if call.args.is_empty() {
+            format!("bb_{}", call.block.0)
+        } else {
+            format!("bb_{}({})", call.block.0, self.operands(&call.args))
+        }
+    }
+}
+
+/// The return types of a signature, `()` when there is none.
+struct TyList<'a>(&'a [Ty]);
+impl fmt::Display for TyList<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            [ty] => write!(f, "{}", ty),
+            tys => {
+                let tys: Vec<_> = tys.iter().map(|v| v.to_string()).collect();
+                write!(f, "({})", tys.join(", "))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        compile::{compile_source, CompileSwitch},
+        ir::{
+            opt::testing::{function, new_block, param, print, push},
+            BlockCall, Const, FuncRef, Inst, InstKind, Terminator, Ty, UnaryOp,
+        },
+    };
+
+    #[test]
+    fn committed_test_ir_is_printed() {
+        let switches = CompileSwitch {
+            do_parse_ast: true,
+            do_resolve_module: true,
+            do_codegen: true,
+            ..Default::default()
+        };
+        let source = include_str!("../../../test/test.wsk");
+        let (_, output) = compile_source("test", source, &switches);
+        assert_eq!(
+            output.ir.unwrap().to_string(),
+            include_str!("../../../test/test.wir")
+        );
+    }
+
+    #[test]
+    fn every_form_is_printed() {
+        let mut ext = function("print", &[Ty::Ref], &[]);
+        ext.is_extern = true;
+        ext.blocks.clear();
+
+        let mut func = function("pair", &[Ty::Int, Ty::Bool], &[Ty::Int, Ty::Ref]);
+        func.is_pub = true;
+        let entry = func.entry();
+        let (n, flag) = (param(&func, entry, 0), param(&func, entry, 1));
+        let obj = push(
+            &mut func,
+            entry,
+            InstKind::MakeStruct(vec![n, flag]),
+            Ty::Ref,
+        );
+        let first = push(&mut func, entry, InstKind::GetField(obj, 0), Ty::Int);
+        let results = vec![func.new_value(Ty::Int), func.new_value(Ty::Ref)];
+        func.block_mut(entry).insts.push(Inst {
+            results,
+            kind: InstKind::Call(FuncRef(1), vec![first, flag]),
+        });
+        func.block_mut(entry).insts.push(Inst {
+            results: vec![],
+            kind: InstKind::Call(FuncRef(0), vec![obj]),
+        });
+        let done = new_block(&mut func, &[Ty::Int]);
+        let dead = new_block(&mut func, &[]);
+        let not = push(
+            &mut func,
+            entry,
+            InstKind::Unary(UnaryOp::Not, flag),
+            Ty::Bool,
+        );
+        func.block_mut(entry).term = Terminator::Branch {
+            cond: not,
+            then: BlockCall {
+                block: done,
+                args: vec![Const::Int(-1).into()],
+            },
+            else_: dead.into(),
+        };
+        let v = param(&func, done, 0);
+        func.block_mut(done).term = Terminator::Return(vec![v, obj]);
+        func.block_mut(dead).term = Terminator::Unreachable;
+        let mut unit = function("unit", &[], &[]);
+        let entry = unit.entry();
+        unit.block_mut(entry).term = Terminator::Return(vec![]);
+
+        assert_eq!(
+            print(&[ext, func, unit]),
+            "\
+extern func print(ref) ()
+
+pub func pair(int %0, bool %1) (int, ref) {
+  bb_0: // test
+    %2 = ref make_struct(int %0, bool %1)
+    %3 = int get_field ref %2, 0
+    %4, %5 = int, ref call pair(int %3, bool %1)
+    call print(ref %2)
+    %7 = bool not bool %1
+    branch bool %7, bb_1(int -1), bb_2
+
+  bb_1(int %6): // test
+    ret int %6, ref %2
+
+  bb_2: // test
+    unreachable
+}
+
+func unit() () {
+  bb_0: // test
+    ret
+}
+"
+        );
+    }
+}
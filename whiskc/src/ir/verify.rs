@@ -0,0 +1,409 @@
+use core::fmt;
+
+use super::{
+    dominates, BinaryOp, Block, BlockCall, CmpOp, Function, InstKind, Module, Operand, Terminator,
+    Ty, UnaryOp, Value,
+};
+
+/// An invariant of the IR broken by a function, usually a bug in the pass that produced it.
+#[derive(Debug, Clone)]
+pub struct VerifyError {
+    pub func: String,
+    pub block: Option<Block>,
+    pub message: String,
+}
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.block {
+            Some(block) => write!(f, "func {}, bb_{}: {}", self.func, block.0, self.message),
+            None => write!(f, "func {}: {}", self.func, self.message),
+        }
+    }
+}
+
+/// Check that every function is well formed: values are defined once and before their uses,
+/// operands and block arguments have the expected types and jumps stay inside the function.
+pub fn verify(module: &Module) -> Result<(), VerifyError> {
+    for func in &module.funcs {
+        Verifier {
+            module,
+            func,
+            block: None,
+            defs: vec![None; func.values.len()],
+        }
+        .verify()?;
+    }
+    Ok(())
+}
+
+/// Where a value is defined, the position is the index of its instruction, params come first.
+#[derive(Debug, Clone, Copy)]
+struct Def {
+    block: Block,
+    pos: usize,
+}
+
+struct Verifier<'a> {
+    module: &'a Module,
+    func: &'a Function,
+    block: Option<Block>,
+    defs: Vec<Option<Def>>,
+}
+impl Verifier<'_> {
+    fn error(&self, message: impl Into<String>) -> VerifyError {
+        VerifyError {
+            func: self.func.name.clone(),
+            block: self.block,
+            message: message.into(),
+        }
+    }
+
+    fn verify(mut self) -> Result<(), VerifyError> {
+        let func = self.func;
+        if func.is_extern {
+            return if func.blocks.is_empty() {
+                Ok(())
+            } else {
+                Err(self.error("extern function has a body"))
+            };
+        }
+        let Some(entry) = func.blocks.first() else {
+            return Err(self.error("function has no entry block"));
+        };
+        let entry_tys = entry
+            .params
+            .iter()
+            .map(|v| self.value_ty(*v))
+            .collect::<Result<Vec<_>, _>>()?;
+        if entry_tys != func.sig.params {
+            return Err(self.error("entry block parameters do not match the signature"));
+        }
+
+        for block in func.block_ids() {
+            self.block = Some(block);
+            let data = func.block(block);
+            for value in &data.params {
+                self.define(*value, block, 0)?;
+            }
+            for (i, inst) in data.insts.iter().enumerate() {
+                for value in &inst.results {
+                    self.define(*value, block, i + 1)?;
+                }
+            }
+            // checked before walking the graph for the dominators.
+            if let Some(succ) = data
+                .term
+                .successors()
+                .find(|v| v.index() >= func.blocks.len())
+            {
+                return Err(self.error(format!("bb_{} is out of range", succ.0)));
+            }
+        }
+
+        let idoms = func.dominators();
+        for block in func.block_ids() {
+            self.block = Some(block);
+            let data = func.block(block);
+            // uses in unreachable blocks are not checked for dominance, nothing runs them.
+            let reachable = idoms[block.index()].is_some();
+            let use_at = |this: &Self, op: Operand, pos: usize| -> Result<Ty, VerifyError> {
+                let Operand::Value(value) = op else {
+                    return Ok(this.func.operand_ty(op));
+                };
+                let ty = this.value_ty(value)?;
+                let def = this.defs[value.index()]
+                    .ok_or_else(|| this.error(format!("%{} is never defined", value.0)))?;
+                let dominated = if def.block == block {
+                    def.pos < pos
+                } else {
+                    dominates(&idoms, def.block, block)
+                };
+                if reachable && !dominated {
+                    return Err(this.error(format!(
+                        "%{} is used where its definition does not dominate",
+                        value.0
+                    )));
+                }
+                Ok(ty)
+            };
+
+            for (i, inst) in data.insts.iter().enumerate() {
+                let pos = i + 1;
+                let tys = inst
+                    .operands()
+                    .into_iter()
+                    .map(|v| use_at(&self, v, pos))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let result_tys = inst
+                    .results
+                    .iter()
+                    .map(|v| self.value_ty(*v))
+                    .collect::<Result<Vec<_>, _>>()?;
+                self.check_inst(&inst.kind, &tys, &result_tys)
+                    .map_err(|msg| self.error(format!("instruction {}: {}", i, msg)))?;
+            }
+
+            let pos = data.insts.len() + 1;
+            let tys = data
+                .term
+                .operands()
+                .into_iter()
+                .map(|v| use_at(&self, v, pos))
+                .collect::<Result<Vec<_>, _>>()?;
+            match &data.term {
+                Terminator::Jump(call) => self.check_block_call(call)?,
+                Terminator::Branch { then, else_, .. } => {
+                    if tys[0] != Ty::Bool {
+                        return Err(self.error("branch condition is not a bool"));
+                    }
+                    self.check_block_call(then)?;
+                    self.check_block_call(else_)?;
+                }
+                Terminator::Return(_) => {
+                    if tys != func.sig.rets {
+                        return Err(self.error("returned values do not match the signature"));
+                    }
+                }
+                Terminator::Unreachable => {}
+                Terminator::Missing => return Err(self.error("block has no terminator")),
+            }
+        }
+        Ok(())
+    }
+
+    fn value_ty(&self, value: Value) -> Result<Ty, VerifyError> {
+        self.func
+            .values
+            .get(value.index())
+            .copied()
+            .ok_or_else(|| self.error(format!("%{} is out of range", value.0)))
+    }
+
+    fn define(&mut self, value: Value, block: Block, pos: usize) -> Result<(), VerifyError> {
+        self.value_ty(value)?;
+        if self.defs[value.index()].is_some() {
+            return Err(self.error(format!("%{} is defined more than once", value.0)));
+        }
+        self.defs[value.index()] = Some(Def { block, pos });
+        Ok(())
+    }
+
+    fn check_block_call(&self, call: &BlockCall) -> Result<(), VerifyError> {
+        if call.block == self.func.entry() {
+            return Err(self.error("the entry block cannot be jumped to"));
+        }
+        let target = self.func.block(call.block);
+        let arg_tys: Vec<_> = call.args.iter().map(|v| self.func.operand_ty(*v)).collect();
+        let param_tys = target
+            .params
+            .iter()
+            .map(|v| self.value_ty(*v))
+            .collect::<Result<Vec<_>, _>>()?;
+        if arg_tys != param_tys {
+            return Err(self.error(format!(
+                "arguments do not match the parameters of bb_{}",
+                call.block.0
+            )));
+        }
+        Ok(())
+    }
+
+    fn check_inst(&self, kind: &InstKind, tys: &[Ty], results: &[Ty]) -> Result<(), String> {
+        let expected: Vec<Ty> = match kind {
+            InstKind::Unary(op, _) => {
+                let ty = match op {
+                    UnaryOp::Neg => Ty::Int,
+                    UnaryOp::Not => Ty::Bool,
+                };
+                if tys[0] != ty {
+                    return Err(format!("operand is not of type {}", ty));
+                }
+                vec![ty]
+            }
+            InstKind::Binary(op, _, _) => {
+                let ok = match op {
+                    BinaryOp::And | BinaryOp::Or => tys[0] == tys[1] && tys[0] != Ty::Ref,
+                    _ => tys == [Ty::Int, Ty::Int],
+                };
+                if !ok {
+                    return Err("operands have invalid types".to_owned());
+                }
+                vec![tys[0]]
+            }
+            InstKind::Cmp(op, _, _) => {
+                let ok = match op {
+                    CmpOp::Equal | CmpOp::NotEqual => tys[0] == tys[1],
+                    _ => tys == [Ty::Int, Ty::Int],
+                };
+                if !ok {
+                    return Err("operands have invalid types".to_owned());
+                }
+                vec![Ty::Bool]
+            }
+            InstKind::Call(callee, _) => {
+                let Some(callee) = self.module.funcs.get(callee.index()) else {
+                    return Err("callee is out of range".to_owned());
+                };
+                if tys != callee.sig.params {
+                    return Err(format!(
+                        "arguments do not match the parameters of {}",
+                        callee.name
+                    ));
+                }
+                callee.sig.rets.clone()
+            }
+            InstKind::MakeStruct(_) => vec![Ty::Ref],
+            InstKind::GetField(_, _) => {
+                if tys[0] != Ty::Ref {
+                    return Err("field of a value that is not a reference".to_owned());
+                }
+                // fields are untyped, the result may be of any type.
+                return match results {
+                    [_] => Ok(()),
+                    _ => Err("expected one result".to_owned()),
+                };
+            }
+        };
+        if results != expected {
+            return Err("results have invalid types".to_owned());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::{
+        opt::testing::{function, new_block, param, push},
+        Inst,
+    };
+
+    fn verify_funcs(funcs: Vec<Function>) -> Result<(), VerifyError> {
+        verify(&Module {
+            name: "test".to_owned(),
+            funcs,
+        })
+    }
+
+    fn error(func: Function) -> (Option<Block>, String) {
+        let err = verify_funcs(vec![func]).unwrap_err();
+        (err.block, err.message)
+    }
+
+    /// `f(x)` adding one to `x` in a block of its own.
+    fn add_one() -> (Function, Block) {
+        let mut func = function("f", &[Ty::Int], &[Ty::Int]);
+        let entry = func.entry();
+        let x = param(&func, entry, 0);
+        let next = new_block(&mut func, &[Ty::Int]);
+        func.block_mut(entry).term = Terminator::Jump(BlockCall {
+            block: next,
+            args: vec![x],
+        });
+        let y = param(&func, next, 0);
+        let sum = push(
+            &mut func,
+            next,
+            InstKind::Binary(BinaryOp::Add, y, 1.into()),
+            Ty::Int,
+        );
+        func.block_mut(next).term = Terminator::Return(vec![sum]);
+        (func, next)
+    }
+
+    #[test]
+    fn well_formed_function_is_accepted() {
+        assert!(verify_funcs(vec![add_one().0]).is_ok());
+    }
+
+    #[test]
+    fn jump_to_missing_block_is_rejected() {
+        let (mut func, _) = add_one();
+        let entry = func.entry();
+        func.block_mut(entry).term = Terminator::Jump(Block(5).into());
+        assert_eq!(
+            error(func),
+            (Some(entry), "bb_5 is out of range".to_owned())
+        );
+    }
+
+    #[test]
+    fn use_before_definition_is_rejected() {
+        // in the same block, the use comes before the instruction defining the value.
+        let (mut func, next) = add_one();
+        let later = func.new_value(Ty::Int);
+        let y = param(&func, next, 0);
+        let result = func.new_value(Ty::Int);
+        func.block_mut(next).insts.insert(
+            0,
+            Inst {
+                results: vec![result],
+                kind: InstKind::Binary(BinaryOp::Add, later.into(), 1.into()),
+            },
+        );
+        func.block_mut(next).insts.push(Inst {
+            results: vec![later],
+            kind: InstKind::Binary(BinaryOp::Mul, y, 2.into()),
+        });
+        assert_eq!(
+            error(func),
+            (
+                Some(next),
+                format!(
+                    "%{} is used where its definition does not dominate",
+                    later.0
+                )
+            )
+        );
+
+        // in another block, defined on one side of a branch and used after it.
+        let mut func = function("f", &[Ty::Bool], &[Ty::Int]);
+        let entry = func.entry();
+        let cond = param(&func, entry, 0);
+        let (then, else_, merge) = (
+            new_block(&mut func, &[]),
+            new_block(&mut func, &[]),
+            new_block(&mut func, &[]),
+        );
+        func.block_mut(entry).term = Terminator::Branch {
+            cond,
+            then: then.into(),
+            else_: else_.into(),
+        };
+        let v = push(
+            &mut func,
+            then,
+            InstKind::Unary(UnaryOp::Neg, 1.into()),
+            Ty::Int,
+        );
+        func.block_mut(then).term = Terminator::Jump(merge.into());
+        func.block_mut(else_).term = Terminator::Jump(merge.into());
+        func.block_mut(merge).term = Terminator::Return(vec![v]);
+        assert_eq!(
+            error(func),
+            (
+                Some(merge),
+                "%1 is used where its definition does not dominate".to_owned()
+            )
+        );
+    }
+
+    #[test]
+    fn block_without_terminator_is_rejected() {
+        let (mut func, next) = add_one();
+        func.block_mut(next).term = Terminator::Missing;
+        assert_eq!(
+            error(func),
+            (Some(next), "block has no terminator".to_owned())
+        );
+
+        // a block that is never jumped to must be terminated too.
+        let (mut func, _) = add_one();
+        let dead = new_block(&mut func, &[]);
+        assert_eq!(
+            error(func),
+            (Some(dead), "block has no terminator".to_owned())
+        );
+    }
+}
@@ -1,8 +1,13 @@
 pub mod ast;
 pub mod codegen;
 pub mod compile;
+pub mod format;
 mod interner;
+pub mod ir;
 pub mod lowering;
+pub mod source_map;
 pub mod symbol;
 
-pub use compile::compile;
+pub use compile::{
+    compile, compile_file, compile_files, compile_source, CompileOutput, Diagnostic,
+};
@@ -1,9 +1,16 @@
+use core::fmt;
+
 use crate::ast::{
     location::{Located, Span},
     parsing::token::Operator,
 };
 
-use crate::symbol::TypeId as Type;
+use crate::{
+    source_map::SourceMap,
+    symbol::{SymbolTable, TypeId as Type},
+};
+
+use super::index::SourceIndex;
 
 #[derive(Debug, Clone)]
 pub enum ResolveError {
@@ -46,7 +53,7 @@ pub enum ValueResolveError {
         val: Located<i64>,
     },
     ArgumentCountMismatch {
-        func_ty: Located<Type>,
+        func_name: Located<String>,
         expect_count: usize,
         actual_count: usize,
     },
@@ -55,6 +62,15 @@ pub enum ValueResolveError {
         expect_count: usize,
         actual_count: usize,
     },
+    VariantArgumentCountMismatch {
+        variant: Located<String>,
+        expect_count: usize,
+        actual_count: usize,
+    },
+    TupleIndexOutOfRange {
+        tuple_ty: Located<Type>,
+        index: Located<i64>,
+    },
 }
 
 #[derive(Debug, Clone)]
@@ -73,6 +89,14 @@ pub enum IdentResolveError {
     UnexpectedAttrib {
         attribute: Span,
     },
+    VarNameAlreadyBound(Located<String>),
+    FieldNameAlreadyUsed {
+        ident: String,
+        first_origin: Span,
+        dup_origin: Span,
+    },
+    /// A type name used where a value is expected.
+    TypeUsedAsValue(Located<String>),
 }
 
 #[derive(Debug, Clone)]
@@ -116,7 +140,7 @@ pub enum TypeResolveError {
     },
     CallOnNonFunctionType(Located<Type>),
     ArgumentTypeMismatch {
-        func_ty: Located<Type>,
+        func_name: Located<String>,
         argument_index: usize,
         expect_type: Type,
         actual_type: Located<Type>,
@@ -136,4 +160,349 @@ pub enum TypeResolveError {
         from_ty: Type,
         to_ty: Type,
     },
+    UnknownGenericType(Located<String>),
+    GenericArgumentCountMismatch {
+        ty: Located<String>,
+        expect_count: usize,
+        actual_count: usize,
+    },
+    TryOnNonTryableType(Located<Type>),
+    TryInIncompatibleFunction {
+        function_name: String,
+        ret_ty: Type,
+        operand_ty: Located<Type>,
+    },
+    IndexingOnNonTupleType(Located<Type>),
+    PatternTypeMismatch {
+        pattern: Span,
+        value_ty: Type,
+    },
+    /// Aliases that end up naming themselves, listed in the order they refer to each other.
+    AliasCycle(Vec<Located<Type>>),
+    /// Types that contain themselves by value, listed in the order they contain each other.
+    InfinitelySizedType(Vec<Located<Type>>),
+}
+
+/// Errors of a failed resolve, kept with the symbol table so that the types they refer to can be
+/// printed with the names the user wrote.
+#[derive(Debug)]
+pub struct ResolveErrors {
+    pub errors: Vec<ResolveError>,
+    pub sym_table: SymbolTable,
+    /// What could be resolved despite the errors.
+    pub index: SourceIndex,
+}
+impl ResolveErrors {
+    /// Formats the errors one per line with their spans resolved to lines and columns.
+    pub fn display<'a>(&'a self, sources: &'a SourceMap) -> impl fmt::Display + 'a {
+        DisplayResolveErrors {
+            errs: self,
+            sources,
+        }
+    }
+}
+
+struct DisplayResolveErrors<'a> {
+    errs: &'a ResolveErrors,
+    sources: &'a SourceMap,
+}
+impl fmt::Display for DisplayResolveErrors<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for e in &self.errs.errors {
+            writeln!(
+                f,
+                "{}: {}",
+                self.sources.display_span(e.span()),
+                e.message(&self.errs.sym_table, self.sources)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl ResolveError {
+    pub fn span(&self) -> Span {
+        match self {
+            ResolveError::ValueResolveError(e) => match e {
+                ValueResolveError::IntegerOutOfRange { val, .. } => val.1,
+                ValueResolveError::ArgumentCountMismatch { func_name, .. } => func_name.1,
+                ValueResolveError::ArrayLengthMismatch { loc, .. } => *loc,
+                ValueResolveError::VariantArgumentCountMismatch { variant, .. } => variant.1,
+                ValueResolveError::TupleIndexOutOfRange { index, .. } => index.1,
+            },
+            ResolveError::IdentResolveError(e) => match e {
+                IdentResolveError::GlobalIdentAlreadyUsed { dup_origin, .. } => *dup_origin,
+                IdentResolveError::VarNameAlreadyUsed { dup_origin, .. } => dup_origin.1,
+                IdentResolveError::UnknownIdentifier(ident) => ident.1,
+                IdentResolveError::UnexpectedAttrib { attribute } => *attribute,
+                IdentResolveError::VarNameAlreadyBound(ident) => ident.1,
+                IdentResolveError::FieldNameAlreadyUsed { dup_origin, .. } => *dup_origin,
+                IdentResolveError::TypeUsedAsValue(ident) => ident.1,
+            },
+            ResolveError::TypeResolveError(e) => match e {
+                TypeResolveError::ReturnTypeMismatch { actual_type, .. } => actual_type.1,
+                TypeResolveError::NonBoolInIfCond(ty) => ty.1,
+                TypeResolveError::BlockBranchTypeMismatch { branch, .. } => branch.1,
+                TypeResolveError::NonAssignableType(ty) => ty.1,
+                TypeResolveError::AssignmentTypeMismatch { value_ty, .. } => value_ty.1,
+                TypeResolveError::UnknownTypeForIdent(ident) => ident.1,
+                TypeResolveError::NonBoolUsedInNotOp(ty) => ty.1,
+                TypeResolveError::NonNumericInUnaryOp(_, ty) => ty.1,
+                TypeResolveError::UnsignedIntegerInUnaryOp(op) => op.1,
+                TypeResolveError::NonNumericTypeInBinaryOp { ty, .. } => ty.1,
+                TypeResolveError::UnorderedTypeInBinaryOp { ty, .. } => ty.1,
+                TypeResolveError::UnexpectedTypeInBinaryOp { actual_type, .. } => actual_type.1,
+                TypeResolveError::TypeMismatchInBinaryOp { op, .. } => op.1,
+                TypeResolveError::CallOnNonFunctionType(ty) => ty.1,
+                TypeResolveError::ArgumentTypeMismatch { actual_type, .. } => actual_type.1,
+                TypeResolveError::ArrayElementTypeMismatch { actual_type, .. } => actual_type.1,
+                TypeResolveError::IndexingOnNonArrayType(ty) => ty.1,
+                TypeResolveError::ExpectUnsignedIntOnArrayIndex { index_ty, .. } => index_ty.1,
+                TypeResolveError::InvalidTypeCast { loc, .. } => *loc,
+                TypeResolveError::UnknownGenericType(ty) => ty.1,
+                TypeResolveError::GenericArgumentCountMismatch { ty, .. } => ty.1,
+                TypeResolveError::TryOnNonTryableType(ty) => ty.1,
+                TypeResolveError::TryInIncompatibleFunction { operand_ty, .. } => operand_ty.1,
+                TypeResolveError::IndexingOnNonTupleType(ty) => ty.1,
+                TypeResolveError::PatternTypeMismatch { pattern, .. } => *pattern,
+                TypeResolveError::AliasCycle(chain)
+                | TypeResolveError::InfinitelySizedType(chain) => {
+                    chain.first().map(|v| v.1).unwrap_or_default()
+                }
+            },
+            ResolveError::ControlFlowError(e) => match e {
+                ControlFlowError::NotAllFuncPathReturned(name) => name.1,
+            },
+        }
+    }
+
+    /// The message of the error, `sources` resolves the spans mentioned in it.
+    pub fn message(&self, table: &SymbolTable, sources: &SourceMap) -> String {
+        let name = |ty: &Type| format!("'{}'", ty.sym(table).name);
+        match self {
+            ResolveError::ValueResolveError(e) => match e {
+                ValueResolveError::IntegerOutOfRange {
+                    is_signed,
+                    int_size,
+                    val,
+                } => format!(
+                    "integer {} is out of the range of {}{}",
+                    val.0,
+                    if *is_signed { 'i' } else { 'u' },
+                    int_size
+                ),
+                ValueResolveError::ArgumentCountMismatch {
+                    func_name,
+                    expect_count,
+                    actual_count,
+                } => format!(
+                    "'{}' expects {} argument(s), but {} are given",
+                    func_name.0, expect_count, actual_count
+                ),
+                ValueResolveError::ArrayLengthMismatch {
+                    expect_count,
+                    actual_count,
+                    ..
+                } => format!(
+                    "expected an array of length {}, found length {}",
+                    expect_count, actual_count
+                ),
+                ValueResolveError::VariantArgumentCountMismatch {
+                    variant,
+                    expect_count,
+                    actual_count,
+                } => format!(
+                    "'{}' expects {} argument(s), but {} are given",
+                    variant.0, expect_count, actual_count
+                ),
+                ValueResolveError::TupleIndexOutOfRange { tuple_ty, index } => {
+                    format!("{} has no element {}", name(&tuple_ty.0), index.0)
+                }
+            },
+            ResolveError::IdentResolveError(e) => match e {
+                IdentResolveError::GlobalIdentAlreadyUsed { ident, .. } => {
+                    format!("'{}' is already declared", ident)
+                }
+                IdentResolveError::VarNameAlreadyUsed { ident, .. }
+                | IdentResolveError::VarNameAlreadyBound(Located(ident, _)) => {
+                    format!("variable '{}' is already declared in this block", ident)
+                }
+                IdentResolveError::UnknownIdentifier(ident) => {
+                    format!("unknown identifier '{}'", ident.0)
+                }
+                IdentResolveError::UnexpectedAttrib { .. } => "unexpected attribute".to_owned(),
+                IdentResolveError::FieldNameAlreadyUsed { ident, .. } => {
+                    format!("field '{}' is already declared in this struct", ident)
+                }
+                IdentResolveError::TypeUsedAsValue(ident) => {
+                    format!("type '{}' cannot be used as a value", ident.0)
+                }
+            },
+            ResolveError::TypeResolveError(e) => match e {
+                TypeResolveError::ReturnTypeMismatch {
+                    function_name,
+                    expected_type,
+                    actual_type,
+                } => format!(
+                    "function '{}' returns {}, found {}",
+                    function_name,
+                    name(expected_type),
+                    name(&actual_type.0)
+                ),
+                TypeResolveError::NonBoolInIfCond(ty) => {
+                    format!("if condition must be 'bool', found {}", name(&ty.0))
+                }
+                TypeResolveError::BlockBranchTypeMismatch { branch, other } => format!(
+                    "branch evaluates to {}, but the other branch evaluates to {}",
+                    name(&branch.0),
+                    name(&other.0)
+                ),
+                TypeResolveError::NonAssignableType(ty) => {
+                    format!("value of {} is not assignable", name(&ty.0))
+                }
+                TypeResolveError::AssignmentTypeMismatch {
+                    target_ty,
+                    value_ty,
+                } => format!(
+                    "cannot assign {} to {}",
+                    name(&value_ty.0),
+                    name(&target_ty.0)
+                ),
+                TypeResolveError::UnknownTypeForIdent(ident) => {
+                    format!("unknown type '{}'", ident.0)
+                }
+                TypeResolveError::NonBoolUsedInNotOp(ty) => {
+                    format!("'!' expects 'bool', found {}", name(&ty.0))
+                }
+                TypeResolveError::NonNumericInUnaryOp(op, ty) => {
+                    format!("'{}' expects a number, found {}", op, name(&ty.0))
+                }
+                TypeResolveError::UnsignedIntegerInUnaryOp(op) => {
+                    format!("'{}' cannot be used on unsigned integers", op.0)
+                }
+                TypeResolveError::NonNumericTypeInBinaryOp { op, ty } => {
+                    format!("'{}' expects numbers, found {}", op.0, name(&ty.0))
+                }
+                TypeResolveError::UnorderedTypeInBinaryOp { op, ty } => {
+                    format!("'{}' cannot order values of {}", op.0, name(&ty.0))
+                }
+                TypeResolveError::UnexpectedTypeInBinaryOp {
+                    op,
+                    expect_type,
+                    actual_type,
+                } => format!(
+                    "'{}' expects {}, found {}",
+                    op.0,
+                    name(expect_type),
+                    name(&actual_type.0)
+                ),
+                TypeResolveError::TypeMismatchInBinaryOp {
+                    op,
+                    left_ty,
+                    right_ty,
+                } => format!(
+                    "'{}' cannot be used between {} and {}",
+                    op.0,
+                    name(left_ty),
+                    name(right_ty)
+                ),
+                TypeResolveError::CallOnNonFunctionType(ty) => {
+                    format!("{} cannot be called", name(&ty.0))
+                }
+                TypeResolveError::ArgumentTypeMismatch {
+                    func_name,
+                    argument_index,
+                    expect_type,
+                    actual_type,
+                } => format!(
+                    "argument {} of '{}' expects {}, found {}",
+                    argument_index,
+                    func_name.0,
+                    name(expect_type),
+                    name(&actual_type.0)
+                ),
+                TypeResolveError::ArrayElementTypeMismatch {
+                    element_index,
+                    expect_type,
+                    actual_type,
+                } => format!(
+                    "array element {} expects {}, found {}",
+                    element_index,
+                    name(&expect_type.0),
+                    name(&actual_type.0)
+                ),
+                TypeResolveError::IndexingOnNonArrayType(ty) => {
+                    format!("{} cannot be indexed", name(&ty.0))
+                }
+                TypeResolveError::ExpectUnsignedIntOnArrayIndex { index_ty, .. } => format!(
+                    "array index must be an unsigned integer, found {}",
+                    name(&index_ty.0)
+                ),
+                TypeResolveError::InvalidTypeCast { from_ty, to_ty, .. } => {
+                    format!("cannot cast {} to {}", name(from_ty), name(to_ty))
+                }
+                TypeResolveError::UnknownGenericType(ty) => {
+                    format!("unknown generic type '{}'", ty.0)
+                }
+                TypeResolveError::GenericArgumentCountMismatch {
+                    ty,
+                    expect_count,
+                    actual_count,
+                } => format!(
+                    "'{}' expects {} type argument(s), but {} are given",
+                    ty.0, expect_count, actual_count
+                ),
+                TypeResolveError::TryOnNonTryableType(ty) => {
+                    format!("'?' cannot be used on {}", name(&ty.0))
+                }
+                TypeResolveError::TryInIncompatibleFunction {
+                    function_name,
+                    ret_ty,
+                    operand_ty,
+                } => format!(
+                    "'?' on {} cannot return from function '{}' returning {}",
+                    name(&operand_ty.0),
+                    function_name,
+                    name(ret_ty)
+                ),
+                TypeResolveError::IndexingOnNonTupleType(ty) => {
+                    format!("{} is not a tuple", name(&ty.0))
+                }
+                TypeResolveError::PatternTypeMismatch { value_ty, .. } => {
+                    format!("pattern does not match {}", name(value_ty))
+                }
+                TypeResolveError::AliasCycle(chain) => {
+                    format!(
+                        "type alias refers to itself: {}",
+                        format_chain(chain, table, sources)
+                    )
+                }
+                TypeResolveError::InfinitelySizedType(chain) => format!(
+                    "type contains itself and has infinite size: {}",
+                    format_chain(chain, table, sources)
+                ),
+            },
+            ResolveError::ControlFlowError(e) => match e {
+                ControlFlowError::NotAllFuncPathReturned(name) => {
+                    format!("not all paths of function '{}' return a value", name.0)
+                }
+            },
+        }
+    }
+}
+
+/// Format the types as `'A' [1:6] -> 'B' [2:6] -> 'A'` with the line and column where each one is
+/// declared, closing the cycle with the first type.
+fn format_chain(chain: &[Located<Type>], table: &SymbolTable, sources: &SourceMap) -> String {
+    let mut parts: Vec<_> = chain
+        .iter()
+        .map(|v| {
+            let at = sources.get(v.1.file).location(v.1.start);
+            format!("'{}' [{:?}]", v.0.sym(table).name, at)
+        })
+        .collect();
+    if let Some(first) = chain.first() {
+        parts.push(format!("'{}'", first.0.sym(table).name));
+    }
+    parts.join(" -> ")
 }
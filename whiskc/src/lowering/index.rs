@@ -0,0 +1,80 @@
+use crate::{
+    ast::location::Span,
+    symbol::{FuncId, SymbolTable, TypeId, VarId},
+};
+
+/// What the resolver made of the source spans, kept for editor tooling.
+#[derive(Debug, Clone, Default)]
+pub struct SourceIndex {
+    /// The type of every resolved expression.
+    pub expr_types: Vec<(Span, TypeId)>,
+    /// Every place a symbol is declared or referred to.
+    pub symbol_refs: Vec<(Span, SymbolRef)>,
+}
+impl SourceIndex {
+    /// The innermost expression covering byte `offset`.
+    pub fn type_at(&self, offset: u32) -> Option<(Span, TypeId)> {
+        innermost(&self.expr_types, offset)
+    }
+
+    pub fn symbol_at(&self, offset: u32) -> Option<(Span, SymbolRef)> {
+        innermost(&self.symbol_refs, offset)
+    }
+
+    /// Spans of the declaration and every use of `sym`.
+    pub fn references(&self, sym: SymbolRef) -> impl Iterator<Item = Span> + '_ {
+        self.symbol_refs
+            .iter()
+            .filter(move |(_, v)| *v == sym)
+            .map(|(span, _)| *span)
+    }
+}
+
+/// The end is inclusive so that a cursor right after an identifier still finds it.
+fn innermost<T: Copy>(entries: &[(Span, T)], offset: u32) -> Option<(Span, T)> {
+    entries
+        .iter()
+        .filter(|(span, _)| span.start <= offset && offset <= span.end)
+        .min_by_key(|(span, _)| span.len())
+        .copied()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SymbolRef {
+    Var(VarId),
+    Func(FuncId),
+    Type(TypeId),
+}
+impl SymbolRef {
+    pub fn name<'a>(&self, table: &'a SymbolTable) -> &'a str {
+        match self {
+            SymbolRef::Var(id) => &id.sym(table).name,
+            SymbolRef::Func(id) => &id.sym(table).name,
+            SymbolRef::Type(id) => &id.sym(table).name,
+        }
+    }
+
+    /// Where the symbol is declared.
+    pub fn origin(&self, table: &SymbolTable) -> Span {
+        match self {
+            SymbolRef::Var(id) => id.sym(table).origin,
+            SymbolRef::Func(id) => id.sym(table).origin,
+            SymbolRef::Type(id) => id.sym(table).origin,
+        }
+    }
+}
+impl From<VarId> for SymbolRef {
+    fn from(value: VarId) -> Self {
+        Self::Var(value)
+    }
+}
+impl From<FuncId> for SymbolRef {
+    fn from(value: FuncId) -> Self {
+        Self::Func(value)
+    }
+}
+impl From<TypeId> for SymbolRef {
+    fn from(value: TypeId) -> Self {
+        Self::Type(value)
+    }
+}
@@ -1,6 +1,6 @@
 use crate::{
     ast::parsing::token::Operator,
-    symbol::{BlockId, FuncId, TypeId, VarId},
+    symbol::{prelude::Variant, BlockId, FuncId, TypeId, VarId},
 };
 
 use super::stmt::Stmt;
@@ -25,6 +25,11 @@ pub enum ExprKind {
     Return(ReturnExpr),
     If(IfExpr),
     Loop(LoopExpr),
+    Variant(VariantExpr),
+    Try(TryExpr),
+    Tuple(TupleExpr),
+    TupleIndex(TupleIndexExpr),
+    Cast(CastExpr),
 }
 
 #[derive(Debug, Clone)]
@@ -68,11 +73,20 @@ pub struct ReturnExpr {
     pub expr: Option<Box<Expr>>,
 }
 
+/// `if a {} else if b {} else {}` chains are flattened into their branches, tried in order.
 #[derive(Debug, Clone)]
 pub struct IfExpr {
-    pub cond: Box<Expr>,
-    pub then: BlockExpr,
-    pub else_: Option<BlockExpr>,
+    pub branches: Vec<IfBranch>,
+    /// Always a block expression.
+    pub else_: Option<Box<Expr>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct IfBranch {
+    pub cond: Expr,
+    /// Always a block expression, its type tells whether the branch falls through to the end of
+    /// the chain.
+    pub body: Expr,
 }
 
 #[derive(Debug, Clone)]
@@ -80,6 +94,34 @@ pub struct LoopExpr {
     pub body: BlockExpr,
 }
 
+#[derive(Debug, Clone)]
+pub struct VariantExpr {
+    pub variant: Variant,
+    pub value: Option<Box<Expr>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TryExpr {
+    pub expr: Box<Expr>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TupleExpr {
+    pub elems: Vec<Expr>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TupleIndexExpr {
+    pub expr: Box<Expr>,
+    pub index: usize,
+}
+
+/// Conversion between a newtype and its base type, the value keeps its representation.
+#[derive(Debug, Clone)]
+pub struct CastExpr {
+    pub expr: Box<Expr>,
+}
+
 impl From<i64> for ExprKind {
     fn from(value: i64) -> Self {
         Self::Integer(value)
@@ -135,3 +177,28 @@ impl From<LoopExpr> for ExprKind {
         Self::Loop(value)
     }
 }
+impl From<VariantExpr> for ExprKind {
+    fn from(value: VariantExpr) -> Self {
+        Self::Variant(value)
+    }
+}
+impl From<TryExpr> for ExprKind {
+    fn from(value: TryExpr) -> Self {
+        Self::Try(value)
+    }
+}
+impl From<TupleExpr> for ExprKind {
+    fn from(value: TupleExpr) -> Self {
+        Self::Tuple(value)
+    }
+}
+impl From<TupleIndexExpr> for ExprKind {
+    fn from(value: TupleIndexExpr) -> Self {
+        Self::TupleIndex(value)
+    }
+}
+impl From<CastExpr> for ExprKind {
+    fn from(value: CastExpr) -> Self {
+        Self::Cast(value)
+    }
+}
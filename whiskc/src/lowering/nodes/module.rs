@@ -1,4 +1,4 @@
-use crate::symbol::SymbolTable;
+use crate::{lowering::index::SourceIndex, symbol::SymbolTable};
 
 use super::item::Item;
 
@@ -7,4 +7,5 @@ pub struct Module {
     pub sym_table: SymbolTable,
     pub name: String,
     pub items: Vec<Item>,
+    pub index: SourceIndex,
 }
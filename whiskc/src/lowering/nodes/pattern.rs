@@ -0,0 +1,7 @@
+use crate::symbol::VarId;
+
+#[derive(Debug, Clone)]
+pub enum Pattern {
+    Var(VarId),
+    Tuple(Vec<Pattern>),
+}
@@ -1,6 +1,4 @@
-use crate::symbol::VarId;
-
-use super::expr::Expr;
+use super::{expr::Expr, pattern::Pattern};
 
 #[derive(Debug, Clone)]
 pub enum Stmt {
@@ -15,6 +13,6 @@ pub struct ExprStmt {
 
 #[derive(Debug, Clone)]
 pub struct LetStmt {
-    pub var_id: VarId,
+    pub pat: Pattern,
     pub value: Expr,
 }
@@ -2,7 +2,7 @@ use std::fmt::{Display, Write};
 
 use crate::symbol::{ty::TypeKind, SymbolTable};
 
-use super::{visit::Visit, Module};
+use super::{nodes::pattern::Pattern, visit::Visit, Module};
 
 impl Module {
     pub fn pretty_print<W: Write>(&self, w: &mut W) {
@@ -124,6 +124,29 @@ impl<W: Write> PrintVisitor<'_, W> {
     }
 }
 
+impl<W: Write> PrintVisitor<'_, W> {
+    fn visit_pattern(&mut self, pat: &Pattern) {
+        match pat {
+            Pattern::Var(id) => {
+                self.start_item("var");
+
+                let sym = id.sym(self.table);
+
+                self.add_attrib("name", &sym.name);
+                self.add_attrib("type", &sym.ty.sym(self.table).name);
+            }
+            Pattern::Tuple(elems) => {
+                self.start_item("tuple");
+
+                for elem in elems {
+                    self.visit_pattern(elem);
+                }
+            }
+        }
+        self.end_item();
+    }
+}
+
 struct Item {
     name: String,
     attribs: Vec<String>,
@@ -262,15 +285,17 @@ impl<W: Write> Visit for PrintVisitor<'_, W> {
     fn visit_if_expr(&mut self, node: &super::nodes::expr::IfExpr) {
         self.start_item("if");
 
-        self.set_prefix("cond: ");
-        self.visit_expr(&node.cond);
+        for (i, branch) in node.branches.iter().enumerate() {
+            self.set_prefix(format!("cond {}: ", i));
+            self.visit_expr(&branch.cond);
 
-        self.set_prefix("then: ");
-        self.visit_block_expr(&node.then);
+            self.set_prefix(format!("then {}: ", i));
+            self.visit_expr(&branch.body);
+        }
 
         if let Some(else_) = &node.else_ {
             self.set_prefix("else: ");
-            self.visit_block_expr(else_);
+            self.visit_expr(else_);
         }
 
         self.end_item();
@@ -284,10 +309,8 @@ impl<W: Write> Visit for PrintVisitor<'_, W> {
     fn visit_let_stmt(&mut self, node: &super::nodes::stmt::LetStmt) {
         self.start_item("let_stmt");
 
-        let sym = node.var_id.sym(self.table);
-
-        self.add_attrib("name", &sym.name);
-        self.add_attrib("type", &sym.ty.sym(self.table).name);
+        self.set_prefix("pattern: ");
+        self.visit_pattern(&node.pat);
 
         self.set_prefix("value: ");
         self.visit_expr(&node.value);
@@ -325,6 +348,20 @@ impl<W: Write> Visit for PrintVisitor<'_, W> {
                 }
                 TypeKind::Ident(v) => self.add_attrib("ident", &v.sym(self.table).name),
                 TypeKind::Alias(v) => self.add_attrib("alias", &v.sym(self.table).name),
+                TypeKind::Option(v) => self.add_attrib("option", &v.sym(self.table).name),
+                TypeKind::Result(ok, err) => {
+                    self.start_item("result");
+                    self.add_attrib("ok", &ok.sym(self.table).name);
+                    self.add_attrib("err", &err.sym(self.table).name);
+                    self.end_item();
+                }
+                TypeKind::Tuple(elems) => {
+                    self.start_item("tuple");
+                    for (i, ty) in elems.iter().enumerate() {
+                        self.add_attrib(&i.to_string(), &ty.sym(self.table).name)
+                    }
+                    self.end_item();
+                }
             };
             self.end_item();
         } else {
@@ -348,4 +385,50 @@ impl<W: Write> Visit for PrintVisitor<'_, W> {
         self.start_item("unit");
         self.end_item();
     }
+
+    fn visit_variant_expr(&mut self, node: &super::nodes::expr::VariantExpr) {
+        self.start_item(format!("variant: {}", node.variant));
+
+        if let Some(value) = &node.value {
+            self.visit_expr(value);
+        }
+
+        self.end_item();
+    }
+
+    fn visit_try_expr(&mut self, node: &super::nodes::expr::TryExpr) {
+        self.start_item("try");
+
+        self.visit_expr(&node.expr);
+
+        self.end_item();
+    }
+
+    fn visit_tuple_expr(&mut self, node: &super::nodes::expr::TupleExpr) {
+        self.start_item("tuple");
+
+        for elem in &node.elems {
+            self.visit_expr(elem);
+        }
+
+        self.end_item();
+    }
+
+    fn visit_tuple_index_expr(&mut self, node: &super::nodes::expr::TupleIndexExpr) {
+        self.start_item("tuple_index");
+
+        self.add_attrib("index", node.index);
+
+        self.visit_expr(&node.expr);
+
+        self.end_item();
+    }
+
+    fn visit_cast_expr(&mut self, node: &super::nodes::expr::CastExpr) {
+        self.start_item("cast");
+
+        self.visit_expr(&node.expr);
+
+        self.end_item();
+    }
 }
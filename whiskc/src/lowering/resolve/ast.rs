@@ -1,9 +1,9 @@
 use crate::{
-    ast,
+    ast::{self, nodes::item::Item as AstItem},
     lowering::{nodes::item::Item, resolve::Record},
 };
 
-use super::Resolve;
+use super::{ty::validate_type_graph, Resolve};
 
 impl Resolve<(), Vec<Item>> for ast::AST {
     fn resolve(&self, ctx: &mut super::ResolveContext, _: ()) -> Vec<Item> {
@@ -14,13 +14,46 @@ impl Resolve<(), Vec<Item>> for ast::AST {
             }
         }
 
+        // type declarations are resolved first, so that every type is complete and validated
+        // before any function body uses it.
+        let (ty_indexes, other_indexes): (Vec<usize>, Vec<usize>) = ok_indexes
+            .into_iter()
+            .partition(|i| matches!(self.items[*i], AstItem::TypeDecl(_)));
+
         let mut items = Vec::new();
-        for index in ok_indexes {
-            let Some(item) = self.items[index].resolve(ctx, ()) else {
-                continue;
-            };
-            items.push(item);
+        for &index in &ty_indexes {
+            if let Some(item) = self.items[index].resolve(ctx, ()) {
+                items.push((index, item));
+            }
+        }
+
+        let decls: Vec<_> = ty_indexes
+            .iter()
+            .filter_map(|i| match &self.items[*i] {
+                AstItem::TypeDecl(decl) => Some(decl),
+                _ => None,
+            })
+            .collect();
+        validate_type_graph(ctx, &decls);
+
+        // every signature is resolved before the bodies, so that calls can refer to functions
+        // declared later in the source.
+        let other_indexes: Vec<usize> = other_indexes
+            .into_iter()
+            .filter(|i| match &self.items[*i] {
+                AstItem::Function(func) => func.sig.resolve(ctx, ()).is_some(),
+                AstItem::ExternFunction(func) => func.sig.resolve(ctx, ()).is_some(),
+                _ => true,
+            })
+            .collect();
+
+        for index in other_indexes {
+            if let Some(item) = self.items[index].resolve(ctx, ()) {
+                items.push((index, item));
+            }
         }
-        items
+
+        items.sort_by_key(|(index, _)| *index);
+        items.into_iter().map(|(_, item)| item).collect()
     }
 }
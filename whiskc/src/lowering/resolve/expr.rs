@@ -1,18 +1,36 @@
 use crate::{
-    ast::{location::Located, nodes as ast, parsing::token::Operator},
+    ast::{
+        location::{Locatable, Located, Span},
+        nodes as ast,
+        parsing::token::Operator,
+    },
     lowering::{
+        errors::{IdentResolveError, TypeResolveError, ValueResolveError},
         nodes::expr::{
-            BinaryExpr, BlockExpr, CallExpr, Expr, ExprKind, FuncIdentExpr, IfExpr, LoopExpr,
-            ReturnExpr, UnaryExpr, VarIdentExpr,
+            BinaryExpr, BlockExpr, CallExpr, CastExpr, Expr, ExprKind, FuncIdentExpr, IfBranch,
+            IfExpr, LoopExpr, ReturnExpr, TryExpr, TupleExpr, TupleIndexExpr, UnaryExpr,
+            VarIdentExpr, VariantExpr,
         },
+        nodes::stmt::{ExprStmt, Stmt},
         resolve::Flow,
     },
+    symbol::{prelude::Variant, ty::TypeKind, TypeId},
 };
 
 use super::{FlowObj, Resolve, ResolveContext};
 
 impl Resolve<(), FlowObj<Expr>> for ast::expr::Expr {
     fn resolve(&self, ctx: &mut ResolveContext, _: ()) -> FlowObj<Expr> {
+        let result = self.resolve_kind(ctx);
+        if let Some(expr) = &result.value {
+            ctx.index.expr_types.push((self.get_location(), expr.ty));
+        }
+        result
+    }
+}
+
+impl ast::expr::Expr {
+    fn resolve_kind(&self, ctx: &mut ResolveContext) -> FlowObj<Expr> {
         match self {
             ast::expr::Expr::Unit(_) => FlowObj::cont(Expr {
                 kind: ExprKind::Unit,
@@ -35,6 +53,11 @@ impl Resolve<(), FlowObj<Expr>> for ast::expr::Expr {
             ast::expr::Expr::Return(v) => v.resolve(ctx, ()),
             ast::expr::Expr::If(v) => v.resolve(ctx, ()),
             ast::expr::Expr::Loop(v) => v.resolve(ctx, ()),
+            ast::expr::Expr::Try(v) => v.resolve(ctx, ()),
+            ast::expr::Expr::Tuple(v) => v.resolve(ctx, ()),
+            ast::expr::Expr::TupleIndex(v) => v.resolve(ctx, ()),
+            // the syntax error is already reported by the parser.
+            ast::expr::Expr::Error(_) => error_placeholder(ctx),
         }
     }
 }
@@ -56,57 +79,82 @@ impl Resolve<(), FlowObj<Expr>> for ast::expr::BinaryExpr {
             return FlowObj::none(merged_flow);
         };
 
-        let check_ty_equal = || {
-            if !ctx.table.is_type_symmetric(left.ty, right.ty) {
-                todo!("report error")
-            }
-        };
-        let check_ty_num = |expr: &Expr| {
-            if !ctx
-                .table
-                .is_type_coercible(expr.ty, ctx.table.common_type().int)
+        let left_loc = self.left.get_location();
+        let right_loc = self.right.get_location();
+        let op = self.op;
+        let (int, bool) = (ctx.table.common_type().int, ctx.table.common_type().bool);
+        let mut errors: Vec<TypeResolveError> = Vec::new();
+
+        // a diverging operand, e.g. one that failed to parse, matches either side. An operand
+        // already reported is the mistake, not the pair.
+        let check_ty_equal = |errors: &mut Vec<TypeResolveError>| {
+            if errors.is_empty()
+                && ctx
+                    .table
+                    .compare_type_asymmetric(left.ty, right.ty)
+                    .is_none()
             {
-                todo!("report error")
+                errors.push(TypeResolveError::TypeMismatchInBinaryOp {
+                    op,
+                    left_ty: left.ty,
+                    right_ty: right.ty,
+                });
             }
         };
-        let check_ty_bool = |expr: &Expr| {
-            if !ctx
-                .table
-                .is_type_coercible(expr.ty, ctx.table.common_type().bool)
-            {
-                todo!("report error")
+        // both operands of the same wrong type, as in `m * m`, are one mistake reported once.
+        let check_ty_num = |errors: &mut Vec<TypeResolveError>, ty: TypeId, loc| {
+            let reported = errors.iter().any(|e| match e {
+                TypeResolveError::NonNumericTypeInBinaryOp {
+                    ty: Located(v, _), ..
+                } => *v == ty,
+                _ => false,
+            });
+            if !reported && !ctx.table.is_type_coercible(ty, int) {
+                errors.push(TypeResolveError::NonNumericTypeInBinaryOp {
+                    op,
+                    ty: Located(ty, loc),
+                });
             }
         };
-        let check_ty_int = |expr: &Expr| {
-            if !ctx
-                .table
-                .is_type_coercible(expr.ty, ctx.table.common_type().int)
-            {
-                todo!("report error")
+        let check_ty = |errors: &mut Vec<TypeResolveError>, ty: TypeId, loc, expect_type| {
+            if !ctx.table.is_type_coercible(ty, expect_type) {
+                errors.push(TypeResolveError::UnexpectedTypeInBinaryOp {
+                    op,
+                    expect_type,
+                    actual_type: Located(ty, loc),
+                });
             }
         };
 
         let op_ty = match self.op.0 {
             Operator::Assign => {
-                // TODO: check if expr is assignable
-                check_ty_equal();
+                if !matches!(left.kind, ExprKind::VarIdent(_)) {
+                    errors.push(TypeResolveError::NonAssignableType(Located(
+                        left.ty, left_loc,
+                    )));
+                } else if !ctx.table.is_type_coercible(right.ty, left.ty) {
+                    errors.push(TypeResolveError::AssignmentTypeMismatch {
+                        target_ty: Located(left.ty, left_loc),
+                        value_ty: Located(right.ty, right_loc),
+                    });
+                }
                 ctx.table.common_type().unit
             }
             Operator::Add | Operator::Sub | Operator::Mul | Operator::Div => {
-                check_ty_num(&left);
-                check_ty_num(&right);
-                check_ty_equal();
+                check_ty_num(&mut errors, left.ty, left_loc);
+                check_ty_num(&mut errors, right.ty, right_loc);
+                check_ty_equal(&mut errors);
                 left.ty
             }
             Operator::Mod => {
-                check_ty_num(&left);
-                check_ty_int(&right);
-                ctx.table.common_type().int
+                check_ty_num(&mut errors, left.ty, left_loc);
+                check_ty(&mut errors, right.ty, right_loc, int);
+                int
             }
             Operator::And | Operator::Or => {
-                check_ty_bool(&left);
-                check_ty_bool(&right);
-                ctx.table.common_type().bool
+                check_ty(&mut errors, left.ty, left_loc, bool);
+                check_ty(&mut errors, right.ty, right_loc, bool);
+                bool
             }
             Operator::Equal
             | Operator::NotEqual
@@ -114,13 +162,23 @@ impl Resolve<(), FlowObj<Expr>> for ast::expr::BinaryExpr {
             | Operator::LessEqual
             | Operator::Greater
             | Operator::GreaterEqual => {
-                check_ty_num(&left);
-                check_ty_num(&right);
-                check_ty_equal();
-                ctx.table.common_type().bool
+                check_ty_num(&mut errors, left.ty, left_loc);
+                check_ty_num(&mut errors, right.ty, right_loc);
+                check_ty_equal(&mut errors);
+                bool
             }
-            _ => unimplemented!(),
+            _ => unreachable!("the parser only builds binary expressions of binary operators"),
+        };
+        // the expression is still built, with the error type so that its users are not reported
+        // again.
+        let op_ty = if errors.is_empty() {
+            op_ty
+        } else {
+            ctx.table.common_type().error
         };
+        for e in errors {
+            ctx.error(e);
+        }
 
         FlowObj::new(
             Expr {
@@ -137,6 +195,27 @@ impl Resolve<(), FlowObj<Expr>> for ast::expr::BinaryExpr {
     }
 }
 
+/// Stands in for an expression whose error is already reported, the error type keeps it and the
+/// expressions built from it from causing type errors of their own. Modules with errors never
+/// reach codegen.
+fn error_placeholder(ctx: &ResolveContext) -> FlowObj<Expr> {
+    FlowObj::cont(error_expr(ctx))
+}
+
+fn error_expr(ctx: &ResolveContext) -> Expr {
+    Expr {
+        kind: ExprKind::Unit,
+        ty: ctx.table.common_type().error,
+    }
+}
+
+/// Whether nothing is known of what the value is, either as it is never produced or as its error
+/// is already reported.
+fn is_never_or_error(ctx: &ResolveContext, ty: TypeId) -> bool {
+    let common = ctx.table.common_type();
+    ty == common.never || ty == common.error
+}
+
 impl Resolve<(), FlowObj<Expr>> for ast::expr::UnaryExpr {
     fn resolve(&self, ctx: &mut ResolveContext, _: ()) -> FlowObj<Expr> {
         let FlowObj { value, flow } = self.expr.resolve(ctx, ());
@@ -153,7 +232,10 @@ impl Resolve<(), FlowObj<Expr>> for ast::expr::UnaryExpr {
                     .table
                     .is_type_coercible(value.ty, ctx.table.common_type().int)
                 {
-                    todo!("report error")
+                    ctx.error(TypeResolveError::NonNumericInUnaryOp(
+                        self.op.0,
+                        Located(value.ty, self.expr.get_location()),
+                    ));
                 }
                 ctx.table.common_type().int
             }
@@ -162,11 +244,14 @@ impl Resolve<(), FlowObj<Expr>> for ast::expr::UnaryExpr {
                     .table
                     .is_type_coercible(value.ty, ctx.table.common_type().bool)
                 {
-                    todo!("report error")
+                    ctx.error(TypeResolveError::NonBoolUsedInNotOp(Located(
+                        value.ty,
+                        self.expr.get_location(),
+                    )));
                 }
                 ctx.table.common_type().bool
             }
-            _ => unimplemented!(),
+            _ => unreachable!("the parser only builds unary expressions of prefix operators"),
         };
 
         FlowObj::new(
@@ -185,86 +270,135 @@ impl Resolve<(), FlowObj<Expr>> for ast::expr::UnaryExpr {
 
 impl Resolve<(), FlowObj<Expr>> for ast::expr::IfExpr {
     fn resolve(&self, ctx: &mut ResolveContext, _: ()) -> FlowObj<Expr> {
-        let FlowObj { value, flow } = self.cond.resolve(ctx, ());
-        let Some(cond) = value else {
-            return FlowObj::none(flow);
-        };
-        if flow != Flow::Continue {
-            return FlowObj::new(cond, flow);
-        }
-
-        if !ctx
-            .table
-            .is_type_coercible(cond.ty, ctx.table.common_type().bool)
-        {
-            todo!("report error")
-        }
-
-        let FlowObj {
-            value: then_body,
-            flow: then_flow,
-        } = self.then.resolve(ctx, ());
-
-        if let Some(else_) = &self.else_expr {
-            let FlowObj {
-                value: else_body,
-                flow: else_flow,
-            } = else_.body.resolve(ctx, ());
-
-            let merged_flow = then_flow & else_flow;
-
-            let (Some(then), Some(else_)) = (then_body, else_body) else {
-                // TODO: is this the right behavior?
-                return FlowObj::none(merged_flow);
-            };
-
-            let Some(if_ty) = ctx.table.compare_type_asymmetric(then.ty, else_.ty) else {
-                todo!("report error")
-            };
-            let (ExprKind::Block(then), ExprKind::Block(else_)) = (then.kind, else_.kind) else {
-                unreachable!()
-            };
-
-            FlowObj::new(
-                Expr {
-                    kind: IfExpr {
-                        cond: Box::new(cond),
-                        then,
-                        else_: Some(else_),
-                    }
-                    .into(),
-                    ty: if_ty,
-                },
-                merged_flow,
-            )
-        } else {
-            let Some(then_body) = then_body else {
-                // TODO: is this the right behavior?
-                // use continue_none since having return in the then block doesnt imply that the
-                // control flow will return entirely.
-                return FlowObj::cont_none();
+        let mut branches = Vec::new();
+        let mut else_ = None;
+        let mut has_else = false;
+        // resolved bodies of every branch including the else, None if the body failed to resolve.
+        let mut bodies: Vec<(Option<Expr>, Flow, Span)> = Vec::new();
+
+        let mut if_expr = self;
+        loop {
+            let FlowObj { value, flow } = if_expr.cond.resolve(ctx, ());
+            let Some(cond) = value else {
+                return FlowObj::none(merge_if_flow(&bodies, false));
             };
+            if flow != Flow::Continue {
+                if branches.is_empty() {
+                    return FlowObj::new(cond, flow);
+                }
+                // the rest of the chain is unreachable, the condition itself becomes the else.
+                let body = unreachable_block(ctx, cond);
+                bodies.push((Some(body.clone()), flow, if_expr.cond.get_location()));
+                else_ = Some(Box::new(body));
+                has_else = true;
+                break;
+            }
 
             if !ctx
                 .table
-                .is_type_coercible(then_body.ty, ctx.table.common_type().unit)
+                .is_type_coercible(cond.ty, ctx.table.common_type().bool)
             {
-                todo!("report error")
+                ctx.error(TypeResolveError::NonBoolInIfCond(Located(
+                    cond.ty,
+                    if_expr.cond.get_location(),
+                )));
             }
 
-            let ExprKind::Block(then) = then_body.kind else {
-                unreachable!()
+            let FlowObj { value, flow } = if_expr.then.resolve(ctx, ());
+            bodies.push((value.clone(), flow, if_expr.then.get_location()));
+            if let Some(body) = value {
+                branches.push(IfBranch { cond, body });
+            }
+
+            let Some(else_expr) = &if_expr.else_expr else {
+                break;
             };
-            FlowObj::cont(Expr {
-                kind: IfExpr {
-                    cond: Box::new(cond),
-                    then,
-                    else_: None,
+            match &else_expr.body {
+                ast::expr::ElseBody::Block(block) => {
+                    let FlowObj { value, flow } = block.resolve(ctx, ());
+                    bodies.push((value.clone(), flow, block.get_location()));
+                    else_ = value.map(Box::new);
+                    has_else = true;
+                    break;
                 }
-                .into(),
-                ty: ctx.table.common_type().unit,
-            })
+                ast::expr::ElseBody::If(next) => if_expr = next,
+            }
+        }
+
+        let flow = merge_if_flow(&bodies, has_else);
+        if bodies.iter().any(|(body, _, _)| body.is_none()) {
+            // TODO: is this the right behavior?
+            // without an else, having return in a branch doesnt imply that the control flow will
+            // return entirely.
+            return FlowObj::none(flow);
+        }
+
+        let if_ty = if has_else {
+            let mut joined: Option<Located<TypeId>> = None;
+            for (body, _, loc) in &bodies {
+                let ty = body.as_ref().unwrap().ty;
+                let Some(other) = joined else {
+                    joined = Some(Located(ty, *loc));
+                    continue;
+                };
+                let Some(ty) = ctx.table.join_types(other.0, ty) else {
+                    ctx.error(TypeResolveError::BlockBranchTypeMismatch {
+                        branch: Located(ty, *loc),
+                        other,
+                    });
+                    return FlowObj::new(error_expr(ctx), flow);
+                };
+                joined = Some(Located(ty, other.1));
+            }
+            joined.unwrap().0
+        } else {
+            let unit = ctx.table.common_type().unit;
+            for (body, _, loc) in &bodies {
+                let ty = body.as_ref().unwrap().ty;
+                if !ctx.table.is_type_coercible(ty, unit) {
+                    ctx.error(TypeResolveError::BlockBranchTypeMismatch {
+                        branch: Located(ty, *loc),
+                        other: Located(unit, self.get_location()),
+                    });
+                    return FlowObj::new(error_expr(ctx), flow);
+                }
+            }
+            unit
+        };
+
+        FlowObj::new(
+            Expr {
+                kind: IfExpr { branches, else_ }.into(),
+                ty: if_ty,
+            },
+            flow,
+        )
+    }
+}
+
+/// The chain only breaks when it is exhaustive and every path out of it breaks.
+fn merge_if_flow(bodies: &[(Option<Expr>, Flow, Span)], has_else: bool) -> Flow {
+    if !has_else {
+        return Flow::Continue;
+    }
+    bodies
+        .iter()
+        .fold(Flow::Break, |flow, (_, body_flow, _)| flow & *body_flow)
+}
+
+/// Block holding an expression that never yields a value, used where the lowered tree expects a
+/// block in code that cannot be reached normally.
+fn unreachable_block(ctx: &mut ResolveContext, expr: Expr) -> Expr {
+    let bid = ctx.table.new_block(ctx.get_func_id());
+    bid.sym_mut(ctx.table).parent_block = Some(ctx.get_block());
+    Expr {
+        kind: BlockExpr {
+            block_id: bid,
+            stmts: vec![Stmt::Expr(ExprStmt { expr })],
+            eval_expr: None,
         }
+        .into(),
+        ty: ctx.table.common_type().never,
     }
 }
 
@@ -298,11 +432,13 @@ impl Resolve<(), FlowObj<Expr>> for ast::expr::ReturnExpr {
             if flow != Flow::Continue {
                 return FlowObj::new(value, flow);
             }
-            if !ctx
-                .table
-                .is_type_coercible(value.ty, ctx.get_func_id().sym(ctx.table).ret_ty)
-            {
-                todo!("report error");
+            let func_sym = ctx.get_func_id().sym(ctx.table);
+            if !ctx.table.is_type_coercible(value.ty, func_sym.ret_ty) {
+                ctx.error(TypeResolveError::ReturnTypeMismatch {
+                    function_name: func_sym.name.clone(),
+                    expected_type: func_sym.ret_ty,
+                    actual_type: Located(value.ty, self.expr.as_ref().unwrap().get_location()),
+                });
             }
             FlowObj::brk(Expr {
                 kind: ReturnExpr {
@@ -312,8 +448,14 @@ impl Resolve<(), FlowObj<Expr>> for ast::expr::ReturnExpr {
                 ty: ctx.table.common_type().never,
             })
         } else {
-            if ctx.get_func_id().sym(ctx.table).ret_ty != ctx.table.common_type().unit {
-                todo!("report error");
+            let func_sym = ctx.get_func_id().sym(ctx.table);
+            let unit = ctx.table.common_type().unit;
+            if !ctx.table.is_type_coercible(unit, func_sym.ret_ty) {
+                ctx.error(TypeResolveError::ReturnTypeMismatch {
+                    function_name: func_sym.name.clone(),
+                    expected_type: func_sym.ret_ty,
+                    actual_type: Located(unit, self.return_tok.1),
+                });
             }
             FlowObj::brk(Expr {
                 kind: ReturnExpr { expr: None }.into(),
@@ -323,8 +465,289 @@ impl Resolve<(), FlowObj<Expr>> for ast::expr::ReturnExpr {
     }
 }
 
+impl Resolve<(), FlowObj<Expr>> for ast::expr::TryExpr {
+    fn resolve(&self, ctx: &mut ResolveContext, _: ()) -> FlowObj<Expr> {
+        let FlowObj { value, flow } = self.expr.resolve(ctx, ());
+        let Some(value) = value else {
+            return FlowObj::none(flow);
+        };
+        if flow != Flow::Continue || is_never_or_error(ctx, value.ty) {
+            return FlowObj::new(value, flow);
+        }
+
+        let func_sym = ctx.get_func_id().sym(ctx.table);
+        let (function_name, ret_ty) = (func_sym.name.clone(), func_sym.ret_ty);
+        let operand_ty = Located(value.ty, self.expr.get_location());
+
+        let ok_ty = match (
+            ctx.table.get_type_kind(value.ty),
+            ctx.table.get_type_kind(ret_ty),
+        ) {
+            (Some(TypeKind::Option(inner)), Some(TypeKind::Option(_))) => *inner,
+            (Some(TypeKind::Result(ok, err)), Some(TypeKind::Result(_, ret_err)))
+                if ctx.table.is_type_coercible(*err, *ret_err) =>
+            {
+                *ok
+            }
+            (Some(TypeKind::Option(_) | TypeKind::Result(_, _)), _) => {
+                ctx.error(TypeResolveError::TryInIncompatibleFunction {
+                    function_name,
+                    ret_ty,
+                    operand_ty,
+                });
+                return error_placeholder(ctx);
+            }
+            _ => {
+                ctx.error(TypeResolveError::TryOnNonTryableType(operand_ty));
+                return error_placeholder(ctx);
+            }
+        };
+
+        // the success side can never be produced, so `?` always returns early.
+        let flow = if ok_ty == ctx.table.common_type().never {
+            Flow::Break
+        } else {
+            Flow::Continue
+        };
+        FlowObj::new(
+            Expr {
+                kind: TryExpr {
+                    expr: Box::new(value),
+                }
+                .into(),
+                ty: ok_ty,
+            },
+            flow,
+        )
+    }
+}
+
+impl Resolve<(), FlowObj<Expr>> for ast::expr::TupleExpr {
+    fn resolve(&self, ctx: &mut ResolveContext, _: ()) -> FlowObj<Expr> {
+        let mut elems = Vec::new();
+        for ast_elem in &self.elems.items {
+            let FlowObj { value, flow } = ast_elem.resolve(ctx, ());
+            let Some(elem) = value else {
+                return FlowObj::none(flow);
+            };
+            if flow != Flow::Continue {
+                // the subsequence elements are never reached, the tuple is never built.
+                elems.push(elem);
+                return FlowObj::new(
+                    Expr {
+                        kind: TupleExpr { elems }.into(),
+                        ty: ctx.table.common_type().never,
+                    },
+                    flow,
+                );
+            }
+            elems.push(elem);
+        }
+
+        let ty = ctx
+            .table
+            .get_tuple_type(elems.iter().map(|v| v.ty).collect());
+        FlowObj::cont(Expr {
+            kind: TupleExpr { elems }.into(),
+            ty,
+        })
+    }
+}
+
+impl Resolve<(), FlowObj<Expr>> for ast::expr::TupleIndexExpr {
+    fn resolve(&self, ctx: &mut ResolveContext, _: ()) -> FlowObj<Expr> {
+        let FlowObj { value, flow } = self.expr.resolve(ctx, ());
+        let Some(value) = value else {
+            return FlowObj::none(flow);
+        };
+        if flow != Flow::Continue || is_never_or_error(ctx, value.ty) {
+            return FlowObj::new(value, flow);
+        }
+
+        let tuple_ty = Located(value.ty, self.expr.get_location());
+        if let Some(&TypeKind::Ident(base)) = ctx.table.get_type_kind(value.ty) {
+            // `.0` unwraps a newtype into its base type.
+            if self.index.0 != 0 {
+                ctx.error(ValueResolveError::TupleIndexOutOfRange {
+                    tuple_ty,
+                    index: self.index,
+                });
+                return error_placeholder(ctx);
+            }
+            return FlowObj::new(
+                Expr {
+                    kind: CastExpr {
+                        expr: Box::new(value),
+                    }
+                    .into(),
+                    ty: base,
+                },
+                flow,
+            );
+        }
+        let Some(TypeKind::Tuple(elems)) = ctx.table.get_type_kind(value.ty) else {
+            ctx.error(TypeResolveError::IndexingOnNonTupleType(tuple_ty));
+            return error_placeholder(ctx);
+        };
+        let Some(&ty) = usize::try_from(self.index.0)
+            .ok()
+            .and_then(|index| elems.get(index))
+        else {
+            ctx.error(ValueResolveError::TupleIndexOutOfRange {
+                tuple_ty,
+                index: self.index,
+            });
+            return error_placeholder(ctx);
+        };
+
+        FlowObj::new(
+            Expr {
+                kind: TupleIndexExpr {
+                    expr: Box::new(value),
+                    index: self.index.0 as usize,
+                }
+                .into(),
+                ty,
+            },
+            flow,
+        )
+    }
+}
+
+fn resolve_variant_call(
+    ctx: &mut ResolveContext,
+    variant: Variant,
+    name: &Located<String>,
+    args: &[ast::expr::Expr],
+) -> FlowObj<Expr> {
+    let expect_count = variant.has_value() as usize;
+    if args.len() != expect_count {
+        ctx.error(ValueResolveError::VariantArgumentCountMismatch {
+            variant: name.clone(),
+            expect_count,
+            actual_count: args.len(),
+        });
+        return error_placeholder(ctx);
+    }
+
+    let never = ctx.table.common_type().never;
+    let (value, flow) = if let Some(arg) = args.first() {
+        let FlowObj { value, flow } = arg.resolve(ctx, ());
+        let Some(value) = value else {
+            return FlowObj::none(flow);
+        };
+        if flow != Flow::Continue {
+            return FlowObj::new(value, flow);
+        }
+        (Some(value), flow)
+    } else {
+        (None, Flow::Continue)
+    };
+
+    let value_ty = value.as_ref().map(|v| v.ty).unwrap_or(never);
+    let ty = match variant {
+        Variant::Some | Variant::None => ctx.table.get_option_type(value_ty),
+        Variant::Ok => ctx.table.get_result_type(value_ty, never),
+        Variant::Err => ctx.table.get_result_type(never, value_ty),
+    };
+    FlowObj::new(
+        Expr {
+            kind: VariantExpr {
+                variant,
+                value: value.map(Box::new),
+            }
+            .into(),
+            ty,
+        },
+        flow,
+    )
+}
+
+/// Wrap the single argument into the newtype `ty`, e.g. `Meters(5)`.
+fn resolve_newtype_call(
+    ctx: &mut ResolveContext,
+    ty: Located<TypeId>,
+    args: &[ast::expr::Expr],
+) -> FlowObj<Expr> {
+    let Some(&TypeKind::Ident(base)) = ctx.table.get_type_kind(ty.0) else {
+        ctx.error(TypeResolveError::CallOnNonFunctionType(ty));
+        return error_placeholder(ctx);
+    };
+    let func_name = Located(ty.0.sym(ctx.table).name.clone(), ty.1);
+    let [arg] = args else {
+        ctx.error(ValueResolveError::ArgumentCountMismatch {
+            func_name,
+            expect_count: 1,
+            actual_count: args.len(),
+        });
+        return error_placeholder(ctx);
+    };
+
+    let FlowObj { value, flow } = arg.resolve(ctx, ());
+    let Some(value) = value else {
+        return FlowObj::none(flow);
+    };
+    if flow != Flow::Continue {
+        return FlowObj::new(value, flow);
+    }
+    if !ctx.table.is_type_coercible(value.ty, base) {
+        ctx.error(TypeResolveError::ArgumentTypeMismatch {
+            func_name,
+            argument_index: 0,
+            expect_type: base,
+            actual_type: Located(value.ty, arg.get_location()),
+        });
+    }
+
+    FlowObj::new(
+        Expr {
+            kind: CastExpr {
+                expr: Box::new(value),
+            }
+            .into(),
+            ty: ty.0,
+        },
+        flow,
+    )
+}
+
+/// Prelude variants can be shadowed by user declared variables and functions.
+fn lookup_variant(ctx: &ResolveContext, name: &str) -> Option<Variant> {
+    let variant = Variant::from_name(name)?;
+    let is_shadowed = ctx
+        .table
+        .get_variable_id_by_name(ctx.get_block(), name)
+        .is_some()
+        || ctx.table.get_function_id(name).is_some();
+    (!is_shadowed).then_some(variant)
+}
+
+/// Type names are only looked up when no variable or function of the same name is in scope.
+fn lookup_type(ctx: &ResolveContext, name: &str) -> Option<TypeId> {
+    let is_shadowed = ctx
+        .table
+        .get_variable_id_by_name(ctx.get_block(), name)
+        .is_some()
+        || ctx.table.get_function_id(name).is_some();
+    if is_shadowed {
+        None
+    } else {
+        ctx.table.get_type_id(name)
+    }
+}
+
 impl Resolve<(), FlowObj<Expr>> for ast::expr::CallExpr {
     fn resolve(&self, ctx: &mut ResolveContext, _: ()) -> FlowObj<Expr> {
+        if let ast::expr::Expr::Identifier(name) = self.caller.as_ref() {
+            if let Some(variant) = lookup_variant(ctx, &name.0) {
+                return resolve_variant_call(ctx, variant, name, &self.args.items);
+            }
+            if let Some(ty) = lookup_type(ctx, &name.0) {
+                ctx.add_ref(name.1, ty);
+                return resolve_newtype_call(ctx, Located(ty, name.1), &self.args.items);
+            }
+        }
+
         let FlowObj {
             value,
             flow: mut result_flow,
@@ -334,16 +757,28 @@ impl Resolve<(), FlowObj<Expr>> for ast::expr::CallExpr {
             return FlowObj::none(result_flow);
         };
         let ExprKind::FuncIdent(FuncIdentExpr { id: fid }) = caller.kind else {
-            todo!("report error")
+            if !is_never_or_error(ctx, caller.ty) {
+                ctx.error(TypeResolveError::CallOnNonFunctionType(Located(
+                    caller.ty,
+                    self.caller.get_location(),
+                )));
+            }
+            return error_placeholder(ctx);
         };
 
         let sym = fid.sym(ctx.table);
-        if self.args.items.len() != sym.params.len() {
-            todo!("report error");
+        let func_name = Located(sym.name.clone(), self.caller.get_location());
+        let params = sym.params.clone();
+        if self.args.items.len() != params.len() {
+            ctx.error(ValueResolveError::ArgumentCountMismatch {
+                func_name: func_name.clone(),
+                expect_count: params.len(),
+                actual_count: self.args.items.len(),
+            });
         }
 
         let mut args = Vec::new();
-        for (ast_arg, param_id) in self.args.items.iter().zip(sym.params.clone()) {
+        for (i, (ast_arg, param_id)) in self.args.items.iter().zip(params).enumerate() {
             let FlowObj { value, flow } = ast_arg.resolve(ctx, ());
             let Some(arg) = value else {
                 // assumed the resolve called had already reported the error.
@@ -351,7 +786,12 @@ impl Resolve<(), FlowObj<Expr>> for ast::expr::CallExpr {
             };
             let param_ty = param_id.sym(ctx.table).ty;
             if !ctx.table.is_type_coercible(arg.ty, param_ty) {
-                todo!("report error");
+                ctx.error(TypeResolveError::ArgumentTypeMismatch {
+                    func_name: func_name.clone(),
+                    argument_index: i,
+                    expect_type: param_ty,
+                    actual_type: Located(arg.ty, ast_arg.get_location()),
+                });
             }
             args.push(arg);
             result_flow = flow;
@@ -378,19 +818,29 @@ impl Resolve<(), FlowObj<Expr>> for ast::expr::CallExpr {
 impl Resolve<(), FlowObj<Expr>> for Located<String> {
     fn resolve(&self, ctx: &mut ResolveContext, _: ()) -> FlowObj<Expr> {
         if let Some(var) = ctx.table.get_variable_by_name_mut(ctx.get_block(), &self.0) {
+            let var_id = var.get_id();
+            let ty = var.ty;
+            ctx.add_ref(self.1, var_id);
             FlowObj::cont(Expr {
-                kind: VarIdentExpr { id: var.get_id() }.into(),
-                ty: var.ty,
+                kind: VarIdentExpr { id: var_id }.into(),
+                ty,
             })
         } else if let Some(func) = ctx.table.get_function_by_name_mut(&self.0) {
+            let func_id = func.get_id();
+            let ty = func.ret_ty;
+            ctx.add_ref(self.1, func_id);
             FlowObj::cont(Expr {
-                kind: FuncIdentExpr { id: func.get_id() }.into(),
-                ty: func.ret_ty,
+                kind: FuncIdentExpr { id: func_id }.into(),
+                ty,
             })
+        } else if let Some(variant) = lookup_variant(ctx, &self.0) {
+            resolve_variant_call(ctx, variant, self, &[])
         } else if ctx.table.get_type_by_name_mut(&self.0).is_some() {
-            todo!("report error")
+            ctx.error(IdentResolveError::TypeUsedAsValue(self.clone()));
+            error_placeholder(ctx)
         } else {
-            todo!("report error")
+            ctx.error(IdentResolveError::UnknownIdentifier(self.clone()));
+            error_placeholder(ctx)
         }
     }
 }
@@ -437,14 +887,21 @@ impl Resolve<(), FlowObj<Expr>> for ast::expr::BlockExpr {
             );
         }
 
-        let eval_expr = self.eval_expr.as_ref().map(|v| v.resolve(ctx, ()));
-        let eval_expr_ty = eval_expr
-            .as_ref()
-            .and_then(|v| v.value.as_ref().map(|v| v.ty))
-            .unwrap_or(ctx.table.common_type().unit);
-        if let Some(expr) = &eval_expr {
-            result_flow = expr.flow;
+        let mut eval_expr = None;
+        if let Some(FlowObj { value, flow }) = self.eval_expr.as_ref().map(|v| v.resolve(ctx, ())) {
+            result_flow = flow;
+            eval_expr = value;
+        }
+        // a trailing if, loop or block is only the value when it has one, otherwise it is a
+        // statement and a function ending in it still reports the missing value.
+        let unit = ctx.table.common_type().unit;
+        if self.eval_expr.as_ref().is_some_and(|v| v.is_block())
+            && eval_expr.as_ref().is_some_and(|v| v.ty == unit)
+        {
+            let expr = eval_expr.take().unwrap();
+            stmts.push(Stmt::Expr(ExprStmt { expr }));
         }
+        let eval_expr_ty = eval_expr.as_ref().map_or(unit, |v| v.ty);
 
         ctx.pop_block();
 
@@ -453,7 +910,7 @@ impl Resolve<(), FlowObj<Expr>> for ast::expr::BlockExpr {
                 kind: BlockExpr {
                     block_id: bid,
                     stmts,
-                    eval_expr: eval_expr.and_then(|v| v.value.map(Box::new)),
+                    eval_expr: eval_expr.map(Box::new),
                 }
                 .into(),
                 ty: eval_expr_ty,
@@ -1,21 +1,41 @@
 use super::{Flow, FlowObj, Record, Resolve, ResolveContext};
 
 use crate::{
-    ast::nodes as ast,
-    lowering::nodes::{
-        expr::ExprKind,
-        func::{ExternFunction, Function},
+    ast::{
+        location::{Locatable, Located},
+        nodes as ast,
+        parsing::token::Keyword,
+    },
+    lowering::{
+        errors::{ControlFlowError, IdentResolveError, TypeResolveError},
+        nodes::{
+            expr::ExprKind,
+            func::{ExternFunction, Function},
+        },
     },
     symbol::{BlockId, FuncId, VarId},
 };
 
 impl Record<(), bool> for ast::func::FunctionSig {
     fn record(&self, ctx: &mut ResolveContext, _: ()) -> bool {
-        if ctx.table.new_function(self.name.0.clone()).is_none() {
-            todo!("report error");
-        } else {
-            true
-        }
+        let Some(fid) = ctx.table.new_function(self.name.0.clone()) else {
+            let first_origin = ctx
+                .table
+                .get_function_by_name_mut(&self.name.0)
+                .map(|v| v.origin)
+                .unwrap_or_default();
+            ctx.error(IdentResolveError::GlobalIdentAlreadyUsed {
+                ident: self.name.0.clone(),
+                first_origin,
+                dup_origin: self.name.1,
+            });
+            return false;
+        };
+        let sym = fid.sym_mut(ctx.table);
+        sym.origin = self.name.1;
+        sym.is_pub = self.attributes.attribs.iter().any(|v| v.0 == Keyword::Pub);
+        ctx.add_ref(self.name.1, fid);
+        true
     }
 }
 
@@ -26,21 +46,28 @@ impl Resolve<(), Option<(FuncId, BlockId)>> for ast::func::FunctionSig {
 
         let mut params: Vec<VarId> = Vec::new();
         for ast_param in &self.params.items {
-            let Some(param_ty) = ast_param.1.resolve(ctx, ()) else {
-                // assumed an error is reported by the resolve call.
-                continue;
-            };
+            // the unknown type is already reported, the parameter is kept so that calls still
+            // match the arity.
+            let param_ty = ast_param
+                .1
+                .resolve(ctx, ())
+                .unwrap_or(ctx.table.common_type().error);
             let Some(param_id) = ctx.table.new_variable(ast_param.0 .0.clone(), bid) else {
-                todo!("report error")
+                ctx.error(IdentResolveError::VarNameAlreadyBound(ast_param.0.clone()));
+                continue;
             };
-            param_id.sym_mut(ctx.table).ty = param_ty;
+            let sym = param_id.sym_mut(ctx.table);
+            sym.ty = param_ty;
+            sym.origin = ast_param.0 .1;
+            ctx.add_ref(ast_param.0 .1, param_id);
             params.push(param_id)
         }
 
-        let Some(ret_ty) = self.ret_ty.resolve(ctx, ()) else {
-            // assumed an error is reported by the resolve call.
-            return None;
-        };
+        // the unknown type is already reported, the function is kept so that calls still find it.
+        let ret_ty = self
+            .ret_ty
+            .resolve(ctx, ())
+            .unwrap_or(ctx.table.common_type().error);
 
         let sym = fid.sym_mut(ctx.table);
         sym.params = params;
@@ -50,13 +77,21 @@ impl Resolve<(), Option<(FuncId, BlockId)>> for ast::func::FunctionSig {
     }
 }
 
+/// The signature must be resolved beforehand, the module resolves every signature before the
+/// bodies so that calls can refer to functions declared later in the source.
 impl Resolve<(), Option<Function>> for ast::func::Function {
     fn resolve(&self, ctx: &mut ResolveContext, _: ()) -> Option<Function> {
-        let (fid, bid) = self.sig.resolve(ctx, ())?;
+        let fid = ctx
+            .table
+            .get_function_id(&self.sig.name.0)
+            .expect("recorded function name");
+        let bid = fid.sym(ctx.table).entry_block;
         ctx.set_func_id(fid);
         ctx.push_block(bid);
 
         let FlowObj { value: body, flow } = self.body.resolve(ctx, ());
+        ctx.pop_block();
+        ctx.unset_func_id();
         let ExprKind::Block(body) = body?.kind else {
             unreachable!()
         };
@@ -66,20 +101,34 @@ impl Resolve<(), Option<Function>> for ast::func::Function {
             .as_ref()
             .map(|v| v.ty)
             .unwrap_or(ctx.table.common_type().unit);
-        let expect_ret_ty = fid.sym(ctx.table).ret_ty;
-        if flow != Flow::Break && !ctx.table.is_type_coercible(ret_ty, expect_ret_ty) {
-            todo!("report error");
+        let sym = fid.sym(ctx.table);
+        // a trailing if, loop or block without a value is left as a statement by the resolver.
+        let eval_loc = self
+            .body
+            .eval_expr
+            .as_ref()
+            .filter(|_| body.eval_expr.is_some())
+            .map(|v| v.get_location());
+        if flow != Flow::Break && !ctx.table.is_type_coercible(ret_ty, sym.ret_ty) {
+            match eval_loc {
+                Some(loc) => ctx.error(TypeResolveError::ReturnTypeMismatch {
+                    function_name: sym.name.clone(),
+                    expected_type: sym.ret_ty,
+                    actual_type: Located(ret_ty, loc),
+                }),
+                None => ctx.error(ControlFlowError::NotAllFuncPathReturned(
+                    self.sig.name.clone(),
+                )),
+            }
         }
 
-        ctx.pop_block();
-        ctx.unset_func_id();
         Some(Function { func_id: fid, body })
     }
 }
 
 impl Resolve<(), Option<ExternFunction>> for ast::func::ExternFunction {
     fn resolve(&self, ctx: &mut ResolveContext, _: ()) -> Option<ExternFunction> {
-        let (fid, _) = self.sig.resolve(ctx, ())?;
+        let fid = ctx.table.get_function_id(&self.sig.name.0)?;
         Some(ExternFunction(fid))
     }
 }
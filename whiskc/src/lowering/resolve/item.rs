@@ -8,6 +8,7 @@ impl Record<(), bool> for ast::item::Item {
             ast::item::Item::Function(v) => v.sig.record(ctx, ()),
             ast::item::Item::ExternFunction(v) => v.sig.record(ctx, ()),
             ast::item::Item::TypeDecl(v) => v.record(ctx, ()),
+            ast::item::Item::Error(_) => false,
         }
     }
 }
@@ -18,6 +19,7 @@ impl Resolve<(), Option<Item>> for ast::item::Item {
             ast::item::Item::Function(v) => v.resolve(ctx, ()).map(Item::Function),
             ast::item::Item::ExternFunction(v) => v.resolve(ctx, ()).map(Item::ExternFunction),
             ast::item::Item::TypeDecl(v) => v.resolve(ctx, ()).map(Item::TypeDecl),
+            ast::item::Item::Error(_) => None,
         }
     }
 }
@@ -1,11 +1,15 @@
 use std::ops::BitAnd;
 
 use crate::{
-    ast::AST,
+    ast::{location::Span, AST},
     symbol::{BlockId, FuncId, SymbolTable},
 };
 
-use super::{errors::ResolveError, Module};
+use super::{
+    errors::{ResolveError, ResolveErrors},
+    index::{SourceIndex, SymbolRef},
+    Module,
+};
 
 mod ast;
 mod expr;
@@ -14,19 +18,26 @@ mod item;
 mod stmt;
 mod ty;
 
-pub fn resolve(ast: &AST, module_name: String) -> Result<Module, Vec<ResolveError>> {
+pub fn resolve(ast: &AST, module_name: String) -> Result<Module, Box<ResolveErrors>> {
     let mut module = Module {
         sym_table: SymbolTable::new(),
         name: module_name,
         items: vec![],
+        index: SourceIndex::default(),
     };
 
     let mut ctx = ResolveContext::new(&mut module.sym_table);
     module.items = ast.resolve(&mut ctx, ());
     // dbg!(&ctx);
+    let errors = ctx.errors;
+    module.index = ctx.index;
 
-    if !ctx.errors.is_empty() {
-        Err(ctx.errors)
+    if !errors.is_empty() {
+        Err(Box::new(ResolveErrors {
+            errors,
+            sym_table: module.sym_table,
+            index: module.index,
+        }))
     } else {
         Ok(module)
     }
@@ -51,6 +62,7 @@ struct ResolveContext<'md> {
     errors: Vec<ResolveError>,
     current_fid: Option<FuncId>,
     blocks: Vec<BlockId>,
+    index: SourceIndex,
 }
 impl<'a> ResolveContext<'a> {
     pub fn new(table: &'a mut SymbolTable) -> Self {
@@ -59,9 +71,15 @@ impl<'a> ResolveContext<'a> {
             errors: vec![],
             current_fid: None,
             blocks: vec![],
+            index: SourceIndex::default(),
         }
     }
 
+    /// Records that `span` declares or refers to `sym`.
+    pub fn add_ref(&mut self, span: Span, sym: impl Into<SymbolRef>) {
+        self.index.symbol_refs.push((span, sym.into()));
+    }
+
     pub fn set_func_id(&mut self, fid: FuncId) {
         assert!(
             self.current_fid.is_none(),
@@ -93,7 +111,7 @@ impl<'a> ResolveContext<'a> {
         self.blocks.last().copied().unwrap()
     }
 
-    pub fn _error(&mut self, e: impl Into<ResolveError>) {
+    pub fn error(&mut self, e: impl Into<ResolveError>) {
         self.errors.push(e.into());
     }
 }
@@ -141,13 +159,6 @@ impl<T> FlowObj<T> {
         }
     }
 
-    pub fn cont_none() -> Self {
-        Self {
-            value: None,
-            flow: Flow::Continue,
-        }
-    }
-
     pub fn brk(t: T) -> Self {
         Self {
             value: Some(t),
@@ -162,3 +173,429 @@ impl<T> FlowObj<T> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::compile::{compile_source, CompileSwitch, Stage};
+
+    /// The messages of the errors of the source, the stage of which is given first.
+    fn errors(source: &str, switches: &CompileSwitch) -> Vec<(Stage, String)> {
+        let (_, output) = compile_source("test", source, switches);
+        output
+            .diagnostics
+            .into_iter()
+            .map(|v| (v.stage, v.message))
+            .collect()
+    }
+
+    #[track_caller]
+    fn assert_errors(source: &str, expected: &[(Stage, &str)]) {
+        let switches = CompileSwitch {
+            do_parse_ast: true,
+            do_resolve_module: true,
+            ..Default::default()
+        };
+        let expected: Vec<_> = expected.iter().map(|(s, m)| (*s, m.to_string())).collect();
+        assert_eq!(errors(source, &switches), expected);
+    }
+
+    #[track_caller]
+    fn assert_syntax_errors(source: &str, expected: &[&str]) {
+        let switches = CompileSwitch {
+            do_parse_ast: true,
+            ..Default::default()
+        };
+        let expected: Vec<_> = expected
+            .iter()
+            .map(|m| (Stage::Parse, m.to_string()))
+            .collect();
+        assert_eq!(errors(source, &switches), expected);
+    }
+
+    #[test]
+    fn parser_reports_every_syntax_error_of_a_function() {
+        assert_syntax_errors(
+            "func main() int { let x = ; let y = 1 +; y }",
+            &[
+                "expected an expression, found ';'",
+                "expected an expression, found ';'",
+            ],
+        );
+    }
+
+    #[test]
+    fn parser_reports_each_error_of_a_let_once() {
+        assert_errors(
+            "func main() int { let x: bool = 1; 0 }",
+            &[
+                (Stage::Parse, "expected '=' operator"),
+                (Stage::Parse, "expected an expression, found ':'"),
+            ],
+        );
+        assert_errors(
+            "func main() int { let x = (1 +) 2; let y = 3; y }",
+            &[(Stage::Parse, "expected an expression, found ')'")],
+        );
+    }
+
+    #[test]
+    fn parser_recovers_at_the_next_item() {
+        assert_syntax_errors(
+            "func f() int { 1 + }\nfunc g() int { let x = ; 1 }\nfunc main() int { 0 }",
+            &[
+                "expected an expression, found '}'",
+                "expected an expression, found ';'",
+            ],
+        );
+        // the functions after the error are still there to resolve.
+        assert_errors(
+            "func f() int { 1 + }\nfunc main() int { g() }",
+            &[
+                (Stage::Parse, "expected an expression, found '}'"),
+                (Stage::Resolve, "unknown identifier 'g'"),
+            ],
+        );
+    }
+
+    #[test]
+    fn parser_recovers_from_missing_delimiters() {
+        assert_errors(
+            "func main() int { if 1 + { 2 } else { 3 } }",
+            &[(Stage::Parse, "expected '{'")],
+        );
+        assert_errors(
+            "func main() int { let x = 1 x }",
+            &[(Stage::Parse, "expected ';'")],
+        );
+    }
+
+    #[test]
+    fn bad_tuple_index_is_reported_once() {
+        assert_errors(
+            "func main() int { let x = (1, 2); x.2 }",
+            &[(Stage::Resolve, "'(int, int)' has no element 2")],
+        );
+        assert_errors(
+            "func main() int { let x = 1; x.0 }",
+            &[(Stage::Resolve, "'int' is not a tuple")],
+        );
+    }
+
+    #[test]
+    fn let_of_a_bad_try_still_binds() {
+        assert_errors(
+            "func f() int { let x = Some(1)?; x }\nfunc main() int { f() }",
+            &[(
+                Stage::Resolve,
+                "'?' on 'Option[int]' cannot return from function 'f' returning 'int'",
+            )],
+        );
+        assert_errors(
+            "func main() int { let x = 1?; x + 1 }",
+            &[(Stage::Resolve, "'?' cannot be used on 'int'")],
+        );
+    }
+
+    #[test]
+    fn bad_operand_is_reported_once() {
+        assert_errors(
+            "type Meters int;\nfunc main() int { let m = Meters(1); let n int = m + 1; n }",
+            &[(Stage::Resolve, "'+' expects numbers, found 'Meters'")],
+        );
+        assert_errors(
+            "type Meters int;\nfunc main() int { let m = Meters(1); (m * m).0 }",
+            &[(Stage::Resolve, "'*' expects numbers, found 'Meters'")],
+        );
+        assert_errors(
+            "func main() int { let x = y + 1; x * 2 }",
+            &[(Stage::Resolve, "unknown identifier 'y'")],
+        );
+    }
+
+    #[test]
+    fn unknown_field_type_is_reported_once() {
+        assert_errors(
+            "type S = struct { a foo, b int };\nfunc f(s S) int { 1 }\nfunc main() int { 0 }",
+            &[(Stage::Resolve, "unknown type 'foo'")],
+        );
+    }
+
+    #[test]
+    fn bad_pattern_and_call_are_reported_once() {
+        assert_errors(
+            "func main() int { let (a, b) = 1; a + b }",
+            &[(Stage::Resolve, "pattern does not match 'int'")],
+        );
+        assert_errors(
+            "func main() int { let x = Some(1, 2); x? }",
+            &[(
+                Stage::Resolve,
+                "'Some' expects 1 argument(s), but 2 are given",
+            )],
+        );
+    }
+
+    #[test]
+    fn try_needs_an_option_or_result_operand() {
+        assert_errors(
+            "func f() Option[int] { let b = true?; None }\nfunc main() int { 0 }",
+            &[(Stage::Resolve, "'?' cannot be used on 'bool'")],
+        );
+        assert_errors(
+            "func f() Option[int] { let t = (1, 2)?; None }\nfunc main() int { 0 }",
+            &[(Stage::Resolve, "'?' cannot be used on '(int, int)'")],
+        );
+    }
+
+    #[test]
+    fn try_must_fit_the_return_type_of_its_function() {
+        assert_errors(
+            "func f() Result[int, bool] { let x = Some(1)?; Ok(1) }\nfunc main() int { 0 }",
+            &[(
+                Stage::Resolve,
+                "'?' on 'Option[int]' cannot return from function 'f' returning 'Result[int, bool]'",
+            )],
+        );
+        assert_errors(
+            "func f() Option[int] { let x = Ok(1)?; Some(1) }\nfunc main() int { 0 }",
+            &[(
+                Stage::Resolve,
+                "'?' on 'Result[int, never]' cannot return from function 'f' returning 'Option[int]'",
+            )],
+        );
+        assert_errors(
+            "func f() int { let x = Some(1)?; 1 }\nfunc main() int { 0 }",
+            &[(
+                Stage::Resolve,
+                "'?' on 'Option[int]' cannot return from function 'f' returning 'int'",
+            )],
+        );
+        // the error types of results have to agree, the success types need not.
+        assert_errors(
+            "func g() Result[int, bool] { Err(true) }
+            func f() Result[int, int] { let x = g()?; Ok(1) }
+            func main() int { 0 }",
+            &[(
+                Stage::Resolve,
+                "'?' on 'Result[int, bool]' cannot return from function 'f' returning 'Result[int, int]'",
+            )],
+        );
+        assert_errors(
+            "func g() Result[bool, int] { Err(1) }
+            func f() Result[(), int] { let x = g()?; Ok(()) }
+            func main() int { 0 }",
+            &[],
+        );
+    }
+
+    #[test]
+    fn generic_types_are_checked() {
+        assert_errors(
+            "func f() Maybe[int] { 0 }\nfunc main() int { 0 }",
+            &[(Stage::Resolve, "unknown generic type 'Maybe'")],
+        );
+        assert_errors(
+            "func f() Option[int, int] { None }\nfunc main() int { 0 }",
+            &[(
+                Stage::Resolve,
+                "'Option' expects 1 type argument(s), but 2 are given",
+            )],
+        );
+    }
+
+    #[test]
+    fn destructuring_checks_the_shape_and_types_of_tuples() {
+        assert_errors(
+            "func pair() (int, bool) { (1, true) }\nfunc main() int { let (a, b) = pair(); if b { a } else { 0 } }",
+            &[],
+        );
+        assert_errors(
+            "func main() int { let (a, b) = (1, 2, 3); a + b }",
+            &[(Stage::Resolve, "pattern does not match '(int, int, int)'")],
+        );
+        assert_errors(
+            "func main() int { let (a, b) = (1, true); a + b }",
+            &[(Stage::Resolve, "'+' expects numbers, found 'bool'")],
+        );
+        assert_errors(
+            "func pair() (int, int) { (1, true) }\nfunc main() int { pair().0 }",
+            &[(
+                Stage::Resolve,
+                "function 'pair' returns '(int, int)', found '(int, bool)'",
+            )],
+        );
+    }
+
+    #[test]
+    fn tuple_index_needs_a_tuple_with_the_element() {
+        assert_errors(
+            "func main() int { let x = (1, 2); let y = x.2; 0 }",
+            &[(Stage::Resolve, "'(int, int)' has no element 2")],
+        );
+        assert_errors(
+            "func main() int { let x = 1; let y = x.0; 0 }",
+            &[(Stage::Resolve, "'int' is not a tuple")],
+        );
+    }
+
+    #[test]
+    fn alias_is_interchangeable_with_its_target() {
+        assert_errors(
+            "type Num = int;
+            func main() int { let a int = 1; let n Num = a; let m int = n; n + a * m }",
+            &[],
+        );
+        assert_errors(
+            "type Meters int;
+            type Len = Meters;
+            func main() int { let m Len = Meters(1); let n Meters = m; n.0 }",
+            &[],
+        );
+    }
+
+    #[test]
+    fn newtype_is_distinct_from_its_base() {
+        assert_errors(
+            "type Meters int;\nfunc main() int { let a int = 1; let m Meters = a; 0 }",
+            &[(Stage::Resolve, "cannot assign 'int' to 'Meters'")],
+        );
+        assert_errors(
+            "type Meters int;\nfunc main() int { let m = Meters(1); let a int = m; 0 }",
+            &[(Stage::Resolve, "cannot assign 'Meters' to 'int'")],
+        );
+        assert_errors(
+            "type Meters int;\nfunc main() int { let m = Meters(true); 0 }",
+            &[(
+                Stage::Resolve,
+                "argument 0 of 'Meters' expects 'int', found 'bool'",
+            )],
+        );
+        assert_errors(
+            "type Meters int;\nfunc main() int { let m = Meters(1); let n = m.1; 0 }",
+            &[(Stage::Resolve, "'Meters' has no element 1")],
+        );
+    }
+
+    #[test]
+    fn recursive_aliases_are_reported() {
+        assert_errors(
+            "type A = A;\nfunc main() int { 0 }",
+            &[(
+                Stage::Resolve,
+                "type alias refers to itself: 'A' [1:6] -> 'A'",
+            )],
+        );
+        assert_errors(
+            "type A = B;\ntype B = A;\nfunc main() int { 0 }",
+            &[(
+                Stage::Resolve,
+                "type alias refers to itself: 'A' [1:6] -> 'B' [2:6] -> 'A'",
+            )],
+        );
+        assert_errors(
+            "type A = (int, A);\nfunc main() int { 0 }",
+            &[(
+                Stage::Resolve,
+                "type alias refers to itself: 'A' [1:6] -> 'A'",
+            )],
+        );
+        // a chain of aliases that ends in a type is fine.
+        assert_errors(
+            "type A = B;\ntype B = C;\ntype C = (int, int);\nfunc main() int { let a A = (1, 2); a.0 }",
+            &[],
+        );
+    }
+
+    #[test]
+    fn recursive_structs_are_reported() {
+        assert_errors(
+            "type A = struct { a A };\nfunc main() int { 0 }",
+            &[(
+                Stage::Resolve,
+                "type contains itself and has infinite size: 'A' [1:6] -> 'A'",
+            )],
+        );
+        assert_errors(
+            "type A = struct { b B };\ntype B = struct { n int, a A };\nfunc main() int { 0 }",
+            &[(
+                Stage::Resolve,
+                "type contains itself and has infinite size: 'A' [1:6] -> 'B' [2:6] -> 'A'",
+            )],
+        );
+        assert_errors(
+            "type A B;\ntype B A;\nfunc main() int { 0 }",
+            &[(
+                Stage::Resolve,
+                "type contains itself and has infinite size: 'A' [1:6] -> 'B' [2:6] -> 'A'",
+            )],
+        );
+        // an option is a reference, so a struct can hold one of itself.
+        assert_errors(
+            "type List = struct { head int, tail Option[List] };\nfunc main() int { 0 }",
+            &[],
+        );
+        assert_errors(
+            "type A = struct { b B, c B };\ntype B = struct { n int };\nfunc main() int { 0 }",
+            &[],
+        );
+    }
+
+    #[test]
+    fn trailing_control_flow_is_the_value_of_its_block() {
+        assert_errors(
+            "func f(n int) int { { if n < 0 { 0 } else { return 1; } } }\nfunc main() int { f(1) }",
+            &[],
+        );
+        assert_errors(
+            "func f(n int) int { loop { if n < 0 { return 0; } } }\nfunc main() int { f(1) }",
+            &[],
+        );
+        // an earlier statement that fails does not change what the value is.
+        assert_errors(
+            "func f(n int) int { let (a, b) = 1; if n < 0 { 0 } else { return 1; } }\nfunc main() int { f(1) }",
+            &[(Stage::Resolve, "pattern does not match 'int'")],
+        );
+        assert_errors(
+            "func f(n int) int { { if n < 0 { return 0; } } }\nfunc main() int { f(1) }",
+            &[(
+                Stage::Resolve,
+                "not all paths of function 'f' return a value",
+            )],
+        );
+    }
+
+    #[test]
+    fn else_if_branches_are_merged_by_type_and_flow() {
+        assert_errors(
+            "func f(n int) int { if n < 0 { 0 } else if n == 0 { return 1; } else { 2 } }\nfunc main() int { f(1) }",
+            &[],
+        );
+        // every branch returns, nothing is missing after the if.
+        assert_errors(
+            "func f(n int) int { if n < 0 { return 0; } else if n == 0 { return 1; } else { return 2; } }\nfunc main() int { f(1) }",
+            &[],
+        );
+        assert_errors(
+            "func f(n int) int { if n < 0 { return 0; } else if n == 0 { return 1; } }\nfunc main() int { f(1) }",
+            &[(Stage::Resolve, "not all paths of function 'f' return a value")],
+        );
+        assert_errors(
+            "func f(n int) int { if n < 0 { true } else if n == 0 { return 1; } else { false } }\nfunc main() int { f(1) }",
+            &[(Stage::Resolve, "function 'f' returns 'int', found 'bool'")],
+        );
+        assert_errors(
+            "func main() int { if true { 0 } else if false { true } else { 2 } }",
+            &[(
+                Stage::Resolve,
+                "branch evaluates to 'bool', but the other branch evaluates to 'int'",
+            )],
+        );
+        assert_errors(
+            "func main() int { if true { 0 } else if false { 1 }; 2 }",
+            &[(
+                Stage::Resolve,
+                "branch evaluates to 'int', but the other branch evaluates to '()'",
+            )],
+        );
+    }
+}
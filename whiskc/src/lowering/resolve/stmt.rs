@@ -1,6 +1,16 @@
 use crate::{
-    ast::nodes as ast,
-    lowering::nodes::stmt::{ExprStmt, LetStmt, Stmt},
+    ast::{
+        location::{Locatable, Located},
+        nodes as ast,
+    },
+    lowering::{
+        errors::{IdentResolveError, TypeResolveError},
+        nodes::{
+            pattern::Pattern,
+            stmt::{ExprStmt, LetStmt, Stmt},
+        },
+    },
+    symbol::{ty::TypeKind, TypeId},
 };
 
 use super::{FlowObj, Resolve, ResolveContext};
@@ -40,23 +50,77 @@ impl Resolve<(), FlowObj<LetStmt>> for ast::stmt::LetStmt {
     fn resolve(&self, ctx: &mut ResolveContext, _: ()) -> FlowObj<LetStmt> {
         let FlowObj { value, flow } = self.value.resolve(ctx, ());
         let Some(value) = value else {
+            // still bind the names, so that later uses are not reported as unknown.
+            let error = ctx.table.common_type().error;
+            self.pat.resolve(ctx, error);
             return FlowObj::none(flow);
         };
 
         let mut var_ty = value.ty;
-        let anno_ty = self.ty.as_ref().and_then(|v| v.resolve(ctx, ()));
-        if let Some(anno_ty) = anno_ty {
-            if anno_ty != value.ty {
-                todo!("report error")
+        if let Some(ty) = &self.ty {
+            if let Some(anno_ty) = ty.resolve(ctx, ()) {
+                if !ctx.table.is_type_coercible(value.ty, anno_ty) {
+                    ctx.error(TypeResolveError::AssignmentTypeMismatch {
+                        target_ty: Located(anno_ty, ty.get_location()),
+                        value_ty: Located(value.ty, self.value.get_location()),
+                    });
+                }
+                var_ty = anno_ty;
             }
-            var_ty = anno_ty;
         }
 
-        let Some(var_id) = ctx.table.new_variable(self.name.0.clone(), ctx.get_block()) else {
-            todo!("report error");
+        let Some(pat) = self.pat.resolve(ctx, var_ty) else {
+            return FlowObj::none(flow);
         };
-        var_id.sym_mut(ctx.table).ty = var_ty;
 
-        FlowObj::new(LetStmt { var_id, value }, flow)
+        FlowObj::new(LetStmt { pat, value }, flow)
+    }
+}
+
+/// Bind the variables of the pattern to the parts of a value of type `ty`.
+impl Resolve<TypeId, Option<Pattern>> for ast::pattern::Pattern {
+    fn resolve(&self, ctx: &mut ResolveContext, ty: TypeId) -> Option<Pattern> {
+        match self {
+            ast::pattern::Pattern::Ident(name) => {
+                let Some(var_id) = ctx.table.new_variable(name.0.clone(), ctx.get_block()) else {
+                    ctx.error(IdentResolveError::VarNameAlreadyBound(name.clone()));
+                    return None;
+                };
+                let sym = var_id.sym_mut(ctx.table);
+                sym.ty = ty;
+                sym.origin = name.1;
+                ctx.add_ref(name.1, var_id);
+                Some(Pattern::Var(var_id))
+            }
+            ast::pattern::Pattern::Tuple(tuple) => {
+                let common = *ctx.table.common_type();
+                let elem_tys = match ctx.table.get_type_kind(ty) {
+                    Some(TypeKind::Tuple(elems)) if elems.len() == tuple.elems.items.len() => {
+                        elems.clone()
+                    }
+                    _ if ty == common.never || ty == common.error => {
+                        vec![ty; tuple.elems.items.len()]
+                    }
+                    _ => {
+                        ctx.error(TypeResolveError::PatternTypeMismatch {
+                            pattern: tuple.get_location(),
+                            value_ty: ty,
+                        });
+                        // still bind the names so that later uses do not report unknown
+                        // identifiers or mismatches.
+                        for elem in &tuple.elems.items {
+                            elem.resolve(ctx, common.error);
+                        }
+                        return None;
+                    }
+                };
+
+                let mut elems = Vec::new();
+                for (elem, elem_ty) in tuple.elems.items.iter().zip(elem_tys) {
+                    elems.push(elem.resolve(ctx, elem_ty));
+                }
+                Some(Pattern::Tuple(elems.into_iter().collect::<Option<_>>()?))
+            }
+        }
     }
 }
@@ -1,9 +1,18 @@
+use std::collections::HashMap;
+
 use crate::{
-    ast::nodes as ast,
-    lowering::nodes::ty::TypeDecl,
+    ast::{
+        location::{Located, Span},
+        nodes as ast,
+    },
+    lowering::{
+        errors::{IdentResolveError, TypeResolveError},
+        nodes::ty::TypeDecl,
+    },
     symbol::{
+        prelude::PreludeType,
         ty::{StructType, TypeKind},
-        TypeId,
+        SymbolTable, TypeId,
     },
 };
 
@@ -11,11 +20,24 @@ use super::{Record, Resolve, ResolveContext};
 
 impl Record<(), bool> for ast::ty::TypeDecl {
     fn record(&self, ctx: &mut ResolveContext, _: ()) -> bool {
-        if ctx.table.new_type(self.name.0.clone()).is_none() {
-            todo!("report error");
-        } else {
-            true
-        }
+        let Some(ty) = ctx.table.new_type(self.name.0.clone()) else {
+            let first_origin = ctx
+                .table
+                .get_type_by_name_mut(&self.name.0)
+                .map(|v| v.origin)
+                .unwrap_or_default();
+            ctx.error(IdentResolveError::GlobalIdentAlreadyUsed {
+                ident: self.name.0.clone(),
+                first_origin,
+                dup_origin: self.name.1,
+            });
+            return false;
+        };
+        let sym = ty.sym_mut(ctx.table);
+        sym.origin = self.name.1;
+        sym.is_declared = true;
+        ctx.add_ref(self.name.1, ty);
+        true
     }
 }
 
@@ -24,22 +46,35 @@ impl Resolve<(), Option<TypeDecl>> for ast::ty::TypeDecl {
         match &self.kind {
             ast::ty::TypeDeclKind::Type(ast_ty) => {
                 let ty_id = ast_ty.resolve(ctx, ())?;
+                let is_alias = self.is_alias();
                 let sym = ctx
                     .table
                     .get_type_by_name_mut(&self.name.0)
                     .expect("recorded type name");
-                sym.kind = Some(TypeKind::Ident(ty_id));
+                sym.kind = Some(if is_alias {
+                    TypeKind::Alias(ty_id)
+                } else {
+                    TypeKind::Ident(ty_id)
+                });
             }
             ast::ty::TypeDeclKind::Struct(ast_struct) => {
                 let mut fields: Vec<(String, TypeId)> = Vec::new();
-                for ast_field in &ast_struct.fields.items {
-                    if fields.iter().any(|(name, _)| *name == ast_field.name.0) {
-                        todo!("report error");
+                for (i, ast_field) in ast_struct.fields.items.iter().enumerate() {
+                    if let Some(first) = ast_struct.fields.items[..i]
+                        .iter()
+                        .find(|v| v.name.0 == ast_field.name.0)
+                    {
+                        ctx.error(IdentResolveError::FieldNameAlreadyUsed {
+                            ident: ast_field.name.0.clone(),
+                            first_origin: first.name.1,
+                            dup_origin: ast_field.name.1,
+                        });
+                        continue;
                     }
                     let ty_id = match ast_field.ty.resolve(ctx, ()) {
                         Some(id) => id,
-                        // TODO: maybe it should be 'unknown' type.
-                        None => ctx.table.common_type().never,
+                        // the unknown type is already reported.
+                        None => ctx.table.common_type().error,
                     };
                     fields.push((ast_field.name.0.clone(), ty_id));
                 }
@@ -68,7 +103,154 @@ impl Resolve<(), Option<TypeId>> for ast::ty::Type {
                 ast::ty::PrimType::Int => ctx.table.common_type().int,
                 ast::ty::PrimType::Bool => ctx.table.common_type().bool,
             }),
-            ast::ty::Type::Ident(v) => ctx.table.get_type_id(&v.0),
+            ast::ty::Type::Ident(v) => {
+                let Some(ty) = ctx.table.get_type_id(&v.0) else {
+                    ctx.error(TypeResolveError::UnknownTypeForIdent(v.clone()));
+                    return None;
+                };
+                ctx.add_ref(v.1, ty);
+                Some(ty)
+            }
+            ast::ty::Type::Generic(v) => v.resolve(ctx, ()),
+            ast::ty::Type::Tuple(v) => {
+                let mut elems = Vec::new();
+                for elem in &v.elems.items {
+                    elems.push(elem.resolve(ctx, ())?);
+                }
+                Some(ctx.table.get_tuple_type(elems))
+            }
+        }
+    }
+}
+
+impl Resolve<(), Option<TypeId>> for ast::ty::GenericType {
+    fn resolve(&self, ctx: &mut ResolveContext, _: ()) -> Option<TypeId> {
+        let Some(prelude_ty) = PreludeType::from_name(&self.name.0) else {
+            ctx.error(TypeResolveError::UnknownGenericType(self.name.clone()));
+            return None;
+        };
+        if self.args.items.len() != prelude_ty.param_count() {
+            ctx.error(TypeResolveError::GenericArgumentCountMismatch {
+                ty: self.name.clone(),
+                expect_count: prelude_ty.param_count(),
+                actual_count: self.args.items.len(),
+            });
+            return None;
         }
+
+        let mut args = Vec::new();
+        for arg in &self.args.items {
+            args.push(arg.resolve(ctx, ())?);
+        }
+
+        Some(match prelude_ty {
+            PreludeType::Option => ctx.table.get_option_type(args[0]),
+            PreludeType::Result => ctx.table.get_result_type(args[0], args[1]),
+        })
+    }
+}
+
+/// Check the declared types for aliases that name themselves and types that contain themselves by
+/// value, both of which would make the resolver and codegen recurse forever.
+/// It must run after all type declarations are resolved.
+pub(super) fn validate_type_graph(ctx: &mut ResolveContext, decls: &[&ast::ty::TypeDecl]) {
+    let spans: HashMap<TypeId, Span> = decls
+        .iter()
+        .filter_map(|decl| Some((ctx.table.get_type_id(&decl.name.0)?, decl.name.1)))
+        .collect();
+    let mut roots: Vec<TypeId> = spans.keys().copied().collect();
+    // report in source order.
+    roots.sort_by_key(|v| (spans[v].file, spans[v].start));
+
+    let locate = |chain: Vec<TypeId>| -> Vec<Located<TypeId>> {
+        // anonymous types such as tuples have no declaration to point at.
+        chain
+            .into_iter()
+            .filter_map(|ty| spans.get(&ty).map(|span| Located(ty, *span)))
+            .collect()
+    };
+
+    for cycle in find_cycles(ctx.table, &roots, alias_edges) {
+        // leave the aliases unresolved, so that the type comparisons never expand them forever.
+        for ty in &cycle {
+            let sym = ty.sym_mut(ctx.table);
+            if matches!(sym.kind, Some(TypeKind::Alias(_))) {
+                sym.kind = None;
+            }
+        }
+        ctx.error(TypeResolveError::AliasCycle(locate(cycle)));
+    }
+
+    for cycle in find_cycles(ctx.table, &roots, value_edges) {
+        // cycles made of aliases only are already reported above.
+        let is_nominal = |ty: &TypeId| {
+            matches!(
+                ty.sym(ctx.table).kind,
+                Some(TypeKind::Struct(_) | TypeKind::Ident(_))
+            )
+        };
+        if cycle.iter().any(is_nominal) {
+            ctx.error(TypeResolveError::InfinitelySizedType(locate(cycle)));
+        }
+    }
+}
+
+/// Types an alias is expanded into, nominal types end the expansion.
+fn alias_edges(kind: &TypeKind) -> Vec<TypeId> {
+    match kind {
+        TypeKind::Alias(v) | TypeKind::Option(v) => vec![*v],
+        TypeKind::Result(ok, err) => vec![*ok, *err],
+        TypeKind::Tuple(elems) => elems.clone(),
+        TypeKind::Primitive(_) | TypeKind::Struct(_) | TypeKind::Ident(_) => vec![],
+    }
+}
+
+/// Types stored inline in a value of the type, prelude types are behind a heap reference.
+fn value_edges(kind: &TypeKind) -> Vec<TypeId> {
+    match kind {
+        TypeKind::Alias(v) | TypeKind::Ident(v) => vec![*v],
+        TypeKind::Struct(v) => v.fields.iter().map(|(_, ty)| *ty).collect(),
+        TypeKind::Tuple(elems) => elems.clone(),
+        TypeKind::Primitive(_) | TypeKind::Option(_) | TypeKind::Result(_, _) => vec![],
+    }
+}
+
+/// Depth first search over the edges, returning every cycle found through a back edge as the
+/// path from the first type of the cycle to the last one before it loops.
+fn find_cycles(
+    table: &SymbolTable,
+    roots: &[TypeId],
+    edges: fn(&TypeKind) -> Vec<TypeId>,
+) -> Vec<Vec<TypeId>> {
+    fn visit(
+        table: &SymbolTable,
+        ty: TypeId,
+        edges: fn(&TypeKind) -> Vec<TypeId>,
+        done: &mut Vec<TypeId>,
+        path: &mut Vec<TypeId>,
+        cycles: &mut Vec<Vec<TypeId>>,
+    ) {
+        if let Some(pos) = path.iter().position(|v| *v == ty) {
+            cycles.push(path[pos..].to_vec());
+            return;
+        }
+        if done.contains(&ty) {
+            return;
+        }
+        path.push(ty);
+        if let Some(kind) = &ty.sym(table).kind {
+            for next in edges(kind) {
+                visit(table, next, edges, done, path, cycles);
+            }
+        }
+        path.pop();
+        done.push(ty);
+    }
+
+    let mut done = Vec::new();
+    let mut cycles = Vec::new();
+    for root in roots {
+        visit(table, *root, edges, &mut done, &mut vec![], &mut cycles);
     }
+    cycles
 }
@@ -6,8 +6,8 @@ use crate::lowering::nodes::{
 use super::super::{
     nodes::{
         expr::{
-            BinaryExpr, BlockExpr, CallExpr, Expr, IfExpr, LoopExpr, ReturnExpr, UnaryExpr,
-            VarIdentExpr,
+            BinaryExpr, BlockExpr, CallExpr, CastExpr, Expr, IfExpr, LoopExpr, ReturnExpr, TryExpr,
+            TupleExpr, TupleIndexExpr, UnaryExpr, VarIdentExpr, VariantExpr,
         },
         func::{ExternFunction, Function},
         item::Item,
@@ -97,9 +97,29 @@ pub trait Visit: Sized {
         visit_unary_expr(self, node);
     }
 
+    fn visit_try_expr(&mut self, node: &TryExpr) {
+        visit_try_expr(self, node);
+    }
+
+    fn visit_tuple_expr(&mut self, node: &TupleExpr) {
+        visit_tuple_expr(self, node);
+    }
+
+    fn visit_tuple_index_expr(&mut self, node: &TupleIndexExpr) {
+        visit_tuple_index_expr(self, node);
+    }
+
+    fn visit_cast_expr(&mut self, node: &CastExpr) {
+        visit_cast_expr(self, node);
+    }
+
     fn visit_unit_expr(&mut self) {
         /* terminal */
     }
+
+    fn visit_variant_expr(&mut self, node: &VariantExpr) {
+        visit_variant_expr(self, node);
+    }
 }
 
 pub fn visit_module(v: &mut impl Visit, node: &Module) {
@@ -143,6 +163,11 @@ pub fn visit_expr(v: &mut impl Visit, node: &Expr) {
         ExprKind::Return(node) => v.visit_return_expr(node),
         ExprKind::If(node) => v.visit_if_expr(node),
         ExprKind::Loop(node) => v.visit_loop_expr(node),
+        ExprKind::Variant(node) => v.visit_variant_expr(node),
+        ExprKind::Try(node) => v.visit_try_expr(node),
+        ExprKind::Tuple(node) => v.visit_tuple_expr(node),
+        ExprKind::TupleIndex(node) => v.visit_tuple_index_expr(node),
+        ExprKind::Cast(node) => v.visit_cast_expr(node),
     };
 }
 
@@ -155,10 +180,12 @@ pub fn visit_func(v: &mut impl Visit, node: &Function) {
 }
 
 pub fn visit_if_expr(v: &mut impl Visit, node: &IfExpr) {
-    v.visit_expr(&node.cond);
-    v.visit_block_expr(&node.then);
+    for branch in &node.branches {
+        v.visit_expr(&branch.cond);
+        v.visit_expr(&branch.body);
+    }
     if let Some(else_) = &node.else_ {
-        v.visit_block_expr(else_);
+        v.visit_expr(else_);
     }
 }
 
@@ -194,3 +221,27 @@ pub fn visit_stmt(v: &mut impl Visit, node: &Stmt) {
 pub fn visit_unary_expr(v: &mut impl Visit, node: &UnaryExpr) {
     v.visit_expr(&node.expr);
 }
+
+pub fn visit_try_expr(v: &mut impl Visit, node: &TryExpr) {
+    v.visit_expr(&node.expr);
+}
+
+pub fn visit_tuple_expr(v: &mut impl Visit, node: &TupleExpr) {
+    for elem in &node.elems {
+        v.visit_expr(elem);
+    }
+}
+
+pub fn visit_tuple_index_expr(v: &mut impl Visit, node: &TupleIndexExpr) {
+    v.visit_expr(&node.expr);
+}
+
+pub fn visit_cast_expr(v: &mut impl Visit, node: &CastExpr) {
+    v.visit_expr(&node.expr);
+}
+
+pub fn visit_variant_expr(v: &mut impl Visit, node: &VariantExpr) {
+    if let Some(value) = &node.value {
+        v.visit_expr(value);
+    }
+}
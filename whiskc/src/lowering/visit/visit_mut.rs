@@ -6,8 +6,8 @@ use crate::lowering::nodes::{
 use super::super::{
     nodes::{
         expr::{
-            BinaryExpr, BlockExpr, CallExpr, Expr, IfExpr, LoopExpr, ReturnExpr, UnaryExpr,
-            VarIdentExpr,
+            BinaryExpr, BlockExpr, CallExpr, CastExpr, Expr, IfExpr, LoopExpr, ReturnExpr, TryExpr,
+            TupleExpr, TupleIndexExpr, UnaryExpr, VarIdentExpr, VariantExpr,
         },
         func::{ExternFunction, Function},
         item::Item,
@@ -97,9 +97,29 @@ pub trait VisitMut: Sized {
         visit_unary_expr_mut(self, node);
     }
 
+    fn visit_try_expr_mut(&mut self, node: &mut TryExpr) {
+        visit_try_expr_mut(self, node);
+    }
+
+    fn visit_tuple_expr_mut(&mut self, node: &mut TupleExpr) {
+        visit_tuple_expr_mut(self, node);
+    }
+
+    fn visit_tuple_index_expr_mut(&mut self, node: &mut TupleIndexExpr) {
+        visit_tuple_index_expr_mut(self, node);
+    }
+
+    fn visit_cast_expr_mut(&mut self, node: &mut CastExpr) {
+        visit_cast_expr_mut(self, node);
+    }
+
     fn visit_unit_expr_mut(&mut self) {
         /* terminal */
     }
+
+    fn visit_variant_expr_mut(&mut self, node: &mut VariantExpr) {
+        visit_variant_expr_mut(self, node);
+    }
 }
 
 pub fn visit_module_mut(v: &mut impl VisitMut, node: &mut Module) {
@@ -143,6 +163,11 @@ pub fn visit_expr_mut(v: &mut impl VisitMut, node: &mut Expr) {
         ExprKind::Return(node) => v.visit_return_expr_mut(node),
         ExprKind::If(node) => v.visit_if_expr_mut(node),
         ExprKind::Loop(node) => v.visit_loop_expr_mut(node),
+        ExprKind::Variant(node) => v.visit_variant_expr_mut(node),
+        ExprKind::Try(node) => v.visit_try_expr_mut(node),
+        ExprKind::Tuple(node) => v.visit_tuple_expr_mut(node),
+        ExprKind::TupleIndex(node) => v.visit_tuple_index_expr_mut(node),
+        ExprKind::Cast(node) => v.visit_cast_expr_mut(node),
     };
 }
 
@@ -155,10 +180,12 @@ pub fn visit_func_mut(v: &mut impl VisitMut, node: &mut Function) {
 }
 
 pub fn visit_if_expr_mut(v: &mut impl VisitMut, node: &mut IfExpr) {
-    v.visit_expr_mut(&mut node.cond);
-    v.visit_block_expr_mut(&mut node.then);
+    for branch in &mut node.branches {
+        v.visit_expr_mut(&mut branch.cond);
+        v.visit_expr_mut(&mut branch.body);
+    }
     if let Some(else_) = &mut node.else_ {
-        v.visit_block_expr_mut(else_);
+        v.visit_expr_mut(else_);
     }
 }
 
@@ -194,3 +221,27 @@ pub fn visit_stmt_mut(v: &mut impl VisitMut, node: &mut Stmt) {
 pub fn visit_unary_expr_mut(v: &mut impl VisitMut, node: &mut UnaryExpr) {
     v.visit_expr_mut(&mut node.expr);
 }
+
+pub fn visit_try_expr_mut(v: &mut impl VisitMut, node: &mut TryExpr) {
+    v.visit_expr_mut(&mut node.expr);
+}
+
+pub fn visit_tuple_expr_mut(v: &mut impl VisitMut, node: &mut TupleExpr) {
+    for elem in &mut node.elems {
+        v.visit_expr_mut(elem);
+    }
+}
+
+pub fn visit_tuple_index_expr_mut(v: &mut impl VisitMut, node: &mut TupleIndexExpr) {
+    v.visit_expr_mut(&mut node.expr);
+}
+
+pub fn visit_cast_expr_mut(v: &mut impl VisitMut, node: &mut CastExpr) {
+    v.visit_expr_mut(&mut node.expr);
+}
+
+pub fn visit_variant_expr_mut(v: &mut impl VisitMut, node: &mut VariantExpr) {
+    if let Some(value) = &mut node.value {
+        v.visit_expr_mut(value);
+    }
+}
@@ -1,22 +1,81 @@
-use std::env;
+use std::{env, process};
 
-use whiskc::compile::{self, CompileSwitch};
+use whiskc::{
+    codegen::Target,
+    compile::{self, CompileSwitch},
+    format,
+    ir::opt::OptLevel,
+};
 
 fn main() {
     let args: Vec<String> = env::args().collect();
     if args.len() < 2 {
         eprintln!("whiskc: expected path to .wsk sourcefile.");
+        eprintln!("usage: whiskc [--emit-ir] [-O0|-O1|-O2] [--target=vm|reg|c|x86-64|wasm] <file.wsk> | whiskc fmt [--check] <file.wsk>...");
         return;
     }
 
-    compile::compile(
-        args[1].clone().into(),
-        CompileSwitch {
-            do_parse_ast: true,
-            debug_ast: false,
-            do_resolve_module: true,
-            print_module: true,
-            do_codegen: true,
-        },
-    )
+    let ok = if args[1] == "fmt" {
+        let check = args[2..].iter().any(|arg| arg == "--check");
+        let paths: Vec<_> = args[2..].iter().filter(|arg| *arg != "--check").collect();
+        if paths.is_empty() {
+            eprintln!("whiskc: expected paths to .wsk sourcefiles to format.");
+            process::exit(1);
+        }
+        format::format_paths(&paths, check)
+    } else {
+        let emit_ir = args[1..].iter().any(|arg| arg == "--emit-ir");
+        let mut opt_level = OptLevel::O0;
+        for arg in &args[1..] {
+            let Some(level) = arg.strip_prefix("-O") else {
+                continue;
+            };
+            match level.parse() {
+                Ok(level) => opt_level = level,
+                Err(()) => {
+                    eprintln!("whiskc: unknown optimisation level {}.", arg);
+                    process::exit(1);
+                }
+            }
+        }
+        let mut target = Target::WskVm;
+        for arg in &args[1..] {
+            let Some(name) = arg.strip_prefix("--target=") else {
+                continue;
+            };
+            target = match name {
+                "vm" => Target::WskVm,
+                "reg" => Target::Reg,
+                "c" => Target::C,
+                "x86-64" => Target::X86_64,
+                "wasm" => Target::Wasm,
+                _ => {
+                    eprintln!("whiskc: unknown target {}.", name);
+                    process::exit(1);
+                }
+            };
+        }
+        let Some(path) = args[1..].iter().find(|arg| {
+            *arg != "--emit-ir" && !arg.starts_with("-O") && !arg.starts_with("--target=")
+        }) else {
+            eprintln!("whiskc: expected path to .wsk sourcefile.");
+            process::exit(1);
+        };
+        compile::compile(
+            path.into(),
+            CompileSwitch {
+                do_parse_ast: true,
+                debug_ast: false,
+                do_resolve_module: true,
+                print_module: true,
+                emit_ir,
+                opt_level,
+                do_codegen: true,
+                target,
+            },
+        )
+    };
+    if !ok {
+        process::exit(1);
+    }
 }
@@ -0,0 +1,155 @@
+use std::fmt;
+
+use crate::ast::location::{FileId, Location, Span};
+
+/// Owns the sources of every file taking part in a compilation.
+#[derive(Debug, Default, Clone)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+}
+impl SourceMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_file(&mut self, name: impl Into<String>, source: impl Into<String>) -> FileId {
+        let id = FileId(self.files.len() as u32);
+        self.files
+            .push(SourceFile::new(id, name.into(), source.into()));
+        id
+    }
+
+    pub fn get(&self, id: FileId) -> &SourceFile {
+        &self.files[id.0 as usize]
+    }
+
+    pub fn files(&self) -> impl Iterator<Item = &SourceFile> {
+        self.files.iter()
+    }
+
+    pub fn location(&self, span: Span) -> (Location, Location) {
+        let file = self.get(span.file);
+        (file.location(span.start), file.location(span.end))
+    }
+
+    pub fn slice(&self, span: Span) -> &str {
+        self.get(span.file).slice(span)
+    }
+
+    /// Formats `span` as `name:[line:col-line:col]` for diagnostics.
+    pub fn display_span(&self, span: Span) -> impl fmt::Display + '_ {
+        DisplaySpan { map: self, span }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SourceFile {
+    pub id: FileId,
+    pub name: String,
+    pub source: String,
+    /// Byte offset of the first character of each line.
+    line_starts: Vec<u32>,
+}
+impl SourceFile {
+    fn new(id: FileId, name: String, source: String) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(i, _)| i as u32 + 1))
+            .collect();
+        Self {
+            id,
+            name,
+            source,
+            line_starts,
+        }
+    }
+
+    /// Line and column of the character at byte `offset`, offsets past the end are clamped.
+    pub fn location(&self, offset: u32) -> Location {
+        let mut offset = (offset as usize).min(self.source.len());
+        while !self.source.is_char_boundary(offset) {
+            offset -= 1;
+        }
+        let offset = offset as u32;
+        let line = self.line_starts.partition_point(|start| *start <= offset) - 1;
+        let line_start = self.line_starts[line] as usize;
+        let col = self.source[line_start..offset as usize].chars().count();
+        Location::new(line as u32 + 1, col as u32 + 1)
+    }
+
+    /// Byte offset of a 1-based line and column, the inverse of [`SourceFile::location`].
+    pub fn offset(&self, loc: Location) -> Option<u32> {
+        let line_start = *self.line_starts.get(loc.line.checked_sub(1)? as usize)? as usize;
+        let line = self.source[line_start..].split('\n').next().unwrap_or("");
+        let col = loc.col.checked_sub(1)? as usize;
+        let in_line = line
+            .char_indices()
+            .map(|(i, _)| i)
+            .chain(std::iter::once(line.len()))
+            .nth(col)?;
+        Some((line_start + in_line) as u32)
+    }
+
+    pub fn slice(&self, span: Span) -> &str {
+        &self.source[span.start as usize..span.end as usize]
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+}
+
+struct DisplaySpan<'a> {
+    map: &'a SourceMap,
+    span: Span,
+}
+impl fmt::Display for DisplaySpan<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let file = self.map.get(self.span.file);
+        let start = file.location(self.span.start);
+        // the end offset is exclusive, point at the last character instead.
+        let end = file.location(self.span.end.saturating_sub(1).max(self.span.start));
+        if start == end {
+            write!(f, "{}:[{:?}]", file.name, start)
+        } else {
+            write!(f, "{}:[{:?}-{:?}]", file.name, start, end)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compile::{compile_source, CompileSwitch};
+
+    #[test]
+    fn columns_count_characters_after_multi_byte_ones() {
+        let mut map = SourceMap::new();
+        let source = "ab\n\u{e9}\u{1f600} xy\n";
+        let file = map.add_file("a.wsk", source);
+        let at = |s: &str| source.find(s).unwrap() as u32;
+
+        // `é` takes 2 bytes and the emoji 4, but a column each.
+        let xy = Span::new(file, at("xy"), at("xy") + 2);
+        assert_eq!(map.display_span(xy).to_string(), "a.wsk:[2:4-2:5]");
+        let emoji = Span::new(file, at("\u{1f600}"), at(" xy"));
+        assert_eq!(map.display_span(emoji).to_string(), "a.wsk:[2:2]");
+        assert_eq!(map.get(file).offset(Location::new(2, 4)), Some(at("xy")));
+    }
+
+    #[test]
+    fn diagnostics_after_multi_byte_characters() {
+        let switches = CompileSwitch {
+            do_parse_ast: true,
+            do_resolve_module: true,
+            ..Default::default()
+        };
+        let (sources, output) = compile_source(
+            "a.wsk",
+            "func main() int { /* \u{fc}\u{fc} */ x }",
+            &switches,
+        );
+        let span = output.diagnostics[0].span.unwrap();
+        assert_eq!(sources.slice(span), "x");
+        assert_eq!(sources.display_span(span).to_string(), "a.wsk:[1:28]");
+    }
+}
@@ -11,6 +11,7 @@ pub struct Common {
 #[derive(Default, Debug, Clone, Copy)]
 pub struct CommonType {
     pub never: TypeId,
+    pub error: TypeId,
     pub unit: TypeId,
     pub int: TypeId,
     pub bool: TypeId,
@@ -30,6 +31,7 @@ fn inject_primitive_types(table: &mut SymbolTable) -> CommonType {
     };
     CommonType {
         never: f(Primitive::Never),
+        error: f(Primitive::Error),
         unit: f(Primitive::Unit),
         int: f(Primitive::Int),
         bool: f(Primitive::Bool),
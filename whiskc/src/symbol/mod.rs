@@ -1,10 +1,13 @@
 mod common;
+pub mod prelude;
 mod symbol_id;
 mod symbol_table;
 pub mod ty;
 
 pub use symbol_id::*;
-pub use symbol_table::SymbolTable;
+pub use symbol_table::{MergeConflict, SymbolRemap, SymbolTable};
+
+use crate::ast::location::Span;
 
 use self::ty::TypeKind;
 
@@ -13,6 +16,10 @@ pub struct TypeSymbol {
     id: TypeId,
     pub name: String,
     pub kind: Option<TypeKind>,
+    /// Where the type is declared, the default span for builtin and anonymous types.
+    pub origin: Span,
+    /// Whether the type comes from a declaration, builtin and anonymous types are not declared.
+    pub is_declared: bool,
 }
 impl TypeSymbol {
     pub fn get_id(&self) -> TypeId {
@@ -31,6 +38,10 @@ pub struct FuncSymbol {
     pub params: Vec<VarId>,
     pub ret_ty: TypeId,
     pub entry_block: BlockId,
+    /// Whether the function is declared `pub`.
+    pub is_pub: bool,
+    /// Where the function is named in its declaration.
+    pub origin: Span,
 }
 impl FuncSymbol {
     pub fn get_id(&self) -> FuncId {
@@ -56,6 +67,8 @@ pub struct VarSymbol {
     pub block: BlockId,
     pub name: String,
     pub ty: TypeId,
+    /// Where the variable is bound.
+    pub origin: Span,
 }
 impl VarSymbol {
     pub fn get_id(&self) -> VarId {
@@ -0,0 +1,86 @@
+use core::fmt;
+
+/// Generic types that are always in scope, instantiated on demand by the symbol table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PreludeType {
+    /// `Option[T]`
+    Option,
+    /// `Result[T, E]`
+    Result,
+}
+impl PreludeType {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "Option" => Some(Self::Option),
+            "Result" => Some(Self::Result),
+            _ => None,
+        }
+    }
+
+    pub fn param_count(&self) -> usize {
+        match self {
+            Self::Option => 1,
+            Self::Result => 2,
+        }
+    }
+}
+impl fmt::Display for PreludeType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::Option => "Option",
+                Self::Result => "Result",
+            }
+        )
+    }
+}
+
+/// Constructors of the prelude types, usable like functions (`Some(1)`) or values (`None`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Variant {
+    Some,
+    None,
+    Ok,
+    Err,
+}
+impl Variant {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "Some" => Some(Self::Some),
+            "None" => Some(Self::None),
+            "Ok" => Some(Self::Ok),
+            "Err" => Some(Self::Err),
+            _ => None,
+        }
+    }
+
+    pub fn has_value(&self) -> bool {
+        !matches!(self, Self::None)
+    }
+
+    /// Whether the variant is the one `?` unwraps instead of returning early.
+    pub fn is_success(&self) -> bool {
+        matches!(self, Self::Some | Self::Ok)
+    }
+
+    /// Runtime tag of the variant, success variants are tagged 1 and failures 0.
+    pub fn tag(&self) -> i64 {
+        self.is_success() as i64
+    }
+}
+impl fmt::Display for Variant {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::Some => "Some",
+                Self::None => "None",
+                Self::Ok => "Ok",
+                Self::Err => "Err",
+            }
+        )
+    }
+}
@@ -1,64 +1,67 @@
+//! Dense indices into the symbol table arenas, one index space per kind of symbol.
+
 use super::{BlockSymbol, FuncSymbol, SymbolTable, TypeSymbol, VarSymbol};
 
-#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
-pub struct TypeId(pub(super) u64);
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TypeId(pub(super) u32);
 impl<'a> TypeId {
+    pub(super) fn index(&self) -> usize {
+        self.0 as usize
+    }
+
     pub fn sym(&self, table: &'a SymbolTable) -> &'a TypeSymbol {
-        table.types.get(self).unwrap()
+        &table.types[self.index()]
     }
 
     pub fn sym_mut(&self, table: &'a mut SymbolTable) -> &'a mut TypeSymbol {
-        table.types.get_mut(self).unwrap()
-    }
-}
-impl From<u64> for TypeId {
-    fn from(value: u64) -> Self {
-        Self(value)
+        &mut table.types[self.index()]
     }
 }
 
-#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
-pub struct FuncId(pub(super) u64);
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct FuncId(pub(super) u32);
 impl<'a> FuncId {
+    pub(super) fn index(&self) -> usize {
+        self.0 as usize
+    }
+
     pub fn sym(&self, table: &'a SymbolTable) -> &'a FuncSymbol {
-        table.funcs.get(self).unwrap()
+        &table.funcs[self.index()]
     }
 
     pub fn sym_mut(&self, table: &'a mut SymbolTable) -> &'a mut FuncSymbol {
-        table.funcs.get_mut(self).unwrap()
-    }
-}
-impl From<u64> for FuncId {
-    fn from(value: u64) -> Self {
-        Self(value)
+        &mut table.funcs[self.index()]
     }
 }
 
-#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
-pub struct BlockId(pub(super) u64);
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BlockId(pub(super) u32);
 impl<'a> BlockId {
+    pub(super) fn index(&self) -> usize {
+        self.0 as usize
+    }
+
     pub fn sym(&self, table: &'a SymbolTable) -> &'a BlockSymbol {
-        table.blocks.get(self).unwrap()
+        &table.blocks[self.index()]
     }
 
     pub fn sym_mut(&self, table: &'a mut SymbolTable) -> &'a mut BlockSymbol {
-        table.blocks.get_mut(self).unwrap()
-    }
-}
-impl From<u64> for BlockId {
-    fn from(value: u64) -> Self {
-        Self(value)
+        &mut table.blocks[self.index()]
     }
 }
 
-#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
-pub struct VarId(pub(super) BlockId, pub(super) u64);
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct VarId(pub(super) u32);
 impl<'a> VarId {
+    pub(super) fn index(&self) -> usize {
+        self.0 as usize
+    }
+
     pub fn sym(&self, table: &'a SymbolTable) -> &'a VarSymbol {
-        table.vars.get(self).unwrap()
+        &table.vars[self.index()]
     }
 
     pub fn sym_mut(&self, table: &'a mut SymbolTable) -> &'a mut VarSymbol {
-        table.vars.get_mut(self).unwrap()
+        &mut table.vars[self.index()]
     }
 }
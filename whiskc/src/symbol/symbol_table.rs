@@ -1,21 +1,29 @@
 use core::fmt;
 use std::collections::HashMap;
 
-use crate::interner::StringInterner;
+use crate::{
+    ast::location::Span,
+    interner::{StringInterner, Symbol},
+};
 
 use super::{
     common::{inject_symbol_table, Common, CommonType},
+    ty::{StructType, TypeKind},
     BlockId, BlockSymbol, FuncId, FuncSymbol, TypeId, TypeSymbol, VarId, VarSymbol,
 };
 
+/// Symbols are stored in one arena per kind and their ids are indices into it, so ids are dense and
+/// only depend on the order the symbols were created in.
 #[derive(Default, Clone)]
 pub struct SymbolTable {
-    pub(super) types: HashMap<TypeId, TypeSymbol>,
-    pub(super) funcs: HashMap<FuncId, FuncSymbol>,
-    pub(super) blocks: HashMap<BlockId, BlockSymbol>,
-    pub(super) vars: HashMap<VarId, VarSymbol>,
-    pub(super) interner: StringInterner,
-    pub(super) blk_counter: u64,
+    pub(super) types: Vec<TypeSymbol>,
+    pub(super) funcs: Vec<FuncSymbol>,
+    pub(super) blocks: Vec<BlockSymbol>,
+    pub(super) vars: Vec<VarSymbol>,
+    interner: StringInterner,
+    type_names: HashMap<Symbol, TypeId>,
+    func_names: HashMap<Symbol, FuncId>,
+    var_names: HashMap<(BlockId, Symbol), VarId>,
     common: Option<Common>,
 }
 impl fmt::Debug for SymbolTable {
@@ -25,8 +33,6 @@ impl fmt::Debug for SymbolTable {
             .field("funcs", &self.funcs)
             .field("blocks", &self.blocks)
             .field("vars", &self.vars)
-            // .field("interner", &self.interner)
-            .field("blk_counter", &self.blk_counter)
             .finish()
     }
 }
@@ -40,18 +46,19 @@ impl SymbolTable {
     /// Add the type to the type symbol table, returning its id if there is no name collision.
     /// None is returned if there is a type with the same name presented in the table.
     pub fn new_type(&mut self, name: String) -> Option<TypeId> {
-        let tyid: TypeId = self.interner.intern(&name).into();
-        if self.types.contains_key(&tyid) {
+        let sym = self.interner.intern(&name);
+        if self.type_names.contains_key(&sym) {
             return None;
         }
-        self.types.insert(
-            tyid,
-            TypeSymbol {
-                id: tyid,
-                name,
-                kind: None,
-            },
-        );
+        let tyid = TypeId(self.types.len() as u32);
+        self.types.push(TypeSymbol {
+            id: tyid,
+            name,
+            kind: None,
+            origin: Span::default(),
+            is_declared: false,
+        });
+        self.type_names.insert(sym, tyid);
         Some(tyid)
     }
 
@@ -60,31 +67,27 @@ impl SymbolTable {
     }
 
     pub fn get_type_id(&self, name: &str) -> Option<TypeId> {
-        let tyid: TypeId = self.interner.get(name)?.into();
-        if self.types.contains_key(&tyid) {
-            Some(tyid)
-        } else {
-            None
-        }
+        self.type_names.get(&self.interner.get(name)?).copied()
     }
 
     /// Add the function to the function symbol table, returning its id if there is no name collision.
     /// None is returned if there is a function with the same name presented in the table.
     pub fn new_function(&mut self, name: String) -> Option<FuncId> {
-        let fid: FuncId = self.interner.intern(&name).into();
-        if self.funcs.contains_key(&fid) {
+        let sym = self.interner.intern(&name);
+        if self.func_names.contains_key(&sym) {
             return None;
         }
-        self.funcs.insert(
-            fid,
-            FuncSymbol {
-                id: fid,
-                name,
-                params: vec![],
-                ret_ty: Default::default(),
-                entry_block: Default::default(),
-            },
-        );
+        let fid = FuncId(self.funcs.len() as u32);
+        self.funcs.push(FuncSymbol {
+            id: fid,
+            name,
+            params: vec![],
+            ret_ty: Default::default(),
+            entry_block: Default::default(),
+            is_pub: false,
+            origin: Span::default(),
+        });
+        self.func_names.insert(sym, fid);
         Some(fid)
     }
 
@@ -93,30 +96,21 @@ impl SymbolTable {
     }
 
     pub fn get_function_id(&self, name: &str) -> Option<FuncId> {
-        let fid: FuncId = self.interner.get(name)?.into();
-        if self.funcs.contains_key(&fid) {
-            Some(fid)
-        } else {
-            None
-        }
+        self.func_names.get(&self.interner.get(name)?).copied()
     }
 
     pub fn new_block(&mut self, parent_func: FuncId) -> BlockId {
-        let bid: BlockId = self.blk_counter.into();
-        self.blk_counter += 1;
-        self.blocks.insert(
-            bid,
-            BlockSymbol {
-                id: bid,
-                func: parent_func,
-                parent_block: None,
-            },
-        );
+        let bid = BlockId(self.blocks.len() as u32);
+        self.blocks.push(BlockSymbol {
+            id: bid,
+            func: parent_func,
+            parent_block: None,
+        });
         bid
     }
 
     fn get_block(&self, block: BlockId) -> Option<&BlockSymbol> {
-        self.blocks.get(&block)
+        self.blocks.get(block.index())
     }
 
     /// Add the variable to the variable table, returning its id if there is no name collision in
@@ -124,19 +118,21 @@ impl SymbolTable {
     /// None is returned if there is a variable with the same name presented in the same block, or
     /// the parent block id is an invalid id.
     pub fn new_variable(&mut self, name: String, parent_block: BlockId) -> Option<VarId> {
-        let vid = VarId(parent_block, self.interner.intern(&name));
-        if !self.blocks.contains_key(&parent_block) || self.vars.contains_key(&vid) {
+        let sym = self.interner.intern(&name);
+        if self.get_block(parent_block).is_none()
+            || self.var_names.contains_key(&(parent_block, sym))
+        {
             return None;
         }
-        self.vars.insert(
-            vid,
-            VarSymbol {
-                id: vid,
-                block: parent_block,
-                name,
-                ty: Default::default(),
-            },
-        );
+        let vid = VarId(self.vars.len() as u32);
+        self.vars.push(VarSymbol {
+            id: vid,
+            block: parent_block,
+            name,
+            ty: Default::default(),
+            origin: Span::default(),
+        });
+        self.var_names.insert((parent_block, sym), vid);
         Some(vid)
     }
 
@@ -154,12 +150,10 @@ impl SymbolTable {
         mut starting_block: BlockId,
         name: &str,
     ) -> Option<VarId> {
-        let id: u64 = self.interner.get(name)?;
-        let mut vid;
+        let sym = self.interner.get(name)?;
         while let Some(block) = self.get_block(starting_block) {
-            vid = VarId(block.id, id);
-            if self.vars.contains_key(&vid) {
-                return Some(vid);
+            if let Some(vid) = self.var_names.get(&(block.id, sym)) {
+                return Some(*vid);
             }
             if let Some(parent) = block.parent_block {
                 starting_block = parent;
@@ -180,11 +174,96 @@ impl SymbolTable {
         &self.common().ty
     }
 
-    pub fn is_type_coercible(&self, from: TypeId, to: TypeId) -> bool {
-        if from == self.common_type().never {
-            true
+    /// Get the `Option[inner]` type, creating it on first use.
+    pub fn get_option_type(&mut self, inner: TypeId) -> TypeId {
+        let name = format!("Option[{}]", inner.sym(self).name);
+        self.get_or_new_type(name, TypeKind::Option(inner))
+    }
+
+    /// Get the `Result[ok, err]` type, creating it on first use.
+    pub fn get_result_type(&mut self, ok: TypeId, err: TypeId) -> TypeId {
+        let name = format!("Result[{}, {}]", ok.sym(self).name, err.sym(self).name);
+        self.get_or_new_type(name, TypeKind::Result(ok, err))
+    }
+
+    /// Get the tuple type of the elements, creating it on first use.
+    pub fn get_tuple_type(&mut self, elems: Vec<TypeId>) -> TypeId {
+        let names: Vec<_> = elems.iter().map(|v| v.sym(self).name.as_str()).collect();
+        let name = if names.len() == 1 {
+            format!("({},)", names[0])
         } else {
-            from == to
+            format!("({})", names.join(", "))
+        };
+        self.get_or_new_type(name, TypeKind::Tuple(elems))
+    }
+
+    fn get_or_new_type(&mut self, name: String, kind: TypeKind) -> TypeId {
+        if let Some(id) = self.get_type_id(&name) {
+            return id;
+        }
+        let id = self.new_type(name).expect("unused type name");
+        id.sym_mut(self).kind = Some(kind);
+        id
+    }
+
+    /// Follow the alias chain to the type it names, non-alias types are returned as they are.
+    pub fn resolve_alias(&self, mut ty: TypeId) -> TypeId {
+        // bounded by the number of types so that an alias cycle does not hang the resolver.
+        for _ in 0..=self.types.len() {
+            match ty.sym(self).kind {
+                Some(TypeKind::Alias(target)) => ty = target,
+                _ => break,
+            }
+        }
+        ty
+    }
+
+    /// Get the kind of the type, looking through aliases.
+    pub fn get_type_kind(&self, ty: TypeId) -> Option<&TypeKind> {
+        self.resolve_alias(ty).sym(self).kind.as_ref()
+    }
+
+    /// Whether the type is the error type or is built from it, e.g. `(int, {error})`.
+    pub fn contains_error(&self, ty: TypeId) -> bool {
+        let ty = self.resolve_alias(ty);
+        if ty == self.common_type().error {
+            return true;
+        }
+        match &ty.sym(self).kind {
+            Some(TypeKind::Option(inner)) => self.contains_error(*inner),
+            Some(TypeKind::Result(ok, err)) => {
+                self.contains_error(*ok) || self.contains_error(*err)
+            }
+            Some(TypeKind::Tuple(elems)) => elems.iter().any(|v| self.contains_error(*v)),
+            _ => false,
+        }
+    }
+
+    pub fn is_type_coercible(&self, from: TypeId, to: TypeId) -> bool {
+        let (from, to) = (self.resolve_alias(from), self.resolve_alias(to));
+        if from == self.common_type().never || from == to {
+            return true;
+        }
+        // the error is already reported, a mismatch would only repeat it.
+        if self.contains_error(from) || self.contains_error(to) {
+            return true;
+        }
+        match (&from.sym(self).kind, &to.sym(self).kind) {
+            (Some(TypeKind::Option(from)), Some(TypeKind::Option(to))) => {
+                self.is_type_coercible(*from, *to)
+            }
+            (Some(TypeKind::Result(from_ok, from_err)), Some(TypeKind::Result(to_ok, to_err))) => {
+                self.is_type_coercible(*from_ok, *to_ok)
+                    && self.is_type_coercible(*from_err, *to_err)
+            }
+            (Some(TypeKind::Tuple(from)), Some(TypeKind::Tuple(to))) => {
+                from.len() == to.len()
+                    && from
+                        .iter()
+                        .zip(to)
+                        .all(|(from, to)| self.is_type_coercible(*from, *to))
+            }
+            _ => false,
         }
     }
 
@@ -203,4 +282,259 @@ impl SymbolTable {
             None
         }
     }
+
+    /// Find the narrowest type both sides coerce to, building prelude and tuple types when their parts
+    /// have to be joined separately, e.g. `Result[int, never]` and `Result[never, bool]`.
+    pub fn join_types(&mut self, left: TypeId, right: TypeId) -> Option<TypeId> {
+        if let Some(ty) = self.compare_type_asymmetric(left, right) {
+            return Some(ty);
+        }
+        match (
+            self.get_type_kind(left).cloned(),
+            self.get_type_kind(right).cloned(),
+        ) {
+            (Some(TypeKind::Option(left)), Some(TypeKind::Option(right))) => {
+                let inner = self.join_types(left, right)?;
+                Some(self.get_option_type(inner))
+            }
+            (
+                Some(TypeKind::Result(left_ok, left_err)),
+                Some(TypeKind::Result(right_ok, right_err)),
+            ) => {
+                let ok = self.join_types(left_ok, right_ok)?;
+                let err = self.join_types(left_err, right_err)?;
+                Some(self.get_result_type(ok, err))
+            }
+            (Some(TypeKind::Tuple(left)), Some(TypeKind::Tuple(right)))
+                if left.len() == right.len() =>
+            {
+                let elems = left
+                    .into_iter()
+                    .zip(right)
+                    .map(|(left, right)| self.join_types(left, right))
+                    .collect::<Option<Vec<_>>>()?;
+                Some(self.get_tuple_type(elems))
+            }
+            _ => None,
+        }
+    }
+
+    /// Merge the symbols of another module into this table, giving them fresh ids after the ones
+    /// already here. Builtin and structural types, i.e. the ones without a declaration, are shared
+    /// by name; a declared type or function that already exists in this table is a conflict, in
+    /// which case the table is left untouched.
+    pub fn merge(&mut self, other: &SymbolTable) -> Result<SymbolRemap, MergeConflict> {
+        for ty in &other.types {
+            if ty.is_declared && self.get_type_id(&ty.name).is_some() {
+                return Err(MergeConflict::Type(ty.name.clone()));
+            }
+        }
+        for func in &other.funcs {
+            if self.get_function_id(&func.name).is_some() {
+                return Err(MergeConflict::Function(func.name.clone()));
+            }
+        }
+
+        // allocate every id first, symbols refer to each other regardless of their order.
+        let mut remap = SymbolRemap::default();
+        let mut new_types = vec![];
+        for ty in &other.types {
+            let id = match self.get_type_id(&ty.name) {
+                Some(id) => id,
+                None => {
+                    let id = self.new_type(ty.name.clone()).unwrap();
+                    new_types.push((id, ty));
+                    id
+                }
+            };
+            remap.types.push(id);
+        }
+        for func in &other.funcs {
+            remap
+                .funcs
+                .push(self.new_function(func.name.clone()).unwrap());
+        }
+        let (block_base, var_base) = (self.blocks.len() as u32, self.vars.len() as u32);
+        remap.blocks = (0..other.blocks.len() as u32)
+            .map(|i| BlockId(block_base + i))
+            .collect();
+        remap.vars = (0..other.vars.len() as u32)
+            .map(|i| VarId(var_base + i))
+            .collect();
+
+        for (id, ty) in new_types {
+            let sym = id.sym_mut(self);
+            sym.kind = ty.kind.as_ref().map(|kind| remap.kind(kind));
+            sym.origin = ty.origin;
+            sym.is_declared = ty.is_declared;
+        }
+        for func in &other.funcs {
+            let sym = remap.func(func.id).sym_mut(self);
+            sym.params = func.params.iter().map(|v| remap.var(*v)).collect();
+            sym.ret_ty = remap.ty(func.ret_ty);
+            sym.entry_block = remap.block(func.entry_block);
+            sym.is_pub = func.is_pub;
+            sym.origin = func.origin;
+        }
+        for block in &other.blocks {
+            self.blocks.push(BlockSymbol {
+                id: remap.block(block.id),
+                func: remap.func(block.func),
+                parent_block: block.parent_block.map(|v| remap.block(v)),
+            });
+        }
+        for var in &other.vars {
+            let (id, block) = (remap.var(var.id), remap.block(var.block));
+            self.vars.push(VarSymbol {
+                id,
+                block,
+                name: var.name.clone(),
+                ty: remap.ty(var.ty),
+                origin: var.origin,
+            });
+            let sym = self.interner.intern(&var.name);
+            self.var_names.insert((block, sym), id);
+        }
+        Ok(remap)
+    }
+}
+
+/// Where the symbols of a merged table ended up in the table they were merged into.
+#[derive(Debug, Default, Clone)]
+pub struct SymbolRemap {
+    types: Vec<TypeId>,
+    funcs: Vec<FuncId>,
+    blocks: Vec<BlockId>,
+    vars: Vec<VarId>,
+}
+impl SymbolRemap {
+    pub fn ty(&self, id: TypeId) -> TypeId {
+        self.types[id.index()]
+    }
+
+    pub fn func(&self, id: FuncId) -> FuncId {
+        self.funcs[id.index()]
+    }
+
+    pub fn block(&self, id: BlockId) -> BlockId {
+        self.blocks[id.index()]
+    }
+
+    pub fn var(&self, id: VarId) -> VarId {
+        self.vars[id.index()]
+    }
+
+    fn kind(&self, kind: &TypeKind) -> TypeKind {
+        match kind {
+            TypeKind::Primitive(v) => TypeKind::Primitive(*v),
+            TypeKind::Struct(v) => TypeKind::Struct(StructType {
+                fields: v
+                    .fields
+                    .iter()
+                    .map(|(name, ty)| (name.clone(), self.ty(*ty)))
+                    .collect(),
+            }),
+            TypeKind::Ident(v) => TypeKind::Ident(self.ty(*v)),
+            TypeKind::Alias(v) => TypeKind::Alias(self.ty(*v)),
+            TypeKind::Option(v) => TypeKind::Option(self.ty(*v)),
+            TypeKind::Result(ok, err) => TypeKind::Result(self.ty(*ok), self.ty(*err)),
+            TypeKind::Tuple(elems) => TypeKind::Tuple(elems.iter().map(|v| self.ty(*v)).collect()),
+        }
+    }
+}
+
+/// A declared symbol of a merged table whose name is already taken.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MergeConflict {
+    Type(String),
+    Function(String),
+}
+impl fmt::Display for MergeConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Type(name) => write!(f, "type '{}' is declared in more than one module", name),
+            Self::Function(name) => {
+                write!(f, "function '{}' is declared in more than one module", name)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        ast::{self, location::FileId},
+        lowering,
+    };
+
+    use super::*;
+
+    fn resolve(source: &str) -> SymbolTable {
+        let (ast, errors) = ast::parse(FileId(0), source);
+        assert!(errors.is_empty());
+        lowering::resolve(&ast, "test".to_owned())
+            .unwrap()
+            .sym_table
+    }
+
+    #[test]
+    fn merge_rejects_declared_type_taken() {
+        let mut table = resolve("type Meters int;");
+        let before = table.types.len();
+        let other = resolve("type Meters int;");
+        assert_eq!(
+            table.merge(&other).unwrap_err(),
+            MergeConflict::Type("Meters".to_owned())
+        );
+        assert_eq!(table.types.len(), before);
+    }
+
+    #[test]
+    fn merge_rejects_type_declared_at_start_of_file() {
+        let mut table = SymbolTable::new();
+        table.new_type("A".to_owned()).unwrap();
+        let mut other = SymbolTable::new();
+        let ty = other.new_type("A".to_owned()).unwrap().sym_mut(&mut other);
+        // the span of the first token of file 0, as the default span.
+        ty.origin = Span::default();
+        ty.is_declared = true;
+        assert_eq!(
+            table.merge(&other).unwrap_err(),
+            MergeConflict::Type("A".to_owned())
+        );
+    }
+
+    #[test]
+    fn merge_rejects_function_taken() {
+        let mut table = resolve("func f() {}");
+        let other = resolve("func f() int { 1 }");
+        assert_eq!(
+            table.merge(&other).unwrap_err(),
+            MergeConflict::Function("f".to_owned())
+        );
+    }
+
+    #[test]
+    fn merge_remaps_symbols() {
+        let mut table = resolve("type Meters int;\nfunc f() {}");
+        let other = resolve("type Feet int;\nfunc g(a Feet, b int) Option[Feet] { None }");
+        let remap = table.merge(&other).unwrap();
+
+        // builtin types are shared, declared ones get fresh ids.
+        let common = *table.common_type();
+        assert_eq!(remap.ty(other.common_type().int), common.int);
+        let feet = table.get_type_id("Feet").unwrap();
+        assert_eq!(remap.ty(other.get_type_id("Feet").unwrap()), feet);
+        assert!(feet.sym(&table).is_declared);
+        assert!(matches!(feet.sym(&table).kind, Some(TypeKind::Ident(v)) if v == common.int));
+
+        let g = table.get_function_id("g").unwrap();
+        assert_eq!(remap.func(other.get_function_id("g").unwrap()), g);
+        let g = g.sym(&table);
+        let param_tys: Vec<_> = g.params.iter().map(|v| v.sym(&table).ty).collect();
+        assert_eq!(param_tys, [feet, common.int]);
+        assert!(matches!(table.get_type_kind(g.ret_ty), Some(TypeKind::Option(v)) if *v == feet));
+        assert_eq!(g.entry_block.sym(&table).func, g.get_id());
+        assert!(table.get_function_id("f").is_some());
+    }
 }
@@ -7,23 +7,61 @@ use super::{SymbolTable, TypeId};
 pub enum TypeKind {
     Primitive(Primitive),
     Struct(StructType),
+    /// A distinct type with the same representation as its base, `type A B;`.
     Ident(TypeId),
+    /// Another name of the type, interchangeable with it, `type A = B;`.
     Alias(TypeId),
+    Option(TypeId),
+    Result(TypeId, TypeId),
+    Tuple(Vec<TypeId>),
 }
 impl TypeKind {
+    /// Size of the value in bytes, None if the type is incomplete or contains itself by value.
     pub fn get_size(&self, table: &SymbolTable) -> Option<usize> {
+        self.get_size_visiting(table, &mut vec![])
+    }
+
+    fn get_size_visiting(&self, table: &SymbolTable, visiting: &mut Vec<TypeId>) -> Option<usize> {
         match self {
             TypeKind::Primitive(v) => Some(v.get_size()),
-            TypeKind::Struct(v) => v.get_size(table),
-            TypeKind::Ident(v) => v.sym(table).get_size(table),
-            TypeKind::Alias(v) => v.sym(table).get_size(table),
+            TypeKind::Struct(v) => v.get_size_visiting(table, visiting),
+            TypeKind::Ident(v) => size_of_type(*v, table, visiting),
+            TypeKind::Alias(v) => size_of_type(*v, table, visiting),
+            // prelude types live on the heap, the value itself is a reference.
+            TypeKind::Option(_) | TypeKind::Result(_, _) => Some(size_of::<usize>()),
+            TypeKind::Tuple(elems) => {
+                let mut sz = 0;
+                for elem in elems {
+                    sz += size_of_type(*elem, table, visiting)?;
+                }
+                Some(sz)
+            }
         }
     }
 }
 
+/// `visiting` holds the types whose size is being computed, finding the type in it again means the
+/// type contains itself and has no finite size.
+fn size_of_type(ty: TypeId, table: &SymbolTable, visiting: &mut Vec<TypeId>) -> Option<usize> {
+    if visiting.contains(&ty) {
+        return None;
+    }
+    visiting.push(ty);
+    let sz = ty
+        .sym(table)
+        .kind
+        .as_ref()
+        .and_then(|v| v.get_size_visiting(table, visiting));
+    visiting.pop();
+    sz
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Primitive {
     Never,
+    /// The type of an expression whose error is already reported, it coerces to and from every
+    /// type so that the error is not reported again by its users.
+    Error,
     Unit,
     Bool,
     Int,
@@ -31,7 +69,7 @@ pub enum Primitive {
 impl Primitive {
     pub fn get_size(&self) -> usize {
         match self {
-            Self::Never | Self::Unit => 0,
+            Self::Never | Self::Error | Self::Unit => 0,
             Self::Bool => size_of::<bool>(),
             Self::Int => size_of::<i64>(),
         }
@@ -44,6 +82,7 @@ impl fmt::Display for Primitive {
             "{}",
             match self {
                 Self::Never => "never",
+                Self::Error => "{error}",
                 Self::Unit => "()",
                 Self::Int => "int",
                 Self::Bool => "bool",
@@ -58,9 +97,13 @@ pub struct StructType {
 }
 impl StructType {
     pub fn get_size(&self, table: &SymbolTable) -> Option<usize> {
+        self.get_size_visiting(table, &mut vec![])
+    }
+
+    fn get_size_visiting(&self, table: &SymbolTable, visiting: &mut Vec<TypeId>) -> Option<usize> {
         let mut sz = 0;
         for field in &self.fields {
-            sz += field.1.sym(table).get_size(table)?;
+            sz += size_of_type(field.1, table, visiting)?;
         }
         Some(sz)
     }
@@ -0,0 +1,42 @@
+//! Conversions between byte offsets and LSP positions, which count UTF-16 code units.
+
+use serde_json::{json, Value};
+use whiskc::{
+    ast::location::{Location, Span},
+    source_map::SourceFile,
+};
+
+pub fn position(file: &SourceFile, offset: u32) -> Value {
+    let loc = file.location(offset);
+    let line_start = file.offset(Location::new(loc.line, 1)).unwrap_or(0) as usize;
+    let mut end = (offset as usize).min(file.source.len());
+    while !file.source.is_char_boundary(end) {
+        end -= 1;
+    }
+    let character = file.source[line_start..end].encode_utf16().count();
+    json!({ "line": loc.line - 1, "character": character })
+}
+
+pub fn range(file: &SourceFile, span: Span) -> Value {
+    json!({
+        "start": position(file, span.start),
+        "end": position(file, span.end),
+    })
+}
+
+/// The byte offset of an LSP `Position`, clamped to the end of its line.
+pub fn offset(file: &SourceFile, pos: &Value) -> Option<u32> {
+    let line = pos.get("line")?.as_u64()? as u32;
+    let character = pos.get("character")?.as_u64()? as usize;
+    let line_start = file.offset(Location::new(line + 1, 1))? as usize;
+    let line_text = file.source[line_start..].split('\n').next().unwrap_or("");
+
+    let mut units = 0;
+    for (i, c) in line_text.char_indices() {
+        if units >= character {
+            return Some((line_start + i) as u32);
+        }
+        units += c.len_utf16();
+    }
+    Some((line_start + line_text.len()) as u32)
+}
@@ -0,0 +1,49 @@
+use std::{
+    io::{self, BufReader, Write},
+    process,
+};
+
+use serde_json::Value;
+use server::Server;
+
+mod convert;
+mod server;
+mod transport;
+
+fn main() {
+    let stdin = io::stdin();
+    let mut reader = BufReader::new(stdin.lock());
+    let mut stdout = io::stdout().lock();
+    let mut server = Server::default();
+
+    loop {
+        let msg = match transport::read_message(&mut reader) {
+            Ok(Some(msg)) => msg,
+            Ok(None) => break,
+            // the message is skipped and the client told, the next one may be fine.
+            Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                eprintln!("wsk-lsp: {}", e);
+                send(&mut stdout, &server::parse_error(&e.to_string()));
+                continue;
+            }
+            Err(e) => {
+                eprintln!("wsk-lsp: {}", e);
+                break;
+            }
+        };
+
+        for reply in server.handle(msg) {
+            send(&mut stdout, &reply);
+        }
+        if let Some(code) = server.exit_code() {
+            process::exit(code);
+        }
+    }
+}
+
+fn send(w: &mut impl Write, msg: &Value) {
+    if let Err(e) = transport::write_message(w, msg) {
+        eprintln!("wsk-lsp: {}", e);
+        process::exit(1);
+    }
+}
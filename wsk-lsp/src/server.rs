@@ -0,0 +1,483 @@
+use std::collections::HashMap;
+
+use serde_json::{json, Value};
+use whiskc::{
+    ast::{
+        location::{Locatable, Span},
+        nodes::{
+            item::Item,
+            ty::{TypeDecl, TypeDeclKind},
+        },
+    },
+    compile::{compile_file, CompileOutput, CompileSwitch},
+    lowering::index::SymbolRef,
+    source_map::{SourceFile, SourceMap},
+    symbol::SymbolTable,
+};
+
+use crate::convert;
+
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+// https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#symbolKind
+const SYMBOL_KIND_CLASS: u32 = 5;
+const SYMBOL_KIND_FIELD: u32 = 8;
+const SYMBOL_KIND_FUNCTION: u32 = 12;
+const SYMBOL_KIND_STRUCT: u32 = 23;
+
+const SEVERITY_ERROR: u32 = 1;
+
+/// An open document and the result of its last analysis.
+struct Document {
+    sources: SourceMap,
+    output: CompileOutput,
+}
+impl Document {
+    fn new(uri: &str, text: String) -> Self {
+        let mut sources = SourceMap::new();
+        let file = sources.add_file(uri, text);
+        let output = compile_file(
+            &sources,
+            file,
+            &CompileSwitch {
+                do_parse_ast: true,
+                do_resolve_module: true,
+                ..Default::default()
+            },
+        );
+        Self { sources, output }
+    }
+
+    fn file(&self) -> &SourceFile {
+        self.sources.get(self.output.file)
+    }
+}
+
+#[derive(Default)]
+pub struct Server {
+    documents: HashMap<String, Document>,
+    shutdown: bool,
+    exit_code: Option<i32>,
+}
+impl Server {
+    /// Set once the client asked the server to exit.
+    pub fn exit_code(&self) -> Option<i32> {
+        self.exit_code
+    }
+
+    /// Handles a request or notification, returning the messages to send back.
+    pub fn handle(&mut self, msg: Value) -> Vec<Value> {
+        let method = msg.get("method").and_then(Value::as_str).unwrap_or("");
+        let params = msg.get("params").cloned().unwrap_or(Value::Null);
+        let Some(id) = msg.get("id").cloned() else {
+            return self.handle_notification(method, &params);
+        };
+
+        let result = match method {
+            "initialize" => Ok(json!({
+                "capabilities": {
+                    // full text sync
+                    "textDocumentSync": 1,
+                    "hoverProvider": true,
+                    "definitionProvider": true,
+                    "referencesProvider": true,
+                    "documentSymbolProvider": true,
+                },
+                "serverInfo": { "name": "wsk-lsp" },
+            })),
+            "shutdown" => {
+                self.shutdown = true;
+                Ok(Value::Null)
+            }
+            "textDocument/hover" => self.with_document(&params, Self::hover),
+            "textDocument/definition" => self.with_document(&params, Self::definition),
+            "textDocument/references" => self.with_document(&params, Self::references),
+            "textDocument/documentSymbol" => self.with_document(&params, Self::document_symbols),
+            _ => Err((METHOD_NOT_FOUND, format!("unsupported method '{}'", method))),
+        };
+
+        vec![match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err((code, message)) => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": { "code": code, "message": message },
+            }),
+        }]
+    }
+
+    fn handle_notification(&mut self, method: &str, params: &Value) -> Vec<Value> {
+        let uri = params["textDocument"]["uri"]
+            .as_str()
+            .unwrap_or("")
+            .to_owned();
+        match method {
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or("");
+                self.update(uri, text.to_owned())
+            }
+            "textDocument/didChange" => {
+                // with full sync the last change holds the whole text.
+                let Some(text) = params["contentChanges"]
+                    .as_array()
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change["text"].as_str())
+                else {
+                    return vec![];
+                };
+                self.update(uri, text.to_owned())
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                vec![publish_diagnostics(&uri, vec![])]
+            }
+            "exit" => {
+                self.exit_code = Some(if self.shutdown { 0 } else { 1 });
+                vec![]
+            }
+            _ => vec![],
+        }
+    }
+
+    fn update(&mut self, uri: String, text: String) -> Vec<Value> {
+        let doc = Document::new(&uri, text);
+        let file = doc.file();
+        let diagnostics = doc
+            .output
+            .diagnostics
+            .iter()
+            .map(|diag| {
+                let span = diag.span.unwrap_or_default();
+                json!({
+                    "range": convert::range(file, span),
+                    "severity": SEVERITY_ERROR,
+                    "source": "whiskc",
+                    "message": diag.message,
+                })
+            })
+            .collect();
+        let notification = publish_diagnostics(&uri, diagnostics);
+        self.documents.insert(uri, doc);
+        vec![notification]
+    }
+
+    fn with_document(
+        &self,
+        params: &Value,
+        f: fn(&Document, &str, &Value) -> Option<Value>,
+    ) -> Result<Value, (i64, String)> {
+        let Some(uri) = params["textDocument"]["uri"].as_str() else {
+            return Err((INVALID_PARAMS, "missing textDocument.uri".to_owned()));
+        };
+        let Some(doc) = self.documents.get(uri) else {
+            return Err((INVALID_PARAMS, format!("'{}' is not open", uri)));
+        };
+        Ok(f(doc, uri, params).unwrap_or(Value::Null))
+    }
+
+    fn hover(doc: &Document, _uri: &str, params: &Value) -> Option<Value> {
+        let file = doc.file();
+        let offset = convert::offset(file, &params["position"])?;
+        let (table, index) = doc.output.symbols()?;
+
+        let (span, text) = if let Some((span, sym)) = index.symbol_at(offset) {
+            (span, describe_symbol(table, sym))
+        } else {
+            let (span, ty) = index.type_at(offset)?;
+            (span, ty.sym(table).name.clone())
+        };
+        Some(json!({
+            "contents": { "kind": "markdown", "value": format!("```whisk\n{}\n```", text) },
+            "range": convert::range(file, span),
+        }))
+    }
+
+    fn definition(doc: &Document, uri: &str, params: &Value) -> Option<Value> {
+        let file = doc.file();
+        let offset = convert::offset(file, &params["position"])?;
+        let (table, index) = doc.output.symbols()?;
+
+        let (_, sym) = index.symbol_at(offset)?;
+        let origin = sym.origin(table);
+        // builtin symbols have no declaration to jump to.
+        if origin == Span::default() {
+            return None;
+        }
+        Some(json!({ "uri": uri, "range": convert::range(file, origin) }))
+    }
+
+    fn references(doc: &Document, uri: &str, params: &Value) -> Option<Value> {
+        let file = doc.file();
+        let offset = convert::offset(file, &params["position"])?;
+        let (table, index) = doc.output.symbols()?;
+        let include_decl = params["context"]["includeDeclaration"]
+            .as_bool()
+            .unwrap_or(true);
+
+        let (_, sym) = index.symbol_at(offset)?;
+        let origin = sym.origin(table);
+        let locations: Vec<_> = index
+            .references(sym)
+            .filter(|span| include_decl || *span != origin)
+            .map(|span| json!({ "uri": uri, "range": convert::range(file, span) }))
+            .collect();
+        Some(Value::Array(locations))
+    }
+
+    fn document_symbols(doc: &Document, _uri: &str, _params: &Value) -> Option<Value> {
+        let file = doc.file();
+        let ast = doc.output.ast.as_ref()?;
+        let symbols: Vec<_> = ast
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                Item::Function(func) => Some(document_symbol(
+                    file,
+                    &func.sig.name.0,
+                    SYMBOL_KIND_FUNCTION,
+                    item.get_location(),
+                    func.sig.name.1,
+                    vec![],
+                )),
+                Item::ExternFunction(func) => Some(document_symbol(
+                    file,
+                    &func.sig.name.0,
+                    SYMBOL_KIND_FUNCTION,
+                    item.get_location(),
+                    func.sig.name.1,
+                    vec![],
+                )),
+                Item::TypeDecl(decl) => Some(type_decl_symbol(file, decl)),
+                Item::Error(_) => None,
+            })
+            .collect();
+        Some(Value::Array(symbols))
+    }
+}
+
+/// The reply to a message that could not be read, which has no id to answer.
+pub fn parse_error(message: &str) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": null,
+        "error": { "code": PARSE_ERROR, "message": message },
+    })
+}
+
+fn publish_diagnostics(uri: &str, diagnostics: Vec<Value>) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/publishDiagnostics",
+        "params": { "uri": uri, "diagnostics": diagnostics },
+    })
+}
+
+fn describe_symbol(table: &SymbolTable, sym: SymbolRef) -> String {
+    match sym {
+        SymbolRef::Var(id) => {
+            let var = id.sym(table);
+            format!("let {} {}", var.name, var.ty.sym(table).name)
+        }
+        SymbolRef::Func(id) => {
+            let func = id.sym(table);
+            let params: Vec<_> = func
+                .params
+                .iter()
+                .map(|param| {
+                    let param = param.sym(table);
+                    format!("{} {}", param.name, param.ty.sym(table).name)
+                })
+                .collect();
+            format!(
+                "func {}({}) {}",
+                func.name,
+                params.join(", "),
+                func.ret_ty.sym(table).name
+            )
+        }
+        SymbolRef::Type(id) => format!("type {}", id.sym(table).name),
+    }
+}
+
+fn type_decl_symbol(file: &SourceFile, decl: &TypeDecl) -> Value {
+    let (kind, fields) = match &decl.kind {
+        TypeDeclKind::Struct(s) => (
+            SYMBOL_KIND_STRUCT,
+            s.fields
+                .items
+                .iter()
+                .map(|field| {
+                    document_symbol(
+                        file,
+                        &field.name.0,
+                        SYMBOL_KIND_FIELD,
+                        field.get_location(),
+                        field.name.1,
+                        vec![],
+                    )
+                })
+                .collect(),
+        ),
+        TypeDeclKind::Type(_) => (SYMBOL_KIND_CLASS, vec![]),
+    };
+    document_symbol(
+        file,
+        &decl.name.0,
+        kind,
+        decl.get_location(),
+        decl.name.1,
+        fields,
+    )
+}
+
+fn document_symbol(
+    file: &SourceFile,
+    name: &str,
+    kind: u32,
+    range: Span,
+    selection: Span,
+    children: Vec<Value>,
+) -> Value {
+    json!({
+        "name": name,
+        "kind": kind,
+        "range": convert::range(file, range),
+        "selectionRange": convert::range(file, selection),
+        "children": children,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const URI: &str = "file:///main.wsk";
+    const SOURCE: &str = "type Pair = struct {
+    x int,
+    y int,
+};
+
+func double(n int) int {
+  n * 2
+}
+
+func main() int {
+  let a int = double(4);
+  double(a)
+}
+";
+
+    fn open() -> Server {
+        let mut server = Server::default();
+        let replies = server.handle(json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didOpen",
+            "params": { "textDocument": { "uri": URI, "text": SOURCE } },
+        }));
+        assert_eq!(replies[0]["params"]["diagnostics"], json!([]));
+        server
+    }
+
+    /// The result of a request about the document at the position.
+    fn request(server: &mut Server, method: &str, line: u32, character: u32) -> Value {
+        let mut replies = server.handle(json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": method,
+            "params": {
+                "textDocument": { "uri": URI },
+                "position": { "line": line, "character": character },
+                "context": { "includeDeclaration": true },
+            },
+        }));
+        assert_eq!(replies.len(), 1);
+        replies.remove(0)["result"].take()
+    }
+
+    fn range(start: (u32, u32), end: (u32, u32)) -> Value {
+        json!({
+            "start": { "line": start.0, "character": start.1 },
+            "end": { "line": end.0, "character": end.1 },
+        })
+    }
+
+    #[test]
+    fn hover() {
+        let mut server = open();
+        let result = request(&mut server, "textDocument/hover", 10, 15);
+        assert_eq!(
+            result["contents"]["value"],
+            "```whisk\nfunc double(n int) int\n```"
+        );
+        assert_eq!(result["range"], range((10, 14), (10, 20)));
+        assert_eq!(
+            request(&mut server, "textDocument/hover", 4, 0),
+            Value::Null
+        );
+    }
+
+    #[test]
+    fn definition() {
+        let mut server = open();
+        assert_eq!(
+            request(&mut server, "textDocument/definition", 11, 9),
+            json!({ "uri": URI, "range": range((10, 6), (10, 7)) })
+        );
+    }
+
+    #[test]
+    fn references() {
+        let mut server = open();
+        let ranges: Vec<_> = request(&mut server, "textDocument/references", 5, 6)
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|v| v["range"].clone())
+            .collect();
+        assert_eq!(
+            ranges,
+            [
+                range((5, 5), (5, 11)),
+                range((10, 14), (10, 20)),
+                range((11, 2), (11, 8)),
+            ]
+        );
+    }
+
+    #[test]
+    fn document_symbols() {
+        let mut server = open();
+        let result = request(&mut server, "textDocument/documentSymbol", 0, 0);
+        let names = |symbols: &Value| -> Vec<String> {
+            symbols
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|v| v["name"].as_str().unwrap().to_owned())
+                .collect()
+        };
+        assert_eq!(names(&result), ["Pair", "double", "main"]);
+        assert_eq!(result[0]["kind"], SYMBOL_KIND_STRUCT);
+        assert_eq!(names(&result[0]["children"]), ["x", "y"]);
+        assert_eq!(result[1]["selectionRange"], range((5, 5), (5, 11)));
+    }
+
+    #[test]
+    fn closed_document_is_invalid_params() {
+        let mut server = open();
+        server.handle(json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didClose",
+            "params": { "textDocument": { "uri": URI } },
+        }));
+        let replies = server.handle(json!({
+            "jsonrpc": "2.0",
+            "id": 2,
+            "method": "textDocument/hover",
+            "params": { "textDocument": { "uri": URI }, "position": { "line": 0, "character": 0 } },
+        }));
+        assert_eq!(replies[0]["error"]["code"], INVALID_PARAMS);
+    }
+}
@@ -0,0 +1,82 @@
+use std::io::{self, BufRead, Write};
+
+use serde_json::Value;
+
+/// Messages longer than this are rejected rather than allocated.
+const MAX_CONTENT_LEN: usize = 64 << 20;
+
+/// Reads one `Content-Length` framed JSON-RPC message, `None` at the end of the input. A message
+/// that cannot be read is an [`io::ErrorKind::InvalidData`] error after which reading goes on with
+/// the next message.
+pub fn read_message(r: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut content_len = None;
+    loop {
+        let mut line = String::new();
+        if r.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        // the header can follow the content of a message without a length on the same line.
+        const HEADER: &str = "content-length:";
+        if let Some(at) = line.to_ascii_lowercase().rfind(HEADER) {
+            content_len = line[at + HEADER.len()..].trim().parse::<usize>().ok();
+        }
+    }
+
+    let content_len = match content_len {
+        Some(len) if len <= MAX_CONTENT_LEN => len,
+        Some(len) => return Err(invalid_data(format!("Content-Length {} is too long", len))),
+        None => return Err(invalid_data("missing or invalid Content-Length header")),
+    };
+    let mut content = vec![0; content_len];
+    r.read_exact(&mut content)?;
+    serde_json::from_slice(&content)
+        .map(Some)
+        .map_err(invalid_data)
+}
+
+fn invalid_data(e: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+pub fn write_message(w: &mut impl Write, msg: &Value) -> io::Result<()> {
+    let content = msg.to_string();
+    write!(w, "Content-Length: {}\r\n\r\n{}", content.len(), content)?;
+    w.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(content: &str) -> String {
+        format!("Content-Length: {}\r\n\r\n{}", content.len(), content)
+    }
+
+    fn is_invalid(result: io::Result<Option<Value>>) -> bool {
+        result.is_err_and(|e| e.kind() == io::ErrorKind::InvalidData)
+    }
+
+    #[test]
+    fn reading_goes_on_after_invalid_json() {
+        let input = frame("{\"id\": ") + &frame("{\"id\": 1}");
+        let mut r = input.as_bytes();
+        assert!(is_invalid(read_message(&mut r)));
+        assert_eq!(read_message(&mut r).unwrap().unwrap()["id"], 1);
+        assert!(read_message(&mut r).unwrap().is_none());
+    }
+
+    #[test]
+    fn reading_goes_on_after_a_bad_length() {
+        let input = "Content-Length: many\r\n\r\n{\"id\": 0}".to_owned() + &frame("{\"id\": 1}");
+        let mut r = input.as_bytes();
+        assert!(is_invalid(read_message(&mut r)));
+        assert_eq!(read_message(&mut r).unwrap().unwrap()["id"], 1);
+
+        let mut r = "Content-Length: 99999999999\r\n\r\n".as_bytes();
+        assert!(is_invalid(read_message(&mut r)));
+    }
+}
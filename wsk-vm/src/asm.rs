@@ -0,0 +1,671 @@
+//! The `.wska` assembly text format of programs.
+//!
+//! ```text
+//! ; comments run from a semicolon to the end of the line
+//! .entry start
+//!
+//! func start 0 -> 0
+//!     call main
+//!     halt
+//!
+//! func main 0 -> 1
+//!     push 10
+//!     store r0
+//! loop:
+//!     load r0
+//!     push 0
+//!     cmp gt
+//!     jfl done
+//!     load r0
+//!     push 1
+//!     sub
+//!     store r0
+//!     jmp loop
+//! done:
+//!     load r0
+//!     ret
+//! ```
+//!
+//! A function starts with `func <name> <param count> -> <return count>` and runs until the next
+//! one, functions are numbered in the order they appear. `.locals <count>` in a function reserves
+//! local slots past the ones its loads and stores use. `.entry` names the function the program
+//! starts in, by name or as `$<index>`, the first one when it is missing. A label is a name
+//! followed by a colon on its own line, it marks the next instruction and is only visible in its
+//! function.
+//!
+//! The other sections of the container have directives of their own: `.const <value>` adds a
+//! constant, `.import <name> <param count> -> <return count>` a host function and
+//! `.export <name> <function>` an export. `.source <name>` adds debug info, recording the source
+//! name and the names of the functions. `.meta <key> <value>` sets a metadata entry. The arguments
+//! of `.source` and the value of `.meta` run to the end of the line, comments included.
+//!
+//! Instructions are written as in the listing of [`Function`]'s `Display`: `push` takes an
+//! integer, `true` or `false`, `pushc` takes a constant as `#<index>`, `load` and `store` take a
+//! local as `r<index>`, `cmp` takes `equ`, `lt` or `gt`, jumps take a label and `call` takes a
+//! function name or `$<index>`. The other operands are plain numbers.
+
+use std::{collections::HashMap, fmt, fmt::Write};
+
+use crate::{
+    program::{
+        slots_used, BuildError, DebugInfo, Export, Function, FunctionBuilder, Import,
+        JumpOutOfBounds, Label, Program, MAX_SLOTS,
+    },
+    Cmp, Inst, Jump, Value,
+};
+
+/// Read a program from its assembly text.
+pub fn assemble(src: &str) -> Result<Program, AsmError> {
+    let mut prog = Program::default();
+    let mut entry = None;
+    let mut exports = vec![];
+    let mut source = None;
+    let mut funcs: Vec<AsmFunction> = vec![];
+    for (i, raw) in src.lines().enumerate() {
+        let line_no = i + 1;
+        let err = |message: String| AsmError {
+            line: line_no,
+            message,
+        };
+        let line = raw.split(';').next().unwrap().trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let mut words = line.split_whitespace();
+        let first = words.next().unwrap();
+        // the rest of the line after a directive, comments included.
+        let rest = || raw.trim_start()[first.len()..].trim();
+        if first == ".entry" {
+            let name = words
+                .next()
+                .ok_or_else(|| err("expected a function name".to_owned()))?;
+            entry = Some((name.to_owned(), line_no));
+        } else if first == ".const" {
+            prog.add_constant(match words.next() {
+                Some("true") => Value::Bool(true),
+                Some("false") => Value::Bool(false),
+                Some(v) => Value::Int(
+                    v.parse()
+                        .map_err(|_| err(format!("invalid value `{}`", v)))?,
+                ),
+                None => return Err(err("expected a value".to_owned())),
+            });
+        } else if first == ".import" {
+            let (Some(name), Some(params), Some("->"), Some(rets), None) = (
+                words.next(),
+                words.next(),
+                words.next(),
+                words.next(),
+                words.next(),
+            ) else {
+                return Err(err(
+                    "expected `.import <name> <param count> -> <return count>`".to_owned(),
+                ));
+            };
+            prog.add_import(Import {
+                name: name.to_owned(),
+                param_cnt: parse_number(params).map_err(err)?,
+                ret_cnt: parse_number(rets).map_err(err)?,
+            });
+        } else if first == ".export" {
+            let (Some(name), Some(func), None) = (words.next(), words.next(), words.next()) else {
+                return Err(err("expected `.export <name> <function>`".to_owned()));
+            };
+            exports.push((name.to_owned(), func.to_owned(), line_no));
+        } else if first == ".source" {
+            source = Some(rest().to_owned());
+        } else if first == ".meta" {
+            let key = words
+                .next()
+                .ok_or_else(|| err("expected a key".to_owned()))?;
+            prog.set_metadata(key, rest()[key.len()..].trim());
+        } else if first == ".locals" {
+            let func = funcs
+                .last_mut()
+                .ok_or_else(|| err("`.locals` outside of a function".to_owned()))?;
+            let (Some(cnt), None) = (words.next(), words.next()) else {
+                return Err(err("expected `.locals <count>`".to_owned()));
+            };
+            let cnt = parse_number(cnt).map_err(err)?;
+            if cnt > MAX_SLOTS {
+                return Err(err(format!(
+                    "{} local slots are more than the limit of {}",
+                    cnt, MAX_SLOTS
+                )));
+            }
+            func.locals = cnt;
+        } else if first == "func" {
+            let (Some(name), Some(params), Some("->"), Some(rets), None) = (
+                words.next(),
+                words.next(),
+                words.next(),
+                words.next(),
+                words.next(),
+            ) else {
+                return Err(err(
+                    "expected `func <name> <param count> -> <return count>`".to_owned(),
+                ));
+            };
+            if funcs.iter().any(|v| v.name == name) {
+                return Err(err(format!("function `{}` is defined twice", name)));
+            }
+            funcs.push(AsmFunction {
+                name: name.to_owned(),
+                line: line_no,
+                params: parse_number(params).map_err(err)?,
+                rets: parse_number(rets).map_err(err)?,
+                locals: 0,
+                lines: vec![],
+            });
+        } else {
+            let func = funcs
+                .last_mut()
+                .ok_or_else(|| err("instruction outside of a function".to_owned()))?;
+            func.lines.push((line_no, line));
+        }
+    }
+
+    let func_ids: HashMap<_, _> = funcs
+        .iter()
+        .enumerate()
+        .map(|(i, v)| (v.name.as_str(), i))
+        .collect();
+    for func in &funcs {
+        prog.add_func(func.assemble(&func_ids)?);
+    }
+    for (name, func, line) in exports {
+        let func = *func_ids.get(func.as_str()).ok_or_else(|| AsmError {
+            line,
+            message: format!("unknown function `{}`", func),
+        })?;
+        prog.add_export(Export { name, func });
+    }
+    if let Some(source) = source {
+        prog.set_debug_info(DebugInfo {
+            source,
+            func_names: funcs.iter().map(|v| v.name.clone()).collect(),
+        });
+    }
+    match entry {
+        Some((name, line)) => {
+            let err = |message| AsmError { line, message };
+            let id = match name.strip_prefix('$') {
+                Some(index) => parse_number(index).map_err(err)?,
+                None => *func_ids
+                    .get(name.as_str())
+                    .ok_or_else(|| err(format!("unknown function `{}`", name)))?,
+            };
+            if id >= funcs.len() {
+                return Err(err(format!("entry point ${} is not a function", id)));
+            }
+            prog.set_entry_point(id);
+        }
+        None if funcs.is_empty() => {
+            return Err(AsmError {
+                line: 0,
+                message: "program has no functions".to_owned(),
+            })
+        }
+        None => {}
+    }
+    Ok(prog)
+}
+
+struct AsmFunction<'a> {
+    name: String,
+    /// Line of the header.
+    line: usize,
+    params: usize,
+    rets: usize,
+    /// Local slots reserved by `.locals`.
+    locals: usize,
+    lines: Vec<(usize, &'a str)>,
+}
+impl AsmFunction<'_> {
+    fn assemble(&self, func_ids: &HashMap<&str, usize>) -> Result<Function, AsmError> {
+        let mut b = FunctionBuilder::new(self.params, self.rets);
+        let mut labels: HashMap<&str, Label> = HashMap::new();
+        let mut label =
+            |b: &mut FunctionBuilder, name| *labels.entry(name).or_insert_with(|| b.new_label());
+
+        let mut bound = vec![];
+        // the line of every instruction and the jumps with their labels, for the errors found
+        // once the whole function is read.
+        let mut inst_lines = vec![];
+        let mut jumps = vec![];
+        for &(line, text) in &self.lines {
+            let err = |message: String| AsmError { line, message };
+            if let Some(name) = text.strip_suffix(':') {
+                let name = name.trim();
+                if !is_name(name) {
+                    return Err(err(format!("invalid label name `{}`", name)));
+                }
+                if bound.contains(&name) {
+                    return Err(err(format!("label `{}` is defined twice", name)));
+                }
+                bound.push(name);
+                let l = label(&mut b, name);
+                b.bind_label(l);
+                continue;
+            }
+
+            let mut words = text.split_whitespace();
+            let op = words.next().unwrap();
+            let arg = words.next();
+            if let Some(extra) = words.next() {
+                return Err(err(format!("unexpected `{}`", extra)));
+            }
+            let arg = |what: &str| arg.ok_or_else(|| err(format!("`{}` expects {}", op, what)));
+            let num = |what: &str| arg(what).and_then(|v| parse_number(v).map_err(err));
+            let local = || {
+                arg("a local").and_then(|v| {
+                    v.strip_prefix('r')
+                        .ok_or_else(|| err(format!("expected a local, found `{}`", v)))
+                        .and_then(|v| parse_number(v).map_err(err))
+                })
+            };
+
+            let inst = match op {
+                "halt" => Inst::Halt,
+                "push" => Inst::Push(match arg("a value")? {
+                    "true" => Value::Bool(true),
+                    "false" => Value::Bool(false),
+                    v => Value::Int(
+                        v.parse()
+                            .map_err(|_| err(format!("invalid value `{}`", v)))?,
+                    ),
+                }),
+                "pushc" => Inst::PushConst(
+                    arg("a constant")?
+                        .strip_prefix('#')
+                        .ok_or_else(|| err("expected a constant as `#<index>`".to_owned()))
+                        .and_then(|v| parse_number(v).map_err(err))?,
+                ),
+                "pop" => Inst::Pop,
+                "load" => Inst::Load(local()?),
+                "store" => Inst::Store(local()?),
+                "add" => Inst::Add,
+                "sub" => Inst::Sub,
+                "mul" => Inst::Mul,
+                "div" => Inst::Div,
+                "mod" => Inst::Mod,
+                "and" => Inst::And,
+                "or" => Inst::Or,
+                "cmp" => Inst::Cmp(match arg("a comparison")? {
+                    "equ" => Cmp::Equal,
+                    "lt" => Cmp::Less,
+                    "gt" => Cmp::Greater,
+                    v => return Err(err(format!("unknown comparison `{}`", v))),
+                }),
+                "neg" => Inst::Neg,
+                "not" => Inst::Not,
+                "jmp" | "jtr" | "jfl" => {
+                    let name = arg("a label")?;
+                    if !is_name(name) {
+                        return Err(err(format!("invalid label name `{}`", name)));
+                    }
+                    let l = label(&mut b, name);
+                    match op {
+                        "jmp" => b.jmp(l),
+                        "jtr" => b.jmp_true(l),
+                        _ => b.jmp_false(l),
+                    }
+                    inst_lines.push(line);
+                    jumps.push((line, name));
+                    continue;
+                }
+                "call" => {
+                    let name = arg("a function")?;
+                    Inst::Call(match name.strip_prefix('$') {
+                        Some(index) => parse_number(index).map_err(err)?,
+                        None => *func_ids
+                            .get(name)
+                            .ok_or_else(|| err(format!("unknown function `{}`", name)))?,
+                    })
+                }
+                "ret" => Inst::Ret,
+                "mkstruct" => Inst::MakeStruct(num("a field count")?),
+                "mkarr" => Inst::MakeArray(num("an element count")?),
+                "mkstr" => Inst::MakeStr(num("a character count")?),
+                "getf" => Inst::GetField(num("a field index")?),
+                "setf" => Inst::SetField(num("a field index")?),
+                "getidx" => Inst::GetIndex,
+                "setidx" => Inst::SetIndex,
+                "len" => Inst::Len,
+                _ => return Err(err(format!("unknown instruction `{}`", op))),
+            };
+            if arg("").is_ok() && !has_operand(&inst) {
+                return Err(err(format!("`{}` takes no operand", op)));
+            }
+            b.push(inst);
+            inst_lines.push(line);
+        }
+
+        if let Some((line, name)) = jumps.into_iter().find(|(_, v)| !bound.contains(v)) {
+            return Err(AsmError {
+                line,
+                message: format!("label `{}` of `{}` is never defined", name, self.name),
+            });
+        }
+        let mut func = b.build().map_err(|e| AsmError {
+            line: match e {
+                BuildError::JumpOutOfBounds(JumpOutOfBounds { at, .. }) => inst_lines[at],
+                BuildError::UnboundLabel(_) => self.line,
+            },
+            message: format!("in `{}`: {}", self.name, e),
+        })?;
+        func.reserve_locals(self.locals);
+        Ok(func)
+    }
+}
+
+fn parse_number(s: &str) -> Result<usize, String> {
+    s.parse()
+        .map_err(|_| format!("expected a number, found `{}`", s))
+}
+
+fn is_name(s: &str) -> bool {
+    s.chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Write a program as assembly text, [`assemble`] reads it back into the same program. Functions
+/// have the names of the debug info, `f<index>` without it, and labels are named
+/// `L<instruction index>`. An entry point that is not a function is written as its index, which
+/// [`assemble`] rejects.
+pub fn disassemble(prog: &Program) -> String {
+    let debug_names = prog.debug_info().map(|v| &v.func_names).filter(|names| {
+        names.len() == prog.funcs().len()
+            && names.iter().all(|v| is_name(v) && v != "func")
+            && names
+                .iter()
+                .enumerate()
+                .all(|(i, v)| !names[..i].contains(v))
+    });
+    let name = |fi: usize| match debug_names {
+        Some(names) if fi < names.len() => names[fi].clone(),
+        _ => format!("f{}", fi),
+    };
+
+    let mut out = String::new();
+    for (key, value) in prog.metadata() {
+        writeln!(out, ".meta {} {}", key, value).unwrap();
+    }
+    if let Some(debug) = prog.debug_info() {
+        writeln!(out, ".source {}", debug.source).unwrap();
+    }
+    match prog.get_entry_point() {
+        entry if prog.get(entry).is_some() => writeln!(out, ".entry {}", name(entry)).unwrap(),
+        entry => writeln!(out, ".entry ${}", entry).unwrap(),
+    }
+    for value in prog.constants() {
+        writeln!(out, ".const {}", value).unwrap();
+    }
+    for import in prog.imports() {
+        writeln!(
+            out,
+            ".import {} {} -> {}",
+            import.name, import.param_cnt, import.ret_cnt
+        )
+        .unwrap();
+    }
+    for export in prog.exports() {
+        writeln!(out, ".export {} {}", export.name, name(export.func)).unwrap();
+    }
+
+    for (i, func) in prog.funcs().iter().enumerate() {
+        writeln!(
+            out,
+            "\nfunc {} {} -> {}",
+            name(i),
+            func.param_cnt(),
+            func.ret_cnt()
+        )
+        .unwrap();
+        if func.local_cnt() > slots_used(func.get_insts()) {
+            writeln!(out, "    .locals {}", func.local_cnt()).unwrap();
+        }
+        let targets: Vec<_> = func
+            .get_insts()
+            .iter()
+            .enumerate()
+            .filter_map(|(at, inst)| Some(at.wrapping_add_signed(inst.jump_offset()?)))
+            .collect();
+        for (at, inst) in func.get_insts().iter().enumerate() {
+            if targets.contains(&at) {
+                writeln!(out, "L{}:", at).unwrap();
+            }
+            write!(out, "    ").unwrap();
+            match inst {
+                Inst::Push(v) => writeln!(out, "push {}", v),
+                Inst::Load(i) => writeln!(out, "load r{}", i),
+                Inst::PushConst(i) => writeln!(out, "pushc #{}", i),
+                Inst::Store(i) => writeln!(out, "store r{}", i),
+                Inst::Cmp(cmp) => writeln!(
+                    out,
+                    "cmp {}",
+                    match cmp {
+                        Cmp::Equal => "equ",
+                        Cmp::Less => "lt",
+                        Cmp::Greater => "gt",
+                    }
+                ),
+                Inst::Jmp(offset) | Inst::JmpTrue(offset) | Inst::JmpFalse(offset) => writeln!(
+                    out,
+                    "{} L{}",
+                    mnemonic(inst),
+                    at.wrapping_add_signed(*offset)
+                ),
+                Inst::Call(fi) if prog.get(*fi).is_some() => writeln!(out, "call {}", name(*fi)),
+                Inst::Call(fi) => writeln!(out, "call ${}", fi),
+                Inst::MakeStruct(v)
+                | Inst::MakeArray(v)
+                | Inst::MakeStr(v)
+                | Inst::GetField(v)
+                | Inst::SetField(v) => writeln!(out, "{} {}", mnemonic(inst), v),
+                _ => writeln!(out, "{}", mnemonic(inst)),
+            }
+            .unwrap();
+        }
+    }
+    out
+}
+
+fn has_operand(inst: &Inst) -> bool {
+    !matches!(
+        inst,
+        Inst::Halt
+            | Inst::Pop
+            | Inst::Add
+            | Inst::Sub
+            | Inst::Mul
+            | Inst::Div
+            | Inst::Mod
+            | Inst::And
+            | Inst::Or
+            | Inst::Neg
+            | Inst::Not
+            | Inst::Ret
+            | Inst::GetIndex
+            | Inst::SetIndex
+            | Inst::Len
+    )
+}
+
+fn mnemonic(inst: &Inst) -> &'static str {
+    match inst {
+        Inst::Halt => "halt",
+        Inst::Push(_) => "push",
+        Inst::PushConst(_) => "pushc",
+        Inst::Pop => "pop",
+        Inst::Load(_) => "load",
+        Inst::Store(_) => "store",
+        Inst::Add => "add",
+        Inst::Sub => "sub",
+        Inst::Mul => "mul",
+        Inst::Div => "div",
+        Inst::Mod => "mod",
+        Inst::And => "and",
+        Inst::Or => "or",
+        Inst::Cmp(_) => "cmp",
+        Inst::Neg => "neg",
+        Inst::Not => "not",
+        Inst::Jmp(_) => "jmp",
+        Inst::JmpTrue(_) => "jtr",
+        Inst::JmpFalse(_) => "jfl",
+        Inst::Call(_) => "call",
+        Inst::Ret => "ret",
+        Inst::MakeStruct(_) => "mkstruct",
+        Inst::MakeArray(_) => "mkarr",
+        Inst::MakeStr(_) => "mkstr",
+        Inst::GetField(_) => "getf",
+        Inst::SetField(_) => "setf",
+        Inst::GetIndex => "getidx",
+        Inst::SetIndex => "setidx",
+        Inst::Len => "len",
+    }
+}
+
+/// An error in assembly text, the line is 1-based.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    pub line: usize,
+    pub message: String,
+}
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_round_trip(prog: &Program) {
+        let text = disassemble(prog);
+        let back = assemble(&text).unwrap_or_else(|e| panic!("{}\n{}", e, text));
+        assert_eq!(back.to_bin().unwrap(), prog.to_bin().unwrap(), "{}", text);
+    }
+
+    #[test]
+    fn round_trip_of_assembled_program() {
+        let src = "
+            .entry start
+            func start 0 -> 0
+                call main
+                halt
+            func main 0 -> 1
+                push 10
+                store r0
+            loop:
+                load r0
+                push 0
+                cmp gt
+                jfl done
+                load r0
+                push -1
+                add
+                store r0
+                jmp loop
+            done:
+                load r0
+                ret
+        ";
+        assert_round_trip(&assemble(src).unwrap());
+    }
+
+    #[test]
+    fn round_trip_of_every_section() {
+        let mut prog = Program::default();
+        let index = prog.add_constant(Value::Int(-7));
+        prog.add_constant(Value::Bool(true));
+        prog.add_import(Import {
+            name: "print".to_owned(),
+            param_cnt: 1,
+            ret_cnt: 0,
+        });
+        let mut start = Function::from_insts([
+            Inst::PushConst(index),
+            Inst::MakeArray(1),
+            Inst::Len,
+            Inst::Call(1),
+            Inst::Halt,
+        ]);
+        // slots no instruction uses.
+        start.reserve_locals(3);
+        prog.add_func(start);
+        let mut len = Function::new(1, 1);
+        len.push_insts([Inst::Push(Value::Bool(false)), Inst::Pop, Inst::Ret]);
+        prog.add_func(len);
+        prog.add_export(Export {
+            name: "len".to_owned(),
+            func: 1,
+        });
+        prog.set_debug_info(DebugInfo {
+            source: "main.wsk".to_owned(),
+            func_names: vec!["start".to_owned(), "len".to_owned()],
+        });
+        prog.set_metadata("producer", "whiskc 0.1");
+        assert_round_trip(&prog);
+    }
+
+    #[test]
+    fn round_trip_without_debug_names() {
+        let mut prog = Program::new(1);
+        prog.add_func(Function::from_insts([Inst::Ret]));
+        prog.add_func(Function::from_insts([
+            Inst::Push(Value::Bool(true)),
+            Inst::JmpTrue(2),
+            Inst::Call(7),
+            Inst::Halt,
+        ]));
+        assert!(disassemble(&prog).contains(".entry f1"));
+        assert_round_trip(&prog);
+    }
+
+    #[test]
+    fn locals_are_reserved() {
+        let prog = assemble("func main 0 -> 0\n.locals 4\nstore r1\nhalt").unwrap();
+        assert_eq!(prog.funcs()[0].local_cnt(), 4);
+        let err = assemble(&format!(
+            "func main 0 -> 0\n.locals {}\nhalt",
+            MAX_SLOTS + 1
+        ));
+        assert_eq!(err.unwrap_err().line, 2);
+        assert_eq!(assemble(".locals 1").unwrap_err().line, 1);
+    }
+
+    #[test]
+    fn bad_jumps_are_reported_at_their_line() {
+        let src = "func main 0 -> 0\n    jmp top\ntop:\n    jtr done\n    halt";
+        let err = assemble(src).unwrap_err();
+        assert_eq!(err.line, 4);
+        assert_eq!(err.message, "label `done` of `main` is never defined");
+
+        // a label after the last instruction is bound but not in the function.
+        let err = assemble("func main 0 -> 0\n    push true\n    jfl end\nend:").unwrap_err();
+        assert_eq!(err.line, 3);
+    }
+
+    #[test]
+    fn entry_out_of_range_is_rejected() {
+        let mut prog = Program::new(2);
+        prog.add_func(Function::from_insts([Inst::Halt]));
+        let text = disassemble(&prog);
+        assert!(text.contains(".entry $2"));
+        assert_eq!(
+            assemble(&text).unwrap_err().message,
+            "entry point $2 is not a function"
+        );
+        assert_eq!(
+            assemble(".entry $0\nfunc main 0 -> 0\nhalt")
+                .unwrap()
+                .get_entry_point(),
+            0
+        );
+    }
+}
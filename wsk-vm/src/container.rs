@@ -0,0 +1,591 @@
+//! The `.wc` container of programs.
+//!
+//! A file starts with the magic bytes `\0wsk`, the format version as two little endian `u16`
+//! (major, minor), the number of sections as a `u32` and the CRC-32 of these as a `u32`. Each
+//! section is a kind byte, the length of its payload as a `u64`, the CRC-32 of the kind, length
+//! and payload as a `u32` and the payload. The code section is required, the others are written
+//! only when they have content. Integers in payloads are unsigned LEB128 varints, or signed ones
+//! for constants, and strings are a length followed by UTF-8 bytes. Instructions are written in
+//! the [`Encoding::Compact`] encoding.
+//!
+//! Compatibility: a reader accepts files of its own and of every earlier major version, and
+//! rejects newer ones. A minor version adds new section kinds a reader can do without, so sections
+//! of unknown kinds are skipped in files of a newer minor version and rejected otherwise.
+//!
+//! Version 2 replaced the fixed width integers of version 1 with varints and the
+//! [`Encoding::Fixed`] encoding of instructions with the compact one. Version 3 added the number of
+//! local slots to the header of functions, for earlier versions it is found from the instructions.
+//! Version 4 added the checksum of the header and extended the checksums of the sections, which
+//! cover only the payload before, to their headers.
+//!
+//! Files without the magic bytes are read as the unversioned format before the container, a
+//! function count and an entry point followed by the functions in the [`Encoding::Fixed`]
+//! encoding. Unversioned files written before functions had a header of their instruction count,
+//! parameter count and result count can not be read, as their functions have no signature.
+
+use std::{fmt, io::Read};
+
+use crate::{
+    inst_code::{read_sleb, read_uleb, write_sleb, write_uleb, Encoding},
+    program::{DebugInfo, EncodeError, Export, Function, Import, Program, ProgramParseError},
+    Value,
+};
+
+pub const MAGIC: [u8; 4] = *b"\0wsk";
+pub const VERSION_MAJOR: u16 = 4;
+pub const VERSION_MINOR: u16 = 0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SectionKind {
+    /// The entry point and the functions.
+    Code,
+    Constants,
+    Imports,
+    Exports,
+    Debug,
+    /// Free form key value pairs, e.g. the producer of the file.
+    Metadata,
+}
+impl SectionKind {
+    const ALL: [SectionKind; 6] = [
+        SectionKind::Code,
+        SectionKind::Constants,
+        SectionKind::Imports,
+        SectionKind::Exports,
+        SectionKind::Debug,
+        SectionKind::Metadata,
+    ];
+
+    fn id(self) -> u8 {
+        match self {
+            SectionKind::Code => 1,
+            SectionKind::Constants => 2,
+            SectionKind::Imports => 3,
+            SectionKind::Exports => 4,
+            SectionKind::Debug => 5,
+            SectionKind::Metadata => 6,
+        }
+    }
+
+    fn from_id(id: u8) -> Option<Self> {
+        Self::ALL.into_iter().find(|v| v.id() == id)
+    }
+}
+impl fmt::Display for SectionKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                SectionKind::Code => "code",
+                SectionKind::Constants => "constants",
+                SectionKind::Imports => "imports",
+                SectionKind::Exports => "exports",
+                SectionKind::Debug => "debug",
+                SectionKind::Metadata => "metadata",
+            }
+        )
+    }
+}
+
+pub(crate) fn write(prog: &Program) -> Result<Vec<u8>, EncodeError> {
+    let mut sections = vec![];
+
+    let mut code = vec![];
+    write_uint(&mut code, prog.get_entry_point());
+    write_uint(&mut code, prog.funcs().len());
+    for func in prog.funcs() {
+        func.to_bin(&mut code, Encoding::Compact)?;
+    }
+    sections.push((SectionKind::Code, code));
+
+    if !prog.constants().is_empty() {
+        let mut out = vec![];
+        write_uint(&mut out, prog.constants().len());
+        for value in prog.constants() {
+            match value {
+                Value::Int(v) => {
+                    out.push(0);
+                    write_sleb(&mut out, *v);
+                }
+                Value::Bool(v) => out.extend([1, *v as u8]),
+                Value::Ref(_) => return Err(EncodeError::RefConstant),
+            }
+        }
+        sections.push((SectionKind::Constants, out));
+    }
+    if !prog.imports().is_empty() {
+        let mut out = vec![];
+        write_uint(&mut out, prog.imports().len());
+        for import in prog.imports() {
+            write_str(&mut out, &import.name);
+            write_uint(&mut out, import.param_cnt);
+            write_uint(&mut out, import.ret_cnt);
+        }
+        sections.push((SectionKind::Imports, out));
+    }
+    if !prog.exports().is_empty() {
+        let mut out = vec![];
+        write_uint(&mut out, prog.exports().len());
+        for export in prog.exports() {
+            write_str(&mut out, &export.name);
+            write_uint(&mut out, export.func);
+        }
+        sections.push((SectionKind::Exports, out));
+    }
+    if let Some(debug) = prog.debug_info() {
+        let mut out = vec![];
+        write_str(&mut out, &debug.source);
+        write_uint(&mut out, debug.func_names.len());
+        for name in &debug.func_names {
+            write_str(&mut out, name);
+        }
+        sections.push((SectionKind::Debug, out));
+    }
+    if !prog.metadata().is_empty() {
+        let mut out = vec![];
+        write_uint(&mut out, prog.metadata().len());
+        for (key, value) in prog.metadata() {
+            write_str(&mut out, key);
+            write_str(&mut out, value);
+        }
+        sections.push((SectionKind::Metadata, out));
+    }
+
+    let mut bytes = MAGIC.to_vec();
+    bytes.extend(VERSION_MAJOR.to_le_bytes());
+    bytes.extend(VERSION_MINOR.to_le_bytes());
+    bytes.extend((sections.len() as u32).to_le_bytes());
+    bytes.extend(crc32(&[&bytes]).to_le_bytes());
+    for (kind, payload) in sections {
+        let len = (payload.len() as u64).to_le_bytes();
+        bytes.push(kind.id());
+        bytes.extend(len);
+        bytes.extend(crc32(&[&[kind.id()], &len, &payload]).to_le_bytes());
+        bytes.extend(payload);
+    }
+    Ok(bytes)
+}
+
+pub(crate) fn read(file: &[u8]) -> Result<Program, ProgramParseError> {
+    let Some(mut bytes) = file.strip_prefix(&MAGIC) else {
+        return read_unversioned(file);
+    };
+    let major = u16::from_le_bytes(take(&mut bytes)?);
+    let minor = u16::from_le_bytes(take(&mut bytes)?);
+    if !(1..=VERSION_MAJOR).contains(&major) {
+        return Err(ProgramParseError::UnsupportedVersion { major, minor });
+    }
+    let section_cnt = u32::from_le_bytes(take(&mut bytes)?);
+    let header = &file[..file.len() - bytes.len()];
+    if major >= 4 && crc32(&[header]) != u32::from_le_bytes(take(&mut bytes)?) {
+        return Err(ProgramParseError::HeaderChecksumMismatch);
+    }
+
+    let mut prog = None;
+    let mut seen = vec![];
+    let mut rest = vec![];
+    for _ in 0..section_cnt {
+        let [id] = take(&mut bytes)?;
+        let len_bytes = take(&mut bytes)?;
+        let len = u64::from_le_bytes(len_bytes);
+        let crc = u32::from_le_bytes(take(&mut bytes)?);
+        let kind = SectionKind::from_id(id);
+        let payload = usize::try_from(len)
+            .ok()
+            .and_then(|len| bytes.get(..len))
+            .ok_or(match kind {
+                Some(kind) => ProgramParseError::TruncatedSection(kind),
+                None => ProgramParseError::InsufficientBytes,
+            })?;
+        bytes = &bytes[payload.len()..];
+        let checked: &[&[u8]] = match major {
+            1..=3 => &[payload],
+            _ => &[&[id], &len_bytes, payload],
+        };
+        if crc32(checked) != crc {
+            return Err(match kind {
+                Some(kind) => ProgramParseError::ChecksumMismatch(kind),
+                None => ProgramParseError::CorruptSection(id),
+            });
+        }
+
+        let Some(kind) = kind else {
+            if major == VERSION_MAJOR && minor > VERSION_MINOR {
+                continue;
+            }
+            return Err(ProgramParseError::UnknownSection(id));
+        };
+        if seen.contains(&kind) {
+            return Err(ProgramParseError::DuplicateSection(kind));
+        }
+        seen.push(kind);
+        if kind == SectionKind::Code {
+            let mut r = SectionReader::new(kind, major, payload);
+            let entry = r.usize()?;
+            let mut code = Program::new(entry);
+            let encoding = match major {
+                1 => Encoding::Fixed,
+                _ => Encoding::Compact,
+            };
+            for _ in 0..r.u64()? {
+                let func = Function::from_bytes_with_locals(&mut r.bytes, encoding, major >= 3)
+                    .map_err(|e| r.truncated_if_short(e))?;
+                code.add_func(func);
+            }
+            r.finish()?;
+            prog = Some(code);
+        } else {
+            rest.push((kind, payload));
+        }
+    }
+    if !bytes.is_empty() {
+        return Err(ProgramParseError::TrailingBytes);
+    }
+
+    let mut prog = prog.ok_or(ProgramParseError::MissingSection(SectionKind::Code))?;
+    for (kind, payload) in rest {
+        let mut r = SectionReader::new(kind, major, payload);
+        match kind {
+            SectionKind::Code => unreachable!(),
+            SectionKind::Constants => {
+                for _ in 0..r.u64()? {
+                    let value = match r.byte()? {
+                        0 => Value::Int(r.int()?),
+                        1 => match r.byte()? {
+                            0 => Value::Bool(false),
+                            1 => Value::Bool(true),
+                            _ => return Err(r.invalid()),
+                        },
+                        _ => return Err(r.invalid()),
+                    };
+                    prog.add_constant(value);
+                }
+            }
+            SectionKind::Imports => {
+                for _ in 0..r.u64()? {
+                    prog.add_import(Import {
+                        name: r.string()?,
+                        param_cnt: r.usize()?,
+                        ret_cnt: r.usize()?,
+                    });
+                }
+            }
+            SectionKind::Exports => {
+                for _ in 0..r.u64()? {
+                    prog.add_export(Export {
+                        name: r.string()?,
+                        func: r.usize()?,
+                    });
+                }
+            }
+            SectionKind::Debug => {
+                let source = r.string()?;
+                let mut func_names = vec![];
+                for _ in 0..r.u64()? {
+                    func_names.push(r.string()?);
+                }
+                prog.set_debug_info(DebugInfo { source, func_names });
+            }
+            SectionKind::Metadata => {
+                for _ in 0..r.u64()? {
+                    let key = r.string()?;
+                    prog.set_metadata(key, r.string()?);
+                }
+            }
+        }
+        r.finish()?;
+    }
+    Ok(prog)
+}
+
+/// The format written before the container existed.
+fn read_unversioned(mut bytes: &[u8]) -> Result<Program, ProgramParseError> {
+    let fn_cnt = u64::from_le_bytes(take(&mut bytes)?);
+    let entry = u64::from_le_bytes(take(&mut bytes)?);
+    let mut prog = Program::new(entry as usize);
+    for _ in 0..fn_cnt {
+        prog.add_func(Function::from_bytes(&mut bytes, Encoding::Fixed)?);
+    }
+    Ok(prog)
+}
+
+fn take<const N: usize>(bytes: &mut &[u8]) -> Result<[u8; N], ProgramParseError> {
+    let mut out = [0; N];
+    bytes
+        .read_exact(&mut out)
+        .map_err(|_| ProgramParseError::InsufficientBytes)?;
+    Ok(out)
+}
+
+/// Reads the payload of a section, reporting running out of bytes as a truncated section.
+struct SectionReader<'a> {
+    kind: SectionKind,
+    /// The major version of the file, integers are fixed width in version 1.
+    major: u16,
+    bytes: &'a [u8],
+}
+impl<'a> SectionReader<'a> {
+    fn new(kind: SectionKind, major: u16, bytes: &'a [u8]) -> Self {
+        Self { kind, major, bytes }
+    }
+
+    fn truncated_if_short(&self, e: ProgramParseError) -> ProgramParseError {
+        match e {
+            ProgramParseError::InsufficientBytes => ProgramParseError::TruncatedSection(self.kind),
+            e => e,
+        }
+    }
+
+    fn invalid(&self) -> ProgramParseError {
+        ProgramParseError::InvalidSection(self.kind)
+    }
+
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], ProgramParseError> {
+        take(&mut self.bytes).map_err(|e| self.truncated_if_short(e))
+    }
+
+    fn byte(&mut self) -> Result<u8, ProgramParseError> {
+        Ok(self.take::<1>()?[0])
+    }
+
+    fn u64(&mut self) -> Result<u64, ProgramParseError> {
+        match self.major {
+            1 => Ok(u64::from_le_bytes(self.take()?)),
+            _ => read_uleb(&mut self.bytes).map_err(|e| self.truncated_if_short(e)),
+        }
+    }
+
+    fn int(&mut self) -> Result<i64, ProgramParseError> {
+        match self.major {
+            1 => Ok(i64::from_le_bytes(self.take()?)),
+            _ => read_sleb(&mut self.bytes).map_err(|e| self.truncated_if_short(e)),
+        }
+    }
+
+    fn usize(&mut self) -> Result<usize, ProgramParseError> {
+        usize::try_from(self.u64()?).map_err(|_| self.invalid())
+    }
+
+    fn string(&mut self) -> Result<String, ProgramParseError> {
+        let len = self.usize()?;
+        let bytes = self
+            .bytes
+            .get(..len)
+            .ok_or(ProgramParseError::TruncatedSection(self.kind))?;
+        self.bytes = &self.bytes[len..];
+        String::from_utf8(bytes.to_vec()).map_err(|_| self.invalid())
+    }
+
+    /// Every byte of the payload must be read.
+    fn finish(self) -> Result<(), ProgramParseError> {
+        if self.bytes.is_empty() {
+            Ok(())
+        } else {
+            Err(self.invalid())
+        }
+    }
+}
+
+fn write_uint(out: &mut Vec<u8>, value: usize) {
+    write_uleb(out, value as u64);
+}
+
+fn write_str(out: &mut Vec<u8>, s: &str) {
+    write_uint(out, s.len());
+    out.extend(s.as_bytes());
+}
+
+/// CRC-32 as used by zip and png, of the parts one after the other.
+fn crc32(parts: &[&[u8]]) -> u32 {
+    let mut crc = !0u32;
+    for byte in parts.iter().copied().flatten() {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        heap::{Heap, Object},
+        Inst, VM,
+    };
+
+    fn program() -> Program {
+        let mut prog = Program::default();
+        let index = prog.add_constant(Value::Int(42));
+        prog.add_func(Function::from_insts([Inst::PushConst(index), Inst::Ret]));
+        prog
+    }
+
+    #[test]
+    fn round_trip() {
+        let bytes = write(&program()).unwrap();
+        let prog = read(&bytes).unwrap();
+        assert_eq!(prog.constants(), &[Value::Int(42)]);
+        assert_eq!(prog.funcs().len(), 1);
+    }
+
+    #[test]
+    fn ref_constant_is_an_encode_error() {
+        let mut prog = program();
+        let r = Heap::default().alloc(Object::Str("a".to_owned())).unwrap();
+        prog.add_constant(Value::Ref(r));
+        assert_eq!(write(&prog).unwrap_err(), EncodeError::RefConstant);
+    }
+
+    #[test]
+    fn corrupt_header_is_detected() {
+        let mut bytes = write(&program()).unwrap();
+        // the low byte of the section count.
+        bytes[MAGIC.len() + 4] ^= 1;
+        assert!(matches!(
+            read(&bytes),
+            Err(ProgramParseError::HeaderChecksumMismatch)
+        ));
+    }
+
+    #[test]
+    fn corrupt_section_header_is_detected() {
+        let bytes = write(&program()).unwrap();
+        let first_section = MAGIC.len() + 12;
+
+        let mut kind = bytes.clone();
+        kind[first_section] = match SectionKind::from_id(kind[first_section]) {
+            Some(SectionKind::Code) => SectionKind::Constants.id(),
+            _ => SectionKind::Code.id(),
+        };
+        assert!(matches!(
+            read(&kind),
+            Err(ProgramParseError::ChecksumMismatch(_))
+        ));
+
+        let mut unknown = bytes.clone();
+        unknown[first_section] = 0xff;
+        assert!(matches!(
+            read(&unknown),
+            Err(ProgramParseError::CorruptSection(0xff))
+        ));
+
+        let mut len = bytes;
+        len[first_section + 1] ^= 1;
+        assert!(matches!(
+            read(&len),
+            Err(ProgramParseError::ChecksumMismatch(_) | ProgramParseError::InsufficientBytes)
+        ));
+    }
+
+    // the same program written by the writers of earlier versions: a start function calling a
+    // function that stores 40 in a local, loads it and adds 2, with a constant and metadata.
+    const VERSION_1: &[u8] = &[
+        0, 119, 115, 107, 1, 0, 0, 0, 3, 0, 0, 0, 1, 112, 0, 0, 0, 0, 0, 0, 0, 11, 203, 164, 87, 0,
+        0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 64, 1, 0, 0, 0, 0, 0, 0, 0, 0, 6, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 1, 40, 0, 0, 0, 0, 0, 0, 0, 6, 0, 0, 0, 0, 0, 0, 0,
+        0, 5, 0, 0, 0, 0, 0, 0, 0, 0, 1, 2, 0, 0, 0, 0, 0, 0, 0, 16, 65, 2, 17, 0, 0, 0, 0, 0, 0,
+        0, 246, 61, 10, 72, 1, 0, 0, 0, 0, 0, 0, 0, 0, 253, 255, 255, 255, 255, 255, 255, 255, 6,
+        36, 0, 0, 0, 0, 0, 0, 0, 223, 157, 31, 254, 1, 0, 0, 0, 0, 0, 0, 0, 8, 0, 0, 0, 0, 0, 0, 0,
+        112, 114, 111, 100, 117, 99, 101, 114, 4, 0, 0, 0, 0, 0, 0, 0, 116, 101, 115, 116,
+    ];
+    const VERSION_2: &[u8] = &[
+        0, 119, 115, 107, 2, 0, 0, 0, 3, 0, 0, 0, 1, 20, 0, 0, 0, 0, 0, 0, 0, 238, 99, 238, 190, 0,
+        2, 2, 0, 0, 64, 1, 0, 6, 0, 1, 1, 40, 6, 0, 5, 0, 99, 16, 65, 2, 3, 0, 0, 0, 0, 0, 0, 0,
+        164, 190, 55, 208, 1, 0, 125, 6, 15, 0, 0, 0, 0, 0, 0, 0, 184, 138, 91, 67, 1, 8, 112, 114,
+        111, 100, 117, 99, 101, 114, 4, 116, 101, 115, 116,
+    ];
+    const VERSION_3: &[u8] = &[
+        0, 119, 115, 107, 3, 0, 0, 0, 3, 0, 0, 0, 1, 22, 0, 0, 0, 0, 0, 0, 0, 228, 65, 95, 8, 0, 2,
+        2, 0, 0, 0, 64, 1, 0, 6, 0, 1, 1, 1, 40, 6, 0, 5, 0, 99, 16, 65, 2, 3, 0, 0, 0, 0, 0, 0, 0,
+        164, 190, 55, 208, 1, 0, 125, 6, 15, 0, 0, 0, 0, 0, 0, 0, 184, 138, 91, 67, 1, 8, 112, 114,
+        111, 100, 117, 99, 101, 114, 4, 116, 101, 115, 116,
+    ];
+    /// Unversioned, with the signatures of functions.
+    const UNVERSIONED: &[u8] = &[
+        2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 64, 1, 0, 0, 0, 0, 0, 0, 0, 0, 6, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 1, 40, 0, 0, 0, 0, 0, 0, 0, 6, 0, 0, 0, 0, 0, 0,
+        0, 0, 5, 0, 0, 0, 0, 0, 0, 0, 0, 1, 2, 0, 0, 0, 0, 0, 0, 0, 16, 65,
+    ];
+
+    /// Unversioned, written before functions had signatures.
+    const BEFORE_SIGNATURES: &[u8] = &[
+        2, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 25, 0, 0, 0, 0, 0, 0, 0, 1, 15, 0, 0, 0, 0,
+        0, 0, 0, 6, 0, 0, 0, 0, 0, 0, 0, 0, 1, 2, 0, 0, 0, 0, 0, 0, 0, 6, 1, 0, 0, 0, 0, 0, 0, 0,
+        5, 1, 0, 0, 0, 0, 0, 0, 0, 5, 0, 0, 0, 0, 0, 0, 0, 0, 24, 33, 50, 3, 0, 0, 0, 0, 0, 0, 0,
+        1, 1, 0, 0, 0, 0, 0, 0, 0, 65, 5, 0, 0, 0, 0, 0, 0, 0, 0, 5, 1, 0, 0, 0, 0, 0, 0, 0, 20, 1,
+        0, 0, 0, 0, 0, 0, 0, 0, 23, 50, 3, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 65, 5,
+        1, 0, 0, 0, 0, 0, 0, 0, 1, 1, 0, 0, 0, 0, 0, 0, 0, 16, 6, 1, 0, 0, 0, 0, 0, 0, 0, 48, 237,
+        255, 255, 255, 255, 255, 255, 255, 65, 2, 0, 0, 0, 0, 0, 0, 0, 64, 0, 0, 0, 0, 0, 0, 0, 0,
+        0,
+    ];
+
+    fn run(prog: Program) -> Vec<Value> {
+        let mut vm = VM::default();
+        vm.execute(prog).unwrap();
+        vm.stack().to_vec()
+    }
+
+    #[test]
+    fn earlier_versions_are_read() {
+        for bytes in [VERSION_1, VERSION_2, VERSION_3] {
+            let prog = read(bytes).unwrap();
+            assert_eq!(prog.constants(), &[Value::Int(-3)]);
+            assert_eq!(
+                prog.metadata(),
+                [("producer".to_owned(), "test".to_owned())]
+            );
+            assert_eq!(prog.funcs()[1].local_cnt(), 1);
+            assert_eq!(run(prog), [Value::Int(42)]);
+        }
+    }
+
+    #[test]
+    fn unversioned_file_is_read() {
+        let prog = read(UNVERSIONED).unwrap();
+        assert_eq!(prog.funcs()[1].param_cnt(), 0);
+        assert_eq!(prog.funcs()[1].ret_cnt(), 1);
+        assert_eq!(run(prog), [Value::Int(42)]);
+    }
+
+    #[test]
+    fn corrupt_payload_of_earlier_version_is_detected() {
+        let mut bytes = VERSION_2.to_vec();
+        // the instruction count of the start function.
+        bytes[MAGIC.len() + 8 + 13 + 2] ^= 1;
+        assert!(matches!(
+            read(&bytes),
+            Err(ProgramParseError::ChecksumMismatch(SectionKind::Code))
+        ));
+    }
+
+    #[test]
+    fn newer_version_is_rejected() {
+        let mut bytes = write(&program()).unwrap();
+        bytes[MAGIC.len()] = VERSION_MAJOR as u8 + 1;
+        assert!(matches!(
+            read(&bytes),
+            Err(ProgramParseError::UnsupportedVersion { major, .. }) if major == VERSION_MAJOR + 1
+        ));
+    }
+
+    #[test]
+    fn file_without_signatures_is_not_read() {
+        assert!(read(BEFORE_SIGNATURES).is_err());
+    }
+
+    #[test]
+    fn committed_test_file_is_read() {
+        // `test/test.wsk`, 15 is not a prime.
+        let prog = read(include_bytes!("../../test/test.wc")).unwrap();
+        assert_eq!(run(prog), [Value::Int(0)]);
+    }
+}
@@ -0,0 +1,265 @@
+use std::{fmt::Display, mem::size_of};
+
+use crate::{value::Value, vm::VMError};
+
+/// A handle to an object living in the VM heap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct HeapRef(u32);
+impl HeapRef {
+    pub fn index(&self) -> usize {
+        self.0 as usize
+    }
+}
+impl Display for HeapRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ref@{}", self.0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Object {
+    Struct(Vec<Value>),
+    Array(Vec<Value>),
+    Str(String),
+}
+impl Object {
+    /// Approximated number of bytes the object occupies, used for heap accounting.
+    pub fn size(&self) -> usize {
+        size_of::<Self>()
+            + match self {
+                Object::Struct(fields) => fields.len() * size_of::<Value>(),
+                Object::Array(elems) => elems.len() * size_of::<Value>(),
+                Object::Str(s) => s.len(),
+            }
+    }
+
+    fn trace(&self, worklist: &mut Vec<HeapRef>) {
+        match self {
+            Object::Struct(values) | Object::Array(values) => {
+                worklist.extend(values.iter().filter_map(Value::as_heap_ref));
+            }
+            Object::Str(_) => (),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct HeapConfig {
+    /// Hard limit of live bytes, allocations beyond it fail with `VMError::OutOfMemory`.
+    pub max_bytes: usize,
+    /// Number of allocated bytes that triggers the first collection.
+    pub initial_threshold: usize,
+    /// The next collection threshold is the live bytes after a collection times this factor.
+    pub growth_factor: usize,
+}
+impl Default for HeapConfig {
+    fn default() -> Self {
+        Self {
+            max_bytes: 64 * 1024 * 1024,
+            initial_threshold: 1024 * 1024,
+            growth_factor: 2,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct GcStats {
+    pub collections: usize,
+    pub objects_allocated: usize,
+    pub objects_freed: usize,
+    pub bytes_allocated: usize,
+    pub bytes_freed: usize,
+    pub live_objects: usize,
+    pub live_bytes: usize,
+    pub peak_bytes: usize,
+}
+
+#[derive(Debug)]
+struct Slot {
+    obj: Object,
+    marked: bool,
+}
+
+#[derive(Debug)]
+pub struct Heap {
+    slots: Vec<Option<Slot>>,
+    free: Vec<u32>,
+    config: HeapConfig,
+    stats: GcStats,
+    next_gc: usize,
+}
+impl Default for Heap {
+    fn default() -> Self {
+        Self::new(HeapConfig::default())
+    }
+}
+impl Heap {
+    pub fn new(config: HeapConfig) -> Self {
+        Self {
+            slots: vec![],
+            free: vec![],
+            config,
+            stats: GcStats::default(),
+            next_gc: config.initial_threshold,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::new(self.config);
+    }
+
+    pub fn config(&self) -> &HeapConfig {
+        &self.config
+    }
+
+    pub fn stats(&self) -> &GcStats {
+        &self.stats
+    }
+
+    /// Returns true when the heap would like a collection before allocating `obj_size` more bytes.
+    pub fn should_collect(&self, obj_size: usize) -> bool {
+        self.stats.live_bytes + obj_size > self.next_gc
+    }
+
+    pub fn alloc(&mut self, obj: Object) -> Result<HeapRef, VMError> {
+        let size = obj.size();
+        if self.stats.live_bytes + size > self.config.max_bytes {
+            return Err(VMError::OutOfMemory);
+        }
+
+        let slot = Some(Slot { obj, marked: false });
+        let index = if let Some(index) = self.free.pop() {
+            self.slots[index as usize] = slot;
+            index
+        } else {
+            self.slots.push(slot);
+            (self.slots.len() - 1) as u32
+        };
+
+        self.stats.objects_allocated += 1;
+        self.stats.bytes_allocated += size;
+        self.stats.live_objects += 1;
+        self.stats.live_bytes += size;
+        self.stats.peak_bytes = self.stats.peak_bytes.max(self.stats.live_bytes);
+        Ok(HeapRef(index))
+    }
+
+    pub fn get(&self, r: HeapRef) -> Result<&Object, VMError> {
+        self.slots
+            .get(r.index())
+            .and_then(|v| v.as_ref())
+            .map(|v| &v.obj)
+            .ok_or(VMError::InvalidReference)
+    }
+
+    pub fn get_mut(&mut self, r: HeapRef) -> Result<&mut Object, VMError> {
+        self.slots
+            .get_mut(r.index())
+            .and_then(|v| v.as_mut())
+            .map(|v| &mut v.obj)
+            .ok_or(VMError::InvalidReference)
+    }
+
+    /// Mark every object reachable from `roots`, then sweep the rest.
+    pub fn collect(&mut self, roots: impl IntoIterator<Item = Value>) {
+        let mut worklist: Vec<HeapRef> =
+            roots.into_iter().filter_map(|v| v.as_heap_ref()).collect();
+        while let Some(r) = worklist.pop() {
+            let Some(Some(slot)) = self.slots.get_mut(r.index()) else {
+                continue;
+            };
+            if slot.marked {
+                continue;
+            }
+            slot.marked = true;
+            slot.obj.trace(&mut worklist);
+        }
+
+        for (index, entry) in self.slots.iter_mut().enumerate() {
+            let Some(slot) = entry else {
+                continue;
+            };
+            if slot.marked {
+                slot.marked = false;
+                continue;
+            }
+            let size = slot.obj.size();
+            *entry = None;
+            self.free.push(index as u32);
+            self.stats.objects_freed += 1;
+            self.stats.bytes_freed += size;
+            self.stats.live_objects -= 1;
+            self.stats.live_bytes -= size;
+        }
+
+        self.stats.collections += 1;
+        self.next_gc =
+            (self.stats.live_bytes * self.config.growth_factor).max(self.config.initial_threshold);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn array(values: &[Value]) -> Object {
+        Object::Array(values.to_vec())
+    }
+
+    #[test]
+    fn reachable_objects_survive_a_collection() {
+        let mut heap = Heap::default();
+        let inner = heap.alloc(Object::Str("inner".to_owned())).unwrap();
+        let outer = heap.alloc(array(&[inner.into()])).unwrap();
+        let garbage = heap.alloc(array(&[Value::Int(1)])).unwrap();
+
+        heap.collect([Value::Int(0), outer.into()]);
+        assert_eq!(heap.get(outer).unwrap(), &array(&[inner.into()]));
+        assert_eq!(heap.get(inner).unwrap(), &Object::Str("inner".to_owned()));
+        assert!(matches!(heap.get(garbage), Err(VMError::InvalidReference)));
+
+        // the slot of the freed object is reused.
+        let reused = heap.alloc(array(&[])).unwrap();
+        assert_eq!(reused, garbage);
+    }
+
+    #[test]
+    fn stats_count_allocations_and_collections() {
+        let mut heap = Heap::default();
+        let kept = heap.alloc(array(&[Value::Int(1), Value::Int(2)])).unwrap();
+        let freed = Object::Str("freed".to_owned());
+        let (kept_size, freed_size) = (heap.get(kept).unwrap().size(), freed.size());
+        heap.alloc(freed).unwrap();
+        heap.collect([kept.into()]);
+
+        assert_eq!(
+            *heap.stats(),
+            GcStats {
+                collections: 1,
+                objects_allocated: 2,
+                objects_freed: 1,
+                bytes_allocated: kept_size + freed_size,
+                bytes_freed: freed_size,
+                live_objects: 1,
+                live_bytes: kept_size,
+                peak_bytes: kept_size + freed_size,
+            }
+        );
+    }
+
+    #[test]
+    fn allocation_past_max_bytes_is_out_of_memory() {
+        let obj = array(&[Value::Int(0)]);
+        let mut heap = Heap::new(HeapConfig {
+            max_bytes: obj.size() * 2,
+            ..Default::default()
+        });
+        heap.alloc(obj.clone()).unwrap();
+        let last = heap.alloc(obj.clone()).unwrap();
+        assert!(matches!(heap.alloc(obj.clone()), Err(VMError::OutOfMemory)));
+
+        // collecting the garbage makes room again.
+        heap.collect([last.into()]);
+        heap.alloc(obj).unwrap();
+    }
+}
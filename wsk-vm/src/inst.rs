@@ -1,7 +1,9 @@
 use crate::{
+    asm::AsmError,
+    label::Jump,
     program::ProgramParseError,
     value::{OpError, Value},
-    vm::{VMError, VM},
+    vm::VMError,
 };
 
 #[derive(Debug, Clone, Copy)]
@@ -11,6 +13,8 @@ pub enum Inst {
     Pop,
     Load(usize),
     Store(usize),
+    /// Push the constant of the program's pool at the index.
+    PushConst(usize),
 
     Add,
     Sub,
@@ -30,56 +34,34 @@ pub enum Inst {
 
     Call(usize),
     Ret,
+
+    /// Pop the given number of values and pack them into a new struct object.
+    MakeStruct(usize),
+    /// Pop the given number of values and pack them into a new array object.
+    MakeArray(usize),
+    /// Pop the given number of integer code points and pack them into a new string object.
+    MakeStr(usize),
+    GetField(usize),
+    SetField(usize),
+    GetIndex,
+    SetIndex,
+    Len,
 }
-impl RunInst for Inst {
-    fn run(self, vm: &mut VM) -> Result<(), RunError> {
+impl Jump for Inst {
+    fn jump_offset(&self) -> Option<isize> {
         match self {
-            Self::Halt => vm.halt(),
-            Inst::Push(v) => vm.push(v),
-            Inst::Pop => vm.pop().map(|_| ())?,
-            Inst::Load(idx) => {
-                let v = vm.load(idx)?;
-                vm.push(v);
-            }
-            Inst::Store(idx) => {
-                let v = vm.pop()?;
-                vm.store(idx, v);
-            }
-
-            Inst::Add => impl_macros::binary_op!(vm, +),
-            Inst::Sub => impl_macros::binary_op!(vm, -),
-            Inst::Mul => impl_macros::binary_op!(vm, *),
-            Inst::Div => impl_macros::binary_op!(vm, /),
-            Inst::Mod => impl_macros::binary_op!(vm, %),
-            Inst::And => impl_macros::binary_op!(vm, &),
-            Inst::Or => impl_macros::binary_op!(vm, |),
-            Inst::Cmp(cmp) => cmp.run(vm)?,
-
-            Inst::Neg => impl_macros::unary_op!(vm, -),
-            Inst::Not => impl_macros::unary_op!(vm, !),
-
-            Inst::Jmp(offset) => vm.jump(offset),
-            Inst::JmpTrue(offset) => {
-                let Value::Bool(cond) = vm.pop()? else {
-                    return Err(OpError::InvalidTypeForOp.into());
-                };
-                if cond {
-                    vm.jump(offset);
-                }
-            }
-            Inst::JmpFalse(offset) => {
-                let Value::Bool(cond) = vm.pop()? else {
-                    return Err(OpError::InvalidTypeForOp.into());
-                };
-                if !cond {
-                    vm.jump(offset);
-                }
-            }
+            Inst::Jmp(offset) | Inst::JmpTrue(offset) | Inst::JmpFalse(offset) => Some(*offset),
+            _ => None,
+        }
+    }
 
-            Inst::Call(fi) => vm.call(fi),
-            Inst::Ret => vm.ret()?,
-        };
-        Ok(())
+    fn with_jump_offset(self, offset: isize) -> Self {
+        match self {
+            Inst::Jmp(_) => Inst::Jmp(offset),
+            Inst::JmpTrue(_) => Inst::JmpTrue(offset),
+            Inst::JmpFalse(_) => Inst::JmpFalse(offset),
+            inst => panic!("{:?} is not a jump", inst),
+        }
     }
 }
 
@@ -89,30 +71,21 @@ pub enum Cmp {
     Less,
     Greater,
 }
-impl RunInst for Cmp {
-    fn run(self, vm: &mut VM) -> Result<(), RunError> {
+impl Cmp {
+    /// Compare two values, only integers are ordered.
+    pub fn eval(self, lhs: Value, rhs: Value) -> Result<bool, OpError> {
         match self {
-            Cmp::Equal => {
-                let rhs = vm.pop()?;
-                let lhs = vm.pop()?;
-                vm.push((lhs == rhs).into());
-            }
+            Cmp::Equal => Ok(lhs == rhs),
             Cmp::Less | Cmp::Greater => {
-                let rhs = vm.pop()?;
-                let lhs = vm.pop()?;
-                let ord = match (lhs, rhs) {
-                    (Value::Int(lhs), Value::Int(rhs)) => PartialOrd::partial_cmp(&lhs, &rhs),
-                    _ => return Err(OpError::InvalidTypeForOp.into()),
+                let (Value::Int(lhs), Value::Int(rhs)) = (lhs, rhs) else {
+                    return Err(OpError::InvalidTypeForOp);
                 };
-                let yes = match self {
-                    Cmp::Less => matches!(ord, Some(std::cmp::Ordering::Less)),
-                    Cmp::Greater => matches!(ord, Some(std::cmp::Ordering::Greater)),
-                    _ => unreachable!(),
-                };
-                vm.push(yes.into());
+                Ok(match self {
+                    Cmp::Less => lhs < rhs,
+                    _ => lhs > rhs,
+                })
             }
-        };
-        Ok(())
+        }
     }
 }
 
@@ -121,13 +94,10 @@ pub enum RunError {
     VMError(VMError),
     OpError(OpError),
     ParseError(ProgramParseError),
+    AsmError(AsmError),
     MissingSourcefile,
 }
 
-pub trait RunInst {
-    fn run(self, vm: &mut VM) -> Result<(), RunError>;
-}
-
 impl From<Cmp> for Inst {
     fn from(value: Cmp) -> Self {
         Self::Cmp(value)
@@ -150,21 +120,8 @@ impl From<ProgramParseError> for RunError {
     }
 }
 
-mod impl_macros {
-    macro_rules! binary_op {
-    ($vm:expr, $sym:tt) => {{
-        let rhs = $vm.pop()?;
-        let lhs = $vm.pop()?;
-        $vm.push((lhs $sym rhs)?);
-    }};
-}
-    macro_rules! unary_op {
-    ($vm:expr, $sym:tt) => {{
-        let val = $vm.pop()?;
-        $vm.push(($sym val)?);
-    }};
-}
-
-    pub(super) use binary_op;
-    pub(super) use unary_op;
+impl From<AsmError> for RunError {
+    fn from(value: AsmError) -> Self {
+        Self::AsmError(value)
+    }
 }
@@ -2,32 +2,65 @@ use std::{io::Read, mem::size_of};
 
 use crate::{
     inst::{Cmp, Inst},
-    program::ProgramParseError,
+    program::{EncodeError, ProgramParseError},
     value::Value,
 };
 
+/// How instruction operands are written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    /// Every operand takes 8 little endian bytes, the format of files before the container.
+    Fixed,
+    /// Operands are LEB128 varints and small integers have opcodes of their own.
+    Compact,
+}
+
+/// The integers pushed by the one byte opcodes starting at [`PUSH_SMALL`].
+const SMALL_INTS: std::ops::RangeInclusive<i64> = -1..=14;
+const PUSH_SMALL: u8 = 0x60;
+
 impl Inst {
-    pub fn encode(self, out: &mut Vec<u8>) {
+    /// Write the instruction, heap references have no encoding as they only exist at runtime.
+    pub fn encode(self, out: &mut Vec<u8>, encoding: Encoding) -> Result<(), EncodeError> {
+        let operand = |out: &mut Vec<u8>, v: usize| match encoding {
+            Encoding::Fixed => out.extend(v.to_le_bytes()),
+            Encoding::Compact => write_uleb(out, v as u64),
+        };
+        let offset = |out: &mut Vec<u8>, v: isize| match encoding {
+            Encoding::Fixed => out.extend(v.to_le_bytes()),
+            Encoding::Compact => write_sleb(out, v as i64),
+        };
         match self {
             Inst::Halt => out.push(0x00),
             Inst::Push(value) => match value {
+                Value::Int(v) if encoding == Encoding::Compact && SMALL_INTS.contains(&v) => {
+                    out.push(PUSH_SMALL + (v - SMALL_INTS.start()) as u8);
+                }
                 Value::Int(v) => {
                     out.push(0x01);
-                    out.extend(v.to_le_bytes());
+                    match encoding {
+                        Encoding::Fixed => out.extend(v.to_le_bytes()),
+                        Encoding::Compact => write_sleb(out, v),
+                    }
                 }
                 Value::Bool(v) => match v {
                     true => out.push(0x02),
                     false => out.push(0x03),
                 },
+                Value::Ref(_) => return Err(EncodeError::RefConstant),
             },
             Inst::Pop => out.push(0x04),
             Inst::Load(i) => {
                 out.push(0x05);
-                out.extend(i.to_le_bytes());
+                operand(out, i);
             }
             Inst::Store(i) => {
                 out.push(0x06);
-                out.extend(i.to_le_bytes());
+                operand(out, i);
+            }
+            Inst::PushConst(i) => {
+                out.push(0x07);
+                operand(out, i);
             }
             Inst::Add => out.push(0x10),
             Inst::Sub => out.push(0x11),
@@ -43,24 +76,48 @@ impl Inst {
             },
             Inst::Neg => out.push(0x20),
             Inst::Not => out.push(0x21),
-            Inst::Jmp(offset) => {
+            Inst::Jmp(v) => {
                 out.push(0x30);
-                out.extend(offset.to_le_bytes());
+                offset(out, v);
             }
-            Inst::JmpTrue(offset) => {
+            Inst::JmpTrue(v) => {
                 out.push(0x31);
-                out.extend(offset.to_le_bytes());
+                offset(out, v);
             }
-            Inst::JmpFalse(offset) => {
+            Inst::JmpFalse(v) => {
                 out.push(0x32);
-                out.extend(offset.to_le_bytes());
+                offset(out, v);
             }
             Inst::Call(fi) => {
                 out.push(0x40);
-                out.extend(fi.to_le_bytes());
+                operand(out, fi);
             }
             Inst::Ret => out.push(0x41),
+            Inst::MakeStruct(cnt) => {
+                out.push(0x50);
+                operand(out, cnt);
+            }
+            Inst::MakeArray(cnt) => {
+                out.push(0x51);
+                operand(out, cnt);
+            }
+            Inst::MakeStr(cnt) => {
+                out.push(0x52);
+                operand(out, cnt);
+            }
+            Inst::GetField(i) => {
+                out.push(0x53);
+                operand(out, i);
+            }
+            Inst::SetField(i) => {
+                out.push(0x54);
+                operand(out, i);
+            }
+            Inst::GetIndex => out.push(0x55),
+            Inst::SetIndex => out.push(0x56),
+            Inst::Len => out.push(0x57),
         }
+        Ok(())
     }
 
     fn next_bytes<const N: usize>(it: &mut &[u8]) -> Result<[u8; N], ProgramParseError>
@@ -68,37 +125,42 @@ impl Inst {
         [u8; N]: Default,
     {
         let mut le_bytes: [u8; N] = Default::default();
-        it.read(&mut le_bytes)
+        it.read_exact(&mut le_bytes)
             .map_err(|_| ProgramParseError::InsufficientBytes)?;
         Ok(le_bytes)
     }
 
-    pub fn decode(bytes: &mut &[u8]) -> Result<Self, ProgramParseError> {
+    pub fn decode(bytes: &mut &[u8], encoding: Encoding) -> Result<Self, ProgramParseError> {
         const USIZE_BYTES: usize = size_of::<usize>();
         const ISIZE_BYTES: usize = size_of::<isize>();
         const I64_BYTES: usize = size_of::<i64>();
 
+        let operand = |bytes: &mut &[u8]| match encoding {
+            Encoding::Fixed => Self::next_bytes::<USIZE_BYTES>(bytes).map(usize::from_le_bytes),
+            Encoding::Compact => read_usize(bytes),
+        };
+        let offset = |bytes: &mut &[u8]| match encoding {
+            Encoding::Fixed => Self::next_bytes::<ISIZE_BYTES>(bytes).map(isize::from_le_bytes),
+            Encoding::Compact => read_sleb(bytes)
+                .and_then(|v| isize::try_from(v).map_err(|_| ProgramParseError::InvalidVarint)),
+        };
+
         let mut byte: [u8; 1] = [0];
         bytes
-            .read(&mut byte)
+            .read_exact(&mut byte)
             .map_err(|_| ProgramParseError::InsufficientBytes)?;
         Ok(match byte[0] {
             0x00 => Inst::Halt,
-            0x01 => {
-                let int_bytes = Self::next_bytes::<I64_BYTES>(bytes)?;
-                Inst::Push(i64::from_le_bytes(int_bytes).into())
-            }
+            0x01 => Inst::Push(Value::Int(match encoding {
+                Encoding::Fixed => i64::from_le_bytes(Self::next_bytes::<I64_BYTES>(bytes)?),
+                Encoding::Compact => read_sleb(bytes)?,
+            })),
             0x02 => Inst::Push(true.into()),
             0x03 => Inst::Push(false.into()),
             0x04 => Inst::Pop,
-            0x05 => {
-                let index_bytes = Self::next_bytes::<USIZE_BYTES>(bytes)?;
-                Inst::Load(usize::from_le_bytes(index_bytes))
-            }
-            0x06 => {
-                let index_bytes = Self::next_bytes::<USIZE_BYTES>(bytes)?;
-                Inst::Store(usize::from_le_bytes(index_bytes))
-            }
+            0x05 => Inst::Load(operand(bytes)?),
+            0x06 => Inst::Store(operand(bytes)?),
+            0x07 => Inst::PushConst(operand(bytes)?),
             0x10 => Inst::Add,
             0x11 => Inst::Sub,
             0x12 => Inst::Mul,
@@ -111,26 +173,201 @@ impl Inst {
             0x19 => Cmp::Greater.into(),
             0x20 => Inst::Neg,
             0x21 => Inst::Not,
-            0x30 => {
-                let index_bytes = Self::next_bytes::<ISIZE_BYTES>(bytes)?;
-                Inst::Jmp(isize::from_le_bytes(index_bytes))
-            }
-            0x31 => {
-                let index_bytes = Self::next_bytes::<ISIZE_BYTES>(bytes)?;
-                Inst::JmpTrue(isize::from_le_bytes(index_bytes))
+            0x30 => Inst::Jmp(offset(bytes)?),
+            0x31 => Inst::JmpTrue(offset(bytes)?),
+            0x32 => Inst::JmpFalse(offset(bytes)?),
+            0x40 => Inst::Call(operand(bytes)?),
+            0x41 => Inst::Ret,
+            0x50 => Inst::MakeStruct(operand(bytes)?),
+            0x51 => Inst::MakeArray(operand(bytes)?),
+            0x52 => Inst::MakeStr(operand(bytes)?),
+            0x53 => Inst::GetField(operand(bytes)?),
+            0x54 => Inst::SetField(operand(bytes)?),
+            0x55 => Inst::GetIndex,
+            0x56 => Inst::SetIndex,
+            0x57 => Inst::Len,
+            op if encoding == Encoding::Compact
+                && (PUSH_SMALL..PUSH_SMALL + SMALL_INTS.clone().count() as u8).contains(&op) =>
+            {
+                Inst::Push(Value::Int(SMALL_INTS.start() + (op - PUSH_SMALL) as i64))
             }
-            0x32 => {
-                let index_bytes = Self::next_bytes::<ISIZE_BYTES>(bytes)?;
-                Inst::JmpFalse(isize::from_le_bytes(index_bytes))
+            op => return Err(ProgramParseError::UnknownOpcode(op)),
+        })
+    }
+
+    /// Number of bytes of the instruction in the encoding.
+    pub fn encoded_len(self, encoding: Encoding) -> Result<usize, EncodeError> {
+        let mut out = vec![];
+        self.encode(&mut out, encoding)?;
+        Ok(out.len())
+    }
+}
+
+pub(crate) fn write_uleb(out: &mut Vec<u8>, mut v: u64) {
+    loop {
+        let byte = (v & 0x7f) as u8;
+        v >>= 7;
+        if v == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+pub(crate) fn write_sleb(out: &mut Vec<u8>, mut v: i64) {
+    loop {
+        let byte = (v & 0x7f) as u8;
+        v >>= 7;
+        // done when the rest is only copies of the sign bit of this byte.
+        if (v == 0 && byte & 0x40 == 0) || (v == -1 && byte & 0x40 != 0) {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+pub(crate) fn read_uleb(bytes: &mut &[u8]) -> Result<u64, ProgramParseError> {
+    let mut v = 0u64;
+    let mut shift = 0;
+    loop {
+        let [byte] = Inst::next_bytes::<1>(bytes)?;
+        if shift == 63 && byte > 1 {
+            return Err(ProgramParseError::InvalidVarint);
+        }
+        v |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(v);
+        }
+        shift += 7;
+        if shift > 63 {
+            return Err(ProgramParseError::InvalidVarint);
+        }
+    }
+}
+
+pub(crate) fn read_usize(bytes: &mut &[u8]) -> Result<usize, ProgramParseError> {
+    usize::try_from(read_uleb(bytes)?).map_err(|_| ProgramParseError::InvalidVarint)
+}
+
+pub(crate) fn read_sleb(bytes: &mut &[u8]) -> Result<i64, ProgramParseError> {
+    let mut v = 0i64;
+    let mut shift = 0;
+    loop {
+        let [byte] = Inst::next_bytes::<1>(bytes)?;
+        if shift == 63 && byte != 0 && byte != 0x7f {
+            return Err(ProgramParseError::InvalidVarint);
+        }
+        v |= ((byte & 0x7f) as i64) << shift;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            if shift < 64 && byte & 0x40 != 0 {
+                v |= -1 << shift;
             }
-            0x40 => {
-                let index_bytes = Self::next_bytes::<USIZE_BYTES>(bytes)?;
-                Inst::Call(usize::from_le_bytes(index_bytes))
+            return Ok(v);
+        }
+        if shift > 63 {
+            return Err(ProgramParseError::InvalidVarint);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unsigned_leb128_edges() {
+        for (v, len) in [
+            (0, 1),
+            (127, 1),
+            (128, 2),
+            (16383, 2),
+            (16384, 3),
+            (u64::MAX, 10),
+        ] {
+            let mut out = vec![];
+            write_uleb(&mut out, v);
+            assert_eq!(out.len(), len, "{}", v);
+            assert_eq!(read_uleb(&mut out.as_slice()).unwrap(), v);
+        }
+        let mut out = vec![];
+        write_uleb(&mut out, 128);
+        assert_eq!(out, [0x80, 0x01]);
+    }
+
+    #[test]
+    fn signed_leb128_edges() {
+        for (v, len) in [
+            (0, 1),
+            (63, 1),
+            (64, 2),
+            (-1, 1),
+            (-64, 1),
+            (-65, 2),
+            (i64::MAX, 10),
+            (i64::MIN, 10),
+        ] {
+            let mut out = vec![];
+            write_sleb(&mut out, v);
+            assert_eq!(out.len(), len, "{}", v);
+            assert_eq!(read_sleb(&mut out.as_slice()).unwrap(), v);
+        }
+        let mut out = vec![];
+        write_sleb(&mut out, -128);
+        assert_eq!(out, [0x80, 0x7f]);
+    }
+
+    #[test]
+    fn overlong_and_truncated_varints_are_rejected() {
+        // 11 bytes, more than a u64 holds.
+        let mut bytes = [0xff; 10].to_vec();
+        bytes.push(0x01);
+        assert!(matches!(
+            read_uleb(&mut bytes.as_slice()),
+            Err(ProgramParseError::InvalidVarint)
+        ));
+        assert!(matches!(
+            read_sleb(&mut bytes.as_slice()),
+            Err(ProgramParseError::InvalidVarint)
+        ));
+        assert!(matches!(
+            read_uleb(&mut [0x80].as_slice()),
+            Err(ProgramParseError::InsufficientBytes)
+        ));
+    }
+
+    #[test]
+    fn instructions_round_trip_in_both_encodings() {
+        let insts = [
+            Inst::Halt,
+            Inst::Push(Value::Int(-1)),
+            Inst::Push(Value::Int(14)),
+            Inst::Push(Value::Int(15)),
+            Inst::Push(Value::Int(i64::MIN)),
+            Inst::Push(Value::Bool(true)),
+            Inst::PushConst(300),
+            Inst::Load(0),
+            Inst::Store(128),
+            Inst::Cmp(Cmp::Greater),
+            Inst::Jmp(-129),
+            Inst::JmpFalse(64),
+            Inst::Call(usize::MAX),
+            Inst::MakeStr(3),
+            Inst::Len,
+        ];
+        for encoding in [Encoding::Fixed, Encoding::Compact] {
+            let mut bytes = vec![];
+            for inst in insts {
+                inst.encode(&mut bytes, encoding).unwrap();
             }
-            0x41 => Inst::Ret,
-            _ => {
-                unimplemented!("unimplemented inst byte {:#04x}", byte[0]);
+            let mut rest = bytes.as_slice();
+            for inst in insts {
+                let decoded = Inst::decode(&mut rest, encoding).unwrap();
+                assert_eq!(format!("{:?}", decoded), format!("{:?}", inst));
             }
-        })
+            assert!(rest.is_empty());
+        }
     }
 }
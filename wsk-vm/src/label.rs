@@ -0,0 +1,99 @@
+//! Jumps to labels in functions under construction, shared by the builders of the stack VM and the
+//! register VM.
+
+use std::fmt::{self, Display};
+
+use crate::program::JumpOutOfBounds;
+
+/// An instruction set with jumps by an offset relative to the jump itself.
+pub trait Jump: Copy + fmt::Debug {
+    /// The offset of a jump, `None` for the other instructions.
+    fn jump_offset(&self) -> Option<isize>;
+
+    /// The same jump with another offset.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the instruction is not a jump.
+    fn with_jump_offset(self, offset: isize) -> Self;
+}
+
+/// A position in a function under construction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Label(usize);
+
+/// Instructions with jumps to labels, the offsets of the jumps are resolved by
+/// [`LabeledInsts::resolve`] once every label is bound.
+#[derive(Debug, Clone)]
+pub(crate) struct LabeledInsts<I> {
+    pub insts: Vec<I>,
+    /// Position of every label, `None` until it is bound.
+    labels: Vec<Option<usize>>,
+    /// Jumps to patch once their labels are bound.
+    fixups: Vec<(usize, Label)>,
+}
+impl<I> Default for LabeledInsts<I> {
+    fn default() -> Self {
+        Self {
+            insts: vec![],
+            labels: vec![],
+            fixups: vec![],
+        }
+    }
+}
+impl<I: Jump> LabeledInsts<I> {
+    pub fn new_label(&mut self) -> Label {
+        self.labels.push(None);
+        Label(self.labels.len() - 1)
+    }
+
+    /// Place the label at the next instruction pushed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the label was already bound.
+    pub fn bind_label(&mut self, label: Label) {
+        let pos = &mut self.labels[label.0];
+        assert!(pos.is_none(), "label bound twice");
+        *pos = Some(self.insts.len());
+    }
+
+    /// Push a jump, its offset is replaced by the one to the label.
+    pub fn push_jump(&mut self, inst: I, label: Label) {
+        self.fixups.push((self.insts.len(), label));
+        self.insts.push(inst);
+    }
+
+    pub fn resolve(mut self) -> Result<Vec<I>, BuildError> {
+        for (at, label) in self.fixups {
+            let target = self.labels[label.0].ok_or(BuildError::UnboundLabel(label))?;
+            let offset = target as isize - at as isize;
+            // a label bound after the last instruction is not in the function.
+            if target >= self.insts.len() {
+                return Err(JumpOutOfBounds { at, offset }.into());
+            }
+            self.insts[at] = self.insts[at].with_jump_offset(offset);
+        }
+        Ok(self.insts)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuildError {
+    /// A jump to a label that was never bound.
+    UnboundLabel(Label),
+    JumpOutOfBounds(JumpOutOfBounds),
+}
+impl From<JumpOutOfBounds> for BuildError {
+    fn from(value: JumpOutOfBounds) -> Self {
+        Self::JumpOutOfBounds(value)
+    }
+}
+impl Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildError::UnboundLabel(label) => write!(f, "jump to unbound label {}", label.0),
+            BuildError::JumpOutOfBounds(e) => e.fmt(f),
+        }
+    }
+}
@@ -1,9 +1,18 @@
+pub mod asm;
+pub mod container;
+pub mod heap;
 pub mod inst;
 pub mod inst_code;
+pub mod label;
+pub mod peephole;
 pub mod program;
+pub mod reg;
 pub mod value;
+pub mod verify;
 pub mod vm;
 
+pub use heap::{GcStats, HeapConfig};
 pub use inst::{Cmp, Inst, RunError};
+pub use label::Jump;
 pub use value::Value;
 pub use vm::{VMError, VM};
@@ -1,27 +1,64 @@
 use std::env;
 use std::fs;
 
-use wsk_vm::program::Program;
+use wsk_vm::asm;
+use wsk_vm::program::{Program, ProgramParseError};
+use wsk_vm::reg;
+use wsk_vm::verify::verify;
 use wsk_vm::RunError;
 use wsk_vm::VM;
 
 fn main() -> Result<(), RunError> {
     let args: Vec<String> = env::args().collect();
-    if args.len() < 2 {
+    let flag = |name: &str| args[1..].iter().any(|arg| arg == name);
+    let (disasm, no_verify) = (flag("--disasm"), flag("--no-verify"));
+    let Some(path) = args[1..].iter().find(|arg| !arg.starts_with("--")) else {
         return Err(RunError::MissingSourcefile);
-    }
+    };
 
-    let bytes = fs::read(args[1].clone()).unwrap();
-    let program = Program::from_bytes(&bytes)?;
+    // `.wska` files are assembly text, anything else is a binary of either VM.
+    let program = if path.ends_with(".wska") {
+        asm::assemble(&fs::read_to_string(path).unwrap())?
+    } else {
+        let bytes = fs::read(path).unwrap();
+        if bytes.starts_with(&reg::MAGIC) {
+            return run_reg(&reg::Program::from_bytes(&bytes)?, disasm);
+        }
+        Program::from_bytes_unverified(&bytes)?
+    };
+    if !no_verify {
+        verify(&program).map_err(ProgramParseError::from)?;
+    }
+    if disasm {
+        print!("{}", asm::disassemble(&program));
+        return Ok(());
+    }
     println!("{}", program);
 
     let mut vm = VM::default();
-    vm.execute(program).map_err(|e| {
+    vm.execute(program).inspect_err(|_| {
         eprintln!("{:#?}", vm);
-        e
     })?;
 
     println!("{:#?}", vm);
 
     Ok(())
 }
+
+/// Register programs are checked by their VM before running, so `--no-verify` does not apply.
+fn run_reg(program: &reg::Program, disasm: bool) -> Result<(), RunError> {
+    if disasm {
+        print!("{}", program);
+        return Ok(());
+    }
+    println!("{}", program);
+
+    let mut vm = reg::VM::default();
+    vm.execute(program).inspect_err(|_| {
+        eprintln!("{:#?}", vm);
+    })?;
+
+    println!("{:?}", vm.results());
+
+    Ok(())
+}
@@ -0,0 +1,292 @@
+//! The form of functions run by [`crate::vm::VM`]. Instructions are lowered to operations with
+//! absolute jump targets and constants of the pool in place, and a peephole pass fuses common
+//! sequences into superinstructions, so the VM dispatches once for the whole sequence.
+
+use crate::{inst::Cmp, program::Program, vm::VMError, Inst, Jump, Value};
+
+/// An operation of a lowered function. Jump targets are operation indices.
+#[derive(Debug, Clone, Copy)]
+pub enum Op {
+    Halt,
+    Push(Value),
+    Pop,
+    Load(usize),
+    Store(usize),
+
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    And,
+    Or,
+    Cmp(Cmp),
+
+    Neg,
+    Not,
+
+    Jmp(usize),
+    JmpTrue(usize),
+    JmpFalse(usize),
+
+    Call(usize),
+    Ret,
+
+    MakeStruct(usize),
+    MakeArray(usize),
+    MakeStr(usize),
+    GetField(usize),
+    SetField(usize),
+    GetIndex,
+    SetIndex,
+    Len,
+
+    /// `load a`, `load b`, `add`.
+    LoadLoadAdd(usize, usize),
+    /// `push v`, `cmp`.
+    PushCmp(Value, Cmp),
+    /// `cmp`, then a jump taken when the comparison is the given bool, from `jtr`, `jfl` or
+    /// either of them after a `not`.
+    CmpJmp(Cmp, bool, usize),
+    /// `push v` followed by the instructions of [`Op::CmpJmp`].
+    PushCmpJmp(Value, Cmp, bool, usize),
+}
+
+/// Lower every function of the program, fusing superinstructions when `fuse` is set.
+pub fn lower(program: &Program, fuse: bool) -> Result<Vec<Vec<Op>>, VMError> {
+    // reuse the buffers between functions.
+    let mut scratch = Scratch::default();
+    program
+        .funcs()
+        .iter()
+        .map(|func| scratch.lower_function(program, func.get_insts(), fuse))
+        .collect()
+}
+
+#[derive(Default)]
+struct Scratch {
+    insts: Vec<Inst>,
+    is_target: Vec<bool>,
+    /// The operation every instruction ends up in.
+    op_at: Vec<usize>,
+}
+impl Scratch {
+    fn lower_function(
+        &mut self,
+        program: &Program,
+        insts: &[Inst],
+        fuse: bool,
+    ) -> Result<Vec<Op>, VMError> {
+        let Self {
+            insts: resolved,
+            is_target,
+            op_at,
+        } = self;
+        resolved.clear();
+        for inst in insts {
+            resolved.push(match *inst {
+                Inst::PushConst(index) => Inst::Push(
+                    *program
+                        .constants()
+                        .get(index)
+                        .ok_or(VMError::InvalidConstantIndex)?,
+                ),
+                inst => inst,
+            });
+        }
+        let insts = &resolved[..];
+        is_target.clear();
+        is_target.resize(insts.len(), false);
+        for (at, inst) in insts.iter().enumerate() {
+            if let Some(offset) = inst.jump_offset() {
+                *is_target
+                    .get_mut(at.wrapping_add_signed(offset))
+                    .ok_or(VMError::InstReadOutOfBound)? = true;
+            }
+        }
+
+        // the operations, with jump targets still instruction indices.
+        let mut ops = Vec::with_capacity(insts.len());
+        op_at.clear();
+        op_at.resize(insts.len(), 0);
+        let mut at = 0;
+        while at < insts.len() {
+            let (op, len) = fuse
+                .then(|| fused(&insts[at..], &is_target[at..], at))
+                .flatten()
+                .unwrap_or_else(|| (lower_inst(insts[at], at), 1));
+            op_at[at..at + len].fill(ops.len());
+            ops.push(op);
+            at += len;
+        }
+
+        for op in &mut ops {
+            match op {
+                Op::Jmp(target)
+                | Op::JmpTrue(target)
+                | Op::JmpFalse(target)
+                | Op::CmpJmp(_, _, target)
+                | Op::PushCmpJmp(_, _, _, target) => *target = op_at[*target],
+                _ => {}
+            }
+        }
+        Ok(ops)
+    }
+}
+fn lower_inst(inst: Inst, at: usize) -> Op {
+    let target = |offset| at.wrapping_add_signed(offset);
+    match inst {
+        Inst::Halt => Op::Halt,
+        Inst::Push(v) => Op::Push(v),
+        Inst::PushConst(_) => unreachable!("constants are pushed in place"),
+        Inst::Pop => Op::Pop,
+        Inst::Load(slot) => Op::Load(slot),
+        Inst::Store(slot) => Op::Store(slot),
+        Inst::Add => Op::Add,
+        Inst::Sub => Op::Sub,
+        Inst::Mul => Op::Mul,
+        Inst::Div => Op::Div,
+        Inst::Mod => Op::Mod,
+        Inst::And => Op::And,
+        Inst::Or => Op::Or,
+        Inst::Cmp(cmp) => Op::Cmp(cmp),
+        Inst::Neg => Op::Neg,
+        Inst::Not => Op::Not,
+        Inst::Jmp(offset) => Op::Jmp(target(offset)),
+        Inst::JmpTrue(offset) => Op::JmpTrue(target(offset)),
+        Inst::JmpFalse(offset) => Op::JmpFalse(target(offset)),
+        Inst::Call(fi) => Op::Call(fi),
+        Inst::Ret => Op::Ret,
+        Inst::MakeStruct(cnt) => Op::MakeStruct(cnt),
+        Inst::MakeArray(cnt) => Op::MakeArray(cnt),
+        Inst::MakeStr(cnt) => Op::MakeStr(cnt),
+        Inst::GetField(index) => Op::GetField(index),
+        Inst::SetField(index) => Op::SetField(index),
+        Inst::GetIndex => Op::GetIndex,
+        Inst::SetIndex => Op::SetIndex,
+        Inst::Len => Op::Len,
+    }
+}
+
+/// The superinstruction starting the instructions at `at` and the number of instructions it
+/// replaces, preferring the longest.
+fn fused(insts: &[Inst], is_target: &[bool], at: usize) -> Option<(Op, usize)> {
+    // an instruction jumped to cannot be inside a superinstruction.
+    let fits = |len: usize| !is_target[1..len].contains(&true);
+    // the jump ending a comparison starting at `first`: the bool it jumps on, its target and its
+    // number of instructions.
+    let cmp_jump = |first: usize| {
+        let target = |at: usize, offset| at.wrapping_add_signed(offset);
+        match insts[first - at..] {
+            [Inst::JmpFalse(offset), ..] => Some((false, target(first, offset), 1)),
+            [Inst::JmpTrue(offset), ..] => Some((true, target(first, offset), 1)),
+            [Inst::Not, Inst::JmpFalse(offset), ..] => Some((true, target(first + 1, offset), 2)),
+            [Inst::Not, Inst::JmpTrue(offset), ..] => Some((false, target(first + 1, offset), 2)),
+            _ => None,
+        }
+        .filter(|&(_, _, len)| fits(first - at + len))
+    };
+
+    match *insts {
+        [Inst::Push(v), Inst::Cmp(cmp), ..] if fits(2) => Some(
+            cmp_jump(at + 2)
+                .map(|(on, target, len)| (Op::PushCmpJmp(v, cmp, on, target), len + 2))
+                .unwrap_or((Op::PushCmp(v, cmp), 2)),
+        ),
+        [Inst::Cmp(cmp), ..] => {
+            cmp_jump(at + 1).map(|(on, target, len)| (Op::CmpJmp(cmp, on, target), len + 1))
+        }
+        [Inst::Load(a), Inst::Load(b), Inst::Add, ..] if fits(3) => {
+            Some((Op::LoadLoadAdd(a, b), 3))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{asm::assemble, VM};
+
+    fn lower_main(src: &str) -> Vec<Op> {
+        lower(&assemble(src).unwrap(), true).unwrap().remove(0)
+    }
+
+    #[test]
+    fn fusions_stop_at_jump_targets() {
+        type IsFused = fn(&Op) -> bool;
+        let cases: [(&str, IsFused); 3] = [
+            ("load r0\n {}load r0\n add\n jmp x", |op| {
+                matches!(op, Op::LoadLoadAdd(..))
+            }),
+            ("push 1\n {}cmp lt\n jmp x", |op| {
+                matches!(op, Op::PushCmp(..))
+            }),
+            ("load r0\n load r0\n cmp lt\n {}jfl x", |op| {
+                matches!(op, Op::CmpJmp(..))
+            }),
+        ];
+        for (body, is_fused) in cases {
+            let inside = format!("func main 0 -> 0\n {}", body.replace("{}", "x:\n "));
+            assert!(!lower_main(&inside).iter().any(is_fused), "{}", inside);
+            let before = format!("func main 0 -> 0\nx:\n {}", body.replace("{}", ""));
+            assert!(lower_main(&before).iter().any(is_fused), "{}", before);
+        }
+    }
+
+    #[test]
+    fn fused_and_plain_runs_agree() {
+        let src = "
+            func start 0 -> 0
+                call main
+                halt
+            func main 0 -> 3
+                push 0
+                store r0
+                push 0
+                store r1
+            top:
+                load r0
+                push 4
+                cmp lt
+                not
+                jtr done
+                load r1
+                load r0
+                add
+                store r1
+                load r0
+                push 1
+                add
+                store r0
+                load r0
+                load r1
+                cmp gt
+                jfl top
+                jmp top
+            done:
+                load r1
+                push 6
+                cmp equ
+                load r1
+                load r0
+                add
+                load r0
+                load r1
+                cmp lt
+                ret
+        ";
+        let mut results = vec![];
+        for fuse in [false, true] {
+            let mut vm = VM::default();
+            vm.set_superinstructions(fuse);
+            vm.execute(assemble(src).unwrap()).unwrap();
+            results.push(vm.stack().to_vec());
+        }
+        assert_eq!(
+            results[0],
+            [Value::Bool(true), Value::Int(10), Value::Bool(true)]
+        );
+        assert_eq!(results[0], results[1]);
+    }
+}
@@ -1,11 +1,24 @@
-use std::{fmt::Display, io::Read, mem::size_of};
+use std::{collections::HashMap, fmt::Display, io::Read, mem::size_of};
 
-use crate::{Cmp, Inst};
+use crate::{
+    container::{self, SectionKind},
+    inst_code::{read_uleb, read_usize, write_uleb, Encoding},
+    label::{Jump, LabeledInsts},
+    verify::{verify, VerifyError},
+    Cmp, Inst, Value,
+};
+
+pub use crate::label::{BuildError, Label};
 
 #[derive(Debug, Clone)]
 pub struct Program {
     funcs: Vec<Function>,
     entry_point: usize,
+    constants: Vec<Value>,
+    imports: Vec<Import>,
+    exports: Vec<Export>,
+    debug_info: Option<DebugInfo>,
+    metadata: Vec<(String, String)>,
 }
 impl Default for Program {
     fn default() -> Self {
@@ -17,31 +30,24 @@ impl Program {
         Self {
             funcs: vec![],
             entry_point,
+            constants: vec![],
+            imports: vec![],
+            exports: vec![],
+            debug_info: None,
+            metadata: vec![],
         }
     }
 
-    pub fn from_bytes(mut bytes: &[u8]) -> Result<Self, ProgramParseError> {
-        const U64_SIZE: usize = size_of::<u64>();
-        let (fn_cnt, entry_fi) = {
-            let mut header_bytes: [u8; U64_SIZE * 2] = Default::default();
-            bytes
-                .read(&mut header_bytes)
-                .map_err(|_| ProgramParseError::InsufficientBytes)?;
-            (
-                u64::from_le_bytes(header_bytes[0..U64_SIZE].try_into().unwrap()),
-                u64::from_le_bytes(header_bytes[U64_SIZE..].try_into().unwrap()),
-            )
-        };
-
-        let mut funcs = Vec::new();
-        for _ in 0..fn_cnt {
-            funcs.push(Function::from_bytes(&mut bytes)?);
-        }
+    /// Read a program and [`verify`] it, the bytes may come from anywhere.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ProgramParseError> {
+        let prog = Self::from_bytes_unverified(bytes)?;
+        verify(&prog)?;
+        Ok(prog)
+    }
 
-        Ok(Self {
-            funcs,
-            entry_point: entry_fi as usize,
-        })
+    /// Read a program without verifying it, for bytes from a trusted source.
+    pub fn from_bytes_unverified(bytes: &[u8]) -> Result<Self, ProgramParseError> {
+        container::read(bytes)
     }
 
     pub fn set_entry_point(&mut self, index: usize) {
@@ -50,6 +56,7 @@ impl Program {
     }
 
     pub fn add_func(&mut self, func: Function) -> usize {
+        debug_assert!(func.check_jumps().is_ok(), "jump out of the function");
         let id = self.funcs.len();
         self.funcs.push(func);
         id
@@ -67,74 +74,281 @@ impl Program {
         self.entry_point
     }
 
-    pub fn to_bin(&self) -> Vec<u8> {
-        let mut bytes = Vec::new();
+    pub fn funcs(&self) -> &[Function] {
+        &self.funcs
+    }
+
+    pub fn add_constant(&mut self, value: Value) -> usize {
+        self.constants.push(value);
+        self.constants.len() - 1
+    }
 
-        // func count
-        bytes.extend((self.funcs.len() as u64).to_le_bytes());
+    pub fn constants(&self) -> &[Value] {
+        &self.constants
+    }
 
-        // entry fi
-        bytes.extend((self.entry_point as u64).to_le_bytes());
+    /// Move integers pushed often enough into the constant pool, where it makes the compact
+    /// encoding of the program smaller. Integers used most get the smallest indices.
+    pub fn pool_constants(&mut self) {
+        // in the order of the first push, which breaks ties of the sort below.
+        let mut uses: Vec<(i64, usize)> = vec![];
+        let mut use_index: HashMap<i64, usize> = HashMap::new();
+        for inst in self.funcs.iter().flat_map(|v| &v.insts) {
+            if let Inst::Push(Value::Int(v)) = *inst {
+                let i = *use_index.entry(v).or_insert_with(|| {
+                    uses.push((v, 0));
+                    uses.len() - 1
+                });
+                uses[i].1 += 1;
+            }
+        }
+        let inline_len = |v: i64| {
+            Inst::Push(Value::Int(v))
+                .encoded_len(Encoding::Compact)
+                .expect("integers can be encoded")
+        };
+        uses.sort_by_key(|&(v, cnt)| std::cmp::Reverse(cnt * inline_len(v)));
 
-        for func in &self.funcs {
-            func.to_bin(&mut bytes);
+        let mut pool_index: HashMap<i64, usize> = HashMap::new();
+        for (i, c) in self.constants.iter().enumerate() {
+            if let Value::Int(v) = *c {
+                pool_index.entry(v).or_insert(i);
+            }
+        }
+        let mut pooled = HashMap::new();
+        for (v, cnt) in uses {
+            let existing = pool_index.get(&v).copied();
+            let index = existing.unwrap_or(self.constants.len());
+            // the entry of the pool takes a tag byte in place of the opcode.
+            let entry_len = if existing.is_some() { 0 } else { inline_len(v) };
+            let pooled_len = cnt
+                * Inst::PushConst(index)
+                    .encoded_len(Encoding::Compact)
+                    .expect("pool indices can be encoded");
+            if pooled_len + entry_len < cnt * inline_len(v) {
+                if existing.is_none() {
+                    self.constants.push(Value::Int(v));
+                }
+                pooled.insert(v, index);
+            }
+        }
+        for inst in self.funcs.iter_mut().flat_map(|v| &mut v.insts) {
+            if let Inst::Push(Value::Int(v)) = *inst {
+                if let Some(&index) = pooled.get(&v) {
+                    *inst = Inst::PushConst(index);
+                }
+            }
         }
+    }
+
+    pub fn add_import(&mut self, import: Import) {
+        self.imports.push(import);
+    }
 
-        bytes
+    pub fn imports(&self) -> &[Import] {
+        &self.imports
     }
+
+    pub fn add_export(&mut self, export: Export) {
+        self.exports.push(export);
+    }
+
+    pub fn exports(&self) -> &[Export] {
+        &self.exports
+    }
+
+    pub fn set_debug_info(&mut self, debug_info: DebugInfo) {
+        self.debug_info = Some(debug_info);
+    }
+
+    pub fn debug_info(&self) -> Option<&DebugInfo> {
+        self.debug_info.as_ref()
+    }
+
+    /// Set a metadata entry, replacing the value of an existing key.
+    pub fn set_metadata(&mut self, key: impl Into<String>, value: impl Into<String>) {
+        let (key, value) = (key.into(), value.into());
+        match self.metadata.iter_mut().find(|(k, _)| *k == key) {
+            Some(entry) => entry.1 = value,
+            None => self.metadata.push((key, value)),
+        }
+    }
+
+    pub fn metadata(&self) -> &[(String, String)] {
+        &self.metadata
+    }
+
+    /// Write the program in the `.wc` container format, see [`container`].
+    pub fn to_bin(&self) -> Result<Vec<u8>, EncodeError> {
+        container::write(self)
+    }
+}
+
+/// A host function the program expects to be given.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Import {
+    pub name: String,
+    pub param_cnt: usize,
+    pub ret_cnt: usize,
+}
+
+/// A function of the program the host may call by name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Export {
+    pub name: String,
+    pub func: usize,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct DebugInfo {
+    /// Name of the module or source file the program was compiled from.
+    pub source: String,
+    /// Name of every function, indexed like the functions.
+    pub func_names: Vec<String>,
 }
 
 impl Display for Program {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         writeln!(f, "entry: ${}\n", self.entry_point)?;
         for (i, func) in self.funcs.iter().enumerate() {
-            writeln!(f, "func ${}:\n{}", i, func)?;
+            writeln!(
+                f,
+                "func ${} ({} -> {}, {} locals):\n{}",
+                i, func.param_cnt, func.ret_cnt, func.local_cnt, func
+            )?;
         }
         Ok(())
     }
 }
 
+/// The most parameters, return values or local slots a function can have.
+pub const MAX_SLOTS: usize = u16::MAX as usize;
+
 #[derive(Debug, Default, Clone)]
 pub struct Function {
     insts: Vec<Inst>,
+    /// Number of values the caller pushes before calling the function.
+    param_cnt: usize,
+    /// Number of values left on the caller's stack when the function returns.
+    ret_cnt: usize,
+    /// Number of local slots of a frame of the function, the locals are `r0` up to it.
+    local_cnt: usize,
 }
 impl Function {
-    pub fn from_insts(insts: impl IntoIterator<Item = Inst>) -> Self {
+    pub fn new(param_cnt: usize, ret_cnt: usize) -> Self {
         Self {
-            insts: Vec::from_iter(insts),
+            insts: vec![],
+            param_cnt,
+            ret_cnt,
+            local_cnt: 0,
         }
     }
 
-    pub fn from_bytes(bytes: &mut &[u8]) -> Result<Self, ProgramParseError> {
-        const U64_SIZE: usize = size_of::<u64>();
-        let inst_cnt = {
-            let mut header_bytes: [u8; U64_SIZE] = Default::default();
-            bytes
-                .read(&mut header_bytes)
-                .map_err(|_| ProgramParseError::InsufficientBytes)?;
-            u64::from_le_bytes(header_bytes)
+    pub fn from_insts(insts: impl IntoIterator<Item = Inst>) -> Self {
+        let mut func = Self::default();
+        func.push_insts(insts);
+        func
+    }
+
+    pub fn from_bytes(bytes: &mut &[u8], encoding: Encoding) -> Result<Self, ProgramParseError> {
+        Self::from_bytes_with_locals(bytes, encoding, encoding == Encoding::Compact)
+    }
+
+    /// Read a function whose header has the number of local slots only if `has_local_cnt`, as the
+    /// compact encoding of version 2 of the container has not. Without it, the count is found from
+    /// the instructions.
+    pub(crate) fn from_bytes_with_locals(
+        bytes: &mut &[u8],
+        encoding: Encoding,
+        has_local_cnt: bool,
+    ) -> Result<Self, ProgramParseError> {
+        let (inst_cnt, param_cnt, ret_cnt) = match encoding {
+            Encoding::Fixed => {
+                const U64_SIZE: usize = size_of::<u64>();
+                let mut header_bytes: [u8; U64_SIZE * 3] = [0; U64_SIZE * 3];
+                bytes
+                    .read_exact(&mut header_bytes)
+                    .map_err(|_| ProgramParseError::InsufficientBytes)?;
+                let field = |i: usize| {
+                    u64::from_le_bytes(
+                        header_bytes[U64_SIZE * i..U64_SIZE * (i + 1)]
+                            .try_into()
+                            .unwrap(),
+                    )
+                };
+                (field(0), field(1) as usize, field(2) as usize)
+            }
+            Encoding::Compact => (read_uleb(bytes)?, read_usize(bytes)?, read_usize(bytes)?),
+        };
+        let local_cnt = match has_local_cnt {
+            true => Some(read_usize(bytes)?),
+            false => None,
         };
 
         let mut insts = Vec::new();
 
         for _ in 0..inst_cnt {
-            let inst = Inst::decode(bytes)?;
+            let inst = Inst::decode(bytes, encoding)?;
             insts.push(inst);
         }
 
-        Ok(Self { insts })
+        let local_cnt = local_cnt.unwrap_or_else(|| slots_used(&insts));
+        // the VM and the verifier size frames by these counts, reject them before they do.
+        if let Some(&cnt) = [param_cnt, ret_cnt, local_cnt]
+            .iter()
+            .find(|v| **v > MAX_SLOTS)
+        {
+            return Err(ProgramParseError::TooManySlots(cnt));
+        }
+        let func = Self {
+            insts,
+            param_cnt,
+            ret_cnt,
+            local_cnt,
+        };
+        func.check_jumps()?;
+        Ok(func)
     }
 
-    pub fn push_inst(&mut self, inst: impl Into<Inst>) {
-        self.insts.push(inst.into());
+    /// Check that every jump lands on an instruction of the function.
+    pub fn check_jumps(&self) -> Result<(), JumpOutOfBounds> {
+        for (at, inst) in self.insts.iter().enumerate() {
+            let Some(offset) = inst.jump_offset() else {
+                continue;
+            };
+            match at.checked_add_signed(offset) {
+                Some(target) if target < self.insts.len() => {}
+                _ => return Err(JumpOutOfBounds { at, offset }),
+            }
+        }
+        Ok(())
     }
 
-    pub fn push_insts(&mut self, insts: impl IntoIterator<Item = Inst>) {
-        self.insts.extend(insts);
+    pub fn param_cnt(&self) -> usize {
+        self.param_cnt
     }
 
-    pub fn insert_inst(&mut self, idx: usize, inst: impl Into<Inst>) {
-        self.insts.insert(idx, inst.into());
+    pub fn ret_cnt(&self) -> usize {
+        self.ret_cnt
+    }
+
+    pub fn local_cnt(&self) -> usize {
+        self.local_cnt
+    }
+
+    /// Reserve at least `cnt` local slots. Pushing instructions reserves the slots they use.
+    pub fn reserve_locals(&mut self, cnt: usize) {
+        self.local_cnt = self.local_cnt.max(cnt);
+    }
+
+    pub fn push_inst(&mut self, inst: impl Into<Inst>) {
+        let inst = inst.into();
+        self.reserve_locals(slots_used(&[inst]));
+        self.insts.push(inst);
+    }
+
+    pub fn push_insts(&mut self, insts: impl IntoIterator<Item = Inst>) {
+        insts.into_iter().for_each(|v| self.push_inst(v));
     }
 
     pub fn len(&self) -> usize {
@@ -153,13 +367,30 @@ impl Function {
         &self.insts
     }
 
-    pub fn to_bin(&self, out: &mut Vec<u8>) {
-        // inst count
-        out.extend((self.insts.len() as u64).to_le_bytes());
+    pub fn to_bin(&self, out: &mut Vec<u8>, encoding: Encoding) -> Result<(), EncodeError> {
+        // inst count and signature, then the local count the fixed encoding has no room for.
+        match encoding {
+            Encoding::Fixed => {
+                for field in [self.insts.len(), self.param_cnt, self.ret_cnt] {
+                    out.extend((field as u64).to_le_bytes());
+                }
+            }
+            Encoding::Compact => {
+                for field in [
+                    self.insts.len(),
+                    self.param_cnt,
+                    self.ret_cnt,
+                    self.local_cnt,
+                ] {
+                    write_uleb(out, field as u64);
+                }
+            }
+        }
 
         for inst in &self.insts {
-            inst.encode(out);
+            inst.encode(out, encoding)?;
         }
+        Ok(())
     }
 }
 impl Display for Function {
@@ -170,6 +401,7 @@ impl Display for Function {
                 Inst::Push(value) => format!("push\t\t{}", value),
                 Inst::Pop => "pop".to_owned(),
                 Inst::Load(offset) => format!("load\t\tr{}", offset),
+                Inst::PushConst(index) => format!("pushc\t\t#{}", index),
                 Inst::Store(offset) => format!("store\t\tr{}", offset),
                 Inst::Add => "add".to_owned(),
                 Inst::Sub => "sub".to_owned(),
@@ -193,6 +425,14 @@ impl Display for Function {
                 Inst::JmpFalse(offset) => format!("jfl\t\t{}:", i.wrapping_add_signed(*offset)),
                 Inst::Call(fi) => format!("call\t\t${}", fi),
                 Inst::Ret => "ret".to_owned(),
+                Inst::MakeStruct(cnt) => format!("mkstruct\t{}", cnt),
+                Inst::MakeArray(cnt) => format!("mkarr\t\t{}", cnt),
+                Inst::MakeStr(cnt) => format!("mkstr\t\t{}", cnt),
+                Inst::GetField(i) => format!("getf\t\t{}", i),
+                Inst::SetField(i) => format!("setf\t\t{}", i),
+                Inst::GetIndex => "getidx".to_owned(),
+                Inst::SetIndex => "setidx".to_owned(),
+                Inst::Len => "len".to_owned(),
             };
 
             writeln!(f, "\t{:>4}:\t{}", i, inst_str)?;
@@ -201,7 +441,380 @@ impl Display for Function {
     }
 }
 
+/// Builds a [`Function`] from instructions and jumps to labels, the offsets of the jumps are
+/// resolved by [`FunctionBuilder::build`] once every label is bound.
+#[derive(Debug, Default, Clone)]
+pub struct FunctionBuilder {
+    code: LabeledInsts<Inst>,
+    param_cnt: usize,
+    ret_cnt: usize,
+}
+impl FunctionBuilder {
+    pub fn new(param_cnt: usize, ret_cnt: usize) -> Self {
+        Self {
+            param_cnt,
+            ret_cnt,
+            ..Default::default()
+        }
+    }
+
+    pub fn new_label(&mut self) -> Label {
+        self.code.new_label()
+    }
+
+    /// See [`LabeledInsts::bind_label`].
+    pub fn bind_label(&mut self, label: Label) {
+        self.code.bind_label(label);
+    }
+
+    pub fn push(&mut self, inst: impl Into<Inst>) {
+        self.code.insts.push(inst.into());
+    }
+
+    pub fn push_insts(&mut self, insts: impl IntoIterator<Item = Inst>) {
+        self.code.insts.extend(insts);
+    }
+
+    pub fn jmp(&mut self, label: Label) {
+        self.code.push_jump(Inst::Jmp(0), label);
+    }
+
+    pub fn jmp_true(&mut self, label: Label) {
+        self.code.push_jump(Inst::JmpTrue(0), label);
+    }
+
+    pub fn jmp_false(&mut self, label: Label) {
+        self.code.push_jump(Inst::JmpFalse(0), label);
+    }
+
+    /// Number of instructions pushed so far, the position of the next one.
+    pub fn len(&self) -> usize {
+        self.code.insts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.code.insts.is_empty()
+    }
+
+    pub fn build(self) -> Result<Function, BuildError> {
+        let insts = self.code.resolve()?;
+        let func = Function {
+            local_cnt: slots_used(&insts),
+            insts,
+            param_cnt: self.param_cnt,
+            ret_cnt: self.ret_cnt,
+        };
+        func.check_jumps()?;
+        Ok(func)
+    }
+}
+
+/// Number of local slots needed by the loads and stores of the instructions.
+pub(crate) fn slots_used(insts: &[Inst]) -> usize {
+    insts
+        .iter()
+        .filter_map(|inst| match *inst {
+            Inst::Load(slot) | Inst::Store(slot) => Some(slot.saturating_add(1)),
+            _ => None,
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+/// A jump whose target is not an instruction of its function.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JumpOutOfBounds {
+    /// Index of the jump.
+    pub at: usize,
+    pub offset: isize,
+}
+impl Display for JumpOutOfBounds {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "jump at {} with offset {} leaves the function",
+            self.at, self.offset
+        )
+    }
+}
+
+/// Why a program cannot be written to bytes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EncodeError {
+    /// A heap reference pushed as a constant, references only exist while the program runs.
+    RefConstant,
+}
+impl Display for EncodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EncodeError::RefConstant => write!(f, "heap references cannot be encoded as constants"),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum ProgramParseError {
     InsufficientBytes,
+    /// A container of another major version.
+    UnsupportedVersion {
+        major: u16,
+        minor: u16,
+    },
+    /// A register program of another version, see [`crate::reg::Program::from_bytes`].
+    UnsupportedRegisterVersion(u16),
+    /// A file read as a register program that does not start with [`crate::reg::MAGIC`].
+    NotARegisterProgram,
+    UnknownSection(u8),
+    DuplicateSection(SectionKind),
+    MissingSection(SectionKind),
+    /// The payload of the section is shorter than its content.
+    TruncatedSection(SectionKind),
+    /// The checksum of the section, covering its header and payload, does not match.
+    ChecksumMismatch(SectionKind),
+    /// A section of an unknown kind whose checksum does not match, its kind may be what is corrupt.
+    CorruptSection(u8),
+    /// The checksum of the magic bytes, version and section count does not match.
+    HeaderChecksumMismatch,
+    /// The payload of the section is malformed or longer than its content.
+    InvalidSection(SectionKind),
+    /// Bytes after the last section.
+    TrailingBytes,
+    UnknownOpcode(u8),
+    /// A varint that is too long or does not fit its operand.
+    InvalidVarint,
+    /// A function with more parameters, return values or local slots than [`MAX_SLOTS`].
+    TooManySlots(usize),
+    JumpOutOfBounds(JumpOutOfBounds),
+    Invalid(VerifyError),
+}
+impl Display for ProgramParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProgramParseError::InsufficientBytes => write!(f, "unexpected end of the file"),
+            ProgramParseError::UnsupportedVersion { major, minor } => write!(
+                f,
+                "format version {}.{} is not supported, expected {}.x",
+                major,
+                minor,
+                container::VERSION_MAJOR
+            ),
+            ProgramParseError::UnsupportedRegisterVersion(version) => write!(
+                f,
+                "register program version {} is not supported, expected {}",
+                version,
+                crate::reg::VERSION
+            ),
+            ProgramParseError::NotARegisterProgram => write!(f, "not a register program"),
+            ProgramParseError::UnknownSection(id) => write!(f, "unknown section kind {}", id),
+            ProgramParseError::DuplicateSection(kind) => write!(f, "duplicate {} section", kind),
+            ProgramParseError::MissingSection(kind) => write!(f, "missing {} section", kind),
+            ProgramParseError::TruncatedSection(kind) => write!(f, "truncated {} section", kind),
+            ProgramParseError::ChecksumMismatch(kind) => {
+                write!(f, "checksum mismatch in the {} section", kind)
+            }
+            ProgramParseError::CorruptSection(id) => {
+                write!(f, "checksum mismatch in a section of unknown kind {}", id)
+            }
+            ProgramParseError::HeaderChecksumMismatch => {
+                write!(f, "checksum mismatch in the header")
+            }
+            ProgramParseError::InvalidSection(kind) => write!(f, "malformed {} section", kind),
+            ProgramParseError::TrailingBytes => write!(f, "unexpected bytes after the sections"),
+            ProgramParseError::UnknownOpcode(op) => write!(f, "unknown opcode {:#04x}", op),
+            ProgramParseError::InvalidVarint => write!(f, "invalid varint"),
+            ProgramParseError::TooManySlots(cnt) => {
+                write!(f, "function with {} slots, the limit is {}", cnt, MAX_SLOTS)
+            }
+            ProgramParseError::JumpOutOfBounds(e) => e.fmt(f),
+            ProgramParseError::Invalid(e) => write!(f, "invalid program: {}", e),
+        }
+    }
+}
+impl From<JumpOutOfBounds> for ProgramParseError {
+    fn from(value: JumpOutOfBounds) -> Self {
+        Self::JumpOutOfBounds(value)
+    }
+}
+impl From<VerifyError> for ProgramParseError {
+    fn from(value: VerifyError) -> Self {
+        Self::Invalid(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::verify::VerifyErrorKind;
+
+    #[test]
+    fn oversized_local_count_is_rejected() {
+        let mut func = Function::from_insts([Inst::Ret]);
+        func.reserve_locals(1 << 61);
+        let mut prog = Program::default();
+        prog.add_func(func);
+
+        let bytes = prog.to_bin().unwrap();
+        assert!(matches!(
+            Program::from_bytes(&bytes),
+            Err(ProgramParseError::TooManySlots(cnt)) if cnt == 1 << 61
+        ));
+        assert_eq!(
+            verify(&prog).unwrap_err().kind,
+            VerifyErrorKind::TooManySlots(1 << 61)
+        );
+    }
+
+    #[test]
+    fn oversized_slot_of_unversioned_file_is_rejected() {
+        // one function as the entry point, of one instruction, no parameters and no results.
+        let mut bytes = vec![];
+        for field in [1u64, 0, 1, 0, 0] {
+            bytes.extend(field.to_le_bytes());
+        }
+        Inst::Load(1 << 61)
+            .encode(&mut bytes, Encoding::Fixed)
+            .unwrap();
+        assert!(matches!(
+            Program::from_bytes(&bytes),
+            Err(ProgramParseError::TooManySlots(_))
+        ));
+
+        bytes.truncate(bytes.len() - size_of::<usize>());
+        bytes.extend(usize::MAX.to_le_bytes());
+        assert!(matches!(
+            Program::from_bytes(&bytes),
+            Err(ProgramParseError::TooManySlots(_))
+        ));
+    }
+
+    fn offsets(func: &Function) -> Vec<Option<isize>> {
+        func.get_insts().iter().map(|v| v.jump_offset()).collect()
+    }
+
+    #[test]
+    fn builder_resolves_forward_and_backward_labels() {
+        let mut b = FunctionBuilder::new(0, 0);
+        let (top, end) = (b.new_label(), b.new_label());
+        b.bind_label(top);
+        b.push(Inst::Push(Value::Bool(true)));
+        // used before it is bound.
+        b.jmp_false(end);
+        b.push(Inst::Push(Value::Bool(false)));
+        b.jmp_true(end);
+        b.jmp(top);
+        b.bind_label(end);
+        b.push(Inst::Halt);
+
+        let func = b.build().unwrap();
+        assert_eq!(
+            offsets(&func),
+            [None, Some(4), None, Some(2), Some(-4), None]
+        );
+    }
+
+    #[test]
+    fn builder_rejects_unbound_labels() {
+        let mut b = FunctionBuilder::new(0, 0);
+        let (bound, unbound) = (b.new_label(), b.new_label());
+        b.bind_label(bound);
+        b.jmp(bound);
+        b.jmp(unbound);
+        assert_eq!(b.build().unwrap_err(), BuildError::UnboundLabel(unbound));
+
+        // a label bound after the last instruction is not in the function.
+        let mut b = FunctionBuilder::new(0, 0);
+        let end = b.new_label();
+        b.jmp(end);
+        b.bind_label(end);
+        assert_eq!(
+            b.build().unwrap_err(),
+            BuildError::JumpOutOfBounds(JumpOutOfBounds { at: 0, offset: 1 })
+        );
+    }
+
+    fn pushes(prog: &Program) -> Vec<String> {
+        prog.funcs()[0]
+            .get_insts()
+            .iter()
+            .map(|v| format!("{:?}", v))
+            .collect()
+    }
+
+    #[test]
+    fn pool_constants_pools_repeated_large_integers() {
+        let mut prog = Program::default();
+        prog.add_func(Function::from_insts([
+            Inst::Push(Value::Int(1 << 40)),
+            Inst::Push(Value::Int(1 << 40)),
+            Inst::Push(Value::Int(1 << 40)),
+            // small integers have opcodes of their own and one push does not pay for an entry.
+            Inst::Push(Value::Int(3)),
+            Inst::Push(Value::Int(3)),
+            Inst::Push(Value::Int(1 << 50)),
+            Inst::Halt,
+        ]));
+        prog.pool_constants();
+        assert_eq!(prog.constants(), [Value::Int(1 << 40)]);
+        assert_eq!(
+            pushes(&prog)[..6],
+            [
+                "PushConst(0)",
+                "PushConst(0)",
+                "PushConst(0)",
+                "Push(Int(3))",
+                "Push(Int(3))",
+                "Push(Int(1125899906842624))",
+            ]
+        );
+    }
+
+    #[test]
+    fn pool_constants_reuses_entries() {
+        let mut prog = Program::default();
+        prog.add_constant(Value::Bool(true));
+        prog.add_constant(Value::Int(1 << 40));
+        // even a single push is smaller as an entry already in the pool.
+        prog.add_func(Function::from_insts([
+            Inst::Push(Value::Int(1 << 40)),
+            Inst::Halt,
+        ]));
+        prog.pool_constants();
+        assert_eq!(prog.constants(), [Value::Bool(true), Value::Int(1 << 40)]);
+        assert_eq!(pushes(&prog)[0], "PushConst(1)");
+
+        // pooling again changes nothing.
+        let bytes = prog.to_bin().unwrap();
+        prog.pool_constants();
+        assert_eq!(prog.to_bin().unwrap(), bytes);
+    }
+
+    #[test]
+    fn functions_round_trip_in_both_encodings() {
+        let mut func = Function::new(2, 1);
+        func.push_insts([
+            Inst::Load(1),
+            Inst::Push(Value::Int(-300)),
+            Inst::Add,
+            Inst::JmpTrue(2),
+            Inst::Store(4),
+            Inst::Ret,
+        ]);
+        func.reserve_locals(6);
+        for encoding in [Encoding::Fixed, Encoding::Compact] {
+            let mut bytes = vec![];
+            func.to_bin(&mut bytes, encoding).unwrap();
+            let back = Function::from_bytes(&mut bytes.as_slice(), encoding).unwrap();
+            assert_eq!((back.param_cnt(), back.ret_cnt()), (2, 1));
+            assert_eq!(
+                format!("{:?}", back.get_insts()),
+                format!("{:?}", func.get_insts())
+            );
+            // the fixed encoding has no local count, only the slots used are found.
+            let local_cnt = match encoding {
+                Encoding::Fixed => 5,
+                Encoding::Compact => 6,
+            };
+            assert_eq!(back.local_cnt(), local_cnt);
+        }
+    }
 }
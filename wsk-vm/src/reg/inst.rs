@@ -0,0 +1,84 @@
+use crate::{inst::Cmp, label::Jump, value::Value};
+
+use super::Reg;
+
+/// An instruction of the register machine, the destination register comes first.
+#[derive(Debug, Clone, Copy)]
+pub enum Inst {
+    Halt,
+    Const(Reg, Value),
+    Move(Reg, Reg),
+
+    Add(Reg, Reg, Reg),
+    Sub(Reg, Reg, Reg),
+    Mul(Reg, Reg, Reg),
+    Div(Reg, Reg, Reg),
+    Mod(Reg, Reg, Reg),
+    And(Reg, Reg, Reg),
+    Or(Reg, Reg, Reg),
+    Cmp(Cmp, Reg, Reg, Reg),
+
+    Neg(Reg, Reg),
+    Not(Reg, Reg),
+
+    Jmp(isize),
+    JmpTrue(Reg, isize),
+    JmpFalse(Reg, isize),
+
+    /// Call the function with its arguments in the registers from the given one up, which then
+    /// receive its results.
+    Call(usize, Reg),
+    /// Return the results of the function from the registers starting at the given one.
+    Ret(Reg),
+
+    /// Pack the given number of registers starting at the second into a new struct object.
+    MakeStruct(Reg, Reg, usize),
+    GetField(Reg, Reg, usize),
+}
+impl Jump for Inst {
+    fn jump_offset(&self) -> Option<isize> {
+        match self {
+            Inst::Jmp(offset) | Inst::JmpTrue(_, offset) | Inst::JmpFalse(_, offset) => {
+                Some(*offset)
+            }
+            _ => None,
+        }
+    }
+
+    fn with_jump_offset(self, offset: isize) -> Self {
+        match self {
+            Inst::Jmp(_) => Inst::Jmp(offset),
+            Inst::JmpTrue(cond, _) => Inst::JmpTrue(cond, offset),
+            Inst::JmpFalse(cond, _) => Inst::JmpFalse(cond, offset),
+            inst => panic!("{:?} is not a jump", inst),
+        }
+    }
+}
+impl Inst {
+    /// The registers named by the instruction. The registers of a call past its first one and
+    /// the results of a return after the first depend on the signatures of the functions.
+    pub fn regs(&self) -> Vec<Reg> {
+        match *self {
+            Inst::Halt | Inst::Jmp(_) => vec![],
+            Inst::Const(dst, _) => vec![dst],
+            Inst::JmpTrue(cond, _) | Inst::JmpFalse(cond, _) => vec![cond],
+            Inst::Call(_, first) | Inst::Ret(first) => vec![first],
+            Inst::Move(dst, src)
+            | Inst::Neg(dst, src)
+            | Inst::Not(dst, src)
+            | Inst::GetField(dst, src, _) => vec![dst, src],
+            Inst::Add(dst, lhs, rhs)
+            | Inst::Sub(dst, lhs, rhs)
+            | Inst::Mul(dst, lhs, rhs)
+            | Inst::Div(dst, lhs, rhs)
+            | Inst::Mod(dst, lhs, rhs)
+            | Inst::And(dst, lhs, rhs)
+            | Inst::Or(dst, lhs, rhs)
+            | Inst::Cmp(_, dst, lhs, rhs) => vec![dst, lhs, rhs],
+            Inst::MakeStruct(dst, first, cnt) => {
+                let fields = (0..cnt as u32).map(|i| Reg(first.0 + i));
+                [dst].into_iter().chain(fields).collect()
+            }
+        }
+    }
+}
@@ -0,0 +1,142 @@
+use crate::{
+    inst::Cmp,
+    inst_code::{read_sleb, read_uleb, read_usize, write_sleb, write_uleb},
+    program::{EncodeError, ProgramParseError},
+    value::Value,
+};
+
+use super::{Inst, Reg};
+
+impl Inst {
+    /// Write the instruction, registers and operands as LEB128 varints. Heap references have no
+    /// encoding as they only exist at runtime.
+    pub fn encode(self, out: &mut Vec<u8>) -> Result<(), EncodeError> {
+        let reg = |out: &mut Vec<u8>, r: Reg| write_uleb(out, r.0 as u64);
+        match self {
+            Inst::Halt => out.push(0x00),
+            Inst::Const(dst, value) => match value {
+                Value::Int(v) => {
+                    out.push(0x01);
+                    reg(out, dst);
+                    write_sleb(out, v);
+                }
+                Value::Bool(v) => {
+                    out.push(if v { 0x02 } else { 0x03 });
+                    reg(out, dst);
+                }
+                Value::Ref(_) => return Err(EncodeError::RefConstant),
+            },
+            Inst::Move(dst, src) | Inst::Neg(dst, src) | Inst::Not(dst, src) => {
+                out.push(match self {
+                    Inst::Move(..) => 0x04,
+                    Inst::Neg(..) => 0x20,
+                    _ => 0x21,
+                });
+                reg(out, dst);
+                reg(out, src);
+            }
+            Inst::Add(dst, lhs, rhs)
+            | Inst::Sub(dst, lhs, rhs)
+            | Inst::Mul(dst, lhs, rhs)
+            | Inst::Div(dst, lhs, rhs)
+            | Inst::Mod(dst, lhs, rhs)
+            | Inst::And(dst, lhs, rhs)
+            | Inst::Or(dst, lhs, rhs)
+            | Inst::Cmp(_, dst, lhs, rhs) => {
+                out.push(match self {
+                    Inst::Add(..) => 0x10,
+                    Inst::Sub(..) => 0x11,
+                    Inst::Mul(..) => 0x12,
+                    Inst::Div(..) => 0x13,
+                    Inst::Mod(..) => 0x14,
+                    Inst::And(..) => 0x15,
+                    Inst::Or(..) => 0x16,
+                    Inst::Cmp(Cmp::Equal, ..) => 0x17,
+                    Inst::Cmp(Cmp::Less, ..) => 0x18,
+                    _ => 0x19,
+                });
+                reg(out, dst);
+                reg(out, lhs);
+                reg(out, rhs);
+            }
+            Inst::Jmp(offset) => {
+                out.push(0x30);
+                write_sleb(out, offset as i64);
+            }
+            Inst::JmpTrue(cond, offset) | Inst::JmpFalse(cond, offset) => {
+                out.push(match self {
+                    Inst::JmpTrue(..) => 0x31,
+                    _ => 0x32,
+                });
+                reg(out, cond);
+                write_sleb(out, offset as i64);
+            }
+            Inst::Call(fi, first) => {
+                out.push(0x40);
+                write_uleb(out, fi as u64);
+                reg(out, first);
+            }
+            Inst::Ret(first) => {
+                out.push(0x41);
+                reg(out, first);
+            }
+            Inst::MakeStruct(dst, first, cnt) => {
+                out.push(0x50);
+                reg(out, dst);
+                reg(out, first);
+                write_uleb(out, cnt as u64);
+            }
+            Inst::GetField(dst, src, index) => {
+                out.push(0x53);
+                reg(out, dst);
+                reg(out, src);
+                write_uleb(out, index as u64);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn decode(bytes: &mut &[u8]) -> Result<Self, ProgramParseError> {
+        let reg = |bytes: &mut &[u8]| {
+            read_uleb(bytes)
+                .and_then(|v| u32::try_from(v).map_err(|_| ProgramParseError::InvalidVarint))
+                .map(Reg)
+        };
+        let offset = |bytes: &mut &[u8]| {
+            read_sleb(bytes)
+                .and_then(|v| isize::try_from(v).map_err(|_| ProgramParseError::InvalidVarint))
+        };
+
+        let (&op, rest) = bytes
+            .split_first()
+            .ok_or(ProgramParseError::InsufficientBytes)?;
+        *bytes = rest;
+        Ok(match op {
+            0x00 => Inst::Halt,
+            0x01 => Inst::Const(reg(bytes)?, Value::Int(read_sleb(bytes)?)),
+            0x02 => Inst::Const(reg(bytes)?, true.into()),
+            0x03 => Inst::Const(reg(bytes)?, false.into()),
+            0x04 => Inst::Move(reg(bytes)?, reg(bytes)?),
+            0x10 => Inst::Add(reg(bytes)?, reg(bytes)?, reg(bytes)?),
+            0x11 => Inst::Sub(reg(bytes)?, reg(bytes)?, reg(bytes)?),
+            0x12 => Inst::Mul(reg(bytes)?, reg(bytes)?, reg(bytes)?),
+            0x13 => Inst::Div(reg(bytes)?, reg(bytes)?, reg(bytes)?),
+            0x14 => Inst::Mod(reg(bytes)?, reg(bytes)?, reg(bytes)?),
+            0x15 => Inst::And(reg(bytes)?, reg(bytes)?, reg(bytes)?),
+            0x16 => Inst::Or(reg(bytes)?, reg(bytes)?, reg(bytes)?),
+            0x17 => Inst::Cmp(Cmp::Equal, reg(bytes)?, reg(bytes)?, reg(bytes)?),
+            0x18 => Inst::Cmp(Cmp::Less, reg(bytes)?, reg(bytes)?, reg(bytes)?),
+            0x19 => Inst::Cmp(Cmp::Greater, reg(bytes)?, reg(bytes)?, reg(bytes)?),
+            0x20 => Inst::Neg(reg(bytes)?, reg(bytes)?),
+            0x21 => Inst::Not(reg(bytes)?, reg(bytes)?),
+            0x30 => Inst::Jmp(offset(bytes)?),
+            0x31 => Inst::JmpTrue(reg(bytes)?, offset(bytes)?),
+            0x32 => Inst::JmpFalse(reg(bytes)?, offset(bytes)?),
+            0x40 => Inst::Call(read_usize(bytes)?, reg(bytes)?),
+            0x41 => Inst::Ret(reg(bytes)?),
+            0x50 => Inst::MakeStruct(reg(bytes)?, reg(bytes)?, read_usize(bytes)?),
+            0x53 => Inst::GetField(reg(bytes)?, reg(bytes)?, read_usize(bytes)?),
+            op => return Err(ProgramParseError::UnknownOpcode(op)),
+        })
+    }
+}
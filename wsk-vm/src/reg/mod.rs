@@ -0,0 +1,280 @@
+//! A register based alternative to the stack machine of [`crate::vm`].
+//!
+//! Instructions take their operands from registers and write their result to one, as in
+//! `add r1, r2, r3`. The registers of a function are a window of a register file shared by all
+//! frames. A call slides the window up to its first argument register, so the callee finds its
+//! arguments in `r0` and up, and a return copies the results back to the same place. Returning
+//! from the entry function ends the program with its results.
+//!
+//! Programs are saved as `.wcr` files: [`MAGIC`], [`VERSION`] as a little endian `u16`, then the
+//! entry point and the number of functions, and each function as its instruction count, parameter
+//! count, result count and register count followed by its instructions. Integers are LEB128
+//! varints, signed ones for constants and jump offsets.
+
+use std::fmt;
+
+use crate::{
+    inst::Cmp,
+    inst_code::{read_usize, write_uleb},
+    label::LabeledInsts,
+    program::{EncodeError, ProgramParseError},
+};
+
+mod inst;
+mod inst_code;
+mod vm;
+
+pub use inst::Inst;
+pub use vm::VM;
+
+pub use crate::label::{BuildError, Label};
+
+pub const MAGIC: [u8; 4] = *b"\0wsr";
+pub const VERSION: u16 = 1;
+
+/// A register of the window of the running function.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Reg(pub u32);
+impl Reg {
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+impl fmt::Display for Reg {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "r{}", self.0)
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Program {
+    funcs: Vec<Function>,
+    entry_point: usize,
+}
+impl Program {
+    pub fn add_func(&mut self, func: Function) -> usize {
+        self.funcs.push(func);
+        self.funcs.len() - 1
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Function> {
+        self.funcs.get(index)
+    }
+
+    pub fn funcs(&self) -> &[Function] {
+        &self.funcs
+    }
+
+    pub fn set_entry_point(&mut self, index: usize) {
+        debug_assert!(index < self.funcs.len(), "function index out of bound");
+        self.entry_point = index;
+    }
+
+    pub fn get_entry_point(&self) -> usize {
+        self.entry_point
+    }
+
+    pub fn to_bin(&self) -> Result<Vec<u8>, EncodeError> {
+        let mut out = MAGIC.to_vec();
+        out.extend(VERSION.to_le_bytes());
+        write_uleb(&mut out, self.entry_point as u64);
+        write_uleb(&mut out, self.funcs.len() as u64);
+        for func in &self.funcs {
+            for field in [func.insts.len(), func.param_cnt, func.ret_cnt, func.reg_cnt] {
+                write_uleb(&mut out, field as u64);
+            }
+            for inst in &func.insts {
+                inst.encode(&mut out)?;
+            }
+        }
+        Ok(out)
+    }
+
+    /// Read a program written by [`Program::to_bin`]. It is checked when [`VM::execute`] runs it.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ProgramParseError> {
+        let mut bytes = bytes
+            .strip_prefix(&MAGIC)
+            .ok_or(ProgramParseError::NotARegisterProgram)?;
+        let (version, rest) = bytes
+            .split_first_chunk()
+            .ok_or(ProgramParseError::InsufficientBytes)?;
+        let version = u16::from_le_bytes(*version);
+        if version != VERSION {
+            return Err(ProgramParseError::UnsupportedRegisterVersion(version));
+        }
+        bytes = rest;
+
+        let mut prog = Program {
+            entry_point: read_usize(&mut bytes)?,
+            ..Default::default()
+        };
+        for _ in 0..read_usize(&mut bytes)? {
+            let inst_cnt = read_usize(&mut bytes)?;
+            let mut func = Function {
+                insts: vec![],
+                param_cnt: read_usize(&mut bytes)?,
+                ret_cnt: read_usize(&mut bytes)?,
+                reg_cnt: read_usize(&mut bytes)?,
+            };
+            for _ in 0..inst_cnt {
+                func.insts.push(Inst::decode(&mut bytes)?);
+            }
+            prog.funcs.push(func);
+        }
+        if !bytes.is_empty() {
+            return Err(ProgramParseError::TrailingBytes);
+        }
+        Ok(prog)
+    }
+}
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "entry: ${}\n", self.entry_point)?;
+        for (i, func) in self.funcs.iter().enumerate() {
+            writeln!(
+                f,
+                "func ${} ({} -> {}, {} regs):\n{}",
+                i, func.param_cnt, func.ret_cnt, func.reg_cnt, func
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Function {
+    insts: Vec<Inst>,
+    /// Number of arguments, found in the first registers.
+    param_cnt: usize,
+    /// Number of results, `ret` copies them from consecutive registers.
+    ret_cnt: usize,
+    /// Size of the register window of the function.
+    reg_cnt: usize,
+}
+impl Function {
+    pub fn param_cnt(&self) -> usize {
+        self.param_cnt
+    }
+
+    pub fn ret_cnt(&self) -> usize {
+        self.ret_cnt
+    }
+
+    pub fn reg_cnt(&self) -> usize {
+        self.reg_cnt
+    }
+
+    pub fn get_insts(&self) -> &[Inst] {
+        &self.insts
+    }
+}
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, inst) in self.insts.iter().enumerate() {
+            let target = |offset: isize| i.wrapping_add_signed(offset);
+            let inst_str = match *inst {
+                Inst::Halt => "halt".to_owned(),
+                Inst::Const(dst, value) => format!("const\t\t{}, {}", dst, value),
+                Inst::Move(dst, src) => format!("mov\t\t{}, {}", dst, src),
+                Inst::Add(dst, lhs, rhs) => format!("add\t\t{}, {}, {}", dst, lhs, rhs),
+                Inst::Sub(dst, lhs, rhs) => format!("sub\t\t{}, {}, {}", dst, lhs, rhs),
+                Inst::Mul(dst, lhs, rhs) => format!("mul\t\t{}, {}, {}", dst, lhs, rhs),
+                Inst::Div(dst, lhs, rhs) => format!("div\t\t{}, {}, {}", dst, lhs, rhs),
+                Inst::Mod(dst, lhs, rhs) => format!("mod\t\t{}, {}, {}", dst, lhs, rhs),
+                Inst::And(dst, lhs, rhs) => format!("and\t\t{}, {}, {}", dst, lhs, rhs),
+                Inst::Or(dst, lhs, rhs) => format!("or\t\t{}, {}, {}", dst, lhs, rhs),
+                Inst::Cmp(cmp, dst, lhs, rhs) => format!(
+                    "cmp\t\t{} {}, {}, {}",
+                    match cmp {
+                        Cmp::Equal => "equ",
+                        Cmp::Less => "lt",
+                        Cmp::Greater => "gt",
+                    },
+                    dst,
+                    lhs,
+                    rhs
+                ),
+                Inst::Neg(dst, src) => format!("neg\t\t{}, {}", dst, src),
+                Inst::Not(dst, src) => format!("not\t\t{}, {}", dst, src),
+                Inst::Jmp(offset) => format!("jmp\t\t{}:", target(offset)),
+                Inst::JmpTrue(cond, offset) => format!("jtr\t\t{}, {}:", cond, target(offset)),
+                Inst::JmpFalse(cond, offset) => format!("jfl\t\t{}, {}:", cond, target(offset)),
+                Inst::Call(fi, first) => format!("call\t\t${}, {}", fi, first),
+                Inst::Ret(first) => format!("ret\t\t{}", first),
+                Inst::MakeStruct(dst, first, cnt) => {
+                    format!("mkstruct\t{}, {}, {}", dst, first, cnt)
+                }
+                Inst::GetField(dst, src, index) => format!("getf\t\t{}, {}, {}", dst, src, index),
+            };
+            writeln!(f, "\t{:>4}:\t{}", i, inst_str)?;
+        }
+        Ok(())
+    }
+}
+
+/// Builds a [`Function`], resolving jumps to labels and sizing the register window from the
+/// registers used. The window always holds the arguments.
+#[derive(Debug)]
+pub struct FunctionBuilder {
+    code: LabeledInsts<Inst>,
+    param_cnt: usize,
+    ret_cnt: usize,
+    /// Registers reserved beyond the ones named by the instructions.
+    reg_cnt: usize,
+}
+impl FunctionBuilder {
+    pub fn new(param_cnt: usize, ret_cnt: usize) -> Self {
+        Self {
+            code: LabeledInsts::default(),
+            param_cnt,
+            ret_cnt,
+            reg_cnt: param_cnt,
+        }
+    }
+
+    /// Make the window at least `cnt` registers large, for the registers a call or a return uses
+    /// past the one it names.
+    pub fn reserve_regs(&mut self, cnt: usize) {
+        self.reg_cnt = self.reg_cnt.max(cnt);
+    }
+
+    pub fn new_label(&mut self) -> Label {
+        self.code.new_label()
+    }
+
+    /// See [`LabeledInsts::bind_label`].
+    pub fn bind_label(&mut self, label: Label) {
+        self.code.bind_label(label);
+    }
+
+    pub fn push(&mut self, inst: Inst) {
+        self.code.insts.push(inst);
+    }
+
+    pub fn jmp(&mut self, label: Label) {
+        self.code.push_jump(Inst::Jmp(0), label);
+    }
+
+    pub fn jmp_true(&mut self, cond: Reg, label: Label) {
+        self.code.push_jump(Inst::JmpTrue(cond, 0), label);
+    }
+
+    pub fn jmp_false(&mut self, cond: Reg, label: Label) {
+        self.code.push_jump(Inst::JmpFalse(cond, 0), label);
+    }
+
+    pub fn build(self) -> Result<Function, BuildError> {
+        let insts = self.code.resolve()?;
+        let reg_cnt = insts
+            .iter()
+            .flat_map(Inst::regs)
+            .map(|v| v.index() + 1)
+            .fold(self.reg_cnt, usize::max);
+        Ok(Function {
+            insts,
+            param_cnt: self.param_cnt,
+            ret_cnt: self.ret_cnt,
+            reg_cnt,
+        })
+    }
+}
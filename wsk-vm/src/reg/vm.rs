@@ -0,0 +1,229 @@
+use std::cmp::Ordering;
+
+use crate::{
+    heap::{GcStats, Heap, HeapConfig, Object},
+    inst::{Cmp, RunError},
+    label::Jump,
+    value::{OpError, Value},
+    vm::VMError,
+};
+
+use super::{Inst, Program};
+
+/// Value of a register before the first write, which the code of whiskc never reads.
+const UNSET_REG: Value = Value::Int(0);
+
+#[derive(Debug, Default)]
+pub struct VM {
+    regs: Vec<Value>,
+    frames: Vec<Frame>,
+    heap: Heap,
+    results: Vec<Value>,
+}
+impl VM {
+    pub fn with_heap_config(config: HeapConfig) -> Self {
+        Self {
+            heap: Heap::new(config),
+            ..Default::default()
+        }
+    }
+
+    /// Run the program until it halts or its entry function returns. The registers the
+    /// instructions name are checked before running, so the loop indexes them directly.
+    pub fn execute(&mut self, program: &Program) -> Result<(), RunError> {
+        check(program)?;
+        self.regs.clear();
+        self.frames.clear();
+        self.heap.clear();
+        self.results.clear();
+
+        let mut fi = program.get_entry_point();
+        let mut func = &program.funcs[fi];
+        let mut pc = 0;
+        let mut base = 0;
+        self.regs.resize(func.reg_cnt, UNSET_REG);
+        loop {
+            let inst = func.insts[pc];
+            let regs = &mut self.regs;
+            match inst {
+                Inst::Halt => return Ok(()),
+                Inst::Const(dst, value) => regs[base + dst.index()] = value,
+                Inst::Move(dst, src) => regs[base + dst.index()] = regs[base + src.index()],
+
+                Inst::Add(dst, lhs, rhs) => {
+                    regs[base + dst.index()] =
+                        (regs[base + lhs.index()] + regs[base + rhs.index()])?
+                }
+                Inst::Sub(dst, lhs, rhs) => {
+                    regs[base + dst.index()] =
+                        (regs[base + lhs.index()] - regs[base + rhs.index()])?
+                }
+                Inst::Mul(dst, lhs, rhs) => {
+                    regs[base + dst.index()] =
+                        (regs[base + lhs.index()] * regs[base + rhs.index()])?
+                }
+                Inst::Div(dst, lhs, rhs) => {
+                    regs[base + dst.index()] =
+                        (regs[base + lhs.index()] / regs[base + rhs.index()])?
+                }
+                Inst::Mod(dst, lhs, rhs) => {
+                    regs[base + dst.index()] =
+                        (regs[base + lhs.index()] % regs[base + rhs.index()])?
+                }
+                Inst::And(dst, lhs, rhs) => {
+                    regs[base + dst.index()] =
+                        (regs[base + lhs.index()] & regs[base + rhs.index()])?
+                }
+                Inst::Or(dst, lhs, rhs) => {
+                    regs[base + dst.index()] =
+                        (regs[base + lhs.index()] | regs[base + rhs.index()])?
+                }
+                Inst::Cmp(cmp, dst, lhs, rhs) => {
+                    let (lhs, rhs) = (regs[base + lhs.index()], regs[base + rhs.index()]);
+                    let yes = match cmp {
+                        Cmp::Equal => lhs == rhs,
+                        Cmp::Less | Cmp::Greater => {
+                            let (Value::Int(lhs), Value::Int(rhs)) = (lhs, rhs) else {
+                                return Err(OpError::InvalidTypeForOp.into());
+                            };
+                            lhs.cmp(&rhs)
+                                == match cmp {
+                                    Cmp::Less => Ordering::Less,
+                                    _ => Ordering::Greater,
+                                }
+                        }
+                    };
+                    regs[base + dst.index()] = yes.into();
+                }
+
+                Inst::Neg(dst, src) => regs[base + dst.index()] = (-regs[base + src.index()])?,
+                Inst::Not(dst, src) => regs[base + dst.index()] = (!regs[base + src.index()])?,
+
+                Inst::Jmp(offset) => {
+                    pc = pc.wrapping_add_signed(offset);
+                    continue;
+                }
+                Inst::JmpTrue(cond, offset) | Inst::JmpFalse(cond, offset) => {
+                    let Value::Bool(cond) = regs[base + cond.index()] else {
+                        return Err(OpError::InvalidTypeForOp.into());
+                    };
+                    if cond == matches!(inst, Inst::JmpTrue(..)) {
+                        pc = pc.wrapping_add_signed(offset);
+                        continue;
+                    }
+                }
+
+                Inst::Call(callee, first) => {
+                    self.frames.push(Frame { fi, pc, base });
+                    fi = callee;
+                    func = &program.funcs[fi];
+                    pc = 0;
+                    base += first.index();
+                    if regs.len() < base + func.reg_cnt {
+                        regs.resize(base + func.reg_cnt, UNSET_REG);
+                    }
+                    continue;
+                }
+                Inst::Ret(first) => {
+                    let first = base + first.index();
+                    regs.copy_within(first..first + func.ret_cnt, base);
+                    let Some(frame) = self.frames.pop() else {
+                        self.results = regs[..func.ret_cnt].to_vec();
+                        return Ok(());
+                    };
+                    Frame { fi, pc, base } = frame;
+                    func = &program.funcs[fi];
+                }
+
+                Inst::MakeStruct(dst, first, cnt) => {
+                    let first = base + first.index();
+                    let fields = regs[first..first + cnt].to_vec();
+                    let obj = Object::Struct(fields);
+                    if self.heap.should_collect(obj.size()) {
+                        self.heap.collect(self.regs.iter().copied());
+                    }
+                    let r = self.heap.alloc(obj)?;
+                    self.regs[base + dst.index()] = r.into();
+                }
+                Inst::GetField(dst, src, index) => {
+                    let r = regs[base + src.index()]
+                        .as_heap_ref()
+                        .ok_or(OpError::InvalidTypeForOp)?;
+                    let Object::Struct(fields) = self.heap.get(r)? else {
+                        return Err(OpError::InvalidTypeForOp.into());
+                    };
+                    regs[base + dst.index()] =
+                        *fields.get(index).ok_or(VMError::FieldOutOfBound)?;
+                }
+            }
+            pc += 1;
+        }
+    }
+
+    /// The results of the entry function once it returned.
+    pub fn results(&self) -> &[Value] {
+        &self.results
+    }
+
+    pub fn heap(&self) -> &Heap {
+        &self.heap
+    }
+
+    pub fn gc_stats(&self) -> &GcStats {
+        self.heap.stats()
+    }
+}
+
+/// The caller of a running function.
+#[derive(Debug)]
+struct Frame {
+    fi: usize,
+    /// Index of the call instruction.
+    pc: usize,
+    base: usize,
+}
+
+/// Check that every instruction stays in its function and register window, so running the
+/// program cannot index out of bounds.
+fn check(program: &Program) -> Result<(), VMError> {
+    program
+        .get(program.get_entry_point())
+        .ok_or(VMError::InvalidFunctionIndex)?;
+    for func in &program.funcs {
+        if !matches!(
+            func.insts.last(),
+            Some(Inst::Halt | Inst::Jmp(_) | Inst::Ret(_))
+        ) {
+            return Err(VMError::InstReadOutOfBound);
+        }
+        for (at, inst) in func.insts.iter().enumerate() {
+            if let Some(offset) = inst.jump_offset() {
+                if at
+                    .checked_add_signed(offset)
+                    .is_none_or(|v| v >= func.insts.len())
+                {
+                    return Err(VMError::InstReadOutOfBound);
+                }
+            }
+            // registers past the named one used by calls and returns.
+            let extra = match *inst {
+                Inst::Call(callee, _) => {
+                    let callee = program.get(callee).ok_or(VMError::InvalidFunctionIndex)?;
+                    callee.param_cnt.max(callee.ret_cnt)
+                }
+                Inst::Ret(_) => func.ret_cnt,
+                _ => 1,
+            };
+            let end = inst
+                .regs()
+                .iter()
+                .map(|v| v.index() + extra)
+                .max()
+                .unwrap_or(0);
+            if end > func.reg_cnt {
+                return Err(VMError::InvalidRegister);
+            }
+        }
+    }
+    Ok(())
+}
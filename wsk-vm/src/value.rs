@@ -4,10 +4,21 @@ use std::{
     ops::{Add, BitAnd, BitOr, Div, Mul, Neg, Not, Rem, Sub},
 };
 
+use crate::heap::HeapRef;
+
 #[derive(Debug, Clone, Copy)]
 pub enum Value {
     Int(i64),
     Bool(bool),
+    Ref(HeapRef),
+}
+impl Value {
+    pub fn as_heap_ref(&self) -> Option<HeapRef> {
+        match self {
+            Self::Ref(r) => Some(*r),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -32,6 +43,7 @@ impl PartialEq for Value {
         match (self, other) {
             (Self::Int(lhs), Self::Int(rhs)) => lhs == rhs,
             (Self::Bool(lhs), Self::Bool(rhs)) => lhs == rhs,
+            (Self::Ref(lhs), Self::Ref(rhs)) => lhs == rhs,
             _ => false,
         }
     }
@@ -56,12 +68,18 @@ impl From<bool> for Value {
         Self::Bool(value)
     }
 }
+impl From<HeapRef> for Value {
+    fn from(value: HeapRef) -> Self {
+        Self::Ref(value)
+    }
+}
 
 impl Display for Value {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Value::Int(v) => write!(f, "{}", v),
             Value::Bool(v) => write!(f, "{}", v),
+            Value::Ref(v) => write!(f, "{}", v),
         }
     }
 }
@@ -82,7 +100,7 @@ mod impl_macros {
                         let Self::Int(rhs) = rhs else { unreachable!() };
                         Self::Int(lhs $sym rhs)
                     }
-                    Self::Bool(_) => return Err(OpError::InvalidTypeForOp),
+                    _ => return Err(OpError::InvalidTypeForOp),
                 })
             }
         }
@@ -99,11 +117,11 @@ mod impl_macros {
                 }
 
                 Ok(match self {
-                    Self::Int(_) => return Err(OpError::InvalidTypeForOp),
                     Self::Bool(lhs) => {
                         let Self::Bool(rhs) = rhs else { unreachable!() };
                         Self::Bool(lhs $sym rhs)
                     }
+                    _ => return Err(OpError::InvalidTypeForOp),
                 })
             }
         }
@@ -118,7 +136,7 @@ mod impl_macros {
             fn $op_fn(self) -> Self::Output {
                 Ok(match self {
                     Self::Int(val) => Self::Int($sym val),
-                    Self::Bool(_) => return Err(OpError::InvalidTypeForOp),
+                    _ => return Err(OpError::InvalidTypeForOp),
                 })
             }
         }
@@ -131,8 +149,8 @@ mod impl_macros {
 
             fn $op_fn(self) -> Self::Output {
                 Ok(match self {
-                    Self::Int(_) => return Err(OpError::InvalidTypeForOp),
                     Self::Bool(val) => Self::Bool($sym val),
+                    _ => return Err(OpError::InvalidTypeForOp),
                 })
             }
         }
@@ -0,0 +1,511 @@
+//! Static checks of programs, so that a verified program cannot jump or call out of bounds, run
+//! out of operands, use a local slot it does not have or load one before storing it, return from
+//! the entry function or run past the end of a function.
+
+use std::fmt;
+
+use crate::{
+    program::{Function, Program, MAX_SLOTS},
+    Inst, Jump,
+};
+
+/// Check every function of the program and its entry point.
+pub fn verify(prog: &Program) -> Result<(), VerifyError> {
+    let entry = prog.get_entry_point();
+    let Some(entry_func) = prog.get(entry) else {
+        return Err(VerifyError {
+            func: entry,
+            at: None,
+            kind: VerifyErrorKind::InvalidEntryPoint,
+        });
+    };
+    if entry_func.param_cnt() != 0 {
+        return Err(VerifyError {
+            func: entry,
+            at: None,
+            kind: VerifyErrorKind::EntryTakesParams,
+        });
+    }
+
+    for export in prog.exports() {
+        if prog.get(export.func).is_none() {
+            return Err(VerifyError {
+                func: export.func,
+                at: None,
+                kind: VerifyErrorKind::InvalidExport(export.name.clone()),
+            });
+        }
+    }
+
+    let mut fi = 0;
+    while let Some(func) = prog.get(fi) {
+        verify_function(prog, fi, func)?;
+        fi += 1;
+    }
+    Ok(())
+}
+
+/// What is known at an instruction on every path reaching it.
+#[derive(Clone)]
+struct State {
+    /// Number of values on the stack of the frame.
+    depth: usize,
+    /// The local slots stored into.
+    stored: Vec<bool>,
+}
+
+fn verify_function(prog: &Program, fi: usize, func: &Function) -> Result<(), VerifyError> {
+    let insts = func.get_insts();
+    let error = |at, kind| VerifyError {
+        func: fi,
+        at: Some(at),
+        kind,
+    };
+    if let Some(&cnt) = [func.param_cnt(), func.ret_cnt(), func.local_cnt()]
+        .iter()
+        .find(|v| **v > MAX_SLOTS)
+    {
+        return Err(VerifyError {
+            func: fi,
+            at: None,
+            kind: VerifyErrorKind::TooManySlots(cnt),
+        });
+    }
+    if insts.is_empty() {
+        return Err(VerifyError {
+            func: fi,
+            at: None,
+            kind: VerifyErrorKind::FallsOffEnd,
+        });
+    }
+
+    for (at, inst) in insts.iter().enumerate() {
+        match *inst {
+            Inst::Load(id) | Inst::Store(id) if id >= func.local_cnt() => {
+                return Err(error(at, VerifyErrorKind::InvalidLocal(id)));
+            }
+            Inst::Call(callee) if prog.get(callee).is_none() => {
+                return Err(error(at, VerifyErrorKind::InvalidFunctionIndex(callee)));
+            }
+            Inst::PushConst(index) if index >= prog.constants().len() => {
+                return Err(error(at, VerifyErrorKind::InvalidConstantIndex(index)));
+            }
+            Inst::Ret if fi == prog.get_entry_point() => {
+                return Err(error(at, VerifyErrorKind::RetInEntry));
+            }
+            _ => {}
+        }
+        if let Some(offset) = inst.jump_offset() {
+            if at
+                .checked_add_signed(offset)
+                .is_none_or(|v| v >= insts.len())
+            {
+                return Err(error(at, VerifyErrorKind::JumpOutOfBounds(offset)));
+            }
+        }
+    }
+
+    let mut states: Vec<Option<State>> = vec![None; insts.len()];
+    states[0] = Some(State {
+        depth: func.param_cnt(),
+        stored: vec![false; func.local_cnt()],
+    });
+    let mut work = vec![0];
+    while let Some(at) = work.pop() {
+        let mut state = states[at].clone().unwrap();
+        let inst = insts[at];
+        let (pops, pushes) = match inst {
+            Inst::Halt | Inst::Jmp(_) => (0, 0),
+            Inst::Push(_) | Inst::PushConst(_) | Inst::Load(_) => (0, 1),
+            Inst::Pop | Inst::Store(_) | Inst::JmpTrue(_) | Inst::JmpFalse(_) => (1, 0),
+            Inst::Add
+            | Inst::Sub
+            | Inst::Mul
+            | Inst::Div
+            | Inst::Mod
+            | Inst::And
+            | Inst::Or
+            | Inst::Cmp(_)
+            | Inst::GetIndex => (2, 1),
+            Inst::Neg | Inst::Not | Inst::GetField(_) | Inst::Len => (1, 1),
+            Inst::Call(callee) => {
+                let callee = prog.get(callee).unwrap();
+                (callee.param_cnt(), callee.ret_cnt())
+            }
+            Inst::Ret => (func.ret_cnt(), 0),
+            Inst::MakeStruct(cnt) | Inst::MakeArray(cnt) | Inst::MakeStr(cnt) => (cnt, 1),
+            Inst::SetField(_) => (2, 0),
+            Inst::SetIndex => (3, 0),
+        };
+        if state.depth < pops {
+            return Err(error(
+                at,
+                VerifyErrorKind::StackUnderflow {
+                    depth: state.depth,
+                    needed: pops,
+                },
+            ));
+        }
+        state.depth = state.depth - pops + pushes;
+        match inst {
+            Inst::Load(id) if !state.stored[id] => {
+                return Err(error(at, VerifyErrorKind::UninitializedLocal(id)));
+            }
+            Inst::Store(id) => state.stored[id] = true,
+            _ => {}
+        }
+
+        let jump = inst.jump_offset().map(|v| at.wrapping_add_signed(v));
+        let falls_through = !matches!(inst, Inst::Halt | Inst::Ret | Inst::Jmp(_));
+        let next = falls_through.then_some(at + 1);
+        for succ in jump.into_iter().chain(next) {
+            if succ >= insts.len() {
+                return Err(error(at, VerifyErrorKind::FallsOffEnd));
+            }
+            match &mut states[succ] {
+                None => {
+                    states[succ] = Some(state.clone());
+                    work.push(succ);
+                }
+                Some(old) if old.depth != state.depth => {
+                    return Err(error(
+                        succ,
+                        VerifyErrorKind::StackDepthMismatch {
+                            expected: old.depth,
+                            found: state.depth,
+                        },
+                    ));
+                }
+                Some(old) => {
+                    let mut changed = false;
+                    for (old, new) in old.stored.iter_mut().zip(&state.stored) {
+                        if *old && !new {
+                            *old = false;
+                            changed = true;
+                        }
+                    }
+                    if changed {
+                        work.push(succ);
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A problem of a program, `at` is the index of the instruction in the function when there is one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifyError {
+    pub func: usize,
+    pub at: Option<usize>,
+    pub kind: VerifyErrorKind,
+}
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.at {
+            Some(at) => write!(f, "func ${} at {}: {}", self.func, at, self.kind),
+            None => write!(f, "func ${}: {}", self.func, self.kind),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VerifyErrorKind {
+    /// The entry point is not a function of the program.
+    InvalidEntryPoint,
+    /// Nothing passes arguments to the entry function.
+    EntryTakesParams,
+    /// The entry function has no caller to return to, it ends with a halt instead.
+    RetInEntry,
+    /// An export of a function that does not exist.
+    InvalidExport(String),
+    JumpOutOfBounds(isize),
+    InvalidFunctionIndex(usize),
+    InvalidConstantIndex(usize),
+    /// A load or store of a slot past the local count of the function.
+    InvalidLocal(usize),
+    /// More parameters, return values or local slots than [`MAX_SLOTS`].
+    TooManySlots(usize),
+    StackUnderflow {
+        depth: usize,
+        needed: usize,
+    },
+    /// Paths reaching the instruction leave different numbers of values on the stack.
+    StackDepthMismatch {
+        expected: usize,
+        found: usize,
+    },
+    /// A load of a local that is not stored on every path to it.
+    UninitializedLocal(usize),
+    /// Execution can continue past the last instruction.
+    FallsOffEnd,
+}
+impl fmt::Display for VerifyErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerifyErrorKind::InvalidEntryPoint => write!(f, "entry point is not a function"),
+            VerifyErrorKind::EntryTakesParams => {
+                write!(f, "entry function cannot take parameters")
+            }
+            VerifyErrorKind::RetInEntry => write!(f, "entry function cannot return"),
+            VerifyErrorKind::InvalidExport(name) => {
+                write!(f, "export `{}` is not a function", name)
+            }
+            VerifyErrorKind::JumpOutOfBounds(offset) => {
+                write!(f, "jump with offset {} leaves the function", offset)
+            }
+            VerifyErrorKind::InvalidFunctionIndex(fi) => {
+                write!(f, "call to unknown function ${}", fi)
+            }
+            VerifyErrorKind::InvalidConstantIndex(index) => {
+                write!(f, "push of unknown constant #{}", index)
+            }
+            VerifyErrorKind::InvalidLocal(id) => {
+                write!(f, "r{} is not a local slot of the function", id)
+            }
+            VerifyErrorKind::TooManySlots(cnt) => {
+                write!(f, "{} slots are more than the limit of {}", cnt, MAX_SLOTS)
+            }
+            VerifyErrorKind::StackUnderflow { depth, needed } => write!(
+                f,
+                "needs {} values on the stack but there are {}",
+                needed, depth
+            ),
+            VerifyErrorKind::StackDepthMismatch { expected, found } => write!(
+                f,
+                "stack depth is {} on one path and {} on another",
+                expected, found
+            ),
+            VerifyErrorKind::UninitializedLocal(id) => {
+                write!(f, "r{} may be loaded before it is stored", id)
+            }
+            VerifyErrorKind::FallsOffEnd => {
+                write!(f, "execution runs past the end of the function")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{inst_code::Encoding, program::Export, Value};
+
+    /// A program of the functions with the first as its entry point.
+    fn program(funcs: impl IntoIterator<Item = Function>) -> Program {
+        let mut prog = Program::default();
+        for func in funcs {
+            prog.add_func(func);
+        }
+        prog
+    }
+
+    fn error_of(insts: impl IntoIterator<Item = Inst>) -> VerifyError {
+        verify(&program([Function::from_insts(insts)])).unwrap_err()
+    }
+
+    fn error_at(at: usize, kind: VerifyErrorKind) -> VerifyError {
+        VerifyError {
+            func: 0,
+            at: Some(at),
+            kind,
+        }
+    }
+
+    #[test]
+    fn valid_program() {
+        let mut callee = Function::new(1, 1);
+        callee.push_insts([Inst::Push(Value::Int(1)), Inst::Add, Inst::Ret]);
+        let start = Function::from_insts([
+            Inst::Push(Value::Int(2)),
+            Inst::Call(1),
+            Inst::Store(0),
+            Inst::Load(0),
+            Inst::Pop,
+            Inst::Halt,
+        ]);
+        assert_eq!(verify(&program([start, callee])), Ok(()));
+    }
+
+    #[test]
+    fn invalid_entry_point() {
+        let mut prog = Program::new(1);
+        prog.add_func(Function::from_insts([Inst::Halt]));
+        assert_eq!(
+            verify(&prog).unwrap_err().kind,
+            VerifyErrorKind::InvalidEntryPoint
+        );
+    }
+
+    #[test]
+    fn entry_taking_params() {
+        let mut start = Function::new(1, 0);
+        start.push_insts([Inst::Pop, Inst::Halt]);
+        assert_eq!(
+            verify(&program([start])).unwrap_err().kind,
+            VerifyErrorKind::EntryTakesParams
+        );
+    }
+
+    #[test]
+    fn ret_in_entry() {
+        assert_eq!(
+            error_of([
+                Inst::Push(Value::Bool(true)),
+                Inst::JmpTrue(1),
+                Inst::Ret,
+                Inst::Halt
+            ]),
+            error_at(2, VerifyErrorKind::RetInEntry)
+        );
+    }
+
+    #[test]
+    fn invalid_export() {
+        let mut prog = program([Function::from_insts([Inst::Halt])]);
+        prog.add_export(Export {
+            name: "f".to_owned(),
+            func: 1,
+        });
+        assert_eq!(
+            verify(&prog).unwrap_err().kind,
+            VerifyErrorKind::InvalidExport("f".to_owned())
+        );
+    }
+
+    #[test]
+    fn jump_out_of_bounds() {
+        // adding the function to a program asserts its jumps, so it is checked on its own.
+        let error_of = |insts: [Inst; 2]| {
+            verify_function(&Program::default(), 0, &Function::from_insts(insts)).unwrap_err()
+        };
+        assert_eq!(
+            error_of([Inst::Jmp(2), Inst::Halt]),
+            error_at(0, VerifyErrorKind::JumpOutOfBounds(2))
+        );
+        assert_eq!(
+            error_of([Inst::Halt, Inst::Jmp(-2)]),
+            error_at(1, VerifyErrorKind::JumpOutOfBounds(-2))
+        );
+    }
+
+    #[test]
+    fn invalid_function_index() {
+        assert_eq!(
+            error_of([Inst::Call(1), Inst::Halt]),
+            error_at(0, VerifyErrorKind::InvalidFunctionIndex(1))
+        );
+    }
+
+    #[test]
+    fn invalid_constant_index() {
+        assert_eq!(
+            error_of([Inst::PushConst(0), Inst::Pop, Inst::Halt]),
+            error_at(0, VerifyErrorKind::InvalidConstantIndex(0))
+        );
+    }
+
+    #[test]
+    fn invalid_local() {
+        // pushing instructions reserves their slots, a header can declare fewer.
+        let mut bytes = vec![2, 0, 0, 0];
+        for inst in [Inst::Load(0), Inst::Halt] {
+            inst.encode(&mut bytes, Encoding::Compact).unwrap();
+        }
+        let func = Function::from_bytes(&mut bytes.as_slice(), Encoding::Compact).unwrap();
+        assert_eq!(
+            verify(&program([func])).unwrap_err(),
+            error_at(0, VerifyErrorKind::InvalidLocal(0))
+        );
+    }
+
+    #[test]
+    fn too_many_slots() {
+        let start = Function::from_insts([Inst::Halt]);
+        let mut callee = Function::new(0, 0);
+        callee.reserve_locals(MAX_SLOTS + 1);
+        callee.push_inst(Inst::Ret);
+        assert_eq!(
+            verify(&program([start, callee])).unwrap_err(),
+            VerifyError {
+                func: 1,
+                at: None,
+                kind: VerifyErrorKind::TooManySlots(MAX_SLOTS + 1),
+            }
+        );
+    }
+
+    #[test]
+    fn stack_underflow() {
+        assert_eq!(
+            error_of([Inst::Push(Value::Int(1)), Inst::Add, Inst::Halt]),
+            error_at(
+                1,
+                VerifyErrorKind::StackUnderflow {
+                    depth: 1,
+                    needed: 2
+                }
+            )
+        );
+    }
+
+    #[test]
+    fn stack_depth_mismatch_at_merge() {
+        // the jump skips the push that the fall through path does.
+        let insts = [
+            Inst::Push(Value::Bool(true)),
+            Inst::JmpTrue(2),
+            Inst::Push(Value::Int(1)),
+            Inst::Halt,
+        ];
+        assert_eq!(
+            error_of(insts),
+            error_at(
+                3,
+                VerifyErrorKind::StackDepthMismatch {
+                    expected: 0,
+                    found: 1
+                }
+            )
+        );
+    }
+
+    #[test]
+    fn uninitialized_local() {
+        assert_eq!(
+            error_of([Inst::Load(0), Inst::Pop, Inst::Halt]),
+            error_at(0, VerifyErrorKind::UninitializedLocal(0))
+        );
+        // stored on only one of the paths reaching the load.
+        let insts = [
+            Inst::Push(Value::Bool(true)),
+            Inst::JmpTrue(3),
+            Inst::Push(Value::Int(1)),
+            Inst::Store(0),
+            Inst::Load(0),
+            Inst::Pop,
+            Inst::Halt,
+        ];
+        assert_eq!(
+            error_of(insts),
+            error_at(4, VerifyErrorKind::UninitializedLocal(0))
+        );
+    }
+
+    #[test]
+    fn falls_off_end() {
+        assert_eq!(
+            error_of([Inst::Push(Value::Int(1)), Inst::Pop]),
+            error_at(1, VerifyErrorKind::FallsOffEnd)
+        );
+        assert_eq!(
+            error_of([]),
+            VerifyError {
+                func: 0,
+                at: None,
+                kind: VerifyErrorKind::FallsOffEnd,
+            }
+        );
+    }
+}
@@ -1,114 +1,418 @@
-use std::collections::HashMap;
+use std::fmt;
 
 use crate::{
-    inst::{RunError, RunInst},
+    heap::{GcStats, Heap, HeapConfig, HeapRef, Object},
+    inst::RunError,
+    peephole::{self, Op},
     program::Program,
-    value::Value,
+    value::{OpError, Value},
 };
 
-#[derive(Debug, Default)]
+/// Value of a local slot before the first store, which a verified program never loads.
+const UNSET_LOCAL: Value = Value::Int(0);
+
 pub struct VM {
     stack: Vec<Value>,
     frames: Vec<Frame>,
-    status: VMStatus,
+    heap: Heap,
+    program: Program,
+    /// The lowered functions of the program, `None` until it is first run.
+    code: Option<Vec<Vec<Op>>>,
+    /// Whether the peephole pass fuses superinstructions.
+    fuse: bool,
+}
+impl Default for VM {
+    fn default() -> Self {
+        Self {
+            stack: vec![],
+            frames: vec![],
+            heap: Heap::default(),
+            program: Program::default(),
+            code: None,
+            fuse: true,
+        }
+    }
 }
 impl VM {
-    pub fn reset(&mut self, entry_point: usize) {
+    pub fn with_heap_config(config: HeapConfig) -> Self {
+        Self {
+            heap: Heap::new(config),
+            ..Default::default()
+        }
+    }
+
+    /// Run the programs with or without superinstructions, they are used by default.
+    pub fn set_superinstructions(&mut self, enabled: bool) {
+        if self.fuse != enabled {
+            self.code = None;
+        }
+        self.fuse = enabled;
+    }
+
+    /// Load the program and run it.
+    pub fn execute(&mut self, program: Program) -> Result<(), RunError> {
+        self.load(program)?;
+        self.rerun()
+    }
+
+    /// Load the program for [`VM::rerun`], it is lowered on its first run.
+    pub fn load(&mut self, program: Program) -> Result<(), RunError> {
+        // there are no host functions to give the program yet.
+        if !program.imports().is_empty() {
+            return Err(VMError::UnresolvedImport.into());
+        }
+        self.program = program;
+        self.code = None;
+        Ok(())
+    }
+
+    /// Run the loaded program from its entry point with a fresh stack and heap, the lowered
+    /// functions are kept from the previous run.
+    pub fn rerun(&mut self) -> Result<(), RunError> {
+        let code = match self.code.take() {
+            Some(code) => code,
+            None => peephole::lower(&self.program, self.fuse)?,
+        };
+        let result = self.start(&code);
+        self.code = Some(code);
+        result
+    }
+
+    fn start(&mut self, code: &[Vec<Op>]) -> Result<(), RunError> {
         self.stack.clear();
         self.frames.clear();
-        self.push_frame(entry_point);
-        self.status = VMStatus::default();
+        self.heap.clear();
+        let entry = self.program.get_entry_point();
+        let local_cnt = self
+            .program
+            .get(entry)
+            .ok_or(VMError::InvalidFunctionIndex)?
+            .local_cnt();
+        self.stack.resize(local_cnt, UNSET_LOCAL);
+        self.frames.push(Frame::new(entry, 0, local_cnt));
+        self.run(code)
     }
 
-    pub fn execute(&mut self, program: Program) -> Result<(), RunError> {
-        self.reset(program.get_entry_point());
+    /// The dispatch loop. The state of the running frame is kept in locals and written back to
+    /// its [`Frame`] on calls and when the loop ends.
+    fn run(&mut self, code: &[Vec<Op>]) -> Result<(), RunError> {
+        let Frame {
+            mut fi,
+            mut pc,
+            mut base,
+            mut local_cnt,
+        } = *self.frames.last().unwrap();
+        let mut ops = &code[fi][..];
 
-        while !self.is_halted() {
-            let Frame { fi, pc, .. } = self.get_frame();
-            let Some(func) = program.get(*fi) else {
-                return Err(VMError::InvalidFunctionIndex.into());
+        // `?` for the loop, leaving it with the error.
+        macro_rules! tri {
+            ($e:expr) => {
+                match $e {
+                    Ok(v) => v,
+                    Err(e) => break Err(RunError::from(e)),
+                }
             };
-            let Some(inst) = func.get(*pc) else {
-                return Err(VMError::InstReadOutOfBound.into());
+        }
+        macro_rules! slot {
+            ($key:expr) => {{
+                let key = $key;
+                if key >= local_cnt {
+                    break Err(VMError::InvalidLocalId.into());
+                }
+                base + key
+            }};
+        }
+
+        let result = loop {
+            let Some(&op) = ops.get(pc) else {
+                break Err(VMError::InstReadOutOfBound.into());
             };
+            pc += 1;
+            match op {
+                Op::Halt => break Ok(()),
+                Op::Push(v) => self.stack.push(v),
+                Op::Pop => _ = tri!(self.pop()),
+                Op::Load(key) => {
+                    let v = tri!(self.local(slot!(key)));
+                    self.stack.push(v);
+                }
+                Op::Store(key) => {
+                    let slot = slot!(key);
+                    let v = tri!(self.pop());
+                    *tri!(self
+                        .stack
+                        .get_mut(slot)
+                        .ok_or(VMError::StackWriteOutOfBound)) = v;
+                }
 
-            inst.run(self)?;
+                Op::Add => tri!(self.binary(|lhs, rhs| lhs + rhs)),
+                Op::Sub => tri!(self.binary(|lhs, rhs| lhs - rhs)),
+                Op::Mul => tri!(self.binary(|lhs, rhs| lhs * rhs)),
+                Op::Div => tri!(self.binary(|lhs, rhs| lhs / rhs)),
+                Op::Mod => tri!(self.binary(|lhs, rhs| lhs % rhs)),
+                Op::And => tri!(self.binary(|lhs, rhs| lhs & rhs)),
+                Op::Or => tri!(self.binary(|lhs, rhs| lhs | rhs)),
+                Op::Cmp(cmp) => tri!(self.binary(|lhs, rhs| cmp.eval(lhs, rhs).map(Value::from))),
 
-            if self.is_skipped() {
-                self.status.skip = false;
-            } else {
-                self.get_frame_mut().advance();
-            }
-        }
+                Op::Neg => tri!(self.unary(|v| -v)),
+                Op::Not => tri!(self.unary(|v| !v)),
 
-        Ok(())
+                Op::Jmp(target) => pc = target,
+                Op::JmpTrue(target) => {
+                    if tri!(self.pop_bool()) {
+                        pc = target;
+                    }
+                }
+                Op::JmpFalse(target) => {
+                    if !tri!(self.pop_bool()) {
+                        pc = target;
+                    }
+                }
+
+                Op::Call(callee) => {
+                    let Some(func) = self.program.get(callee) else {
+                        break Err(VMError::InvalidFunctionIndex.into());
+                    };
+                    let (param_cnt, callee_locals) = (func.param_cnt(), func.local_cnt());
+                    // the frame owns the stack from its first argument up, with its local slots
+                    // inserted below the arguments.
+                    let Some(callee_base) = self.stack.len().checked_sub(param_cnt) else {
+                        break Err(VMError::StackUnderflow.into());
+                    };
+                    self.stack.splice(
+                        callee_base..callee_base,
+                        std::iter::repeat_n(UNSET_LOCAL, callee_locals),
+                    );
+                    self.frames.last_mut().unwrap().pc = pc;
+                    self.frames
+                        .push(Frame::new(callee, callee_base, callee_locals));
+                    (fi, pc, base, local_cnt) = (callee, 0, callee_base, callee_locals);
+                    ops = &code[fi];
+                }
+                Op::Ret => {
+                    // only the return values are left on top of the caller's stack.
+                    let ret_cnt = self.program.get(fi).unwrap().ret_cnt();
+                    let Some(rets) = self.stack.len().checked_sub(ret_cnt) else {
+                        break Err(VMError::StackUnderflow.into());
+                    };
+                    if rets < base {
+                        break Err(VMError::StackUnderflow.into());
+                    }
+                    if self.frames.len() <= 1 {
+                        break Err(VMError::StackFrameUnderflow.into());
+                    }
+                    self.stack.drain(base..rets);
+                    self.frames.pop();
+                    Frame {
+                        fi,
+                        pc,
+                        base,
+                        local_cnt,
+                    } = *self.frames.last().unwrap();
+                    ops = &code[fi];
+                }
+
+                Op::MakeStruct(cnt) => {
+                    let fields = tri!(self.pop_n(cnt));
+                    let r = tri!(self.alloc(Object::Struct(fields)));
+                    self.stack.push(r.into());
+                }
+                Op::MakeArray(cnt) => {
+                    let elems = tri!(self.pop_n(cnt));
+                    let r = tri!(self.alloc(Object::Array(elems)));
+                    self.stack.push(r.into());
+                }
+                Op::MakeStr(cnt) => {
+                    let chars = tri!(self.pop_n(cnt));
+                    let s = tri!(chars
+                        .into_iter()
+                        .map(|v| match v {
+                            Value::Int(c) => u32::try_from(c).ok().and_then(char::from_u32),
+                            _ => None,
+                        })
+                        .collect::<Option<String>>()
+                        .ok_or(OpError::InvalidTypeForOp));
+                    let r = tri!(self.alloc(Object::Str(s)));
+                    self.stack.push(r.into());
+                }
+                Op::GetField(index) => {
+                    let r = tri!(self.pop_ref());
+                    let Object::Struct(fields) = tri!(self.heap.get(r)) else {
+                        break Err(OpError::InvalidTypeForOp.into());
+                    };
+                    let v = *tri!(fields.get(index).ok_or(VMError::FieldOutOfBound));
+                    self.stack.push(v);
+                }
+                Op::SetField(index) => {
+                    let v = tri!(self.pop());
+                    let r = tri!(self.pop_ref());
+                    let Object::Struct(fields) = tri!(self.heap.get_mut(r)) else {
+                        break Err(OpError::InvalidTypeForOp.into());
+                    };
+                    *tri!(fields.get_mut(index).ok_or(VMError::FieldOutOfBound)) = v;
+                }
+                Op::GetIndex => {
+                    let index = tri!(self.pop_index());
+                    let r = tri!(self.pop_ref());
+                    let v = match tri!(self.heap.get(r)) {
+                        Object::Array(elems) => {
+                            *tri!(elems.get(index).ok_or(VMError::IndexOutOfBound))
+                        }
+                        Object::Str(s) => {
+                            let c = tri!(s.chars().nth(index).ok_or(VMError::IndexOutOfBound));
+                            Value::Int(c as i64)
+                        }
+                        Object::Struct(_) => break Err(OpError::InvalidTypeForOp.into()),
+                    };
+                    self.stack.push(v);
+                }
+                Op::SetIndex => {
+                    let v = tri!(self.pop());
+                    let index = tri!(self.pop_index());
+                    let r = tri!(self.pop_ref());
+                    let Object::Array(elems) = tri!(self.heap.get_mut(r)) else {
+                        break Err(OpError::InvalidTypeForOp.into());
+                    };
+                    *tri!(elems.get_mut(index).ok_or(VMError::IndexOutOfBound)) = v;
+                }
+                Op::Len => {
+                    let r = tri!(self.pop_ref());
+                    let len = match tri!(self.heap.get(r)) {
+                        Object::Struct(values) | Object::Array(values) => values.len(),
+                        Object::Str(s) => s.chars().count(),
+                    };
+                    self.stack.push(Value::Int(len as i64));
+                }
+
+                Op::LoadLoadAdd(a, b) => {
+                    let (a, b) = (slot!(a), slot!(b));
+                    let v = tri!(tri!(self.local(a)) + tri!(self.local(b)));
+                    self.stack.push(v);
+                }
+                Op::PushCmp(rhs, cmp) => {
+                    tri!(self.unary(|lhs| cmp.eval(lhs, rhs).map(Value::from)))
+                }
+                Op::CmpJmp(cmp, on, target) => {
+                    let rhs = tri!(self.pop());
+                    let lhs = tri!(self.pop());
+                    if tri!(cmp.eval(lhs, rhs)) == on {
+                        pc = target;
+                    }
+                }
+                Op::PushCmpJmp(rhs, cmp, on, target) => {
+                    let lhs = tri!(self.pop());
+                    if tri!(cmp.eval(lhs, rhs)) == on {
+                        pc = target;
+                    }
+                }
+            }
+        };
+        self.frames.last_mut().unwrap().pc = pc;
+        result
     }
 
-    pub fn push_frame(&mut self, fi: usize) {
-        self.frames.push(Frame::new(fi));
+    /// The values on the stack, the results of `main` once a program compiled by whiskc halted.
+    pub fn stack(&self) -> &[Value] {
+        &self.stack
     }
 
-    pub fn pop_frame(&mut self) -> Result<(), VMError> {
-        if self.frames.len() <= 1 {
-            return Err(VMError::StackFrameUnderflow);
-        }
-        self.frames.pop();
-        Ok(())
+    /// The value of a local slot, which unverified code may have popped along with the operands.
+    fn local(&self, slot: usize) -> Result<Value, VMError> {
+        self.stack
+            .get(slot)
+            .copied()
+            .ok_or(VMError::StackReadOutOfBound)
     }
 
-    pub fn get_frame(&self) -> &Frame {
-        self.frames.last().unwrap()
+    fn pop(&mut self) -> Result<Value, VMError> {
+        self.stack.pop().ok_or(VMError::StackUnderflow)
     }
 
-    fn get_frame_mut(&mut self) -> &mut Frame {
-        self.frames.last_mut().unwrap()
+    /// Pop `cnt` values, returning them in the order they were pushed.
+    fn pop_n(&mut self, cnt: usize) -> Result<Vec<Value>, VMError> {
+        if self.stack.len() < cnt {
+            return Err(VMError::StackUnderflow);
+        }
+        Ok(self.stack.split_off(self.stack.len() - cnt))
     }
 
-    pub fn push(&mut self, value: Value) {
-        self.stack.push(value);
+    fn pop_bool(&mut self) -> Result<bool, RunError> {
+        match self.pop()? {
+            Value::Bool(v) => Ok(v),
+            _ => Err(OpError::InvalidTypeForOp.into()),
+        }
     }
 
-    pub fn pop(&mut self) -> Result<Value, VMError> {
-        self.stack.pop().ok_or(VMError::StackUnderflow)
+    fn pop_ref(&mut self) -> Result<HeapRef, RunError> {
+        self.pop()?
+            .as_heap_ref()
+            .ok_or(OpError::InvalidTypeForOp.into())
     }
 
-    pub fn store(&mut self, key: usize, value: Value) {
-        self.get_frame_mut().store(key, value);
+    fn pop_index(&mut self) -> Result<usize, RunError> {
+        let Value::Int(index) = self.pop()? else {
+            return Err(OpError::InvalidTypeForOp.into());
+        };
+        usize::try_from(index).map_err(|_| VMError::IndexOutOfBound.into())
     }
 
-    pub fn load(&self, key: usize) -> Result<Value, VMError> {
-        self.get_frame().load(key)
+    /// Replace the value on top of the stack with `f` of it.
+    #[inline(always)]
+    fn unary(&mut self, f: impl FnOnce(Value) -> Result<Value, OpError>) -> Result<(), RunError> {
+        let v = self.stack.last_mut().ok_or(VMError::StackUnderflow)?;
+        *v = f(*v)?;
+        Ok(())
     }
 
-    pub fn jump(&mut self, offset: isize) {
-        self.get_frame_mut().jump(offset);
-        self.skip();
+    /// Replace the two values on top of the stack with `f` of them.
+    #[inline(always)]
+    fn binary(
+        &mut self,
+        f: impl FnOnce(Value, Value) -> Result<Value, OpError>,
+    ) -> Result<(), RunError> {
+        let rhs = self.pop()?;
+        self.unary(|lhs| f(lhs, rhs))
     }
 
-    pub fn call(&mut self, fi: usize) {
-        self.push_frame(fi);
-        self.skip();
+    /// Allocate an object on the heap, running a collection first if the heap asks for one.
+    pub fn alloc(&mut self, obj: Object) -> Result<HeapRef, VMError> {
+        if self.heap.should_collect(obj.size()) {
+            // the values of the object were popped off the stack, they are roots until it is
+            // allocated.
+            let values = match &obj {
+                Object::Struct(values) | Object::Array(values) => &values[..],
+                Object::Str(_) => &[],
+            };
+            self.heap.collect(self.stack.iter().chain(values).copied());
+        }
+        self.heap.alloc(obj)
     }
 
-    pub fn ret(&mut self) -> Result<(), RunError> {
-        self.pop_frame()?;
-        Ok(())
+    /// Run a full mark-and-sweep collection rooted at the stack, which holds the frame locals.
+    pub fn collect_garbage(&mut self) {
+        self.heap.collect(self.stack.iter().copied());
     }
 
-    pub fn halt(&mut self) {
-        self.status.halt = true;
+    pub fn heap(&self) -> &Heap {
+        &self.heap
     }
 
-    pub fn is_halted(&self) -> bool {
-        self.status.halt
+    pub fn heap_mut(&mut self) -> &mut Heap {
+        &mut self.heap
     }
 
-    pub fn skip(&mut self) {
-        self.status.skip = true;
+    pub fn gc_stats(&self) -> &GcStats {
+        self.heap.stats()
     }
+}
 
-    pub fn is_skipped(&self) -> bool {
-        self.status.skip
+impl fmt::Debug for VM {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VM")
+            .field("stack", &self.stack)
+            .field("frames", &self.frames)
+            .field("heap", &self.heap)
+            .finish()
     }
 }
 
@@ -121,49 +425,132 @@ pub enum VMError {
     StackReadOutOfBound,
     StackWriteOutOfBound,
     InvalidLocalId,
+    InvalidConstantIndex,
+    /// A register outside of the window of the function, see [`crate::reg`].
+    InvalidRegister,
+    InvalidReference,
+    FieldOutOfBound,
+    IndexOutOfBound,
+    OutOfMemory,
+    UnresolvedImport,
 }
 
-#[derive(Debug, Default)]
-pub struct VMStatus {
-    halt: bool,
-    skip: bool,
-}
-
-#[derive(Debug)]
+/// A function being run. The frame's part of the stack starts at `base` with its local slots,
+/// followed by its operands. `pc` is the next operation of the lowered function.
+#[derive(Debug, Clone, Copy)]
 pub struct Frame {
     fi: usize,
     pc: usize,
-    locals: HashMap<usize, Value>,
+    base: usize,
+    local_cnt: usize,
 }
 impl Frame {
-    pub fn new(fi: usize) -> Self {
+    pub fn new(fi: usize, base: usize, local_cnt: usize) -> Self {
         Self {
             fi,
             pc: 0,
-            locals: HashMap::new(),
+            base,
+            local_cnt,
         }
     }
+}
 
-    pub fn store(&mut self, key: usize, value: Value) {
-        if let Some(v) = self.locals.get_mut(&key) {
-            *v = value;
-        } else {
-            self.locals.insert(key, value);
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::assemble;
+
+    /// Run a program without verifying it, as `--no-verify` does.
+    fn run(src: &str, fuse: bool) -> Result<Vec<Value>, RunError> {
+        let mut vm = VM::default();
+        vm.set_superinstructions(fuse);
+        vm.execute(assemble(src).unwrap())?;
+        Ok(vm.stack().to_vec())
     }
 
-    pub fn load(&self, key: usize) -> Result<Value, VMError> {
-        self.locals
-            .get(&key)
-            .copied()
-            .ok_or(VMError::InvalidLocalId)
+    #[test]
+    fn rerun_starts_over() {
+        let src = "
+            func start 0 -> 0
+                call main
+                halt
+            func main 0 -> 1
+                push 10
+                store r0
+            loop:
+                load r0
+                push 0
+                cmp gt
+                jfl done
+                load r0
+                push 1
+                sub
+                store r0
+                jmp loop
+            done:
+                load r0
+                load r0
+                add
+                ret
+        ";
+        let mut vm = VM::default();
+        vm.execute(assemble(src).unwrap()).unwrap();
+        assert_eq!(vm.stack(), [Value::Int(0)]);
+        vm.rerun().unwrap();
+        assert_eq!(vm.stack(), [Value::Int(0)]);
+        vm.set_superinstructions(false);
+        vm.rerun().unwrap();
+        assert_eq!(vm.stack(), [Value::Int(0)]);
+    }
+
+    #[test]
+    fn values_of_an_object_survive_its_allocation() {
+        let src = "
+            func main 0 -> 0
+                push 1
+                push 2
+                mkarr 2
+                mkstruct 1
+                getf 0
+                push 0
+                getidx
+                halt
+        ";
+        let mut vm = VM::with_heap_config(HeapConfig {
+            initial_threshold: 0,
+            ..Default::default()
+        });
+        vm.execute(assemble(src).unwrap()).unwrap();
+        assert_eq!(vm.stack(), [Value::Int(1)]);
+        assert_eq!(vm.gc_stats().objects_freed, 0);
+    }
+
+    #[test]
+    fn load_of_popped_local_is_an_error() {
+        let src = "func main 0 -> 1\n push 1\n store r0\n pop\n load r0\n ret\n";
+        for fuse in [false, true] {
+            assert!(matches!(
+                run(src, fuse),
+                Err(RunError::VMError(VMError::StackReadOutOfBound))
+            ));
+        }
     }
 
-    pub fn advance(&mut self) {
-        self.pc = self.pc.wrapping_add(1);
+    #[test]
+    fn store_to_popped_local_is_an_error() {
+        let src = "func main 0 -> 0\n push 1\n store r0\n pop\n push 2\n store r0\n ret\n";
+        assert!(matches!(
+            run(src, false),
+            Err(RunError::VMError(VMError::StackWriteOutOfBound))
+        ));
     }
 
-    pub fn jump(&mut self, offset: isize) {
-        self.pc = self.pc.wrapping_add_signed(offset);
+    #[test]
+    fn fused_load_of_popped_locals_is_an_error() {
+        let src = "func main 0 -> 1\n push 1\n store r0\n push 2\n store r1\n pop\n pop\n load r0\n load r1\n add\n ret\n";
+        assert!(matches!(
+            run(src, true),
+            Err(RunError::VMError(VMError::StackReadOutOfBound))
+        ));
     }
 }